@@ -0,0 +1,577 @@
+//! The ANTLR4 front-end of the [`Grammar`] import
+
+use alloc::{
+    borrow::ToOwned,
+    boxed::Box,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+use hashbrown::HashSet;
+
+use super::{CharClass, Cursor, Expr, Grammar};
+use crate::Error;
+
+/// Parses the source of a `.g4` file, see [`Grammar::from_antlr4`]
+pub(super) fn parse(source: &str) -> Result<Grammar, Error> {
+    let mut parser = AntlrParser {
+        cursor: Cursor::new(source),
+        skips_tokens: false,
+    };
+    let mut rules = Vec::new();
+    let mut declared_tokens = Vec::new();
+    loop {
+        parser.trivia()?;
+        if parser.cursor.is_done() {
+            break;
+        }
+        if parser.cursor.eat("@") {
+            // Named actions, e.g., `@header { ... }`
+            parser.expect_ident()?;
+            if parser.cursor.eat("::") {
+                parser.expect_ident()?;
+            }
+            parser.trivia()?;
+            parser.skip_balanced('{', '}')?;
+            continue;
+        }
+        let word = parser.expect_ident()?;
+        match word {
+            "lexer" | "parser" => {}
+            "grammar" | "mode" => {
+                parser.expect_ident()?;
+                parser.trivia()?;
+                parser.cursor.expect(";")?;
+            }
+            "import" => {
+                log::warn!("Ignoring the imported grammars of an ANTLR4 grammar");
+                parser.cursor.take_while(|c| c != ';');
+                parser.cursor.expect(";")?;
+            }
+            "options" | "channels" => {
+                parser.trivia()?;
+                parser.skip_balanced('{', '}')?;
+            }
+            "tokens" => {
+                parser.trivia()?;
+                parser.cursor.expect("{")?;
+                loop {
+                    parser.trivia()?;
+                    if parser.cursor.eat("}") {
+                        break;
+                    }
+                    if !parser.cursor.eat(",") {
+                        declared_tokens.push(parser.expect_ident()?.to_owned());
+                    }
+                }
+            }
+            "fragment" => {
+                let name = parser.expect_ident()?;
+                rules.push((name.to_owned(), parser.rule()?));
+            }
+            name => rules.push((name.to_owned(), parser.rule()?)),
+        }
+    }
+
+    let Some(start) = rules
+        .iter()
+        .find(|(name, _)| is_parser_rule(name))
+        .or(rules.first())
+        .map(|(name, _)| name.clone())
+    else {
+        return Err(Error::illegal_argument("The grammar has no rules"));
+    };
+
+    let defined = rules
+        .iter()
+        .map(|(name, _)| name.clone())
+        .collect::<HashSet<_>>();
+    for token in declared_tokens {
+        if !defined.contains(&token) {
+            log::warn!("The token {token} has no rule, it derives the empty string");
+            rules.push((token, Expr::empty()));
+        }
+    }
+
+    if parser.skips_tokens {
+        for (name, expr) in &mut rules {
+            if is_parser_rule(name) {
+                *expr = separate(core::mem::replace(expr, Expr::empty()));
+            }
+        }
+    }
+    Ok(Grammar::new(start, rules))
+}
+
+/// Parser rules start with a lowercase letter, lexer rules with an uppercase letter
+fn is_parser_rule(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_lowercase())
+}
+
+/// Separates the tokens of a parser rule by a space
+fn separate(expr: Expr) -> Expr {
+    match expr {
+        Expr::Seq(exprs) => {
+            let mut separated = Vec::with_capacity(exprs.len() * 2);
+            for expr in exprs.into_iter().map(separate) {
+                if expr == Expr::empty() {
+                    continue;
+                }
+                if !separated.is_empty() {
+                    separated.push(Expr::Literal(" ".to_owned()));
+                }
+                separated.push(expr);
+            }
+            if separated.len() == 1 {
+                separated.pop().unwrap()
+            } else {
+                Expr::Seq(separated)
+            }
+        }
+        Expr::Alt(exprs) => Expr::Alt(exprs.into_iter().map(separate).collect()),
+        Expr::Optional(expr) => Expr::Optional(Box::new(separate(*expr))),
+        Expr::Star(expr) => Expr::Star(Box::new(Expr::Seq(vec![
+            Expr::Literal(" ".to_owned()),
+            separate(*expr),
+        ]))),
+        Expr::Plus(expr) => Expr::Plus(Box::new(Expr::Seq(vec![
+            Expr::Literal(" ".to_owned()),
+            separate(*expr),
+        ]))),
+        expr @ (Expr::Literal(_) | Expr::NonTerminal(_) | Expr::Class(_)) => expr,
+    }
+}
+
+/// The characters an expression of single characters matches, for `~` and ranges
+fn to_class(expr: &Expr) -> Option<CharClass> {
+    match expr {
+        Expr::Literal(literal) => {
+            let mut chars = literal.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Some(CharClass::single(c)),
+                _ => None,
+            }
+        }
+        Expr::Class(class) => Some(class.clone()),
+        Expr::Alt(exprs) => exprs.iter().try_fold(CharClass::default(), |class, expr| {
+            Some(class.union(&to_class(expr)?))
+        }),
+        Expr::Seq(exprs) if exprs.len() == 1 => to_class(&exprs[0]),
+        _ => None,
+    }
+}
+
+/// A recursive descent parser for ANTLR4 grammars
+struct AntlrParser<'a> {
+    cursor: Cursor<'a>,
+    /// Whether a lexer rule skips its tokens, or sends them to another channel
+    skips_tokens: bool,
+}
+
+impl<'a> AntlrParser<'a> {
+    fn trivia(&mut self) -> Result<(), Error> {
+        self.cursor.skip_trivia(true)
+    }
+
+    fn ident(&mut self) -> Option<&'a str> {
+        if self
+            .cursor
+            .peek()
+            .is_some_and(|c| c.is_alphabetic() || c == '_')
+        {
+            Some(self.cursor.take_while(|c| c.is_alphanumeric() || c == '_'))
+        } else {
+            None
+        }
+    }
+
+    fn expect_ident(&mut self) -> Result<&'a str, Error> {
+        self.trivia()?;
+        self.ident()
+            .ok_or_else(|| self.cursor.error("expected an identifier"))
+    }
+
+    /// Skips a block, e.g., an action, from `open` to the matching `close`
+    fn skip_balanced(&mut self, open: char, close: char) -> Result<(), Error> {
+        self.cursor.expect(&open.to_string())?;
+        let mut depth = 1;
+        while depth > 0 {
+            match self.cursor.bump() {
+                None => return Err(self.cursor.error(&alloc::format!("unclosed `{open}`"))),
+                Some(quote @ ('"' | '\'')) => {
+                    while let Some(c) = self.cursor.bump() {
+                        if c == '\\' {
+                            self.cursor.bump();
+                        } else if c == quote {
+                            break;
+                        }
+                    }
+                }
+                Some(c) if c == open => depth += 1,
+                Some(c) if c == close => depth -= 1,
+                Some(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses a rule after its name
+    fn rule(&mut self) -> Result<Expr, Error> {
+        self.trivia()?;
+        if self.cursor.peek() == Some('[') {
+            self.skip_balanced('[', ']')?;
+        }
+        loop {
+            self.trivia()?;
+            if self.cursor.eat("@") {
+                self.expect_ident()?;
+                self.trivia()?;
+                self.skip_balanced('{', '}')?;
+                continue;
+            }
+            let pos = self.cursor.pos;
+            match self.ident() {
+                Some("returns" | "locals") => {
+                    self.trivia()?;
+                    self.skip_balanced('[', ']')?;
+                }
+                Some("options") => {
+                    self.trivia()?;
+                    self.skip_balanced('{', '}')?;
+                }
+                Some("throws") => {
+                    self.cursor.take_while(|c| c != ':');
+                }
+                _ => {
+                    self.cursor.pos = pos;
+                    break;
+                }
+            }
+        }
+        self.cursor.expect(":")?;
+        let expr = self.alternatives()?;
+        self.trivia()?;
+        self.cursor.expect(";")?;
+
+        // Exception handlers
+        loop {
+            self.trivia()?;
+            let pos = self.cursor.pos;
+            match self.ident() {
+                Some("catch") => {
+                    self.trivia()?;
+                    self.skip_balanced('[', ']')?;
+                    self.trivia()?;
+                    self.skip_balanced('{', '}')?;
+                }
+                Some("finally") => {
+                    self.trivia()?;
+                    self.skip_balanced('{', '}')?;
+                }
+                _ => {
+                    self.cursor.pos = pos;
+                    return Ok(expr);
+                }
+            }
+        }
+    }
+
+    fn alternatives(&mut self) -> Result<Expr, Error> {
+        let mut alternatives = vec![self.alternative()?];
+        loop {
+            self.trivia()?;
+            if !self.cursor.eat("|") {
+                break;
+            }
+            alternatives.push(self.alternative()?);
+        }
+        Ok(if alternatives.len() == 1 {
+            alternatives.pop().unwrap()
+        } else {
+            Expr::Alt(alternatives)
+        })
+    }
+
+    fn alternative(&mut self) -> Result<Expr, Error> {
+        let mut seq = Vec::new();
+        loop {
+            self.trivia()?;
+            match self.cursor.peek() {
+                None | Some(';' | '|' | ')') => break,
+                Some('#') => {
+                    // Alternative labels
+                    self.cursor.bump();
+                    self.expect_ident()?;
+                }
+                Some('{') => {
+                    // Actions and predicates
+                    self.skip_balanced('{', '}')?;
+                    self.trivia()?;
+                    self.cursor.eat("?");
+                }
+                Some('<') => self.skip_balanced('<', '>')?,
+                Some('-') if self.cursor.eat("->") => self.lexer_commands()?,
+                Some(_) => seq.push(self.element()?),
+            }
+        }
+        Ok(if seq.len() == 1 {
+            seq.pop().unwrap()
+        } else {
+            Expr::Seq(seq)
+        })
+    }
+
+    /// Lexer commands, e.g., `-> skip` or `-> channel(HIDDEN)`
+    fn lexer_commands(&mut self) -> Result<(), Error> {
+        loop {
+            let command = self.expect_ident()?;
+            if command == "skip" || command == "channel" {
+                self.skips_tokens = true;
+            }
+            self.trivia()?;
+            if self.cursor.peek() == Some('(') {
+                self.skip_balanced('(', ')')?;
+                self.trivia()?;
+            }
+            if !self.cursor.eat(",") {
+                return Ok(());
+            }
+        }
+    }
+
+    fn element(&mut self) -> Result<Expr, Error> {
+        // Element labels, `x=atom` or `x+=atom`
+        let pos = self.cursor.pos;
+        if self.ident().is_some() {
+            self.trivia()?;
+            if !(self.cursor.eat("+=") || self.cursor.eat("=")) {
+                self.cursor.pos = pos;
+            }
+        }
+        let atom = self.atom()?;
+        self.trivia()?;
+        let expr = match self.cursor.peek() {
+            Some('?') => Expr::Optional(Box::new(atom)),
+            Some('*') => Expr::Star(Box::new(atom)),
+            Some('+') => Expr::Plus(Box::new(atom)),
+            _ => return Ok(atom),
+        };
+        self.cursor.bump();
+        // Non-greedy operators generate the same
+        self.cursor.eat("?");
+        Ok(expr)
+    }
+
+    fn atom(&mut self) -> Result<Expr, Error> {
+        self.trivia()?;
+        match self.cursor.peek() {
+            Some('\'') => {
+                let literal = self.literal()?;
+                self.trivia()?;
+                if !self.cursor.eat("..") {
+                    return Ok(Expr::Literal(literal));
+                }
+                self.trivia()?;
+                let to = self.literal()?;
+                match (
+                    to_class(&Expr::Literal(literal)),
+                    to_class(&Expr::Literal(to)),
+                ) {
+                    (Some(from), Some(to)) => {
+                        let (from, to) = (from.ranges()[0].0, to.ranges()[0].0);
+                        Ok(Expr::Class(CharClass::from_code_points([(from, to)])))
+                    }
+                    _ => Err(self.cursor.error("ranges need single characters")),
+                }
+            }
+            Some('[') => Ok(Expr::Class(self.char_set()?)),
+            Some('.') => {
+                self.cursor.bump();
+                Ok(Expr::Class(CharClass::any()))
+            }
+            Some('(') => {
+                self.cursor.bump();
+                self.trivia()?;
+                let pos = self.cursor.pos;
+                if self.ident() == Some("options") {
+                    self.trivia()?;
+                    self.skip_balanced('{', '}')?;
+                    self.trivia()?;
+                    self.cursor.expect(":")?;
+                } else {
+                    self.cursor.pos = pos;
+                }
+                let expr = self.alternatives()?;
+                self.trivia()?;
+                self.cursor.expect(")")?;
+                Ok(expr)
+            }
+            Some('~') => {
+                self.cursor.bump();
+                let atom = self.atom()?;
+                to_class(&atom)
+                    .map(|class| Expr::Class(class.negated()))
+                    .ok_or_else(|| self.cursor.error("`~` needs a set of single characters"))
+            }
+            _ => {
+                let name = self
+                    .ident()
+                    .ok_or_else(|| self.cursor.error("unexpected character"))?;
+                if is_parser_rule(name) && self.cursor.peek() == Some('[') {
+                    // Arguments of a parser rule
+                    self.skip_balanced('[', ']')?;
+                }
+                Ok(if name == "EOF" {
+                    Expr::empty()
+                } else {
+                    Expr::NonTerminal(name.to_owned())
+                })
+            }
+        }
+    }
+
+    /// A string literal, `'...'`
+    fn literal(&mut self) -> Result<String, Error> {
+        self.cursor.expect("'")?;
+        let mut literal = String::new();
+        loop {
+            match self.cursor.bump() {
+                None => return Err(self.cursor.error("unterminated literal")),
+                Some('\'') => return Ok(literal),
+                Some('\\') => literal.push(self.escape()?),
+                Some(c) => literal.push(c),
+            }
+        }
+    }
+
+    /// An escape sequence after the `\`
+    fn escape(&mut self) -> Result<char, Error> {
+        let c = self
+            .cursor
+            .bump()
+            .ok_or_else(|| self.cursor.error("unterminated escape"))?;
+        Ok(match c {
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            'b' => '\u{8}',
+            'f' => '\u{c}',
+            'u' => {
+                let hex = if self.cursor.eat("{") {
+                    let hex = self.cursor.take_while(|c| c != '}');
+                    self.cursor.expect("}")?;
+                    hex
+                } else {
+                    let start = self.cursor.pos;
+                    for _ in 0..4 {
+                        self.cursor.bump();
+                    }
+                    &self.cursor.source[start..self.cursor.pos]
+                };
+                u32::from_str_radix(hex, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| self.cursor.error("invalid unicode escape"))?
+            }
+            c => c,
+        })
+    }
+
+    /// A character set, `[...]`
+    fn char_set(&mut self) -> Result<CharClass, Error> {
+        self.cursor.expect("[")?;
+        let mut class = CharClass::default();
+        loop {
+            let from = match self.cursor.bump() {
+                None => return Err(self.cursor.error("unterminated character set")),
+                Some(']') => return Ok(class),
+                Some('\\') if matches!(self.cursor.peek(), Some('p' | 'P')) => {
+                    self.cursor.bump();
+                    self.cursor.expect("{")?;
+                    let property = self.cursor.take_while(|c| c != '}');
+                    self.cursor.expect("}")?;
+                    log::warn!("Approximating the unicode property {property} by ASCII letters");
+                    class = class.union(&CharClass::new([('a', 'z'), ('A', 'Z')]));
+                    continue;
+                }
+                Some('\\') => self.escape()?,
+                Some(c) => c,
+            };
+            let to = if self.cursor.peek() == Some('-') && self.cursor.peek_nth(1) != Some(']') {
+                self.cursor.bump();
+                match self.cursor.bump() {
+                    Some('\\') => self.escape()?,
+                    Some(c) => c,
+                    None => return Err(self.cursor.error("unterminated character set")),
+                }
+            } else {
+                from
+            };
+            class = class.union(&CharClass::new([(from, to)]));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Expr, Grammar, Symbol};
+
+    const GRAMMAR: &str = r"
+grammar Calc;
+
+options { language = Java; }
+
+@header { package calc; }
+
+// The start rule
+prog : stat+ EOF ;
+
+stat
+    : left=expr ';'               # printExpr
+    | ID '=' expr ';' {System.out.println(1);}  # assign
+    ;
+
+expr
+    : <assoc=right> expr op=('*'|'/') expr
+    | expr ('+'|'-') expr
+    | INT
+    | ID
+    | '(' expr ')'
+    ;
+
+ID  : [a-zA-Z_] [a-zA-Z_0-9]* ;
+INT : DIGIT+ ;
+fragment DIGIT : '0'..'9' ;
+STRING : '\'' ( ~['\\\r\n] | '\\' . )*? '\'' ;
+WS  : [ \t\r\n]+ -> skip ;
+/* Comments go to a channel */
+COMMENT : '/*' .*? '*/' -> channel(HIDDEN) ;
+";
+
+    #[test]
+    fn test_antlr4() {
+        let grammar = Grammar::from_antlr4(GRAMMAR).unwrap();
+        assert_eq!(grammar.start(), "prog");
+        assert_eq!(grammar.rules().len(), 9);
+        let (_, stat) = &grammar.rules()[1];
+        let Expr::Alt(alternatives) = stat else {
+            panic!("stat has two alternatives");
+        };
+        // Parser rules separate their tokens, as the lexer skips whitespace
+        assert!(matches!(&alternatives[0], Expr::Seq(seq) if seq.len() == 3));
+
+        let productions = grammar.productions().unwrap();
+        assert_eq!(productions[0].lhs, "Prog");
+        assert!(productions.iter().any(|p| p.lhs == "DIGIT"));
+        assert!(!productions.iter().any(|p| p.lhs == "WS"));
+        assert!(
+            productions
+                .iter()
+                .any(|p| p.rhs.contains(&Symbol::Terminal("*".into())))
+        );
+
+        assert!(Grammar::from_antlr4("grammar X; a : 'x ;").is_err());
+        assert!(Grammar::from_antlr4("grammar X; a : ~('ab') ;").is_err());
+    }
+}
@@ -0,0 +1,415 @@
+//! The W3C-style EBNF front-end of the [`Grammar`] import, for the notation of, e.g., the XML specification
+
+use alloc::{
+    borrow::ToOwned,
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+use hashbrown::HashMap;
+
+use super::{CharClass, Cursor, Expr, Grammar};
+use crate::Error;
+
+/// How deep nonterminals are followed, to find the character class of a difference
+const MAX_CLASS_DEPTH: usize = 16;
+
+/// Parses W3C-style EBNF, see [`Grammar::from_ebnf`]
+pub(super) fn parse(source: &str) -> Result<Grammar, Error> {
+    let mut parser = EbnfParser {
+        cursor: Cursor::new(source),
+        differences: Vec::new(),
+    };
+    let mut rules = Vec::new();
+    loop {
+        parser.trivia()?;
+        if parser.cursor.is_done() {
+            break;
+        }
+        if parser.skip_annotation() {
+            continue;
+        }
+        let name = parser
+            .name()
+            .ok_or_else(|| parser.cursor.error("expected a rule name"))?;
+        parser.trivia()?;
+        parser.cursor.expect("::=")?;
+        rules.push((name.to_owned(), parser.expr()?));
+    }
+    let Some(start) = rules.first().map(|(name, _)| name.clone()) else {
+        return Err(Error::illegal_argument("The grammar has no rules"));
+    };
+
+    // Differences are resolved once all rules are known, in order, so `A - B - C` can build on `A - B`
+    let differences = parser.differences;
+    if !differences.is_empty() {
+        let by_name = rules
+            .iter()
+            .map(|(name, expr)| (name.clone(), expr.clone()))
+            .collect::<HashMap<_, _>>();
+        let mut resolved = Vec::with_capacity(differences.len());
+        for (left, right) in &differences {
+            let expr = if let (Some(left), Some(right)) = (
+                to_class(left, &by_name, &resolved, MAX_CLASS_DEPTH),
+                to_class(right, &by_name, &resolved, MAX_CLASS_DEPTH),
+            ) {
+                Expr::Class(left.difference(&right))
+            } else {
+                log::warn!("Approximating a difference of more than characters by its left side");
+                left.clone()
+            };
+            resolved.push(expr);
+        }
+        for (_, expr) in &mut rules {
+            replace_differences(expr, &resolved);
+        }
+    }
+    Ok(Grammar::new(start, rules))
+}
+
+/// The placeholder nonterminal of the difference with the index `i`, no rule name contains a `\0`
+fn difference_placeholder(i: usize) -> String {
+    format!("\0{i}")
+}
+
+fn replace_differences(expr: &mut Expr, resolved: &[Expr]) {
+    match expr {
+        Expr::NonTerminal(name) => {
+            if let Some(i) = name.strip_prefix('\0') {
+                *expr = resolved[i.parse::<usize>().unwrap()].clone();
+                replace_differences(expr, resolved);
+            }
+        }
+        Expr::Literal(_) | Expr::Class(_) => {}
+        Expr::Seq(exprs) | Expr::Alt(exprs) => {
+            for expr in exprs {
+                replace_differences(expr, resolved);
+            }
+        }
+        Expr::Optional(expr) | Expr::Star(expr) | Expr::Plus(expr) => {
+            replace_differences(expr, resolved);
+        }
+    }
+}
+
+/// The characters an expression of single characters matches, following nonterminals and the `resolved`
+/// differences up to `depth`
+fn to_class(
+    expr: &Expr,
+    rules: &HashMap<String, Expr>,
+    resolved: &[Expr],
+    depth: usize,
+) -> Option<CharClass> {
+    match expr {
+        Expr::Literal(literal) => {
+            let mut chars = literal.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Some(CharClass::single(c)),
+                _ => None,
+            }
+        }
+        Expr::Class(class) => Some(class.clone()),
+        Expr::NonTerminal(name) if depth > 0 => {
+            let expr = match name.strip_prefix('\0') {
+                Some(i) => resolved.get(i.parse::<usize>().ok()?)?,
+                None => rules.get(name)?,
+            };
+            to_class(expr, rules, resolved, depth - 1)
+        }
+        Expr::Alt(exprs) => exprs.iter().try_fold(CharClass::default(), |class, expr| {
+            Some(class.union(&to_class(expr, rules, resolved, depth)?))
+        }),
+        Expr::Seq(exprs) if exprs.len() == 1 => to_class(&exprs[0], rules, resolved, depth),
+        _ => None,
+    }
+}
+
+/// A recursive descent parser for W3C-style EBNF
+struct EbnfParser<'a> {
+    cursor: Cursor<'a>,
+    /// The differences, `left - right`, by their placeholders
+    differences: Vec<(Expr, Expr)>,
+}
+
+impl<'a> EbnfParser<'a> {
+    fn trivia(&mut self) -> Result<(), Error> {
+        self.cursor.skip_trivia(false)
+    }
+
+    /// A name, which may contain `-`, `.` and `:` between the characters
+    fn name(&mut self) -> Option<&'a str> {
+        if !self
+            .cursor
+            .peek()
+            .is_some_and(|c| c.is_alphabetic() || c == '_')
+        {
+            return None;
+        }
+        let start = self.cursor.pos;
+        loop {
+            self.cursor.take_while(|c| c.is_alphanumeric() || c == '_');
+            let joins = matches!(self.cursor.peek(), Some('-' | '.' | ':'))
+                && self.cursor.peek_nth(1).is_some_and(char::is_alphanumeric);
+            if !joins {
+                return Some(&self.cursor.source[start..self.cursor.pos]);
+            }
+            self.cursor.bump();
+        }
+    }
+
+    /// Whether the source continues with a new rule, `name ::=`
+    fn at_rule(&mut self) -> bool {
+        let pos = self.cursor.pos;
+        let at_rule =
+            self.name().is_some() && self.trivia().is_ok() && self.cursor.rest().starts_with("::=");
+        self.cursor.pos = pos;
+        at_rule
+    }
+
+    /// Skips rule numbers, e.g., `[12]`, and constraints, e.g., `[ WFC: Element Type Match ]`.
+    ///
+    /// A rule number starts a line and is followed by the rule, so classes like `[01]` are not taken for one.
+    fn skip_annotation(&mut self) -> bool {
+        let Some(inner) = self.cursor.rest().strip_prefix('[') else {
+            return false;
+        };
+        let Some(end) = inner.find(']') else {
+            return false;
+        };
+        let inner = inner[..end].trim().to_ascii_lowercase();
+        let pos = self.cursor.pos;
+        if inner.starts_with("wfc:") || inner.starts_with("vc:") {
+            self.cursor.pos += end + 2;
+            return true;
+        }
+
+        let is_number = !inner.is_empty() && inner.chars().all(|c| c.is_ascii_digit());
+        let starts_line = self.cursor.source[..pos]
+            .rsplit('\n')
+            .next()
+            .is_some_and(|line| line.trim().is_empty());
+        if !is_number || !starts_line {
+            return false;
+        }
+        self.cursor.pos += end + 2;
+        if self.trivia().is_ok() && self.at_rule() {
+            true
+        } else {
+            self.cursor.pos = pos;
+            false
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, Error> {
+        let mut alternatives = vec![self.sequence()?];
+        while self.cursor.eat("|") {
+            alternatives.push(self.sequence()?);
+        }
+        Ok(if alternatives.len() == 1 {
+            alternatives.pop().unwrap()
+        } else {
+            Expr::Alt(alternatives)
+        })
+    }
+
+    fn sequence(&mut self) -> Result<Expr, Error> {
+        let mut seq = Vec::new();
+        loop {
+            self.trivia()?;
+            match self.cursor.peek() {
+                None | Some('|' | ')') => break,
+                Some('[') if self.skip_annotation() => {
+                    // The rule ends before its constraints, or the number of the next rule
+                    break;
+                }
+                _ if self.at_rule() => break,
+                _ => seq.push(self.difference()?),
+            }
+        }
+        Ok(if seq.len() == 1 {
+            seq.pop().unwrap()
+        } else {
+            Expr::Seq(seq)
+        })
+    }
+
+    fn difference(&mut self) -> Result<Expr, Error> {
+        let mut left = self.postfix()?;
+        loop {
+            self.trivia()?;
+            if !self.cursor.eat("-") {
+                return Ok(left);
+            }
+            self.trivia()?;
+            let right = self.postfix()?;
+            self.differences.push((left, right));
+            left = Expr::NonTerminal(difference_placeholder(self.differences.len() - 1));
+        }
+    }
+
+    fn postfix(&mut self) -> Result<Expr, Error> {
+        let primary = self.primary()?;
+        let expr = match self.cursor.peek() {
+            Some('?') => Expr::Optional(Box::new(primary)),
+            Some('*') => Expr::Star(Box::new(primary)),
+            Some('+') => Expr::Plus(Box::new(primary)),
+            _ => return Ok(primary),
+        };
+        self.cursor.bump();
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<Expr, Error> {
+        match self.cursor.peek() {
+            Some('(') => {
+                self.cursor.bump();
+                let expr = self.expr()?;
+                self.cursor.expect(")")?;
+                Ok(expr)
+            }
+            Some(quote @ ('"' | '\'')) => {
+                self.cursor.bump();
+                let literal = self.cursor.take_while(|c| c != quote);
+                self.cursor.expect(&quote.to_string())?;
+                Ok(Expr::Literal(literal.to_owned()))
+            }
+            Some('#') => Ok(Expr::Literal(self.char_ref()?.to_string())),
+            Some('[') => Ok(Expr::Class(self.char_class()?)),
+            _ => self
+                .name()
+                .map(|name| Expr::NonTerminal(name.to_owned()))
+                .ok_or_else(|| self.cursor.error("unexpected character")),
+        }
+    }
+
+    /// A character reference, `#xN`
+    fn char_ref(&mut self) -> Result<char, Error> {
+        self.cursor.expect("#x")?;
+        let hex = self.cursor.take_while(|c| c.is_ascii_hexdigit());
+        u32::from_str_radix(hex, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| self.cursor.error("invalid character reference"))
+    }
+
+    /// A character of a class
+    fn class_char(&mut self) -> Result<char, Error> {
+        if self.cursor.rest().starts_with("#x") {
+            self.char_ref()
+        } else {
+            self.cursor
+                .bump()
+                .ok_or_else(|| self.cursor.error("unterminated character class"))
+        }
+    }
+
+    /// A character class, `[a-z#x80]` or `[^<&]`
+    fn char_class(&mut self) -> Result<CharClass, Error> {
+        self.cursor.expect("[")?;
+        let negated = self.cursor.eat("^");
+        let mut class = CharClass::default();
+        while !self.cursor.eat("]") {
+            let from = self.class_char()?;
+            let to = if self.cursor.peek() == Some('-') && self.cursor.peek_nth(1) != Some(']') {
+                self.cursor.bump();
+                self.class_char()?
+            } else {
+                from
+            };
+            class = class.union(&CharClass::new([(from, to)]));
+        }
+        Ok(if negated { class.negated() } else { class })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{boxed::Box, vec::Vec};
+
+    use super::super::{CharClass, Expr, Grammar};
+
+    const GRAMMAR: &str = r#"
+/* A few rules of the XML specification */
+[1]  document ::= prolog element
+[22] prolog   ::= XMLDecl?
+[23] XMLDecl  ::= '<?xml' VersionInfo S? '?>'
+[24] VersionInfo ::= S 'version' Eq ("'" VersionNum "'" | '"' VersionNum '"')
+[25] Eq       ::= S? '=' S?
+[26] VersionNum ::= '1.' [0-9]+
+[39] element  ::= EmptyElemTag | STag content ETag [ WFC: Element Type Match ]
+[40] STag     ::= '<' Name '>'
+[42] ETag     ::= '</' Name '>'
+[43] content  ::= (CharData | element)*
+[44] EmptyElemTag ::= '<' Name '/>'
+[14] CharData ::= (Char - [<&])+
+[2]  Char     ::= #x9 | #xA | #xD | [#x20-#xD7FF] | [#xE000-#xFFFD] | [#x10000-#x10FFFF]
+[3]  S        ::= (#x20 | #x9 | #xD | #xA)+
+[5]  Name     ::= [a-zA-Z_:] [^#x20<>/=]*
+     Bit      ::= [01]
+     Consonant ::= [a-z] - [aeiou] - 'y'
+"#;
+
+    #[test]
+    fn test_ebnf() {
+        let grammar = Grammar::from_ebnf(GRAMMAR).unwrap();
+        assert_eq!(grammar.start(), "document");
+        assert_eq!(grammar.rules().len(), 17);
+        let rule = |name: &str| {
+            grammar
+                .rules()
+                .iter()
+                .find(|(rule, _)| rule == name)
+                .map(|(_, expr)| expr)
+                .unwrap()
+        };
+        assert_eq!(
+            rule("prolog"),
+            &Expr::Optional(Box::new(Expr::NonTerminal("XMLDecl".into())))
+        );
+        let Expr::Plus(char_data) = rule("CharData") else {
+            panic!("CharData is a repetition");
+        };
+        let Expr::Class(char_data) = char_data.as_ref() else {
+            panic!("the difference of classes is a class");
+        };
+        assert!(!char_data.chars().any(|c| c == '<' || c == '&'));
+        assert!(char_data.chars().take(100).any(|c| c == 'a'));
+        let Expr::Seq(name) = rule("Name") else {
+            panic!("Name is a sequence");
+        };
+        assert_eq!(
+            name[0],
+            Expr::Class(CharClass::new([
+                ('a', 'z'),
+                ('A', 'Z'),
+                ('_', '_'),
+                (':', ':')
+            ]))
+        );
+        assert_eq!(rule("Bit"), &Expr::Class(CharClass::new([('0', '1')])));
+        let Expr::Class(consonant) = rule("Consonant") else {
+            panic!("the chained difference of classes is a class");
+        };
+        assert!(consonant.chars().any(|c| c == 'b'));
+        assert!(!consonant.chars().any(|c| c == 'a' || c == 'y'));
+
+        let productions = grammar.productions().unwrap();
+        let (json, start) = grammar.to_gramatron_json().unwrap();
+        assert_eq!(start, "Document");
+        assert!(json["VersionNum"].is_array());
+        assert_eq!(
+            productions
+                .iter()
+                .filter(|p| p.lhs == "Element")
+                .collect::<Vec<_>>()
+                .len(),
+            2
+        );
+
+        assert!(Grammar::from_ebnf("a ::= 'x").is_err());
+        assert!(Grammar::from_ebnf("a ::= (b").is_err());
+    }
+}
@@ -0,0 +1,824 @@
+//! A grammar front-end, importing ANTLR4 (`.g4`) and W3C-style EBNF grammars for the grammar fuzzers,
+//! e.g., to reuse the grammars of the [grammars-v4](https://github.com/antlr/grammars-v4) collection.
+//!
+//! A [`Grammar`] keeps the rules as [`Expr`]essions, with alternatives, repetitions, optionals and character
+//! classes. [`Grammar::productions`] normalizes them into plain [`Production`]s, introducing a nonterminal for
+//! each nested alternative, repetition, or optional. The productions feed `NautilusContext::from_grammar` (with
+//! the `nautilus` feature), and [`Grammar::to_gramatron_json`] turns them into the JSON grammars of the Gramatron
+//! preprocessing scripts in `utils/gramatron`.
+
+use alloc::{
+    borrow::ToOwned,
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::fmt::Write;
+use std::{fs, path::Path};
+
+use hashbrown::{HashMap, HashSet};
+use serde_json::{Map, Value};
+
+use crate::Error;
+
+mod antlr;
+mod ebnf;
+
+/// The largest code point of a [`CharClass`]
+const MAX_CHAR: u32 = char::MAX as u32;
+/// The surrogates, which are no chars
+const SURROGATES: (u32, u32) = (0xD800, 0xDFFF);
+/// The characters character classes expand to for Gramatron, which has no classes: tab, newlines and printable
+/// ASCII. The single quote can not be part of a Gramatron terminal.
+const GRAMATRON_ALPHABET: &[(u32, u32)] = &[(0x09, 0x0A), (0x0D, 0x0D), (0x20, 0x26), (0x28, 0x7E)];
+/// The most nullable nonterminals in one production, when removing empty productions for Gramatron
+const MAX_NULLABLE_PER_PRODUCTION: usize = 12;
+
+/// A set of characters, as sorted and disjoint ranges of code points
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CharClass {
+    ranges: Vec<(u32, u32)>,
+}
+
+impl CharClass {
+    /// Creates a class from inclusive ranges of characters
+    pub fn new<It>(ranges: It) -> Self
+    where
+        It: IntoIterator<Item = (char, char)>,
+    {
+        Self::from_code_points(
+            ranges
+                .into_iter()
+                .map(|(from, to)| (u32::from(from), u32::from(to))),
+        )
+    }
+
+    /// Creates a class of a single character
+    #[must_use]
+    pub fn single(c: char) -> Self {
+        Self::new([(c, c)])
+    }
+
+    /// Creates a class of all characters
+    #[must_use]
+    pub fn any() -> Self {
+        Self::default().negated()
+    }
+
+    fn from_code_points<It>(ranges: It) -> Self
+    where
+        It: IntoIterator<Item = (u32, u32)>,
+    {
+        let mut ranges = ranges
+            .into_iter()
+            .filter(|(from, to)| from <= to)
+            .collect::<Vec<_>>();
+        ranges.sort_unstable();
+        let mut merged: Vec<(u32, u32)> = Vec::with_capacity(ranges.len());
+        for (from, to) in ranges {
+            match merged.last_mut() {
+                Some(last) if from <= last.1.saturating_add(1) => last.1 = last.1.max(to),
+                _ => merged.push((from, to.min(MAX_CHAR))),
+            }
+        }
+        Self { ranges: merged }.without_surrogates()
+    }
+
+    fn without_surrogates(mut self) -> Self {
+        let (low, high) = SURROGATES;
+        let mut ranges = Vec::with_capacity(self.ranges.len() + 1);
+        for (from, to) in self.ranges {
+            if to < low || from > high {
+                ranges.push((from, to));
+                continue;
+            }
+            if from < low {
+                ranges.push((from, low - 1));
+            }
+            if to > high {
+                ranges.push((high + 1, to));
+            }
+        }
+        self.ranges = ranges;
+        self
+    }
+
+    /// The class of all characters not in this class
+    #[must_use]
+    pub fn negated(&self) -> Self {
+        let mut ranges = Vec::with_capacity(self.ranges.len() + 1);
+        let mut next = 0;
+        for &(from, to) in &self.ranges {
+            if from > next {
+                ranges.push((next, from - 1));
+            }
+            next = to + 1;
+        }
+        if next <= MAX_CHAR {
+            ranges.push((next, MAX_CHAR));
+        }
+        Self { ranges }.without_surrogates()
+    }
+
+    /// The class of the characters in this or the other class
+    #[must_use]
+    pub fn union(&self, other: &Self) -> Self {
+        Self::from_code_points(self.ranges.iter().chain(&other.ranges).copied())
+    }
+
+    /// The class of the characters in this, but not in the other class
+    #[must_use]
+    pub fn difference(&self, other: &Self) -> Self {
+        self.negated().union(other).negated()
+    }
+
+    /// The class of the characters in both classes
+    #[must_use]
+    pub fn intersection(&self, other: &Self) -> Self {
+        self.negated().union(&other.negated()).negated()
+    }
+
+    /// Whether the class has no characters
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// The inclusive ranges of code points of the class
+    #[must_use]
+    pub fn ranges(&self) -> &[(u32, u32)] {
+        &self.ranges
+    }
+
+    /// The characters of the class
+    pub fn chars(&self) -> impl Iterator<Item = char> + '_ {
+        self.ranges
+            .iter()
+            .flat_map(|&(from, to)| (from..=to).filter_map(char::from_u32))
+    }
+
+    /// A regular expression matching one character of the class
+    #[must_use]
+    pub fn to_regex(&self) -> String {
+        if self.is_empty() {
+            return "[^\\x{0}-\\x{10FFFF}]".to_owned();
+        }
+        let mut regex = String::from("[");
+        for &(from, to) in &self.ranges {
+            if from == to {
+                write!(regex, "\\x{{{from:X}}}").unwrap();
+            } else {
+                write!(regex, "\\x{{{from:X}}}-\\x{{{to:X}}}").unwrap();
+            }
+        }
+        regex.push(']');
+        regex
+    }
+}
+
+/// An expression of a grammar rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    /// A literal string
+    Literal(String),
+    /// A reference to the rule of a nonterminal
+    NonTerminal(String),
+    /// Any one character of the class
+    Class(CharClass),
+    /// The expressions one after the other, the empty sequence matches the empty string
+    Seq(Vec<Expr>),
+    /// Any one of the expressions
+    Alt(Vec<Expr>),
+    /// The expression or nothing
+    Optional(Box<Expr>),
+    /// The expression any number of times
+    Star(Box<Expr>),
+    /// The expression at least once
+    Plus(Box<Expr>),
+}
+
+impl Expr {
+    /// The expression matching the empty string
+    #[must_use]
+    pub fn empty() -> Self {
+        Self::Seq(Vec::new())
+    }
+
+    /// Visits the names of the nonterminals this expression references
+    fn for_each_nonterminal<'a>(&'a self, f: &mut impl FnMut(&'a str)) {
+        match self {
+            Self::NonTerminal(name) => f(name),
+            Self::Literal(_) | Self::Class(_) => {}
+            Self::Seq(exprs) | Self::Alt(exprs) => {
+                for expr in exprs {
+                    expr.for_each_nonterminal(f);
+                }
+            }
+            Self::Optional(expr) | Self::Star(expr) | Self::Plus(expr) => {
+                expr.for_each_nonterminal(f);
+            }
+        }
+    }
+}
+
+/// A symbol of a [`Production`]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Symbol {
+    /// A literal string
+    Terminal(String),
+    /// A nonterminal
+    NonTerminal(String),
+    /// Any one character of the class
+    Class(CharClass),
+}
+
+/// A normalized rule of a [`Grammar`]: the nonterminal `lhs` derives the symbols `rhs`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Production {
+    /// The nonterminal
+    pub lhs: String,
+    /// The symbols it derives, in order, empty for the empty string
+    pub rhs: Vec<Symbol>,
+}
+
+/// A grammar, imported from ANTLR4 or W3C-style EBNF, as described in the [module documentation](self)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grammar {
+    start: String,
+    rules: Vec<(String, Expr)>,
+}
+
+impl Grammar {
+    /// Creates a grammar from its rules, deriving the inputs from the nonterminal `start`.
+    ///
+    /// Several rules for the same nonterminal are alternatives.
+    #[must_use]
+    pub fn new(start: String, rules: Vec<(String, Expr)>) -> Self {
+        Self { start, rules }
+    }
+
+    /// Imports an ANTLR4 grammar, the source of a `.g4` file.
+    ///
+    /// Lexer and parser rules are imported alike, the first parser rule is the start symbol. Actions, predicates,
+    /// labels, options and lexer commands are ignored. If the lexer skips tokens, e.g., whitespace, the tokens of
+    /// the parser rules are separated by a space.
+    pub fn from_antlr4(source: &str) -> Result<Self, Error> {
+        antlr::parse(source)
+    }
+
+    /// Imports a grammar in the EBNF notation of the W3C, e.g., of the XML specification, the first rule is
+    /// the start symbol.
+    pub fn from_ebnf(source: &str) -> Result<Self, Error> {
+        ebnf::parse(source)
+    }
+
+    /// Imports a grammar file, `.g4` files as ANTLR4 and any others as W3C-style EBNF
+    pub fn from_file<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path)?;
+        if path.extension().is_some_and(|ext| ext == "g4") {
+            Self::from_antlr4(&source)
+        } else {
+            Self::from_ebnf(&source)
+        }
+        .map_err(|err| Error::illegal_argument(format!("In {}: {err}", path.display())))
+    }
+
+    /// The nonterminal the inputs derive from
+    #[must_use]
+    pub fn start(&self) -> &str {
+        &self.start
+    }
+
+    /// The rules of the grammar
+    #[must_use]
+    pub fn rules(&self) -> &[(String, Expr)] {
+        &self.rules
+    }
+
+    /// Normalizes the rules reachable from the start symbol into [`Production`]s, the first one deriving the
+    /// start symbol.
+    ///
+    /// The nonterminals are renamed to start with a capital letter and to only contain `[a-zA-Z_0-9]`, as
+    /// Nautilus expects. The nonterminals introduced for nested expressions contain a `-`, the ones the exports
+    /// introduce contain `--`.
+    pub fn productions(&self) -> Result<Vec<Production>, Error> {
+        let mut exprs: HashMap<&str, Vec<&Expr>> = HashMap::new();
+        for (name, expr) in &self.rules {
+            exprs.entry(name.as_str()).or_default().push(expr);
+        }
+
+        // The rules reachable from the start symbol, in the order of the grammar
+        let mut reachable = HashSet::new();
+        let mut worklist = VecDeque::from([self.start.as_str()]);
+        let mut undefined = BTreeSet::new();
+        while let Some(name) = worklist.pop_front() {
+            if !reachable.insert(name) {
+                continue;
+            }
+            let Some(rules) = exprs.get(name) else {
+                undefined.insert(name);
+                continue;
+            };
+            for expr in rules {
+                expr.for_each_nonterminal(&mut |nt| worklist.push_back(nt));
+            }
+        }
+        if !undefined.is_empty() {
+            return Err(Error::illegal_argument(format!(
+                "Undefined nonterminals: {}",
+                undefined.into_iter().collect::<Vec<_>>().join(", ")
+            )));
+        }
+
+        let mut names = HashMap::new();
+        let mut used = HashSet::new();
+        let mut order = vec![self.start.as_str()];
+        order.extend(
+            self.rules
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .filter(|name| *name != self.start && reachable.contains(name)),
+        );
+        order.dedup();
+        let mut seen = HashSet::new();
+        order.retain(|name| seen.insert(*name));
+        for name in &order {
+            let mut renamed = rename(name);
+            while used.contains(&renamed) {
+                renamed.push('_');
+            }
+            used.insert(renamed.clone());
+            names.insert(*name, renamed);
+        }
+
+        let mut normalizer = Normalizer {
+            names: &names,
+            productions: Vec::new(),
+            counters: HashMap::new(),
+        };
+        for name in order {
+            let lhs = names[name].clone();
+            for expr in &exprs[name] {
+                normalizer.add_alternatives(&lhs, expr);
+            }
+        }
+        Ok(normalizer.productions)
+    }
+
+    /// The grammar in the JSON format of the Gramatron preprocessing scripts in `utils/gramatron`, to convert into
+    /// Greibach normal form with `gnf_converter.py`, using the returned start symbol, and then into an automaton.
+    ///
+    /// Gramatron has neither empty productions nor character classes: empty productions are removed, so the
+    /// empty input can not be generated, and classes become one terminal for each tab, newline, or printable
+    /// ASCII character they contain. Productions with terminals including a `'` are dropped.
+    pub fn to_gramatron_json(&self) -> Result<(Value, String), Error> {
+        let productions = self.productions()?;
+        let start = productions[0].lhs.clone();
+
+        // Character classes become nonterminals, one terminal each
+        let alphabet = CharClass::from_code_points(GRAMATRON_ALPHABET.iter().copied());
+        let mut classes: BTreeMap<CharClass, String> = BTreeMap::new();
+        let mut rules: BTreeMap<String, BTreeSet<Vec<Symbol>>> = BTreeMap::new();
+        for production in productions {
+            let mut rhs = Vec::with_capacity(production.rhs.len());
+            let mut dropped = false;
+            for symbol in production.rhs {
+                match symbol {
+                    Symbol::Class(class) => {
+                        let count = classes.len();
+                        let name = classes
+                            .entry(class)
+                            .or_insert_with(|| format!("Class--{count}"));
+                        rhs.push(Symbol::NonTerminal(name.clone()));
+                    }
+                    Symbol::Terminal(term) if term.contains('\'') => {
+                        log::warn!(
+                            "Gramatron terminals can not contain a ', dropping a production of {}",
+                            production.lhs
+                        );
+                        dropped = true;
+                        break;
+                    }
+                    symbol => rhs.push(symbol),
+                }
+            }
+            let alternatives = rules.entry(production.lhs).or_default();
+            if !dropped {
+                alternatives.insert(rhs);
+            }
+        }
+        for (class, name) in classes {
+            let terminals = class
+                .intersection(&alphabet)
+                .chars()
+                .map(|c| vec![Symbol::Terminal(c.to_string())])
+                .collect::<BTreeSet<_>>();
+            if terminals.is_empty() {
+                return Err(Error::illegal_argument(format!(
+                    "The character class {} has no characters Gramatron supports",
+                    class.to_regex()
+                )));
+            }
+            rules.insert(name, terminals);
+        }
+
+        let rules = remove_empty_productions(&rules)?;
+        if !rules.contains_key(&start) {
+            return Err(Error::illegal_argument(format!(
+                "The start symbol {start} only derives the empty string"
+            )));
+        }
+
+        let mut json = Map::new();
+        for (lhs, alternatives) in rules {
+            let alternatives = alternatives
+                .into_iter()
+                .map(|rhs| {
+                    let symbols = rhs
+                        .into_iter()
+                        .map(|symbol| match symbol {
+                            Symbol::Terminal(term) => format!("'{term}'"),
+                            Symbol::NonTerminal(nt) => nt,
+                            Symbol::Class(_) => unreachable!("classes were replaced"),
+                        })
+                        .collect::<Vec<_>>();
+                    Value::String(symbols.join(" "))
+                })
+                .collect();
+            json.insert(lhs, Value::Array(alternatives));
+        }
+        Ok((Value::Object(json), start))
+    }
+}
+
+/// Renames a nonterminal to start with a capital letter and only contain `[a-zA-Z_0-9]`
+fn rename(name: &str) -> String {
+    let mut renamed = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    match renamed.chars().next() {
+        Some(c) if c.is_ascii_lowercase() => {
+            renamed.replace_range(..1, &c.to_ascii_uppercase().to_string());
+        }
+        Some(c) if c.is_ascii_uppercase() => {}
+        _ => renamed.insert(0, 'N'),
+    }
+    // Reserved for the start rules of Nautilus and Gramatron
+    if renamed == "START" || renamed == "Start" {
+        renamed.push('_');
+    }
+    renamed
+}
+
+/// Turns [`Expr`]essions into [`Production`]s
+struct Normalizer<'a> {
+    names: &'a HashMap<&'a str, String>,
+    productions: Vec<Production>,
+    counters: HashMap<String, usize>,
+}
+
+impl Normalizer<'_> {
+    /// A new nonterminal for a nested expression in a rule of `lhs`
+    fn fresh(&mut self, lhs: &str) -> String {
+        let base = lhs.split('-').next().unwrap_or(lhs).to_owned();
+        let counter = self.counters.entry(base.clone()).or_default();
+        *counter += 1;
+        format!("{base}-{counter}")
+    }
+
+    /// Adds the productions of `lhs` deriving `expr`
+    fn add_alternatives(&mut self, lhs: &str, expr: &Expr) {
+        if let Expr::Alt(alternatives) = expr {
+            for alternative in alternatives {
+                self.add_alternatives(lhs, alternative);
+            }
+        } else {
+            // The production comes before the ones of its nested expressions
+            let index = self.productions.len();
+            self.productions.push(Production {
+                lhs: lhs.to_owned(),
+                rhs: Vec::new(),
+            });
+            let mut rhs = Vec::new();
+            self.push_symbols(lhs, expr, &mut rhs);
+            self.productions[index].rhs = rhs;
+        }
+    }
+
+    /// Appends the symbols deriving `expr` to `rhs`, adding nonterminals for nested expressions
+    fn push_symbols(&mut self, lhs: &str, expr: &Expr, rhs: &mut Vec<Symbol>) {
+        match expr {
+            Expr::Literal(term) => {
+                if !term.is_empty() {
+                    rhs.push(Symbol::Terminal(term.clone()));
+                }
+            }
+            Expr::NonTerminal(name) => {
+                rhs.push(Symbol::NonTerminal(self.names[name.as_str()].clone()));
+            }
+            Expr::Class(class) => rhs.push(Symbol::Class(class.clone())),
+            Expr::Seq(exprs) => {
+                for expr in exprs {
+                    self.push_symbols(lhs, expr, rhs);
+                }
+            }
+            Expr::Alt(_) => {
+                let nt = self.fresh(lhs);
+                self.add_alternatives(&nt, expr);
+                rhs.push(Symbol::NonTerminal(nt));
+            }
+            Expr::Optional(inner) => {
+                let nt = self.fresh(lhs);
+                self.productions.push(Production {
+                    lhs: nt.clone(),
+                    rhs: Vec::new(),
+                });
+                self.add_alternatives(&nt, inner);
+                rhs.push(Symbol::NonTerminal(nt));
+            }
+            Expr::Star(inner) | Expr::Plus(inner) => {
+                // Right recursion, as Gramatron can not remove all left recursions
+                let nt = self.fresh(lhs);
+                let mut once = Vec::new();
+                self.push_symbols(&nt, inner, &mut once);
+                let mut more = once.clone();
+                more.push(Symbol::NonTerminal(nt.clone()));
+                let first = if matches!(expr, Expr::Star(_)) {
+                    Vec::new()
+                } else {
+                    once
+                };
+                self.productions.push(Production {
+                    lhs: nt.clone(),
+                    rhs: first,
+                });
+                self.productions.push(Production {
+                    lhs: nt.clone(),
+                    rhs: more,
+                });
+                rhs.push(Symbol::NonTerminal(nt));
+            }
+        }
+    }
+}
+
+/// Removes the empty productions, adding the productions without the nullable nonterminals instead
+fn remove_empty_productions(
+    rules: &BTreeMap<String, BTreeSet<Vec<Symbol>>>,
+) -> Result<BTreeMap<String, BTreeSet<Vec<Symbol>>>, Error> {
+    let mut nullable = HashSet::new();
+    let mut something_changed = true;
+    while something_changed {
+        something_changed = false;
+        for (lhs, alternatives) in rules {
+            if nullable.contains(lhs) {
+                continue;
+            }
+            let is_nullable = alternatives.iter().any(|rhs| {
+                rhs.iter().all(
+                    |symbol| matches!(symbol, Symbol::NonTerminal(nt) if nullable.contains(nt)),
+                )
+            });
+            if is_nullable {
+                nullable.insert(lhs.clone());
+                something_changed = true;
+            }
+        }
+    }
+
+    let mut result: BTreeMap<String, BTreeSet<Vec<Symbol>>> = BTreeMap::new();
+    for (lhs, alternatives) in rules {
+        let entry = result.entry(lhs.clone()).or_default();
+        for rhs in alternatives {
+            let optional = rhs
+                .iter()
+                .enumerate()
+                .filter(|(_, symbol)| matches!(symbol, Symbol::NonTerminal(nt) if nullable.contains(nt)))
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>();
+            if optional.len() > MAX_NULLABLE_PER_PRODUCTION {
+                return Err(Error::illegal_argument(format!(
+                    "A production of {lhs} has too many optional parts for Gramatron"
+                )));
+            }
+            for mask in 0..(1_usize << optional.len()) {
+                let variant = rhs
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| {
+                        optional
+                            .iter()
+                            .position(|o| o == i)
+                            .is_none_or(|bit| mask & (1 << bit) == 0)
+                    })
+                    .map(|(_, symbol)| symbol.clone())
+                    .collect::<Vec<_>>();
+                if !variant.is_empty() {
+                    entry.insert(variant);
+                }
+            }
+        }
+    }
+
+    // Nonterminals deriving only the empty string are gone, and so are the productions using them
+    loop {
+        let empty = result
+            .iter()
+            .filter(|(_, alternatives)| alternatives.is_empty())
+            .map(|(lhs, _)| lhs.clone())
+            .collect::<HashSet<_>>();
+        if empty.is_empty() {
+            return Ok(result);
+        }
+        result.retain(|lhs, _| !empty.contains(lhs));
+        for alternatives in result.values_mut() {
+            alternatives.retain(|rhs| {
+                !rhs.iter()
+                    .any(|symbol| matches!(symbol, Symbol::NonTerminal(nt) if empty.contains(nt)))
+            });
+        }
+    }
+}
+
+/// A cursor over the source of a grammar, for the front-ends
+#[derive(Debug)]
+struct Cursor<'a> {
+    source: &'a str,
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(source: &'a str) -> Self {
+        Self { source, pos: 0 }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.source[self.pos..]
+    }
+
+    fn is_done(&self) -> bool {
+        self.pos == self.source.len()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn peek_nth(&self, n: usize) -> Option<char> {
+        self.rest().chars().nth(n)
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    /// Consumes `s` if the source continues with it
+    fn eat(&mut self, s: &str) -> bool {
+        if self.rest().starts_with(s) {
+            self.pos += s.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, s: &str) -> Result<(), Error> {
+        if self.eat(s) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{s}`")))
+        }
+    }
+
+    /// Consumes the characters while `f` holds
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> &'a str {
+        let start = self.pos;
+        while self.peek().is_some_and(&f) {
+            self.bump();
+        }
+        &self.source[start..self.pos]
+    }
+
+    /// Skips whitespace, `/* */` comments, and `//` comments if `line_comments` is set
+    fn skip_trivia(&mut self, line_comments: bool) -> Result<(), Error> {
+        loop {
+            self.take_while(char::is_whitespace);
+            if self.rest().starts_with("/*") {
+                let Some(end) = self.rest()[2..].find("*/") else {
+                    return Err(self.error("unterminated comment"));
+                };
+                self.pos += end + 4;
+            } else if line_comments && self.rest().starts_with("//") {
+                self.take_while(|c| c != '\n');
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    /// An error at the current position
+    fn error(&self, msg: &str) -> Error {
+        let before = &self.source[..self.pos];
+        let line = before.matches('\n').count() + 1;
+        let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+        Error::illegal_argument(format!("{msg} at line {line}, column {column}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{boxed::Box, string::ToString, vec, vec::Vec};
+
+    use super::{CharClass, Expr, Grammar, Symbol};
+
+    #[test]
+    fn test_char_class() {
+        let class = CharClass::new([('a', 'z'), ('0', '9'), ('x', 'z')]);
+        assert_eq!(class.ranges(), &[(0x30, 0x39), (0x61, 0x7A)]);
+        let negated = class.negated();
+        assert!(!negated.chars().take(200).any(|c| c.is_ascii_digit()));
+        assert_eq!(negated.negated(), class);
+        let vowels = CharClass::new([('a', 'a'), ('e', 'e')]);
+        assert_eq!(
+            class
+                .difference(&vowels)
+                .intersection(&CharClass::new([('a', 'f')])),
+            CharClass::new([('b', 'd'), ('f', 'f')])
+        );
+        assert_eq!(CharClass::any().negated(), CharClass::default());
+    }
+
+    #[test]
+    fn test_normalize() {
+        let grammar = Grammar::new(
+            "list".to_string(),
+            vec![
+                (
+                    "list".to_string(),
+                    Expr::Seq(vec![
+                        Expr::Literal("[".to_string()),
+                        Expr::Optional(Box::new(Expr::Seq(vec![
+                            Expr::NonTerminal("ITEM".to_string()),
+                            Expr::Star(Box::new(Expr::Seq(vec![
+                                Expr::Literal(",".to_string()),
+                                Expr::NonTerminal("ITEM".to_string()),
+                            ]))),
+                        ]))),
+                        Expr::Literal("]".to_string()),
+                    ]),
+                ),
+                (
+                    "ITEM".to_string(),
+                    Expr::Plus(Box::new(Expr::Class(CharClass::new([('0', '1')])))),
+                ),
+                ("unused".to_string(), Expr::Literal("x".to_string())),
+            ],
+        );
+        let productions = grammar.productions().unwrap();
+        assert_eq!(productions[0].lhs, "List");
+        assert!(productions.iter().all(|p| p.lhs != "Unused"));
+        assert_eq!(productions.iter().filter(|p| p.lhs == "ITEM").count(), 1);
+        assert_eq!(
+            productions.iter().filter(|p| p.lhs.contains('-')).count(),
+            6
+        );
+
+        let (json, start) = grammar.to_gramatron_json().unwrap();
+        assert_eq!(start, "List");
+        let list = json["List"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|rule| rule.as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert!(list.contains(&"'[' ']'"));
+        assert!(list.contains(&"'[' List-1 ']'"));
+        assert_eq!(json["Class--0"].as_array().unwrap().len(), 2);
+
+        let undefined = Grammar::new(
+            "a".to_string(),
+            vec![("a".to_string(), Expr::NonTerminal("b".to_string()))],
+        );
+        assert!(undefined.productions().is_err());
+        assert!(matches!(
+            Grammar::new(
+                "a".to_string(),
+                vec![("a".to_string(), Expr::Class(CharClass::single('\'')))]
+            )
+            .productions()
+            .unwrap()[0]
+                .rhs[0],
+            Symbol::Class(_)
+        ));
+    }
+}
@@ -3,6 +3,8 @@
 use alloc::boxed::Box;
 use core::any::type_name;
 
+#[cfg(feature = "std")]
+pub mod grammar;
 #[cfg(feature = "nautilus")]
 pub mod nautilus;
 
@@ -65,6 +65,12 @@ impl Context {
         &self.rules[id]
     }
 
+    /// All rules of the grammar, indexed by their [`RuleId`]
+    #[must_use]
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
     #[must_use]
     pub fn get_nt(&self, r: &RuleIdOrCustom) -> NTermId {
         self.get_rule(r.id()).nonterm()
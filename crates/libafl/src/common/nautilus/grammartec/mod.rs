@@ -2,6 +2,7 @@ pub mod chunkstore;
 pub mod context;
 pub mod mutator;
 pub mod newtypes;
+pub mod parser;
 #[cfg(feature = "nautilus_py")]
 pub mod python_grammar_loader;
 pub mod recursion_info;
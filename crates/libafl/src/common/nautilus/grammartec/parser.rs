@@ -0,0 +1,600 @@
+//! An Earley parser, turning inputs that match the grammar of a [`Context`] back into [`Tree`]s.
+//!
+//! Plain rules are parsed as the concatenation of their terminals and nonterminals, regex rules
+//! become custom nodes holding the bytes their regex matched. Script rules can not be inverted and
+//! are never used to parse.
+
+use alloc::{format, vec::Vec};
+
+use hashbrown::{HashMap, HashSet};
+use regex_automata::{
+    Anchored, MatchKind,
+    dfa::{Automaton, StartKind, dense::DFA},
+    nfa::thompson,
+    util::start,
+};
+
+use super::{
+    context::Context,
+    newtypes::{NTermId, RuleId},
+    rule::{Rule, RuleChild, RuleIdOrCustom},
+    tree::Tree,
+};
+use crate::Error;
+
+/// An Earley item: `rule` has matched its children before `dot`, starting at `origin`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Item {
+    rule: RuleId,
+    dot: usize,
+    origin: usize,
+}
+
+/// The rules completed while parsing an input
+#[derive(Debug, Default)]
+struct Chart {
+    /// The rules deriving each nonterminal from a start position, with their end positions
+    completed: HashMap<(NTermId, usize), Vec<(RuleId, usize)>>,
+}
+
+/// A pending step of [`Parser::build`], resumed with the result of the steps after it
+#[derive(Debug, Clone, Copy)]
+enum Frame {
+    /// Deriving `rid` over `start..end`, its nodes start at `len`
+    Rule {
+        rid: RuleId,
+        start: usize,
+        end: usize,
+        len: usize,
+    },
+    /// Deriving the children of `rid` after the terminal `child` matched at `pos`
+    Term {
+        rid: RuleId,
+        child: usize,
+        pos: usize,
+        end: usize,
+        cycles: usize,
+    },
+    /// Deriving a nonterminal child
+    NTerm(NTermFrame),
+}
+
+/// Deriving the nonterminal `child` of `rid` at `pos`, with the completed rules from `next` on left to try.
+/// `child_end` is the end of the one tried last, its nodes start at `len`.
+#[derive(Debug, Clone, Copy)]
+struct NTermFrame {
+    rid: RuleId,
+    child: usize,
+    pos: usize,
+    end: usize,
+    cycles: usize,
+    next: usize,
+    child_end: usize,
+    len: usize,
+    phase: Phase,
+}
+
+/// How far a [`Frame::NTerm`] got with the completed rule it tried last
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    /// No rule tried yet
+    Start,
+    /// Deriving the nonterminal with the rule
+    Child,
+    /// Deriving the children after it
+    Rest,
+}
+
+/// The state of building the [`Tree`] from a [`Chart`]
+#[derive(Debug, Default)]
+struct Builder {
+    rules: Vec<RuleIdOrCustom>,
+    /// The rule spans on the path from the root, to not expand unit or empty cycles forever
+    path: HashSet<(RuleId, usize, usize)>,
+    /// The `(rule, child, start, end)` that can not be derived
+    failed: HashSet<(RuleId, usize, usize, usize)>,
+    /// How often a span was skipped because it is on the path, failures then depend on the path
+    cycles: usize,
+}
+
+/// Parses inputs into derivation [`Tree`]s of the grammar of a [`Context`], e.g., to import seeds.
+#[derive(Debug)]
+pub struct Parser<'a> {
+    ctx: &'a Context,
+    rules_for_nt: HashMap<NTermId, Vec<RuleId>>,
+    nullable: HashSet<NTermId>,
+    regexes: HashMap<RuleId, DFA<Vec<u32>>>,
+}
+
+impl<'a> Parser<'a> {
+    /// Creates a new [`Parser`] for the grammar of `ctx`
+    pub fn new(ctx: &'a Context) -> Result<Self, Error> {
+        let mut rules_for_nt: HashMap<NTermId, Vec<RuleId>> = HashMap::new();
+        let mut regexes = HashMap::new();
+        for (id, rule) in ctx.rules().iter().enumerate() {
+            let id = RuleId::from(id);
+            match rule {
+                Rule::Plain(_) => {}
+                Rule::RegExp(r) => {
+                    let error = |err: &dyn core::fmt::Display| {
+                        Error::illegal_argument(format!(
+                            "Could not parse with {}: {err}",
+                            r.debug_show(ctx)
+                        ))
+                    };
+                    let nfa = thompson::Compiler::new()
+                        .configure(thompson::Config::new().utf8(false))
+                        .build_from_hir(&r.hir)
+                        .map_err(|err| error(&err))?;
+                    // All match ends, not only the leftmost-first one, as the rule may match any of them
+                    let dfa = DFA::builder()
+                        .configure(
+                            DFA::config()
+                                .match_kind(MatchKind::All)
+                                .start_kind(StartKind::Anchored),
+                        )
+                        .build_from_nfa(&nfa)
+                        .map_err(|err| error(&err))?;
+                    regexes.insert(id, dfa);
+                }
+                #[cfg(feature = "nautilus_py")]
+                Rule::Script(_) => continue,
+            }
+            rules_for_nt.entry(rule.nonterm()).or_default().push(id);
+        }
+
+        let mut parser = Self {
+            ctx,
+            rules_for_nt,
+            nullable: HashSet::new(),
+            regexes,
+        };
+        parser.calc_nullable();
+        Ok(parser)
+    }
+
+    /// Finds the nonterminals deriving the empty input
+    fn calc_nullable(&mut self) {
+        let mut something_changed = true;
+        while something_changed {
+            something_changed = false;
+            for rules in self.rules_for_nt.values() {
+                for rid in rules {
+                    let rule = self.ctx.get_rule(*rid);
+                    if self.nullable.contains(&rule.nonterm()) {
+                        continue;
+                    }
+                    let nullable = match rule {
+                        Rule::Plain(r) => r.children.iter().all(|child| match child {
+                            RuleChild::Term(term) => term.is_empty(),
+                            RuleChild::NTerm(nt) => self.nullable.contains(nt),
+                        }),
+                        Rule::RegExp(_) => {
+                            let mut nullable = false;
+                            match_ends(&self.regexes[rid], b"", 0, |_| nullable = true);
+                            nullable
+                        }
+                        #[cfg(feature = "nautilus_py")]
+                        Rule::Script(_) => false,
+                    };
+                    if nullable {
+                        self.nullable.insert(rule.nonterm());
+                        something_changed = true;
+                    }
+                }
+            }
+        }
+    }
+
+    /// The child of a plain rule after `dot`, `None` for completed items and regex rules
+    fn next_child(&self, item: &Item) -> Option<&'a RuleChild> {
+        match self.ctx.get_rule(item.rule) {
+            Rule::Plain(r) => r.children.get(item.dot),
+            Rule::RegExp(_) => None,
+            #[cfg(feature = "nautilus_py")]
+            Rule::Script(_) => None,
+        }
+    }
+
+    /// Whether `item` matched all of its rule
+    fn is_complete(&self, item: &Item) -> bool {
+        match self.ctx.get_rule(item.rule) {
+            Rule::Plain(r) => item.dot == r.children.len(),
+            Rule::RegExp(_) => item.dot == 1,
+            #[cfg(feature = "nautilus_py")]
+            Rule::Script(_) => false,
+        }
+    }
+
+    /// Parses `input` as derived from the nonterminal `start`.
+    ///
+    /// Fails with the position the input stops matching the grammar at.
+    pub fn parse(&self, start: NTermId, input: &[u8]) -> Result<Tree, Error> {
+        let len = input.len();
+        let mut sets: Vec<Vec<Item>> = vec![Vec::new(); len + 1];
+        let mut seen: Vec<HashSet<Item>> = vec![HashSet::new(); len + 1];
+        let mut chart = Chart::default();
+
+        let mut add = |sets: &mut Vec<Vec<Item>>, pos: usize, item: Item| {
+            if seen[pos].insert(item) {
+                sets[pos].push(item);
+            }
+        };
+
+        for rid in self.rules_for_nt.get(&start).into_iter().flatten() {
+            add(
+                &mut sets,
+                0,
+                Item {
+                    rule: *rid,
+                    dot: 0,
+                    origin: 0,
+                },
+            );
+        }
+
+        let mut furthest = 0;
+        for pos in 0..=len {
+            if !sets[pos].is_empty() {
+                furthest = pos;
+            }
+            let mut i = 0;
+            while i < sets[pos].len() {
+                let item = sets[pos][i];
+                i += 1;
+
+                if self.is_complete(&item) {
+                    let nt = self.ctx.get_rule(item.rule).nonterm();
+                    chart
+                        .completed
+                        .entry((nt, item.origin))
+                        .or_default()
+                        .push((item.rule, pos));
+                    let mut j = 0;
+                    while j < sets[item.origin].len() {
+                        let waiting = sets[item.origin][j];
+                        j += 1;
+                        if matches!(self.next_child(&waiting), Some(RuleChild::NTerm(next)) if *next == nt)
+                        {
+                            add(
+                                &mut sets,
+                                pos,
+                                Item {
+                                    dot: waiting.dot + 1,
+                                    ..waiting
+                                },
+                            );
+                        }
+                    }
+                    continue;
+                }
+
+                match self.next_child(&item) {
+                    Some(RuleChild::Term(term)) => {
+                        if input[pos..].starts_with(term) {
+                            add(
+                                &mut sets,
+                                pos + term.len(),
+                                Item {
+                                    dot: item.dot + 1,
+                                    ..item
+                                },
+                            );
+                        }
+                    }
+                    Some(RuleChild::NTerm(nt)) => {
+                        for rid in self.rules_for_nt.get(nt).into_iter().flatten() {
+                            add(
+                                &mut sets,
+                                pos,
+                                Item {
+                                    rule: *rid,
+                                    dot: 0,
+                                    origin: pos,
+                                },
+                            );
+                        }
+                        // Nullable nonterminals may complete before the items waiting for them
+                        if self.nullable.contains(nt) {
+                            add(
+                                &mut sets,
+                                pos,
+                                Item {
+                                    dot: item.dot + 1,
+                                    ..item
+                                },
+                            );
+                        }
+                    }
+                    None => {
+                        // A regex rule matches all the lengths its regex accepts
+                        match_ends(&self.regexes[&item.rule], input, pos, |end| {
+                            add(&mut sets, end, Item { dot: 1, ..item });
+                        });
+                    }
+                }
+            }
+        }
+
+        let mut builder = Builder::default();
+        let roots = chart
+            .completed
+            .get(&(start, 0))
+            .cloned()
+            .unwrap_or_default();
+        for (rid, end) in roots {
+            if end == len && self.build(&chart, &mut builder, input, rid, 0, len) {
+                return Ok(Tree::from_rule_vec(builder.rules, self.ctx));
+            }
+        }
+        Err(Error::illegal_argument(format!(
+            "The input does not match the grammar from {}, parsing failed at byte {furthest} of {len}",
+            self.ctx.nt_id_to_s(start)
+        )))
+    }
+
+    /// Appends the nodes of a derivation of `input[start..end]` with `rid` to the builder.
+    ///
+    /// A depth-first search over the completed rules of the chart, with an explicit stack of [`Frame`]s, so deep
+    /// trees do not overflow the stack.
+    fn build(
+        &self,
+        chart: &Chart,
+        builder: &mut Builder,
+        input: &[u8],
+        rid: RuleId,
+        start: usize,
+        end: usize,
+    ) -> bool {
+        let mut stack = Vec::new();
+        // The next step, derive `rid` over `start..end` if `child` is `None`, or its children from `child` on
+        let mut step = Some((rid, None, start, end));
+        let mut res = false;
+
+        loop {
+            if let Some((rid, child, pos, end)) = step.take() {
+                match (self.ctx.get_rule(rid), child) {
+                    (Rule::Plain(_), None) => {
+                        let len = builder.rules.len();
+                        builder.rules.push(RuleIdOrCustom::Rule(rid));
+                        builder.path.insert((rid, pos, end));
+                        stack.push(Frame::Rule {
+                            rid,
+                            start: pos,
+                            end,
+                            len,
+                        });
+                        step = Some((rid, Some(0), pos, end));
+                        continue;
+                    }
+                    (Rule::Plain(rule), Some(child)) => match rule.children.get(child) {
+                        None => res = pos == end,
+                        Some(_) if builder.failed.contains(&(rid, child, pos, end)) => res = false,
+                        Some(RuleChild::Term(term)) => {
+                            if pos + term.len() <= end && input[pos..].starts_with(term) {
+                                stack.push(Frame::Term {
+                                    rid,
+                                    child,
+                                    pos,
+                                    end,
+                                    cycles: builder.cycles,
+                                });
+                                step = Some((rid, Some(child + 1), pos + term.len(), end));
+                                continue;
+                            }
+                            builder.failed.insert((rid, child, pos, end));
+                            res = false;
+                        }
+                        Some(RuleChild::NTerm(_)) => {
+                            stack.push(Frame::NTerm(NTermFrame {
+                                rid,
+                                child,
+                                pos,
+                                end,
+                                cycles: builder.cycles,
+                                next: 0,
+                                child_end: pos,
+                                len: builder.rules.len(),
+                                phase: Phase::Start,
+                            }));
+                            res = false;
+                        }
+                    },
+                    _ => {
+                        builder
+                            .rules
+                            .push(RuleIdOrCustom::Custom(rid, input[pos..end].to_vec()));
+                        res = true;
+                    }
+                }
+            }
+
+            // Resume the pending step with the result
+            let Some(frame) = stack.pop() else {
+                return res;
+            };
+            match frame {
+                Frame::Rule {
+                    rid,
+                    start,
+                    end,
+                    len,
+                } => {
+                    builder.path.remove(&(rid, start, end));
+                    if !res {
+                        builder.rules.truncate(len);
+                    }
+                }
+                Frame::Term {
+                    rid,
+                    child,
+                    pos,
+                    end,
+                    cycles,
+                } => {
+                    if !res {
+                        Self::fail(builder, (rid, child, pos, end), cycles);
+                    }
+                }
+                Frame::NTerm(mut frame) => {
+                    if res && frame.phase == Phase::Child {
+                        stack.push(Frame::NTerm(NTermFrame {
+                            phase: Phase::Rest,
+                            ..frame
+                        }));
+                        step = Some((frame.rid, Some(frame.child + 1), frame.child_end, frame.end));
+                        continue;
+                    }
+                    if res {
+                        continue;
+                    }
+                    builder.rules.truncate(frame.len);
+
+                    let Rule::Plain(rule) = self.ctx.get_rule(frame.rid) else {
+                        unreachable!("only plain rules have children");
+                    };
+                    let RuleChild::NTerm(nt) = &rule.children[frame.child] else {
+                        unreachable!("the child is a nonterminal");
+                    };
+                    let completed = chart
+                        .completed
+                        .get(&(*nt, frame.pos))
+                        .map_or(&[][..], Vec::as_slice);
+                    while let Some(&(child_rid, child_end)) = completed.get(frame.next) {
+                        frame.next += 1;
+                        if child_end > frame.end {
+                            continue;
+                        }
+                        if builder.path.contains(&(child_rid, frame.pos, child_end)) {
+                            builder.cycles += 1;
+                            continue;
+                        }
+                        stack.push(Frame::NTerm(NTermFrame {
+                            child_end,
+                            phase: Phase::Child,
+                            ..frame
+                        }));
+                        step = Some((child_rid, None, frame.pos, child_end));
+                        break;
+                    }
+                    if step.is_none() {
+                        Self::fail(
+                            builder,
+                            (frame.rid, frame.child, frame.pos, frame.end),
+                            frame.cycles,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Remembers that the children of a rule from a child on can not be derived over a span
+    fn fail(builder: &mut Builder, failed: (RuleId, usize, usize, usize), cycles: usize) {
+        // Failures caused by the path may succeed elsewhere in the tree
+        if builder.cycles == cycles {
+            builder.failed.insert(failed);
+        }
+    }
+}
+
+/// Calls `found` with the end of each match of `dfa` starting at `input[start]`, in one forward pass
+fn match_ends<F>(dfa: &DFA<Vec<u32>>, input: &[u8], start: usize, mut found: F)
+where
+    F: FnMut(usize),
+{
+    let Ok(mut state) = dfa.start_state(&start::Config::new().anchored(Anchored::Yes)) else {
+        return;
+    };
+    for (end, byte) in input.iter().enumerate().skip(start) {
+        if dfa.is_match_state(dfa.next_eoi_state(state)) {
+            found(end);
+        }
+        state = dfa.next_state(state, *byte);
+        if dfa.is_dead_state(state) || dfa.is_quit_state(state) {
+            return;
+        }
+    }
+    if dfa.is_match_state(dfa.next_eoi_state(state)) {
+        found(input.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Parser;
+    use crate::common::nautilus::grammartec::{
+        context::Context,
+        rule::RuleIdOrCustom,
+        tree::{Tree, TreeLike},
+    };
+
+    #[test]
+    fn test_parse_expressions() {
+        let mut ctx = Context::new();
+        let _ = ctx.add_rule("START", b"{E}");
+        let _ = ctx.add_rule("E", b"{E}+{E}");
+        let _ = ctx.add_rule("E", b"({E})");
+        let _ = ctx.add_rule("E", b"{N}");
+        let _ = ctx.add_rule("N", b"{D}{N}");
+        let _ = ctx.add_rule("N", b"{D}");
+        let digits = ctx.add_regex("D", "[0-9]");
+        ctx.initialize(100);
+
+        let parser = Parser::new(&ctx).unwrap();
+        for seed in [&b"1"[..], b"(12+3)+45", b"((1))"] {
+            let tree = parser.parse(ctx.nt_id("START"), seed).unwrap();
+            assert_eq!(tree.unparse_to_vec(&ctx), seed);
+            assert_eq!(tree.sizes[0], tree.size());
+            let resized = Tree::from_rule_vec(tree.rules.clone(), &ctx);
+            assert_eq!(resized.sizes, tree.sizes);
+        }
+        let tree = parser.parse(ctx.nt_id("START"), b"7").unwrap();
+        assert!(
+            tree.rules
+                .contains(&RuleIdOrCustom::Custom(digits, b"7".to_vec()))
+        );
+
+        assert!(parser.parse(ctx.nt_id("START"), b"(1+").is_err());
+        assert!(parser.parse(ctx.nt_id("START"), b"").is_err());
+    }
+
+    #[test]
+    fn test_parse_nullable() {
+        let mut ctx = Context::new();
+        let _ = ctx.add_rule("START", b"<{L}>");
+        let _ = ctx.add_rule("L", b"{L}{I}");
+        let _ = ctx.add_rule("L", b"");
+        let _ = ctx.add_rule("I", b"{O}a");
+        let _ = ctx.add_rule("O", b"");
+        let _ = ctx.add_rule("O", b"-");
+        ctx.initialize(100);
+
+        let parser = Parser::new(&ctx).unwrap();
+        for seed in [&b"<>"[..], b"<a>", b"<a-aa>"] {
+            let tree = parser.parse(ctx.nt_id("START"), seed).unwrap();
+            assert_eq!(tree.unparse_to_vec(&ctx), seed);
+        }
+        assert!(parser.parse(ctx.nt_id("START"), b"<b>").is_err());
+    }
+
+    #[test]
+    fn test_parse_deep() {
+        let mut ctx = Context::new();
+        let _ = ctx.add_rule("START", b"{L}");
+        let _ = ctx.add_rule("L", b"({L})");
+        let _ = ctx.add_rule("L", b"{N}-{N}");
+        // The first number has to stop before the longest match
+        let _ = ctx.add_regex("N", "[0-9]+");
+        ctx.initialize(100);
+
+        let parser = Parser::new(&ctx).unwrap();
+        let depth = 20_000;
+        let mut seed = vec![b'('; depth];
+        seed.extend_from_slice(b"12-34");
+        seed.extend(vec![b')'; depth]);
+        let tree = parser.parse(ctx.nt_id("START"), &seed).unwrap();
+        assert_eq!(tree.unparse_to_vec(&ctx), seed);
+        assert!(parser.parse(ctx.nt_id("START"), b"1234").is_err());
+    }
+}
@@ -431,6 +431,10 @@ impl<I> InMemoryOnDiskCorpus<I> {
                 metadata: testcase.metadata_map(),
                 exec_time: testcase.exec_time(),
                 executions: testcase.executions(),
+                #[cfg(feature = "track_hit_feedbacks")]
+                hit_feedbacks: testcase.hit_feedbacks(),
+                #[cfg(feature = "track_hit_feedbacks")]
+                hit_objectives: testcase.hit_objectives(),
             };
 
             let mut tmpfile = File::create(&tmpfile_path)?;
@@ -22,6 +22,11 @@ pub mod ondisk;
 #[cfg(feature = "std")]
 pub use ondisk::OnDiskCorpus;
 
+#[cfg(feature = "std")]
+pub mod packed;
+#[cfg(feature = "std")]
+pub use packed::PackedCorpus;
+
 #[cfg(feature = "std")]
 pub mod cached;
 #[cfg(feature = "std")]
@@ -6,6 +6,8 @@
 //! which stores a certain number of [`Testcase`]s in memory and removes additional ones in a FIFO manner.
 
 use alloc::string::String;
+#[cfg(feature = "track_hit_feedbacks")]
+use alloc::{borrow::Cow, vec::Vec};
 use core::{
     cell::{Ref, RefCell, RefMut},
     time::Duration,
@@ -45,6 +47,12 @@ pub struct OnDiskMetadata<'a> {
     pub exec_time: &'a Option<Duration>,
     /// The executions of this [`Testcase`]
     pub executions: &'a u64,
+    /// The names of the feedbacks that found this [`Testcase`] interesting
+    #[cfg(feature = "track_hit_feedbacks")]
+    pub hit_feedbacks: &'a Vec<Cow<'static, str>>,
+    /// The names of the objectives that found this [`Testcase`] interesting
+    #[cfg(feature = "track_hit_feedbacks")]
+    pub hit_objectives: &'a Vec<Cow<'static, str>>,
 }
 
 /// A corpus able to store [`Testcase`]s to disk, and load them from disk, when they are being used.
@@ -0,0 +1,968 @@
+//! The [`PackedCorpus`] stores the inputs of all [`Testcase`]s content-addressed in a single pack file.
+//!
+//! Instead of one input file and one metadata file per [`Testcase`], inputs are appended to `corpus.pack` once per
+//! unique content, and every change to the corpus is appended to the `corpus.idx` log.
+//! Re-opening a directory replays the log, so a crashed fuzzer can pick up exactly where it stopped.
+//! Only a bounded number of inputs is kept in memory, so this scales to millions of entries, where the per-file
+//! layout of the [`crate::corpus::InMemoryOnDiskCorpus`] starts to hurt the file system.
+
+use alloc::{collections::vec_deque::VecDeque, string::String, vec, vec::Vec};
+use core::{
+    cell::{Ref, RefCell, RefMut},
+    time::Duration,
+};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use hashbrown::HashMap;
+use libafl_bolts::{hash_std, serdeany::SerdeAnyMap};
+use serde::{Deserialize, Serialize};
+
+use super::{EnableDisableCorpus, HasTestcase, ondisk::OnDiskMetadata};
+use crate::{
+    Error, HasMetadata,
+    corpus::{Corpus, CorpusId, InMemoryCorpus, Testcase},
+    inputs::Input,
+};
+
+/// The name of the file holding the content-addressed inputs
+pub const PACK_FILE_NAME: &str = "corpus.pack";
+/// The name of the append-only log holding the index
+pub const INDEX_FILE_NAME: &str = "corpus.idx";
+
+/// Each record in both files starts with a `u64` hash and a `u64` length, in little endian
+const RECORD_HEADER_LEN: u64 = 16;
+
+/// The location of an input in the pack file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PackLocation {
+    /// The offset of the serialized input in the pack file
+    pub offset: u64,
+    /// The length of the serialized input
+    pub len: u64,
+}
+
+/// The parts of a [`Testcase`] that the [`PackedCorpus`] keeps in its index
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PackedTestcaseMeta {
+    metadata: SerdeAnyMap,
+    exec_time: Option<Duration>,
+    executions: u64,
+    parent_id: Option<CorpusId>,
+}
+
+impl PackedTestcaseMeta {
+    fn new<I>(testcase: &Testcase<I>) -> Self {
+        Self {
+            metadata: testcase.metadata_map().clone(),
+            exec_time: *testcase.exec_time(),
+            executions: *testcase.executions(),
+            parent_id: testcase.parent_id(),
+        }
+    }
+
+    fn into_testcase<I>(self, hash: u64) -> Testcase<I> {
+        let mut testcase = Testcase::default();
+        *testcase.filename_mut() = Some(hash_to_filename(hash));
+        self.apply_to(&mut testcase);
+        testcase
+    }
+
+    fn apply_to<I>(self, testcase: &mut Testcase<I>) {
+        *testcase.metadata_map_mut() = self.metadata;
+        *testcase.exec_time_mut() = self.exec_time;
+        testcase.set_executions(self.executions);
+        testcase.set_parent_id_optional(self.parent_id);
+    }
+
+    /// The hash of the serialized metadata, to tell if it changed since it was written to the index
+    fn hash(&self) -> Result<u64, Error> {
+        Ok(hash_std(&postcard::to_allocvec(self)?))
+    }
+}
+
+/// A single entry of the append-only index log
+#[derive(Debug, Serialize, Deserialize)]
+enum IndexRecord {
+    Add {
+        id: CorpusId,
+        hash: u64,
+        location: PackLocation,
+        disabled: bool,
+        meta: PackedTestcaseMeta,
+    },
+    Replace {
+        id: CorpusId,
+        hash: u64,
+        location: PackLocation,
+        meta: PackedTestcaseMeta,
+    },
+    Remove {
+        id: CorpusId,
+    },
+    Disable {
+        id: CorpusId,
+    },
+    Enable {
+        id: CorpusId,
+    },
+    Update {
+        id: CorpusId,
+        meta: PackedTestcaseMeta,
+    },
+}
+
+/// The owned counterpart of [`OnDiskMetadata`], used to import metadata files
+#[derive(Debug, Deserialize)]
+struct OnDiskMetadataOwned {
+    metadata: SerdeAnyMap,
+    exec_time: Option<Duration>,
+    executions: u64,
+}
+
+/// In a [`PackedCorpus`], the filename of a [`Testcase`] is the hex-encoded hash of its content.
+fn hash_to_filename(hash: u64) -> String {
+    format!("{hash:016x}")
+}
+
+/// Appends a record to the file at `path` and returns the offset of its payload
+fn append_record(path: &Path, hash: u64, payload: &[u8]) -> Result<u64, Error> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let offset = file.seek(SeekFrom::End(0))?;
+
+    // A single write, so a crash will at most leave a truncated record at the end
+    let mut record = Vec::with_capacity(RECORD_HEADER_LEN as usize + payload.len());
+    record.extend_from_slice(&hash.to_le_bytes());
+    record.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    record.extend_from_slice(payload);
+    file.write_all(&record)?;
+
+    Ok(offset + RECORD_HEADER_LEN)
+}
+
+/// Reads all valid records from the index at `path`.
+///
+/// A truncated or corrupted tail, left behind by a crash, is cut off.
+fn read_index(path: &Path) -> Result<Vec<IndexRecord>, Error> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut records = Vec::new();
+    let mut pos = 0;
+    while pos < bytes.len() {
+        let header_end = pos + RECORD_HEADER_LEN as usize;
+        let Some(header) = bytes.get(pos..header_end) else {
+            break;
+        };
+        let hash = u64::from_le_bytes(header[..8].try_into().unwrap());
+        let len = u64::from_le_bytes(header[8..].try_into().unwrap());
+        let Some(payload) = usize::try_from(len)
+            .ok()
+            .and_then(|len| bytes.get(header_end..header_end.checked_add(len)?))
+        else {
+            break;
+        };
+        if hash_std(payload) != hash {
+            break;
+        }
+        records.push(postcard::from_bytes(payload)?);
+        pos = header_end + payload.len();
+    }
+
+    if pos < bytes.len() {
+        log::warn!(
+            "Dropping {} bytes of truncated records at the end of {}",
+            bytes.len() - pos,
+            path.display()
+        );
+        OpenOptions::new()
+            .write(true)
+            .open(path)?
+            .set_len(pos as u64)?;
+    }
+
+    Ok(records)
+}
+
+/// A corpus storing [`Testcase`]s in a content-addressed pack file, with an append-only index.
+///
+/// Inputs are deduplicated: adding the same input twice only stores it once.
+/// The [`Testcase`]s, without their inputs, are kept in memory. Inputs are loaded from the pack when a [`Testcase`]
+/// is accessed, and at most `cache_max_len` of them are kept in memory, evicting in a FIFO manner, like the
+/// [`crate::corpus::CachedOnDiskCorpus`] does.
+/// The filename of each [`Testcase`] is the hex-encoded hash of its serialized input.
+///
+/// # Persistence
+///
+/// Metadata is written to the index when a [`Testcase`] is added or replaced, and again when its input is evicted
+/// from the cache, if it changed in the meantime. Changes to [`Testcase`]s that are still cached are lost on a
+/// crash, unless they are written with [`PackedCorpus::sync_metadata`] or [`PackedCorpus::compact_index`].
+/// Compact the index from time to time, as every write grows the log.
+///
+/// The directory must not be shared with other corpora.
+#[derive(Default, Serialize, Deserialize, Clone, Debug)]
+pub struct PackedCorpus<I> {
+    inner: InMemoryCorpus<I>,
+    dir_path: PathBuf,
+    blobs: RefCell<HashMap<u64, PackLocation>>,
+    /// The hash of the metadata last written to the index, per [`Testcase`]
+    meta_hashes: RefCell<HashMap<CorpusId, u64>>,
+    cached_indexes: RefCell<VecDeque<CorpusId>>,
+    cache_max_len: usize,
+}
+
+impl<I> Corpus<I> for PackedCorpus<I>
+where
+    I: Input,
+{
+    /// Returns the number of all enabled entries
+    #[inline]
+    fn count(&self) -> usize {
+        self.inner.count()
+    }
+
+    /// Returns the number of all disabled entries
+    fn count_disabled(&self) -> usize {
+        self.inner.count_disabled()
+    }
+
+    /// Returns the number of elements including disabled entries
+    #[inline]
+    fn count_all(&self) -> usize {
+        self.inner.count_all()
+    }
+
+    /// Add an enabled testcase to the corpus and return its index
+    #[inline]
+    fn add(&mut self, testcase: Testcase<I>) -> Result<CorpusId, Error> {
+        self.add_inner(testcase, false)
+    }
+
+    /// Add a disabled testcase to the corpus and return its index
+    #[inline]
+    fn add_disabled(&mut self, testcase: Testcase<I>) -> Result<CorpusId, Error> {
+        self.add_inner(testcase, true)
+    }
+
+    /// Replaces the testcase at the given idx
+    fn replace(&mut self, id: CorpusId, mut testcase: Testcase<I>) -> Result<Testcase<I>, Error> {
+        // Validate the id first, a blob appended to the pack cannot be taken back
+        self.inner.get(id)?;
+        let (hash, location) = self.store_testcase(&mut testcase)?;
+        let meta = PackedTestcaseMeta::new(&testcase);
+        let meta_hash = meta.hash()?;
+        let prev = self.inner.replace(id, testcase)?;
+        self.cached_indexes.borrow_mut().retain(|e| *e != id);
+        self.append_index(&IndexRecord::Replace {
+            id,
+            hash,
+            location,
+            meta,
+        })?;
+        self.meta_hashes.borrow_mut().insert(id, meta_hash);
+        Ok(prev)
+    }
+
+    /// Removes an entry from the corpus, returning it if it was present; considers both enabled and disabled testcases
+    #[inline]
+    fn remove(&mut self, id: CorpusId) -> Result<Testcase<I>, Error> {
+        let testcase = self.inner.remove(id)?;
+        self.cached_indexes.borrow_mut().retain(|e| *e != id);
+        self.meta_hashes.borrow_mut().remove(&id);
+        self.append_index(&IndexRecord::Remove { id })?;
+        Ok(testcase)
+    }
+
+    /// Get by id; considers only enabled testcases
+    #[inline]
+    fn get(&self, id: CorpusId) -> Result<&RefCell<Testcase<I>>, Error> {
+        let testcase = self.inner.get(id)?;
+        self.cache_testcase(testcase, id)?;
+        Ok(testcase)
+    }
+
+    /// Get by id; considers both enabled and disabled testcases
+    #[inline]
+    fn get_from_all(&self, id: CorpusId) -> Result<&RefCell<Testcase<I>>, Error> {
+        let testcase = self.inner.get_from_all(id)?;
+        self.cache_testcase(testcase, id)?;
+        Ok(testcase)
+    }
+
+    /// Current testcase scheduled
+    #[inline]
+    fn current(&self) -> &Option<CorpusId> {
+        self.inner.current()
+    }
+
+    /// Current testcase scheduled (mutable)
+    #[inline]
+    fn current_mut(&mut self) -> &mut Option<CorpusId> {
+        self.inner.current_mut()
+    }
+
+    #[inline]
+    fn next(&self, id: CorpusId) -> Option<CorpusId> {
+        self.inner.next(id)
+    }
+
+    /// Peek the next free corpus id
+    #[inline]
+    fn peek_free_id(&self) -> CorpusId {
+        self.inner.peek_free_id()
+    }
+
+    #[inline]
+    fn prev(&self, id: CorpusId) -> Option<CorpusId> {
+        self.inner.prev(id)
+    }
+
+    #[inline]
+    fn first(&self) -> Option<CorpusId> {
+        self.inner.first()
+    }
+
+    #[inline]
+    fn last(&self) -> Option<CorpusId> {
+        self.inner.last()
+    }
+
+    /// Get the nth corpus id; considers only enabled testcases
+    #[inline]
+    fn nth(&self, nth: usize) -> CorpusId {
+        self.inner.nth(nth)
+    }
+    /// Get the nth corpus id; considers both enabled and disabled testcases
+    #[inline]
+    fn nth_from_all(&self, nth: usize) -> CorpusId {
+        self.inner.nth_from_all(nth)
+    }
+
+    fn load_input_into(&self, testcase: &mut Testcase<I>) -> Result<(), Error> {
+        if testcase.input().is_none() {
+            let Some(hash) = testcase
+                .filename()
+                .as_ref()
+                .and_then(|name| u64::from_str_radix(name, 16).ok())
+            else {
+                return Err(Error::illegal_argument(
+                    "No content hash set for testcase. Could not load inputs.",
+                ));
+            };
+            let bytes = self.read_blob(hash)?;
+            testcase.set_input(postcard::from_bytes(&bytes)?);
+        }
+        Ok(())
+    }
+
+    /// Stores the input of the testcase in the pack file, if its content is not stored yet.
+    fn store_input_from(&self, testcase: &Testcase<I>) -> Result<(), Error> {
+        let Some(input) = testcase.input() else {
+            return Err(Error::illegal_argument(
+                "No input available for testcase. Could not store anything.",
+            ));
+        };
+        self.store_blob(&postcard::to_allocvec(input)?)?;
+        Ok(())
+    }
+}
+
+impl<I> EnableDisableCorpus for PackedCorpus<I>
+where
+    I: Input,
+{
+    #[inline]
+    fn disable(&mut self, id: CorpusId) -> Result<(), Error> {
+        self.inner.disable(id)?;
+        self.append_index(&IndexRecord::Disable { id })
+    }
+
+    #[inline]
+    fn enable(&mut self, id: CorpusId) -> Result<(), Error> {
+        self.inner.enable(id)?;
+        self.append_index(&IndexRecord::Enable { id })
+    }
+}
+
+impl<I> HasTestcase<I> for PackedCorpus<I>
+where
+    I: Input,
+{
+    fn testcase(&self, id: CorpusId) -> Result<Ref<'_, Testcase<I>>, Error> {
+        Ok(self.get(id)?.borrow())
+    }
+
+    fn testcase_mut(&self, id: CorpusId) -> Result<RefMut<'_, Testcase<I>>, Error> {
+        Ok(self.get(id)?.borrow_mut())
+    }
+}
+
+impl<I> PackedCorpus<I>
+where
+    I: Input,
+{
+    /// Creates a [`PackedCorpus`] in the given directory, keeping at most `cache_max_len` inputs in memory.
+    ///
+    /// If the directory already contains a packed corpus, its index is replayed and all [`Testcase`]s are restored
+    /// with their original [`CorpusId`]s.
+    ///
+    /// Will error, if `cache_max_len` is `0`, if [`fs::create_dir_all()`] failed for `dir_path`, or if the index
+    /// cannot be read.
+    pub fn new<P>(dir_path: P, cache_max_len: usize) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        if cache_max_len == 0 {
+            return Err(Error::illegal_argument(
+                "The max cache len in PackedCorpus cannot be 0",
+            ));
+        }
+        let dir_path = dir_path.as_ref();
+        match fs::create_dir_all(dir_path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        let mut corpus = Self {
+            inner: InMemoryCorpus::new(),
+            dir_path: dir_path.into(),
+            blobs: RefCell::new(HashMap::new()),
+            meta_hashes: RefCell::new(HashMap::new()),
+            cached_indexes: RefCell::new(VecDeque::new()),
+            cache_max_len,
+        };
+        for record in read_index(&corpus.index_path())? {
+            corpus.replay(record)?;
+        }
+        Ok(corpus)
+    }
+
+    /// The directory backing this corpus
+    #[must_use]
+    pub fn dir_path(&self) -> &Path {
+        &self.dir_path
+    }
+
+    /// The number of unique inputs stored in the pack file
+    #[must_use]
+    pub fn blob_count(&self) -> usize {
+        self.blobs.borrow().len()
+    }
+
+    /// The location of an input in the pack file, by the hash of its content
+    #[must_use]
+    pub fn location(&self, hash: u64) -> Option<PackLocation> {
+        self.blobs.borrow().get(&hash).copied()
+    }
+
+    /// Writes the current metadata of the [`Testcase`] to the index, if it changed since it was last written.
+    ///
+    /// Fails if the [`Testcase`] is mutably borrowed.
+    pub fn sync_metadata(&self, id: CorpusId) -> Result<(), Error> {
+        let testcase = self.inner.get_from_all(id)?.try_borrow().map_err(|_| {
+            Error::illegal_state(format!(
+                "Testcase {id} is borrowed, cannot sync its metadata"
+            ))
+        })?;
+        self.write_changed_metadata(id, &testcase)
+    }
+
+    /// Rewrites the index log with a single record per [`Testcase`], including its current metadata.
+    ///
+    /// The new index is written to a temporary file first and then atomically moved in place.
+    /// Inputs that are no longer referenced stay in the pack file.
+    pub fn compact_index(&self) -> Result<(), Error> {
+        let tmp_path = self.dir_path.join(format!(".{INDEX_FILE_NAME}.tmp"));
+        match fs::remove_file(&tmp_path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        let blobs = self.blobs.borrow();
+        let mut meta_hashes = HashMap::new();
+        for cur in self.all_ids() {
+            let testcase = self.inner.get_from_all(cur)?.borrow();
+            let hash = Self::testcase_hash(&testcase)?;
+            let location = *blobs.get(&hash).ok_or_else(|| {
+                Error::key_not_found(format!("Content of testcase {cur} is not in the pack"))
+            })?;
+            let meta = PackedTestcaseMeta::new(&testcase);
+            meta_hashes.insert(cur, meta.hash()?);
+            let record = IndexRecord::Add {
+                id: cur,
+                hash,
+                location,
+                disabled: self.inner.get(cur).is_err(),
+                meta,
+            };
+            let payload = postcard::to_allocvec(&record)?;
+            append_record(&tmp_path, hash_std(&payload), &payload)?;
+        }
+        if !tmp_path.exists() {
+            File::create(&tmp_path)?;
+        }
+
+        fs::rename(&tmp_path, self.index_path())?;
+        *self.meta_hashes.borrow_mut() = meta_hashes;
+        Ok(())
+    }
+
+    /// Exports all [`Testcase`]s to `dir` in the layout of the [`crate::corpus::InMemoryOnDiskCorpus`]:
+    /// one file per input, and a `.<filename>.metadata` file with its metadata as prettified json.
+    ///
+    /// Returns the number of exported [`Testcase`]s.
+    pub fn export_to_dir<P>(&self, dir: P) -> Result<usize, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+
+        let mut count = 0;
+        for cur in self.all_ids() {
+            let mut testcase = self.inner.get_from_all(cur)?.borrow().clone();
+            self.load_input_into(&mut testcase)?;
+            let input = testcase.input().as_ref().unwrap();
+            let filename = input.generate_name(Some(cur));
+            input.to_file(dir.join(&filename))?;
+
+            let ondisk_meta = OnDiskMetadata {
+                metadata: testcase.metadata_map(),
+                exec_time: testcase.exec_time(),
+                executions: testcase.executions(),
+                #[cfg(feature = "track_hit_feedbacks")]
+                hit_feedbacks: testcase.hit_feedbacks(),
+                #[cfg(feature = "track_hit_feedbacks")]
+                hit_objectives: testcase.hit_objectives(),
+            };
+            let serialized = serde_json::to_vec_pretty(&ondisk_meta)
+                .map_err(|err| Error::serialize(format!("Failed to json-ify metadata: {err:?}")))?;
+            fs::write(dir.join(format!(".{filename}.metadata")), serialized)?;
+
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Imports all inputs from `dir`, as written by the [`crate::corpus::InMemoryOnDiskCorpus`] or
+    /// [`PackedCorpus::export_to_dir`].
+    ///
+    /// Metadata is restored from `.<filename>.metadata` files in [`crate::corpus::ondisk::OnDiskMetadataFormat::Json`]
+    /// or [`crate::corpus::ondisk::OnDiskMetadataFormat::JsonPretty`], if present. Hidden files are skipped.
+    pub fn import_from_dir<P>(&mut self, dir: P) -> Result<Vec<CorpusId>, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let dir = dir.as_ref();
+        let mut paths = fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        paths.sort();
+
+        let mut ids = Vec::new();
+        for path in paths {
+            let Some(filename) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+            if filename.starts_with('.') || !path.is_file() {
+                continue;
+            }
+
+            let mut testcase = Testcase::new(I::from_file(&path)?);
+            // With locking, the metadata file name carries a counter
+            for metafile in [
+                format!(".{filename}.metadata"),
+                format!(".{filename}_1.metadata"),
+            ] {
+                let Ok(serialized) = fs::read(dir.join(metafile)) else {
+                    continue;
+                };
+                let ondisk_meta: OnDiskMetadataOwned = serde_json::from_slice(&serialized)
+                    .map_err(|err| {
+                        Error::serialize(format!("Failed to parse metadata: {err:?}"))
+                    })?;
+                *testcase.metadata_map_mut() = ondisk_meta.metadata;
+                *testcase.exec_time_mut() = ondisk_meta.exec_time;
+                testcase.set_executions(ondisk_meta.executions);
+                break;
+            }
+
+            ids.push(self.add(testcase)?);
+        }
+        Ok(ids)
+    }
+
+    /// All ids, enabled and disabled, in ascending order
+    fn all_ids(&self) -> Vec<CorpusId> {
+        let mut ids = (0..self.inner.count_all())
+            .map(|nth| self.inner.nth_from_all(nth))
+            .collect::<Vec<_>>();
+        ids.sort_unstable();
+        ids
+    }
+
+    fn pack_path(&self) -> PathBuf {
+        self.dir_path.join(PACK_FILE_NAME)
+    }
+
+    fn index_path(&self) -> PathBuf {
+        self.dir_path.join(INDEX_FILE_NAME)
+    }
+
+    fn add_inner(&mut self, mut testcase: Testcase<I>, disabled: bool) -> Result<CorpusId, Error> {
+        let (hash, location) = self.store_testcase(&mut testcase)?;
+        let meta = PackedTestcaseMeta::new(&testcase);
+        let meta_hash = meta.hash()?;
+        let id = if disabled {
+            self.inner.add_disabled(testcase)?
+        } else {
+            self.inner.add(testcase)?
+        };
+        self.append_index(&IndexRecord::Add {
+            id,
+            hash,
+            location,
+            disabled,
+            meta,
+        })?;
+        self.meta_hashes.borrow_mut().insert(id, meta_hash);
+        Ok(id)
+    }
+
+    /// Loads the input of the testcase, if it is not loaded yet, and evicts the oldest cached input if the cache is
+    /// full, writing changed metadata of the evicted [`Testcase`] to the index
+    fn cache_testcase(&self, testcase: &RefCell<Testcase<I>>, id: CorpusId) -> Result<(), Error> {
+        // A testcase in use keeps its input until it is accessed again
+        let Ok(mut borrowed) = testcase.try_borrow_mut() else {
+            return Ok(());
+        };
+        if borrowed.input().is_some() {
+            return Ok(());
+        }
+        self.load_input_into(&mut borrowed)?;
+        drop(borrowed);
+
+        let mut borrowed_num = 0;
+        while self.cached_indexes.borrow().len() >= self.cache_max_len {
+            let removed = self.cached_indexes.borrow_mut().pop_front().unwrap();
+
+            if let Ok(mut borrowed) = self.inner.get_from_all(removed)?.try_borrow_mut() {
+                self.write_changed_metadata(removed, &borrowed)?;
+                *borrowed.input_mut() = None;
+            } else {
+                self.cached_indexes.borrow_mut().push_back(removed);
+                borrowed_num += 1;
+                if self.cache_max_len == borrowed_num {
+                    break;
+                }
+            }
+        }
+        self.cached_indexes.borrow_mut().push_back(id);
+        Ok(())
+    }
+
+    /// Appends an update record to the index, if the metadata changed since it was last written
+    fn write_changed_metadata(&self, id: CorpusId, testcase: &Testcase<I>) -> Result<(), Error> {
+        let meta = PackedTestcaseMeta::new(testcase);
+        let meta_hash = meta.hash()?;
+        if self.meta_hashes.borrow().get(&id) == Some(&meta_hash) {
+            return Ok(());
+        }
+        self.append_index(&IndexRecord::Update { id, meta })?;
+        self.meta_hashes.borrow_mut().insert(id, meta_hash);
+        Ok(())
+    }
+
+    /// Stores the input of the testcase in the pack, names it after its hash, and drops it from memory
+    fn store_testcase(&self, testcase: &mut Testcase<I>) -> Result<(u64, PackLocation), Error> {
+        let Some(input) = testcase.input() else {
+            return Err(Error::illegal_argument(
+                "No input available for testcase. Could not store anything.",
+            ));
+        };
+        let (hash, location) = self.store_blob(&postcard::to_allocvec(input)?)?;
+        *testcase.filename_mut() = Some(hash_to_filename(hash));
+        *testcase.file_path_mut() = None;
+        *testcase.input_mut() = None;
+        Ok((hash, location))
+    }
+
+    /// Appends the bytes to the pack, unless the same content is already stored
+    fn store_blob(&self, bytes: &[u8]) -> Result<(u64, PackLocation), Error> {
+        let hash = hash_std(bytes);
+        if let Some(location) = self.location(hash) {
+            if self.read_blob(hash)? == bytes {
+                return Ok((hash, location));
+            }
+            return Err(Error::illegal_state(format!(
+                "Hash collision for content {}",
+                hash_to_filename(hash)
+            )));
+        }
+
+        let offset = append_record(&self.pack_path(), hash, bytes)?;
+        let location = PackLocation {
+            offset,
+            len: bytes.len() as u64,
+        };
+        self.blobs.borrow_mut().insert(hash, location);
+        Ok((hash, location))
+    }
+
+    fn read_blob(&self, hash: u64) -> Result<Vec<u8>, Error> {
+        let location = self.location(hash).ok_or_else(|| {
+            Error::key_not_found(format!(
+                "Content {} not found in the pack",
+                hash_to_filename(hash)
+            ))
+        })?;
+
+        let mut file = File::open(self.pack_path())?;
+        file.seek(SeekFrom::Start(location.offset))?;
+        let mut bytes = vec![0; usize::try_from(location.len)?];
+        file.read_exact(&mut bytes)?;
+
+        if hash_std(&bytes) != hash {
+            return Err(Error::illegal_state(format!(
+                "Content {} is corrupted in the pack",
+                hash_to_filename(hash)
+            )));
+        }
+        Ok(bytes)
+    }
+
+    fn append_index(&self, record: &IndexRecord) -> Result<(), Error> {
+        let payload = postcard::to_allocvec(record)?;
+        append_record(&self.index_path(), hash_std(&payload), &payload)?;
+        Ok(())
+    }
+
+    fn testcase_hash(testcase: &Testcase<I>) -> Result<u64, Error> {
+        testcase
+            .filename()
+            .as_ref()
+            .and_then(|name| u64::from_str_radix(name, 16).ok())
+            .ok_or_else(|| Error::illegal_state("Testcase in a packed corpus without content hash"))
+    }
+
+    /// Applies a record of the index log to the in-memory state
+    fn replay(&mut self, record: IndexRecord) -> Result<(), Error> {
+        match record {
+            IndexRecord::Add {
+                id,
+                hash,
+                location,
+                disabled,
+                meta,
+            } => {
+                if id < self.inner.peek_free_id() {
+                    return Err(Error::illegal_state(format!(
+                        "Index of the packed corpus adds id {id} twice"
+                    )));
+                }
+                // Ids removed before the index was compacted leave gaps; skip over them.
+                while self.inner.peek_free_id() < id {
+                    let gap = self.inner.add(Testcase::default())?;
+                    self.inner.remove(gap)?;
+                }
+                self.blobs.borrow_mut().insert(hash, location);
+                self.meta_hashes.borrow_mut().insert(id, meta.hash()?);
+                let testcase = meta.into_testcase(hash);
+                if disabled {
+                    self.inner.add_disabled(testcase)?;
+                } else {
+                    self.inner.add(testcase)?;
+                }
+            }
+            IndexRecord::Replace {
+                id,
+                hash,
+                location,
+                meta,
+            } => {
+                self.blobs.borrow_mut().insert(hash, location);
+                self.meta_hashes.borrow_mut().insert(id, meta.hash()?);
+                self.inner.replace(id, meta.into_testcase(hash))?;
+            }
+            IndexRecord::Remove { id } => {
+                self.inner.remove(id)?;
+                self.meta_hashes.borrow_mut().remove(&id);
+            }
+            IndexRecord::Disable { id } => self.inner.disable(id)?,
+            IndexRecord::Enable { id } => self.inner.enable(id)?,
+            IndexRecord::Update { id, meta } => {
+                self.meta_hashes.borrow_mut().insert(id, meta.hash()?);
+                meta.apply_to(&mut self.inner.get_from_all(id)?.borrow_mut());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use std::{env, fs, path::PathBuf, process};
+
+    use super::{INDEX_FILE_NAME, PackedCorpus};
+    use crate::{
+        HasMetadata,
+        corpus::{Corpus, CorpusId, EnableDisableCorpus, SchedulerTestcaseMetadata, Testcase},
+        inputs::BytesInput,
+    };
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("libafl_packed_{name}_{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    fn input_of(corpus: &PackedCorpus<BytesInput>, id: CorpusId) -> Vec<u8> {
+        let mut testcase = corpus.get_from_all(id).unwrap().borrow().clone();
+        corpus.load_input_into(&mut testcase).unwrap();
+        testcase.input().clone().unwrap().into()
+    }
+
+    #[test]
+    fn test_packed_dedup_and_replay() {
+        let dir = test_dir("replay");
+        {
+            let mut corpus = PackedCorpus::<BytesInput>::new(&dir, 16).unwrap();
+            let a = corpus
+                .add(Testcase::new(BytesInput::new(b"aaaa".to_vec())))
+                .unwrap();
+            let b = corpus
+                .add(Testcase::new(BytesInput::new(b"bbbb".to_vec())))
+                .unwrap();
+            let c = corpus
+                .add(Testcase::new(BytesInput::new(b"aaaa".to_vec())))
+                .unwrap();
+            assert_eq!(corpus.blob_count(), 2);
+
+            corpus.remove(a).unwrap();
+            corpus.disable(b).unwrap();
+            corpus
+                .replace(c, Testcase::new(BytesInput::new(b"cccc".to_vec())))
+                .unwrap();
+        }
+
+        let corpus = PackedCorpus::<BytesInput>::new(&dir, 16).unwrap();
+        assert_eq!(corpus.count(), 1);
+        assert_eq!(corpus.count_disabled(), 1);
+        assert_eq!(input_of(&corpus, CorpusId(1)), b"bbbb");
+        assert_eq!(input_of(&corpus, CorpusId(2)), b"cccc");
+
+        // Compacting keeps the ids, including the gap of the removed testcase
+        corpus.compact_index().unwrap();
+        let mut corpus = PackedCorpus::<BytesInput>::new(&dir, 16).unwrap();
+        assert_eq!(corpus.count_all(), 2);
+        assert_eq!(input_of(&corpus, CorpusId(2)), b"cccc");
+        let d = corpus
+            .add(Testcase::new(BytesInput::new(b"dddd".to_vec())))
+            .unwrap();
+        assert_eq!(d, CorpusId(3));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_packed_cache() {
+        let dir = test_dir("cache");
+        {
+            let mut corpus = PackedCorpus::<BytesInput>::new(&dir, 1).unwrap();
+            let a = corpus
+                .add(Testcase::new(BytesInput::new(b"aaaa".to_vec())))
+                .unwrap();
+            let b = corpus
+                .add(Testcase::new(BytesInput::new(b"bbbb".to_vec())))
+                .unwrap();
+            assert!(corpus.get(a).unwrap().borrow().input().is_some());
+            corpus
+                .get(a)
+                .unwrap()
+                .borrow_mut()
+                .add_metadata(SchedulerTestcaseMetadata::new(3));
+
+            // Only one input stays in memory, the metadata of the evicted testcase is written to the index
+            assert!(corpus.get(b).unwrap().borrow().input().is_some());
+            assert!(corpus.inner.get(a).unwrap().borrow().input().is_none());
+            corpus
+                .get(b)
+                .unwrap()
+                .borrow_mut()
+                .add_metadata(SchedulerTestcaseMetadata::new(5));
+            corpus.sync_metadata(b).unwrap();
+        }
+
+        let corpus = PackedCorpus::<BytesInput>::new(&dir, 1).unwrap();
+        for (id, depth) in [(CorpusId(0), 3), (CorpusId(1), 5)] {
+            let testcase = corpus.get(id).unwrap().borrow();
+            let meta = testcase.metadata::<SchedulerTestcaseMetadata>().unwrap();
+            assert_eq!(meta.depth(), depth);
+        }
+        assert!(PackedCorpus::<BytesInput>::new(&dir, 0).is_err());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_packed_truncated_index() {
+        let dir = test_dir("truncated");
+        {
+            let mut corpus = PackedCorpus::<BytesInput>::new(&dir, 16).unwrap();
+            corpus
+                .add(Testcase::new(BytesInput::new(b"aaaa".to_vec())))
+                .unwrap();
+            corpus
+                .add(Testcase::new(BytesInput::new(b"bbbb".to_vec())))
+                .unwrap();
+        }
+        let index = dir.join(INDEX_FILE_NAME);
+        let len = fs::metadata(&index).unwrap().len();
+        fs::OpenOptions::new()
+            .write(true)
+            .open(&index)
+            .unwrap()
+            .set_len(len - 3)
+            .unwrap();
+
+        let corpus = PackedCorpus::<BytesInput>::new(&dir, 16).unwrap();
+        assert_eq!(corpus.count(), 1);
+        assert_eq!(input_of(&corpus, CorpusId(0)), b"aaaa");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_packed_export_import() {
+        let dir = test_dir("export");
+        let mut corpus = PackedCorpus::<BytesInput>::new(dir.join("pack"), 16).unwrap();
+        corpus
+            .add(Testcase::new(BytesInput::new(b"aaaa".to_vec())))
+            .unwrap();
+        corpus
+            .add(Testcase::new(BytesInput::new(b"bbbb".to_vec())))
+            .unwrap();
+        assert_eq!(corpus.export_to_dir(dir.join("queue")).unwrap(), 2);
+
+        let mut imported = PackedCorpus::<BytesInput>::new(dir.join("imported"), 16).unwrap();
+        let ids = imported.import_from_dir(dir.join("queue")).unwrap();
+        assert_eq!(ids.len(), 2);
+        let mut inputs = ids
+            .iter()
+            .map(|id| input_of(&imported, *id))
+            .collect::<Vec<_>>();
+        inputs.sort();
+        assert_eq!(inputs, [b"aaaa".to_vec(), b"bbbb".to_vec()]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -64,7 +64,7 @@ use crate::{
     Error,
     executors::{Executor, ExitKind, HasObservers},
     inputs::{HasTargetBytes, ToTargetBytes},
-    observers::{ObserversTuple, StdErrObserver, StdOutObserver},
+    observers::{MemoryObserver, ObserversTuple, StdErrObserver, StdOutObserver},
     state::HasExecutions,
 };
 
@@ -321,6 +321,7 @@ pub struct CommandExecutor<C, HT, I, OT, S, T> {
     observers: OT,
     stdout_observer: Option<Handle<StdOutObserver>>,
     stderr_observer: Option<Handle<StdErrObserver>>,
+    memory_observer: Option<Handle<MemoryObserver<'static>>>,
     hooks: HT,
     phantom: PhantomData<(C, I, S)>,
 }
@@ -356,6 +357,7 @@ where
             .field("hooks", &self.hooks)
             .field("stdout_observer", &self.stdout_observer)
             .field("stderr_observer", &self.stderr_observer)
+            .field("memory_observer", &self.memory_observer)
             .finish()
     }
 }
@@ -365,6 +367,16 @@ impl<C, HT, I, OT, S, T> CommandExecutor<C, HT, I, OT, S, T> {
     pub fn inner(&mut self) -> &mut T {
         &mut self.configurator
     }
+
+    /// Reports the peak resident set size of each [`Child`] process to the [`MemoryObserver`] of `memory_observer`
+    #[must_use]
+    pub fn with_memory_observer(
+        mut self,
+        memory_observer: Handle<MemoryObserver<'static>>,
+    ) -> Self {
+        self.memory_observer = Some(memory_observer);
+        self
+    }
 }
 
 // this only works on unix because of the reliance on checking the process signal for detecting OOM
@@ -388,18 +400,35 @@ where
             .configurator
             .spawn_child(target_bytes_converter.to_target_bytes(input))?;
 
-        let exit_kind = child
+        #[cfg(unix)]
+        let status = if let Some(memory_handle) = self.memory_observer.clone() {
+            let waited = wait_with_rusage(&child, self.configurator.exec_timeout())?;
+            if let Some((_, usage)) = &waited {
+                self.observers_mut()
+                    .index_mut(&memory_handle)
+                    .record_rusage(usage);
+            }
+            waited.map(|(status, _)| status)
+        } else {
+            child
+                .wait_timeout(self.configurator.exec_timeout())
+                .expect("waiting on child failed")
+        };
+        #[cfg(not(unix))]
+        let status = child
             .wait_timeout(self.configurator.exec_timeout())
-            .expect("waiting on child failed")
-            .map(|status| self.configurator.exit_kind_from_status(&status))
-            .unwrap_or_else(|| {
-                // if this fails, there is not much we can do. let's hope it failed because the process finished
-                // in the meantime.
-                drop(child.kill());
-                // finally, try to wait to properly clean up system resources.
-                drop(child.wait());
-                ExitKind::Timeout
-            });
+            .expect("waiting on child failed");
+
+        let exit_kind = if let Some(status) = status {
+            self.configurator.exit_kind_from_status(&status)
+        } else {
+            // if this fails, there is not much we can do. let's hope it failed because the process finished
+            // in the meantime.
+            drop(child.kill());
+            // finally, try to wait to properly clean up system resources.
+            drop(child.wait());
+            ExitKind::Timeout
+        };
 
         // Manually update stdout/stderr here if we use piped implementation.
         // Reason of not putting into state and pass by post_exec_all is that
@@ -427,6 +456,53 @@ where
     }
 }
 
+/// Waits up to `timeout` for `child` to exit, returning its exit status and resource usage, or `None` on timeout.
+///
+/// Unlike `wait_timeout`, this polls, backing off up to [`WAIT_RUSAGE_MAX_BACKOFF`] between the checks.
+#[cfg(unix)]
+fn wait_with_rusage(
+    child: &Child,
+    timeout: Duration,
+) -> Result<Option<(std::process::ExitStatus, libc::rusage)>, Error> {
+    use std::{os::unix::process::ExitStatusExt, time::Instant};
+
+    let pid = libc::pid_t::try_from(child.id())?;
+    let start = Instant::now();
+    let mut backoff = Duration::from_micros(10);
+    loop {
+        let mut status = 0;
+        let mut usage = core::mem::MaybeUninit::<libc::rusage>::zeroed();
+        // # Safety
+        // `wait4` only writes to the status and the usage, both valid for writes
+        match unsafe { libc::wait4(pid, &raw mut status, libc::WNOHANG, usage.as_mut_ptr()) } {
+            0 => {}
+            -1 => {
+                let err = std::io::Error::last_os_error();
+                if err.kind() != std::io::ErrorKind::Interrupted {
+                    return Err(err.into());
+                }
+            }
+            _ => {
+                // # Safety
+                // The child exited, so `wait4` filled the usage
+                let usage = unsafe { usage.assume_init() };
+                return Ok(Some((std::process::ExitStatus::from_raw(status), usage)));
+            }
+        }
+
+        let elapsed = start.elapsed();
+        if elapsed >= timeout {
+            return Ok(None);
+        }
+        std::thread::sleep(backoff.min(timeout - elapsed));
+        backoff = (backoff * 2).min(WAIT_RUSAGE_MAX_BACKOFF);
+    }
+}
+
+/// The longest time [`wait_with_rusage`] sleeps before checking on the child again
+#[cfg(unix)]
+const WAIT_RUSAGE_MAX_BACKOFF: Duration = Duration::from_millis(10);
+
 impl<EM, HT, I, OT, S, T, Z> Executor<EM, I, S, Z> for CommandExecutor<Child, HT, I, OT, S, T>
 where
     S: HasExecutions,
@@ -560,6 +636,7 @@ where
 pub struct CommandExecutorBuilder {
     target_inner: StdTargetArgsInner,
     child_env_inner: StdChildArgsInner,
+    memory_observer: Option<Handle<MemoryObserver<'static>>>,
 }
 
 impl StdTargetArgs for CommandExecutorBuilder {
@@ -595,9 +672,20 @@ impl CommandExecutorBuilder {
         CommandExecutorBuilder {
             target_inner: StdTargetArgsInner::default(),
             child_env_inner: StdChildArgsInner::default(),
+            memory_observer: None,
         }
     }
 
+    /// Sets the [`MemoryObserver`] to report the peak resident set size of each child process to.
+    ///
+    /// The executor then polls the child with `wait4`, see [`MemoryObserver`].
+    #[cfg(unix)]
+    #[must_use]
+    pub fn memory_observer(mut self, memory_observer: Handle<MemoryObserver<'static>>) -> Self {
+        self.memory_observer = Some(memory_observer);
+        self
+    }
+
     /// Builds the `CommandExecutor`
     pub fn build<I, OT, S>(
         &self,
@@ -720,11 +808,13 @@ impl CommandExecutorBuilder {
             command,
         };
 
-        Ok(configurator.into_executor::<I, OT, S>(
+        let mut executor = configurator.into_executor::<I, OT, S>(
             observers,
             self.child_env_inner.stdout_observer.clone(),
             self.child_env_inner.stderr_observer.clone(),
-        ))
+        );
+        executor.memory_observer.clone_from(&self.memory_observer);
+        Ok(executor)
     }
 }
 
@@ -834,6 +924,7 @@ pub trait CommandConfigurator<C>: Sized {
             hooks: (),
             stderr_observer,
             stdout_observer,
+            memory_observer: None,
             phantom: PhantomData,
         }
     }
@@ -852,6 +943,7 @@ pub trait CommandConfigurator<C>: Sized {
             hooks,
             stderr_observer,
             stdout_observer,
+            memory_observer: None,
             phantom: PhantomData,
         }
     }
@@ -893,7 +985,10 @@ mod tests {
         state::NopState,
     };
     #[cfg(unix)]
-    use crate::{executors::StdChildArgs, observers::StdOutObserver};
+    use crate::{
+        executors::{ExitKind, StdChildArgs},
+        observers::{MemoryObserver, StdOutObserver},
+    };
 
     #[test]
     #[cfg_attr(miri, ignore)]
@@ -948,4 +1043,34 @@ mod tests {
 
         assert!(executor.observers.0.output.is_some());
     }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    #[cfg(unix)]
+    fn test_memory_observer() {
+        let mut mgr: SimpleEventManager<NopInput, _, NopState<NopInput>> =
+            SimpleEventManager::new(SimpleMonitor::new(|status| {
+                log::info!("{status}");
+            }));
+
+        let memory = MemoryObserver::owned("memory");
+        let executor = CommandExecutor::builder()
+            .program("ls")
+            .memory_observer(memory.handle())
+            .input(InputLocation::Arg { argnum: 0 });
+        let executor = executor.build(tuple_list!(memory));
+        let mut executor = executor.unwrap();
+
+        let exit_kind = executor
+            .run_target(
+                &mut NopFuzzer::new(),
+                &mut NopState::<NopInput>::new(),
+                &mut mgr,
+                &BytesInput::new(b".".to_vec()),
+            )
+            .unwrap();
+
+        assert_eq!(exit_kind, ExitKind::Ok);
+        assert!(executor.observers.0.peak() > 0);
+    }
 }
@@ -17,9 +17,13 @@ use std::{
     path::PathBuf,
     process::{Child, Command, Stdio},
 };
+#[cfg(target_os = "linux")]
+use std::{fs, time::Instant};
 
+#[cfg(any(feature = "regex", target_os = "linux"))]
+use libafl_bolts::tuples::Handle;
 #[cfg(feature = "regex")]
-use libafl_bolts::tuples::{Handle, Handled};
+use libafl_bolts::tuples::Handled;
 use libafl_bolts::{
     AsSlice, AsSliceMut, InputLocation, StdTargetArgs, StdTargetArgsInner, Truncate,
     core_affinity::CoreId,
@@ -40,6 +44,8 @@ use nix::{
 };
 
 use super::{HasTimeout, StdChildArgs, StdChildArgsInner};
+#[cfg(target_os = "linux")]
+use crate::observers::MemoryObserver;
 #[cfg(feature = "regex")]
 use crate::observers::{
     AsanBacktraceObserver, get_asan_runtime_flags, get_asan_runtime_flags_with_log_path,
@@ -57,6 +63,12 @@ use crate::{
 pub const FORKSRV_FD: i32 = 198;
 #[expect(clippy::cast_possible_wrap)]
 const FS_NEW_ERROR: i32 = 0xeffe0000_u32 as i32;
+/// The first interval at which the peak memory of a child is sampled
+#[cfg(target_os = "linux")]
+const RSS_SAMPLE_INTERVAL_MIN: Duration = Duration::from_micros(100);
+/// The interval at which the peak memory of a long-running child is sampled
+#[cfg(target_os = "linux")]
+const RSS_SAMPLE_INTERVAL_MAX: Duration = Duration::from_millis(10);
 
 /// Minimum number for new version
 pub const FS_NEW_VERSION_MIN: u32 = 1;
@@ -713,6 +725,8 @@ pub struct ForkserverExecutor<I, OT, S, SHM> {
     asan_obs: Handle<AsanBacktraceObserver>,
     timeout: TimeSpec,
     crash_exitcode: Option<i8>,
+    #[cfg(target_os = "linux")]
+    memory_observer: Option<Handle<MemoryObserver<'static>>>,
 }
 
 impl<I, OT, S, SHM> Debug for ForkserverExecutor<I, OT, S, SHM>
@@ -853,7 +867,16 @@ where
 
         self.forkserver.set_child_pid(Pid::from_raw(pid));
 
-        if let Some(status) = self.forkserver.read_st_timed(&self.timeout)? {
+        #[cfg(target_os = "linux")]
+        let status = if self.memory_observer.is_some() {
+            self.read_st_timed_sampling_rss(pid)?
+        } else {
+            self.forkserver.read_st_timed(&self.timeout)?
+        };
+        #[cfg(not(target_os = "linux"))]
+        let status = self.forkserver.read_st_timed(&self.timeout)?;
+
+        if let Some(status) = status {
             self.forkserver.set_status(status);
             let exitcode_is_crash = if let Some(crash_exitcode) = self.crash_exitcode {
                 (libc::WEXITSTATUS(self.forkserver().status()) as i8) == crash_exitcode
@@ -886,6 +909,72 @@ where
 
         Ok(exit_kind)
     }
+
+    /// Waits for the status of the child like [`Forkserver::read_st_timed`], and records the peak resident set size
+    /// of the child to the memory observer.
+    ///
+    /// The forkserver reaps the child, so the peak is sampled from `/proc` while it runs, more rarely the longer it
+    /// runs. It may thus miss a spike right before the child exits.
+    #[cfg(target_os = "linux")]
+    fn read_st_timed_sampling_rss(&mut self, pid: i32) -> Result<Option<i32>, Error> {
+        let timeout = Duration::from(self.timeout);
+        let start = Instant::now();
+        let mut interval = RSS_SAMPLE_INTERVAL_MIN;
+        let mut peak = 0;
+        let status = loop {
+            if let Some(rss) = read_peak_rss(pid) {
+                peak = peak.max(rss);
+            }
+            let elapsed = start.elapsed();
+            if elapsed >= timeout {
+                break None;
+            }
+            let wait = TimeSpec::from_duration(interval.min(timeout - elapsed));
+            if let Some(status) = self.forkserver.read_st_timed(&wait)? {
+                break Some(status);
+            }
+            interval = (interval * 2).min(RSS_SAMPLE_INTERVAL_MAX);
+        };
+
+        // In persistent mode, the child stops and lives on to the next execution, so its peak is exact but has to be reset
+        if let Some(status) = status
+            && libc::WIFSTOPPED(status)
+        {
+            if let Some(rss) = read_peak_rss(pid) {
+                peak = peak.max(rss);
+            }
+            reset_peak_rss(pid);
+        }
+
+        if let Some(handle) = &self.memory_observer
+            && let Some(memory_observer) = self.observers.get_mut(handle)
+        {
+            memory_observer.record_peak_rss(peak);
+        }
+        Ok(status)
+    }
+}
+
+/// Reads the peak resident set size (`VmHWM`) of the process `pid` from `/proc`, in bytes
+#[cfg(target_os = "linux")]
+fn read_peak_rss(pid: i32) -> Option<u64> {
+    let status = fs::read_to_string(format!("/proc/{pid}/status")).ok()?;
+    let kilobytes = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))?
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kilobytes * 1024)
+}
+
+/// Resets the peak resident set size of the process `pid`, see `proc(5)`
+#[cfg(target_os = "linux")]
+fn reset_peak_rss(pid: i32) {
+    // A child that already exited has nothing to reset
+    let _ = fs::write(format!("/proc/{pid}/clear_refs"), "5");
 }
 
 /// The builder for `ForkserverExecutor`
@@ -907,6 +996,8 @@ pub struct ForkserverExecutorBuilder<'a, SP> {
     #[cfg(feature = "regex")]
     asan_obs: Option<Handle<AsanBacktraceObserver>>,
     crash_exitcode: Option<i8>,
+    #[cfg(target_os = "linux")]
+    memory_observer: Option<Handle<MemoryObserver<'static>>>,
 }
 
 impl<SP> StdChildArgs for ForkserverExecutorBuilder<'_, SP> {
@@ -997,6 +1088,8 @@ where
                 .clone()
                 .unwrap_or(AsanBacktraceObserver::default().handle()),
             crash_exitcode: self.crash_exitcode,
+            #[cfg(target_os = "linux")]
+            memory_observer: self.memory_observer.clone(),
         })
     }
 
@@ -1057,6 +1150,8 @@ where
                 .clone()
                 .unwrap_or(AsanBacktraceObserver::default().handle()),
             crash_exitcode: self.crash_exitcode,
+            #[cfg(target_os = "linux")]
+            memory_observer: self.memory_observer.clone(),
         })
     }
 
@@ -1413,6 +1508,16 @@ where
         self
     }
 
+    /// Reports the peak resident set size of each child to the [`MemoryObserver`] of `memory_observer`.
+    ///
+    /// The peak is sampled from `/proc` while the child runs, so it may miss a spike right before the child exits.
+    #[cfg(target_os = "linux")]
+    #[must_use]
+    pub fn memory_observer(mut self, memory_observer: Handle<MemoryObserver<'static>>) -> Self {
+        self.memory_observer = Some(memory_observer);
+        self
+    }
+
     /// Call this if the harness uses deferred forkserver mode; default is false
     #[must_use]
     pub fn is_deferred_frksrv(mut self, is_deferred_frksrv: bool) -> Self {
@@ -1474,6 +1579,8 @@ impl<'a> ForkserverExecutorBuilder<'a, UnixShMemProvider> {
             #[cfg(feature = "regex")]
             asan_obs: None,
             crash_exitcode: None,
+            #[cfg(target_os = "linux")]
+            memory_observer: None,
         }
     }
 }
@@ -1502,6 +1609,8 @@ impl<'a> ForkserverExecutorBuilder<'a, UnixShMemProvider> {
             #[cfg(feature = "regex")]
             asan_obs: self.asan_obs,
             crash_exitcode: self.crash_exitcode,
+            #[cfg(target_os = "linux")]
+            memory_observer: self.memory_observer,
         }
     }
 }
@@ -1569,6 +1678,8 @@ where
 mod tests {
     use std::ffi::OsString;
 
+    #[cfg(target_os = "linux")]
+    use libafl_bolts::tuples::Handled;
     use libafl_bolts::{
         AsSliceMut, StdTargetArgs,
         shmem::{ShMem, ShMemProvider, UnixShMemProvider},
@@ -1576,6 +1687,8 @@ mod tests {
     };
     use serial_test::serial;
 
+    #[cfg(target_os = "linux")]
+    use super::read_peak_rss;
     use crate::{
         Error,
         corpus::NopCorpus,
@@ -1586,6 +1699,8 @@ mod tests {
         inputs::BytesInput,
         observers::{ConstMapObserver, HitcountsMapObserver},
     };
+    #[cfg(target_os = "linux")]
+    use crate::{executors::ExitKind, observers::MemoryObserver};
 
     #[test]
     #[serial]
@@ -1631,4 +1746,57 @@ mod tests {
         };
         assert!(result);
     }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    #[cfg_attr(miri, ignore)]
+    fn test_read_peak_rss() {
+        let own = read_peak_rss(i32::try_from(std::process::id()).unwrap()).unwrap();
+        assert!(own > 0);
+        assert_eq!(read_peak_rss(-1), None);
+    }
+
+    #[test]
+    #[serial]
+    #[cfg(target_os = "linux")]
+    #[cfg_attr(miri, ignore)]
+    fn test_forkserver_memory_observer() {
+        const MAP_SIZE: usize = 65536;
+        // An old-style forkserver: say hello, then, for each execution, fork a child that holds about 30 MB and
+        // report its pid and exit status
+        let script = r#"
+            printf '\000\000\000\000' >/proc/self/fd/199
+            while dd bs=4 count=1 </proc/self/fd/198 >/dev/null 2>&1; do
+                ( x=$(head -c 30000000 /dev/zero | tr '\000' a); sleep 0.2; : "${#x}" ) &
+                p=$!
+                printf "$(printf '\\%03o\\%03o\\%03o\\%03o' $((p & 255)) $((p >> 8 & 255)) $((p >> 16 & 255)) $((p >> 24 & 255)))" >/proc/self/fd/199
+                wait $p
+                printf '\000\000\000\000' >/proc/self/fd/199
+            done
+        "#;
+
+        let mut shmem_provider = UnixShMemProvider::new().unwrap();
+        let shmem = shmem_provider.new_shmem(MAP_SIZE).unwrap();
+        // # Safety
+        // The test runs serially
+        unsafe {
+            shmem.write_to_env("__AFL_SHM_ID").unwrap();
+        }
+
+        let memory = MemoryObserver::owned("memory");
+        let mut executor = ForkserverExecutor::builder()
+            .program("sh")
+            .arg("-c")
+            .arg(script)
+            .coverage_map_size(MAP_SIZE)
+            .memory_observer(memory.handle())
+            .build::<BytesInput, _, NopCorpus<BytesInput>>(tuple_list!(memory))
+            .unwrap();
+
+        for _ in 0..2 {
+            let exit_kind = executor.execute_input_uncounted(b"a").unwrap();
+            assert_eq!(exit_kind, ExitKind::Ok);
+            assert!(executor.observers.0.peak() > 30_000_000);
+        }
+    }
 }
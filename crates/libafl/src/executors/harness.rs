@@ -0,0 +1,610 @@
+//! The [`HarnessExecutor`] persistently fuzzes a target that speaks the harness protocol, without forking.
+//!
+//! The target is spawned once and runs a loop over the inputs, like in the persistent mode of AFL++, but needs
+//! neither instrumentation nor a forkserver:
+//!
+//! * The fuzzer creates a shared memory region of `max_input_size` bytes, and passes its id and size in the
+//!   [`HARNESS_SHM_ENV_VAR`] and [`HARNESS_SHM_SIZE_ENV_VAR`] environment variables.
+//! * It opens the control pipe on fd [`HARNESS_CTL_FD`] and the status pipe on fd [`HARNESS_ST_FD`] of the target.
+//! * The target maps the shared memory and writes [`HARNESS_HELLO`] to the status pipe.
+//! * For each input, the fuzzer writes it to the shared memory and its length as native-endian `u32` to the
+//!   control pipe. The target runs it and answers with a native-endian `u32` status: [`HARNESS_STATUS_OK`], or
+//!   [`HARNESS_STATUS_CRASH`] to report a bug without crashing.
+//! * Once the fuzzer closes the control pipe, the target exits.
+//!
+//! If the target crashes or times out, it is spawned again for the next input. The target may also exit between
+//! two inputs, e.g., to clean up after a number of runs: if it did not read the length of an input, the input runs in
+//! a new target. `libafl_targets` has the clients for targets in Rust and C.
+
+use alloc::{string::ToString, vec::Vec};
+use core::{
+    fmt::{self, Debug, Formatter},
+    marker::PhantomData,
+    time::Duration,
+};
+use std::{
+    ffi::OsString,
+    io::{self, ErrorKind, Read, Write},
+    os::{
+        fd::{AsRawFd, BorrowedFd},
+        unix::{
+            io::RawFd,
+            process::{CommandExt, ExitStatusExt},
+        },
+    },
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+};
+
+use libafl_bolts::{
+    AsSliceMut, InputLocation, StdTargetArgs, StdTargetArgsInner,
+    core_affinity::CoreId,
+    os::{dup2, pipes::Pipe},
+    shmem::{ShMem, ShMemProvider, UnixShMem, UnixShMemProvider},
+    tuples::RefIndexable,
+};
+use nix::{
+    sys::{
+        select::{FdSet, pselect},
+        signal::{SigSet, Signal, kill},
+        time::TimeSpec,
+    },
+    unistd::Pid,
+};
+
+use super::{HasTimeout, StdChildArgs, StdChildArgsInner};
+use crate::{
+    Error,
+    executors::{Executor, ExitKind, HasObservers, forkserver::ConfigTarget},
+    inputs::ToTargetBytes,
+    observers::ObserversTuple,
+    state::HasExecutions,
+};
+
+/// The fd of the control pipe in the target, the fuzzer writes the input lengths to it
+pub const HARNESS_CTL_FD: RawFd = 196;
+/// The fd of the status pipe in the target, the target writes the hello and the statuses to it
+pub const HARNESS_ST_FD: RawFd = 197;
+/// The environment variable holding the id of the input shared memory
+pub const HARNESS_SHM_ENV_VAR: &str = "__LIBAFL_HARNESS_SHM_ID";
+/// The environment variable holding the size of the input shared memory
+pub const HARNESS_SHM_SIZE_ENV_VAR: &str = "__LIBAFL_HARNESS_SHM_SIZE";
+/// The first message of the target on the status pipe, once it is ready
+pub const HARNESS_HELLO: [u8; 4] = *b"LAFL";
+/// The status of a finished run
+pub const HARNESS_STATUS_OK: u32 = 0;
+/// The status of a run that found a bug, without crashing the target
+pub const HARNESS_STATUS_CRASH: u32 = 1;
+/// The default maximum input size
+pub const HARNESS_MAX_INPUT_SIZE_DEFAULT: usize = 1024 * 1024;
+
+/// How long to wait for the [`HARNESS_HELLO`] of a newly spawned target
+const HARNESS_STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+/// The default signal to use to kill the target
+const KILL_SIGNAL_DEFAULT: Signal = Signal::SIGKILL;
+
+/// The answer of the target to a message
+enum HarnessReply {
+    Message([u8; 4]),
+    Exited,
+    TimedOut,
+}
+
+/// A running target and its pipes
+#[derive(Debug)]
+struct HarnessChild {
+    handle: Child,
+    st_pipe: Pipe,
+    ctl_pipe: Pipe,
+}
+
+impl HarnessChild {
+    /// Waits up to `timeout` for the next message of the target
+    fn read_st_timed(&mut self, timeout: &TimeSpec) -> Result<HarnessReply, Error> {
+        let Some(st_read) = self.st_pipe.read_end() else {
+            return Err(Error::os_error(
+                io::Error::new(ErrorKind::BrokenPipe, "Read pipe end was already closed"),
+                "read_st_timed failed",
+            ));
+        };
+
+        // # Safety
+        // The FDs are valid as this point in time.
+        let st_read = unsafe { BorrowedFd::borrow_raw(st_read) };
+
+        let mut readfds = FdSet::new();
+        readfds.insert(st_read);
+        let sret = pselect(
+            Some(readfds.highest().unwrap().as_raw_fd() + 1),
+            &mut readfds,
+            None,
+            None,
+            Some(timeout),
+            Some(&SigSet::empty()),
+        )?;
+        if sret == 0 {
+            return Ok(HarnessReply::TimedOut);
+        }
+        let mut buf = [0_u8; 4];
+        match self.st_pipe.read_exact(&mut buf) {
+            Ok(()) => Ok(HarnessReply::Message(buf)),
+            Err(err) if err.kind() == ErrorKind::UnexpectedEof => Ok(HarnessReply::Exited),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Whether the message on the control pipe is still unread, i.e., the target did not take the input
+    fn has_unread_ctl(&self) -> Result<bool, Error> {
+        let Some(ctl_read) = self.ctl_pipe.read_end() else {
+            return Ok(false);
+        };
+
+        // # Safety
+        // The FDs are valid as this point in time.
+        let ctl_read = unsafe { BorrowedFd::borrow_raw(ctl_read) };
+
+        let mut readfds = FdSet::new();
+        readfds.insert(ctl_read);
+        let sret = pselect(
+            Some(readfds.highest().unwrap().as_raw_fd() + 1),
+            &mut readfds,
+            None,
+            None,
+            Some(&TimeSpec::from_duration(Duration::ZERO)),
+            Some(&SigSet::empty()),
+        )?;
+        Ok(sret > 0)
+    }
+
+    /// Kills the target with `signal` and waits for it
+    fn kill(&mut self, signal: Signal) {
+        let pid = Pid::from_raw(self.handle.id().try_into().unwrap());
+        if let Err(err) = kill(pid, signal) {
+            log::warn!("Failed to deliver {signal} to harness {pid}: {err}");
+        }
+        drop(self.handle.wait());
+    }
+}
+
+/// An [`Executor`] for targets speaking the harness protocol, passing the inputs in shared memory to a long-running
+/// process, as described in the [module documentation](self).
+///
+/// Unlike the [`crate::executors::CommandExecutor`], it does not spawn a process for each input, and unlike the
+/// [`crate::executors::ForkserverExecutor`], it does not need targets instrumented for AFL++.
+pub struct HarnessExecutor<I, OT, S> {
+    program: OsString,
+    args: Vec<OsString>,
+    envs: Vec<(OsString, OsString)>,
+    current_dir: Option<PathBuf>,
+    debug_child: bool,
+    core: Option<CoreId>,
+    shmem: UnixShMem,
+    child: Option<HarnessChild>,
+    timeout: TimeSpec,
+    kill_signal: Signal,
+    observers: OT,
+    phantom: PhantomData<fn() -> (I, S)>,
+}
+
+impl<I, OT, S> Debug for HarnessExecutor<I, OT, S>
+where
+    OT: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HarnessExecutor")
+            .field("program", &self.program)
+            .field("args", &self.args)
+            .field("child", &self.child)
+            .field("timeout", &self.timeout)
+            .field("observers", &self.observers)
+            .finish_non_exhaustive()
+    }
+}
+
+impl HarnessExecutor<(), (), ()> {
+    /// Creates a builder for a new [`HarnessExecutor`]
+    #[must_use]
+    pub fn builder() -> HarnessExecutorBuilder {
+        HarnessExecutorBuilder::new()
+    }
+}
+
+impl<I, OT, S> HarnessExecutor<I, OT, S> {
+    /// The program to execute
+    pub fn program(&self) -> &OsString {
+        &self.program
+    }
+
+    /// The program arguments
+    pub fn args(&self) -> &[OsString] {
+        &self.args
+    }
+
+    /// The largest input passed to the target, longer inputs are truncated
+    #[must_use]
+    pub fn max_input_size(&self) -> usize {
+        self.shmem.len()
+    }
+
+    /// The pid of the running target, if any
+    #[must_use]
+    pub fn child_pid(&self) -> Option<u32> {
+        self.child.as_ref().map(|child| child.handle.id())
+    }
+
+    /// Spawns the target and waits for its [`HARNESS_HELLO`]
+    fn spawn(&mut self) -> Result<(), Error> {
+        let mut st_pipe = Pipe::new()?;
+        let ctl_pipe = Pipe::new()?;
+        let (st_read, st_write) = (st_pipe.read_end().unwrap(), st_pipe.write_end().unwrap());
+        let (ctl_read, ctl_write) = (ctl_pipe.read_end().unwrap(), ctl_pipe.write_end().unwrap());
+
+        let mut command = Command::new(&self.program);
+        command
+            .args(&self.args)
+            .envs(self.envs.iter().map(|(k, v)| (k, v)))
+            .env(HARNESS_SHM_ENV_VAR, self.shmem.id().to_string())
+            .env(HARNESS_SHM_SIZE_ENV_VAR, format!("{}", self.shmem.len()))
+            .stdin(Stdio::null());
+        if self.debug_child {
+            command.stdout(Stdio::inherit()).stderr(Stdio::inherit());
+        } else {
+            command.stdout(Stdio::null()).stderr(Stdio::null());
+        }
+        if let Some(cwd) = &self.current_dir {
+            command.current_dir(cwd);
+        }
+        if let Some(core) = self.core {
+            command.bind(core);
+        }
+
+        let func = move || {
+            // # Safety
+            // The fds are valid, and the target must not keep the fuzzer's ends open.
+            unsafe {
+                dup2(ctl_read, HARNESS_CTL_FD).map_err(|_| io::Error::last_os_error())?;
+                dup2(st_write, HARNESS_ST_FD).map_err(|_| io::Error::last_os_error())?;
+                libc::close(st_read);
+                libc::close(st_write);
+                libc::close(ctl_read);
+                libc::close(ctl_write);
+            }
+            Ok(())
+        };
+        // # Safety
+        // The closure only calls async-signal-safe functions.
+        let handle = unsafe { command.pre_exec(func) }
+            .spawn()
+            .map_err(|err| Error::illegal_state(format!("Could not spawn the harness: {err}")))?;
+
+        // The fuzzer keeps the read end of the control pipe, to tell whether the target took an input before it
+        // exited. The write end of the status pipe is unnecessary.
+        st_pipe.close_write_end();
+
+        let mut child = HarnessChild {
+            handle,
+            st_pipe,
+            ctl_pipe,
+        };
+        match child.read_st_timed(&TimeSpec::from_duration(HARNESS_STARTUP_TIMEOUT))? {
+            HarnessReply::Message(HARNESS_HELLO) => {
+                self.child = Some(child);
+                Ok(())
+            }
+            HarnessReply::Message(msg) => {
+                child.kill(self.kill_signal);
+                Err(Error::illegal_state(format!(
+                    "The harness sent {msg:?} instead of the hello {HARNESS_HELLO:?}"
+                )))
+            }
+            HarnessReply::Exited | HarnessReply::TimedOut => {
+                child.kill(self.kill_signal);
+                Err(Error::illegal_state(
+                    "The harness did not start up, does it use a harness protocol client?",
+                ))
+            }
+        }
+    }
+
+    /// Runs an input in the target, spawning it if it is not running
+    fn execute_input(&mut self, input: &[u8]) -> Result<ExitKind, Error> {
+        let len = input.len().min(self.shmem.len());
+        self.shmem.as_slice_mut()[..len].copy_from_slice(&input[..len]);
+        let msg = u32::try_from(len).unwrap().to_ne_bytes();
+
+        if let Some(exit_kind) = self.send_input(msg)? {
+            return Ok(exit_kind);
+        }
+        // The target exited after the last input, the input has to run in a new one
+        if let Some(exit_kind) = self.send_input(msg)? {
+            return Ok(exit_kind);
+        }
+        Err(Error::illegal_state(
+            "The harness exited right after starting up, without running the input",
+        ))
+    }
+
+    /// Passes the input of length `msg` to the target, spawning it if it is not running, and waits for its status.
+    /// Returns `None` if the target exited without taking the input.
+    fn send_input(&mut self, msg: [u8; 4]) -> Result<Option<ExitKind>, Error> {
+        if self.child.is_none() {
+            self.spawn()?;
+        }
+        let child = self.child.as_mut().unwrap();
+        child.ctl_pipe.write_all(&msg)?;
+
+        match child.read_st_timed(&self.timeout)? {
+            HarnessReply::Message(status) => {
+                if u32::from_ne_bytes(status) == HARNESS_STATUS_OK {
+                    Ok(Some(ExitKind::Ok))
+                } else {
+                    Ok(Some(ExitKind::Crash))
+                }
+            }
+            HarnessReply::TimedOut => {
+                self.child.take().unwrap().kill(self.kill_signal);
+                Ok(Some(ExitKind::Timeout))
+            }
+            HarnessReply::Exited => {
+                let status = child.handle.wait()?;
+                let took_input = !child.has_unread_ctl()?;
+                self.child = None;
+                if !took_input {
+                    return Ok(None);
+                }
+                Ok(Some(match (status.signal(), status.code()) {
+                    // for reference: https://www.man7.org/linux/man-pages/man7/signal.7.html
+                    (Some(9), _) => ExitKind::Oom,
+                    (None, Some(0)) => ExitKind::Ok,
+                    _ => ExitKind::Crash,
+                }))
+            }
+        }
+    }
+}
+
+impl<I, OT, S> Drop for HarnessExecutor<I, OT, S> {
+    fn drop(&mut self) {
+        if let Some(mut child) = self.child.take() {
+            child.kill(self.kill_signal);
+        }
+    }
+}
+
+impl<EM, I, OT, S, Z> Executor<EM, I, S, Z> for HarnessExecutor<I, OT, S>
+where
+    OT: ObserversTuple<I, S>,
+    S: HasExecutions,
+    Z: ToTargetBytes<I>,
+{
+    fn run_target(
+        &mut self,
+        fuzzer: &mut Z,
+        state: &mut S,
+        _mgr: &mut EM,
+        input: &I,
+    ) -> Result<ExitKind, Error> {
+        let bytes = fuzzer.to_target_bytes(input);
+        self.observers_mut().pre_exec_child_all(state, input)?;
+        *state.executions_mut() += 1;
+        let exit_kind = self.execute_input(&bytes)?;
+        self.observers_mut()
+            .post_exec_child_all(state, input, &exit_kind)?;
+        Ok(exit_kind)
+    }
+}
+
+impl<I, OT, S> HasTimeout for HarnessExecutor<I, OT, S> {
+    #[inline]
+    fn timeout(&self) -> Duration {
+        self.timeout.into()
+    }
+
+    #[inline]
+    fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = TimeSpec::from_duration(timeout);
+    }
+}
+
+impl<I, OT, S> HasObservers for HarnessExecutor<I, OT, S>
+where
+    OT: ObserversTuple<I, S>,
+{
+    type Observers = OT;
+
+    #[inline]
+    fn observers(&self) -> RefIndexable<&Self::Observers, Self::Observers> {
+        RefIndexable::from(&self.observers)
+    }
+
+    #[inline]
+    fn observers_mut(&mut self) -> RefIndexable<&mut Self::Observers, Self::Observers> {
+        RefIndexable::from(&mut self.observers)
+    }
+}
+
+/// The builder for a [`HarnessExecutor`]
+#[derive(Debug, Clone)]
+pub struct HarnessExecutorBuilder {
+    target_inner: StdTargetArgsInner,
+    child_env_inner: StdChildArgsInner,
+    max_input_size: usize,
+    kill_signal: Signal,
+}
+
+impl StdTargetArgs for HarnessExecutorBuilder {
+    fn inner(&self) -> &StdTargetArgsInner {
+        &self.target_inner
+    }
+
+    fn inner_mut(&mut self) -> &mut StdTargetArgsInner {
+        &mut self.target_inner
+    }
+}
+
+impl StdChildArgs for HarnessExecutorBuilder {
+    fn inner(&self) -> &StdChildArgsInner {
+        &self.child_env_inner
+    }
+
+    fn inner_mut(&mut self) -> &mut StdChildArgsInner {
+        &mut self.child_env_inner
+    }
+}
+
+impl Default for HarnessExecutorBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HarnessExecutorBuilder {
+    /// Creates a new [`HarnessExecutorBuilder`]
+    #[must_use]
+    fn new() -> Self {
+        Self {
+            target_inner: StdTargetArgsInner::default(),
+            child_env_inner: StdChildArgsInner::default(),
+            max_input_size: HARNESS_MAX_INPUT_SIZE_DEFAULT,
+            kill_signal: KILL_SIGNAL_DEFAULT,
+        }
+    }
+
+    /// Sets the largest input passed to the target, longer inputs are truncated
+    #[must_use]
+    pub fn max_input_size(mut self, size: usize) -> Self {
+        self.max_input_size = size;
+        self
+    }
+
+    /// Sets the signal to kill the target with, [`Signal::SIGKILL`] by default
+    #[must_use]
+    pub fn kill_signal(mut self, kill_signal: Signal) -> Self {
+        self.kill_signal = kill_signal;
+        self
+    }
+
+    /// Builds the [`HarnessExecutor`]. The target is spawned on the first execution.
+    pub fn build<I, OT, S>(&self, observers: OT) -> Result<HarnessExecutor<I, OT, S>, Error> {
+        let Some(program) = &self.target_inner.program else {
+            return Err(Error::illegal_argument(
+                "HarnessExecutor::builder: no program set!",
+            ));
+        };
+        if !matches!(
+            self.target_inner.input_location,
+            InputLocation::StdIn { input_file: None }
+        ) {
+            return Err(Error::illegal_argument(
+                "HarnessExecutor passes the input in shared memory, the input location is not supported",
+            ));
+        }
+        if self.child_env_inner.stdout_observer.is_some()
+            || self.child_env_inner.stderr_observer.is_some()
+        {
+            return Err(Error::illegal_argument(
+                "StdOut and StdError observers are not supported by the HarnessExecutor",
+            ));
+        }
+        if self.max_input_size == 0 || u32::try_from(self.max_input_size).is_err() {
+            return Err(Error::illegal_argument(format!(
+                "Invalid max input size {}",
+                self.max_input_size
+            )));
+        }
+        // The shared memory has to be `shmat`-able by C targets
+        let shmem = UnixShMemProvider::new()?.new_shmem(self.max_input_size)?;
+
+        Ok(HarnessExecutor {
+            program: program.clone(),
+            args: self.target_inner.arguments.clone(),
+            envs: self.target_inner.envs.clone(),
+            current_dir: self.child_env_inner.current_directory.clone(),
+            debug_child: self.child_env_inner.debug_child,
+            core: self.child_env_inner.core,
+            shmem,
+            child: None,
+            timeout: TimeSpec::from_duration(self.child_env_inner.timeout),
+            kill_signal: self.kill_signal,
+            observers,
+            phantom: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+#[cfg(target_os = "linux")]
+mod tests {
+    use core::time::Duration;
+
+    use libafl_bolts::StdTargetArgs;
+
+    use super::HarnessExecutor;
+    use crate::{
+        events::NopEventManager,
+        executors::{Executor, ExitKind, StdChildArgs},
+        fuzzer::NopFuzzer,
+        inputs::BytesInput,
+        state::NopState,
+    };
+
+    /// A harness in bash, which can not read the shared memory: it crashes, hangs, or exits after the input as the flag
+    /// file says
+    const HARNESS: &str = r#"
+printf 'LAFL' >&197
+while [ "$(dd bs=4 count=1 <&196 2>/dev/null | wc -c)" -eq 4 ]; do
+    flag="$(head -c 1 "$FLAG" 2>/dev/null)"
+    [ "$flag" = "c" ] && kill -SEGV $$
+    [ "$flag" = "s" ] && sleep 10
+    printf '\0\0\0\0' >&197
+    [ "$flag" = "x" ] && exit 3
+done
+"#;
+
+    #[test]
+    fn test_harness_executor() {
+        let flag = std::env::temp_dir().join(format!("libafl_harness_{}", std::process::id()));
+        let mut executor = HarnessExecutor::builder()
+            .program("bash")
+            .arg("-c")
+            .arg(HARNESS)
+            .env("FLAG", &flag)
+            .timeout(Duration::from_millis(500))
+            .build::<BytesInput, (), NopState<BytesInput>>(())
+            .unwrap();
+        let mut state = NopState::new();
+        let mut mgr = NopEventManager::default();
+        let mut fuzzer = NopFuzzer::new();
+        let input = BytesInput::new(b"a".to_vec());
+
+        let mut run = |executor: &mut HarnessExecutor<_, _, _>| {
+            executor
+                .run_target(&mut fuzzer, &mut state, &mut mgr, &input)
+                .unwrap()
+        };
+        let mut run_flagged = |executor: &mut HarnessExecutor<_, _, _>, flag_value: &str| {
+            std::fs::write(&flag, flag_value).unwrap();
+            let exit_kind = run(executor);
+            std::fs::remove_file(&flag).unwrap();
+            exit_kind
+        };
+
+        assert_eq!(run_flagged(&mut executor, "-"), ExitKind::Ok);
+        let pid = executor.child_pid().unwrap();
+        assert_eq!(run_flagged(&mut executor, "-"), ExitKind::Ok);
+        assert_eq!(executor.child_pid(), Some(pid));
+        assert_eq!(run_flagged(&mut executor, "c"), ExitKind::Crash);
+        assert_eq!(executor.child_pid(), None);
+        assert_eq!(run_flagged(&mut executor, "-"), ExitKind::Ok);
+        assert_ne!(executor.child_pid(), Some(pid));
+        assert_eq!(run_flagged(&mut executor, "s"), ExitKind::Timeout);
+        assert_eq!(run_flagged(&mut executor, "-"), ExitKind::Ok);
+
+        // An exit between two inputs is no verdict on the next one, which runs in a new target
+        assert_eq!(run_flagged(&mut executor, "x"), ExitKind::Ok);
+        let pid = executor.child_pid().unwrap();
+        assert_eq!(run_flagged(&mut executor, "-"), ExitKind::Ok);
+        assert_ne!(executor.child_pid(), Some(pid));
+        assert_eq!(run_flagged(&mut executor, "-"), ExitKind::Ok);
+    }
+}
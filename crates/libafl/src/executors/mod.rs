@@ -11,6 +11,8 @@ pub use command::CommandExecutor;
 pub use differential::DiffExecutor;
 #[cfg(all(feature = "std", feature = "fork", unix))]
 pub use forkserver::{Forkserver, ForkserverExecutor};
+#[cfg(all(feature = "std", feature = "fork", unix))]
+pub use harness::HarnessExecutor;
 pub use inprocess::InProcessExecutor;
 #[cfg(all(feature = "std", feature = "fork", unix))]
 pub use inprocess_fork::InProcessForkExecutor;
@@ -19,6 +21,8 @@ use libafl_bolts::os::unix_signals::Signal;
 use libafl_bolts::tuples::RefIndexable;
 #[cfg(feature = "std")]
 use libafl_bolts::{core_affinity::CoreId, tuples::Handle};
+#[cfg(all(feature = "std", feature = "fork", unix))]
+pub use network::NetworkExecutor;
 use serde::{Deserialize, Serialize};
 pub use shadow::ShadowExecutor;
 pub use with_observers::WithObservers;
@@ -33,7 +37,11 @@ pub mod command;
 pub mod differential;
 #[cfg(all(feature = "std", feature = "fork", unix))]
 pub mod forkserver;
+#[cfg(all(feature = "std", feature = "fork", unix))]
+pub mod harness;
 pub mod inprocess;
+#[cfg(all(feature = "std", feature = "fork", unix))]
+pub mod network;
 pub mod nop;
 /// SAND(<https://github.com/wtdcode/sand-aflpp>) implementation
 pub mod sand;
@@ -0,0 +1,875 @@
+//! The [`NetworkExecutor`] fuzzes stateful servers, sending the messages of each input over a socket.
+//!
+//! The server is spawned once and kept running, or an already running server is attached to. For each input, the
+//! executor connects to the server, sends the messages of the input one by one, as given by [`NetworkMessages`],
+//! and waits up to the message timeout for a reply after each of them.
+//!
+//! * If the server process dies during the run, or within the message timeout after it dropped the connection, the
+//!   input is a crash, judging by the exit status of the server. The server is spawned again for the next input.
+//! * If the server does not accept the connection in time, or the run exceeds the timeout, the input is a timeout
+//!   and the server is restarted. With [`NetworkExecutorBuilder::require_replies`], so is a missing reply.
+//! * An attached server can not be restarted: once it refuses connections, each input is reported as a crash.
+//!
+//! Datagram sockets have no connections: on Linux, a UDP server on a loopback address counts as accepting them while
+//! a socket is bound to its port, as listed in `/proc/net/udp`. Elsewhere, it is assumed to be up.
+//!
+//! Servers instrumented for AFL++ can write their coverage to the shared map of the fuzzer, set with
+//! [`NetworkExecutorBuilder::coverage_shmem`], without a forkserver.
+
+use alloc::{string::ToString, vec, vec::Vec};
+use core::{
+    fmt::{self, Debug, Formatter},
+    marker::PhantomData,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+#[cfg(target_os = "linux")]
+use std::fs;
+use std::{
+    ffi::OsString,
+    io::{self, ErrorKind, Read, Write},
+    net::{TcpStream, UdpSocket},
+    os::unix::{net::UnixStream, process::ExitStatusExt},
+    path::PathBuf,
+    process::{Child, Command, ExitStatus, Stdio},
+    thread,
+    time::Instant,
+};
+
+use libafl_bolts::{
+    InputLocation, StdTargetArgs, StdTargetArgsInner, core_affinity::CoreId, ownedref::OwnedSlice,
+    shmem::ShMem, tuples::RefIndexable,
+};
+
+use super::{HasTimeout, StdChildArgs, StdChildArgsInner};
+#[cfg(feature = "multipart_inputs")]
+use crate::inputs::{ListInput, MultipartInput};
+use crate::{
+    Error,
+    executors::{
+        Executor, ExitKind, HasObservers,
+        forkserver::{AFL_MAP_SIZE_ENV_VAR, ConfigTarget, SHM_ENV_VAR},
+    },
+    inputs::{BytesInput, HasTargetBytes},
+    observers::ObserversTuple,
+    state::HasExecutions,
+};
+
+/// The default time to wait for the reply to each message
+pub const NETWORK_MESSAGE_TIMEOUT_DEFAULT: Duration = Duration::from_millis(100);
+/// The default time to wait for a newly spawned server to accept connections
+pub const NETWORK_STARTUP_TIMEOUT_DEFAULT: Duration = Duration::from_secs(10);
+/// The size of the buffer for the replies, longer replies are truncated
+const REPLY_BUF_SIZE: usize = 64 * 1024;
+/// How long to wait between two attempts to connect to the server
+const CONNECT_RETRY_DELAY: Duration = Duration::from_millis(1);
+
+/// The address a server listens on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetworkAddress {
+    /// A TCP socket
+    Tcp(SocketAddr),
+    /// A UDP socket, each message is sent as one datagram.
+    /// Whether the server is up is only known for loopback addresses on Linux, see the [module documentation](self).
+    Udp(SocketAddr),
+    /// A Unix stream socket at the given path
+    Unix(PathBuf),
+}
+
+/// An input that consists of messages, sent to the server one after the other by the [`NetworkExecutor`]
+pub trait NetworkMessages {
+    /// The messages of this input, in order
+    fn messages(&self) -> Vec<OwnedSlice<'_, u8>>;
+}
+
+impl NetworkMessages for BytesInput {
+    fn messages(&self) -> Vec<OwnedSlice<'_, u8>> {
+        vec![self.target_bytes()]
+    }
+}
+
+#[cfg(feature = "multipart_inputs")]
+impl<I> NetworkMessages for ListInput<I>
+where
+    I: HasTargetBytes,
+{
+    fn messages(&self) -> Vec<OwnedSlice<'_, u8>> {
+        self.parts()
+            .iter()
+            .map(HasTargetBytes::target_bytes)
+            .collect()
+    }
+}
+
+#[cfg(feature = "multipart_inputs")]
+impl<I, K> NetworkMessages for MultipartInput<I, K>
+where
+    I: HasTargetBytes,
+{
+    fn messages(&self) -> Vec<OwnedSlice<'_, u8>> {
+        self.parts()
+            .iter()
+            .map(|(_, part)| part.target_bytes())
+            .collect()
+    }
+}
+
+/// A connection to the server, for one run
+enum Connection {
+    Tcp(TcpStream),
+    Udp(UdpSocket),
+    Unix(UnixStream),
+}
+
+impl Connection {
+    fn connect(address: &NetworkAddress) -> io::Result<Self> {
+        match address {
+            NetworkAddress::Tcp(addr) => Ok(Self::Tcp(TcpStream::connect(addr)?)),
+            NetworkAddress::Udp(addr) => {
+                if udp_port_bound(addr) == Some(false) {
+                    return Err(io::Error::new(
+                        ErrorKind::ConnectionRefused,
+                        "no socket is bound to the UDP port",
+                    ));
+                }
+                let local: SocketAddr = if addr.is_ipv4() {
+                    (Ipv4Addr::UNSPECIFIED, 0).into()
+                } else {
+                    (Ipv6Addr::UNSPECIFIED, 0).into()
+                };
+                let socket = UdpSocket::bind(local)?;
+                socket.connect(addr)?;
+                Ok(Self::Udp(socket))
+            }
+            NetworkAddress::Unix(path) => Ok(Self::Unix(UnixStream::connect(path)?)),
+        }
+    }
+
+    fn send(&mut self, message: &[u8]) -> io::Result<()> {
+        match self {
+            Self::Tcp(stream) => stream.write_all(message),
+            Self::Udp(socket) => socket.send(message).map(|_| ()),
+            Self::Unix(stream) => stream.write_all(message),
+        }
+    }
+
+    /// Waits up to `timeout` for a reply, returns `Ok(0)` if the server closed the connection
+    fn recv(&mut self, buf: &mut [u8], timeout: Duration) -> io::Result<usize> {
+        match self {
+            Self::Tcp(stream) => {
+                stream.set_read_timeout(Some(timeout))?;
+                stream.read(buf)
+            }
+            Self::Udp(socket) => {
+                socket.set_read_timeout(Some(timeout))?;
+                socket.recv(buf)
+            }
+            Self::Unix(stream) => {
+                stream.set_read_timeout(Some(timeout))?;
+                stream.read(buf)
+            }
+        }
+    }
+}
+
+/// Whether a socket is bound to the UDP port of `addr` on this host, from `/proc/net`.
+/// `None` if this can not be told, for other than loopback addresses.
+#[cfg(target_os = "linux")]
+fn udp_port_bound(addr: &SocketAddr) -> Option<bool> {
+    if !addr.ip().is_loopback() {
+        return None;
+    }
+    // The local address is the second column, as `0100007F:1F90`
+    let port = format!(":{:04X}", addr.port());
+    let tables = ["/proc/net/udp", "/proc/net/udp6"]
+        .iter()
+        .filter_map(|path| fs::read_to_string(path).ok())
+        .collect::<Vec<_>>();
+    if tables.is_empty() {
+        return None;
+    }
+    Some(tables.iter().any(|table| {
+        table.lines().skip(1).any(|line| {
+            line.split_whitespace()
+                .nth(1)
+                .is_some_and(|local| local.ends_with(&port))
+        })
+    }))
+}
+
+/// Whether a socket is bound to the UDP port of `addr`, which can only be told on Linux
+#[cfg(not(target_os = "linux"))]
+fn udp_port_bound(_addr: &SocketAddr) -> Option<bool> {
+    None
+}
+
+/// The outcome of sending the messages of an input
+enum Delivery {
+    /// All messages were sent
+    Done,
+    /// The server closed or reset the connection
+    Dropped,
+    /// The run took longer than the timeout, or a required reply is missing
+    TimedOut,
+}
+
+/// An [`Executor`] for servers, sending the [`NetworkMessages`] of each input over TCP, UDP, or Unix sockets,
+/// as described in the [module documentation](self).
+pub struct NetworkExecutor<I, OT, S> {
+    address: NetworkAddress,
+    program: Option<OsString>,
+    args: Vec<OsString>,
+    envs: Vec<(OsString, OsString)>,
+    current_dir: Option<PathBuf>,
+    debug_child: bool,
+    core: Option<CoreId>,
+    child: Option<Child>,
+    timeout: Duration,
+    message_timeout: Duration,
+    startup_timeout: Duration,
+    require_replies: bool,
+    replies: Vec<Vec<u8>>,
+    observers: OT,
+    phantom: PhantomData<fn() -> (I, S)>,
+}
+
+impl<I, OT, S> Debug for NetworkExecutor<I, OT, S>
+where
+    OT: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NetworkExecutor")
+            .field("address", &self.address)
+            .field("program", &self.program)
+            .field("args", &self.args)
+            .field("child", &self.child)
+            .field("timeout", &self.timeout)
+            .field("message_timeout", &self.message_timeout)
+            .field("observers", &self.observers)
+            .finish_non_exhaustive()
+    }
+}
+
+impl NetworkExecutor<(), (), ()> {
+    /// Creates a builder for a new [`NetworkExecutor`]
+    #[must_use]
+    pub fn builder() -> NetworkExecutorBuilder {
+        NetworkExecutorBuilder::new()
+    }
+}
+
+impl<I, OT, S> NetworkExecutor<I, OT, S> {
+    /// The address of the server
+    pub fn address(&self) -> &NetworkAddress {
+        &self.address
+    }
+
+    /// The server program, `None` if the executor attaches to a running server
+    pub fn program(&self) -> Option<&OsString> {
+        self.program.as_ref()
+    }
+
+    /// The pid of the spawned server, if it is running
+    #[must_use]
+    pub fn child_pid(&self) -> Option<u32> {
+        self.child.as_ref().map(Child::id)
+    }
+
+    /// The replies of the server to the messages of the last run, empty for messages it did not reply to
+    #[must_use]
+    pub fn last_replies(&self) -> &[Vec<u8>] {
+        &self.replies
+    }
+
+    /// Connects to the server, retrying until `deadline`
+    fn connect_until(&self, deadline: Instant) -> io::Result<Connection> {
+        loop {
+            match Connection::connect(&self.address) {
+                Ok(connection) => return Ok(connection),
+                Err(err) if Instant::now() >= deadline => return Err(err),
+                Err(_) => thread::sleep(CONNECT_RETRY_DELAY),
+            }
+        }
+    }
+
+    /// Spawns the server and waits until it accepts connections
+    fn spawn(&mut self) -> Result<(), Error> {
+        let Some(program) = &self.program else {
+            return Ok(());
+        };
+        let mut command = Command::new(program);
+        command
+            .args(&self.args)
+            .envs(self.envs.iter().map(|(k, v)| (k, v)))
+            .stdin(Stdio::null());
+        if self.debug_child {
+            command.stdout(Stdio::inherit()).stderr(Stdio::inherit());
+        } else {
+            command.stdout(Stdio::null()).stderr(Stdio::null());
+        }
+        if let Some(cwd) = &self.current_dir {
+            command.current_dir(cwd);
+        }
+        if let Some(core) = self.core {
+            command.bind(core);
+        }
+        let mut child = command
+            .spawn()
+            .map_err(|err| Error::illegal_state(format!("Could not spawn the server: {err}")))?;
+
+        let deadline = Instant::now() + self.startup_timeout;
+        loop {
+            if Connection::connect(&self.address).is_ok() {
+                break;
+            }
+            if let Some(status) = child.try_wait()? {
+                return Err(Error::illegal_state(format!(
+                    "The server exited with {status} before accepting connections on {:?}",
+                    self.address
+                )));
+            }
+            if Instant::now() >= deadline {
+                drop(child.kill());
+                drop(child.wait());
+                return Err(Error::illegal_state(format!(
+                    "The server did not accept connections on {:?} in time",
+                    self.address
+                )));
+            }
+            thread::sleep(CONNECT_RETRY_DELAY);
+        }
+        self.child = Some(child);
+        Ok(())
+    }
+
+    /// Kills the spawned server, if any
+    fn kill(&mut self) {
+        if let Some(mut child) = self.child.take() {
+            drop(child.kill());
+            drop(child.wait());
+        }
+    }
+
+    /// Waits up to `timeout` for the spawned server to exit, returns its status if it did
+    fn wait_exit(&mut self, timeout: Duration) -> Result<Option<ExitStatus>, Error> {
+        let Some(child) = &mut self.child else {
+            return Ok(None);
+        };
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(status) = child.try_wait()? {
+                self.child = None;
+                return Ok(Some(status));
+            }
+            if Instant::now() >= deadline {
+                return Ok(None);
+            }
+            thread::sleep(CONNECT_RETRY_DELAY);
+        }
+    }
+
+    /// Sends the messages over `connection`, collecting the replies
+    fn deliver(
+        &mut self,
+        connection: &mut Connection,
+        messages: &[OwnedSlice<'_, u8>],
+        deadline: Instant,
+    ) -> Delivery {
+        let mut buf = vec![0; REPLY_BUF_SIZE];
+        for message in messages {
+            let now = Instant::now();
+            if now >= deadline {
+                return Delivery::TimedOut;
+            }
+            if connection.send(message).is_err() {
+                return Delivery::Dropped;
+            }
+            if self.message_timeout.is_zero() {
+                continue;
+            }
+            match connection.recv(
+                &mut buf,
+                self.message_timeout.min(deadline.duration_since(now)),
+            ) {
+                Ok(0) => return Delivery::Dropped,
+                Ok(len) => self.replies.push(buf[..len].to_vec()),
+                Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    if self.require_replies {
+                        return Delivery::TimedOut;
+                    }
+                    self.replies.push(Vec::new());
+                }
+                Err(_) => return Delivery::Dropped,
+            }
+        }
+        Delivery::Done
+    }
+
+    /// Runs the messages of an input on the server, spawning it if it is not running
+    fn execute_messages(&mut self, messages: &[OwnedSlice<'_, u8>]) -> Result<ExitKind, Error> {
+        self.replies.clear();
+        if self.program.is_some() {
+            if let Some(child) = &mut self.child {
+                if child.try_wait()?.is_some() {
+                    // The server exited after the last input, start it again
+                    self.child = None;
+                }
+            }
+            if self.child.is_none() {
+                self.spawn()?;
+            }
+        }
+
+        let deadline = Instant::now() + self.timeout;
+        let Ok(mut connection) = self.connect_until(deadline) else {
+            if self.program.is_none() {
+                return Ok(ExitKind::Crash);
+            }
+            if let Some(status) = self.wait_exit(Duration::ZERO)? {
+                return Ok(exit_kind_of(status));
+            }
+            self.kill();
+            return Ok(ExitKind::Timeout);
+        };
+
+        let delivery = self.deliver(&mut connection, messages, deadline);
+        drop(connection);
+        match delivery {
+            Delivery::TimedOut => {
+                if let Some(status) = self.wait_exit(Duration::ZERO)? {
+                    return Ok(exit_kind_of(status));
+                }
+                self.kill();
+                Ok(ExitKind::Timeout)
+            }
+            // A dropped connection may be a crash: give the server a moment to die
+            Delivery::Dropped => Ok(self
+                .wait_exit(self.message_timeout)?
+                .map_or(ExitKind::Ok, exit_kind_of)),
+            Delivery::Done => Ok(self
+                .wait_exit(Duration::ZERO)?
+                .map_or(ExitKind::Ok, exit_kind_of)),
+        }
+    }
+}
+
+/// The [`ExitKind`] of a server that exited during a run
+fn exit_kind_of(status: ExitStatus) -> ExitKind {
+    match (status.signal(), status.code()) {
+        // for reference: https://www.man7.org/linux/man-pages/man7/signal.7.html
+        (Some(9), _) => ExitKind::Oom,
+        (None, Some(0)) => ExitKind::Ok,
+        _ => ExitKind::Crash,
+    }
+}
+
+impl<I, OT, S> Drop for NetworkExecutor<I, OT, S> {
+    fn drop(&mut self) {
+        self.kill();
+    }
+}
+
+impl<EM, I, OT, S, Z> Executor<EM, I, S, Z> for NetworkExecutor<I, OT, S>
+where
+    I: NetworkMessages,
+    OT: ObserversTuple<I, S>,
+    S: HasExecutions,
+{
+    fn run_target(
+        &mut self,
+        _fuzzer: &mut Z,
+        state: &mut S,
+        _mgr: &mut EM,
+        input: &I,
+    ) -> Result<ExitKind, Error> {
+        let messages = input.messages();
+        self.observers_mut().pre_exec_child_all(state, input)?;
+        *state.executions_mut() += 1;
+        let exit_kind = self.execute_messages(&messages)?;
+        self.observers_mut()
+            .post_exec_child_all(state, input, &exit_kind)?;
+        Ok(exit_kind)
+    }
+}
+
+impl<I, OT, S> HasTimeout for NetworkExecutor<I, OT, S> {
+    #[inline]
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    #[inline]
+    fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+}
+
+impl<I, OT, S> HasObservers for NetworkExecutor<I, OT, S>
+where
+    OT: ObserversTuple<I, S>,
+{
+    type Observers = OT;
+
+    #[inline]
+    fn observers(&self) -> RefIndexable<&Self::Observers, Self::Observers> {
+        RefIndexable::from(&self.observers)
+    }
+
+    #[inline]
+    fn observers_mut(&mut self) -> RefIndexable<&mut Self::Observers, Self::Observers> {
+        RefIndexable::from(&mut self.observers)
+    }
+}
+
+/// The builder for a [`NetworkExecutor`]
+#[derive(Debug, Clone)]
+pub struct NetworkExecutorBuilder {
+    target_inner: StdTargetArgsInner,
+    child_env_inner: StdChildArgsInner,
+    address: Option<NetworkAddress>,
+    message_timeout: Duration,
+    startup_timeout: Duration,
+    require_replies: bool,
+}
+
+impl StdTargetArgs for NetworkExecutorBuilder {
+    fn inner(&self) -> &StdTargetArgsInner {
+        &self.target_inner
+    }
+
+    fn inner_mut(&mut self) -> &mut StdTargetArgsInner {
+        &mut self.target_inner
+    }
+}
+
+impl StdChildArgs for NetworkExecutorBuilder {
+    fn inner(&self) -> &StdChildArgsInner {
+        &self.child_env_inner
+    }
+
+    fn inner_mut(&mut self) -> &mut StdChildArgsInner {
+        &mut self.child_env_inner
+    }
+}
+
+impl Default for NetworkExecutorBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NetworkExecutorBuilder {
+    /// Creates a new [`NetworkExecutorBuilder`]
+    #[must_use]
+    fn new() -> Self {
+        Self {
+            target_inner: StdTargetArgsInner::default(),
+            child_env_inner: StdChildArgsInner::default(),
+            address: None,
+            message_timeout: NETWORK_MESSAGE_TIMEOUT_DEFAULT,
+            startup_timeout: NETWORK_STARTUP_TIMEOUT_DEFAULT,
+            require_replies: false,
+        }
+    }
+
+    /// Sets the address the server listens on
+    #[must_use]
+    pub fn address(mut self, address: NetworkAddress) -> Self {
+        self.address = Some(address);
+        self
+    }
+
+    /// Sets how long to wait for the reply to each message, and for a spawned server to exit after it dropped the
+    /// connection. No replies are read if it is zero.
+    #[must_use]
+    pub fn message_timeout(mut self, timeout: Duration) -> Self {
+        self.message_timeout = timeout;
+        self
+    }
+
+    /// Sets whether the server has to reply to each message within the message timeout.
+    /// A missing reply is a timeout then, and the server is restarted.
+    #[must_use]
+    pub fn require_replies(mut self, require_replies: bool) -> Self {
+        self.require_replies = require_replies;
+        self
+    }
+
+    /// Sets how long to wait for a newly spawned server to accept connections
+    #[must_use]
+    pub fn startup_timeout(mut self, timeout: Duration) -> Self {
+        self.startup_timeout = timeout;
+        self
+    }
+
+    /// Passes the shared coverage map to the server, as for a [`crate::executors::ForkserverExecutor`],
+    /// for servers instrumented for AFL++.
+    #[must_use]
+    pub fn coverage_shmem<SHM>(self, shmem: &SHM) -> Self
+    where
+        SHM: ShMem,
+    {
+        self.env(SHM_ENV_VAR, shmem.id().to_string())
+            .env(AFL_MAP_SIZE_ENV_VAR, format!("{}", shmem.len()))
+    }
+
+    /// Builds the [`NetworkExecutor`]. Without a program, it attaches to a server already running.
+    /// The server is spawned on the first execution otherwise.
+    pub fn build<I, OT, S>(&self, observers: OT) -> Result<NetworkExecutor<I, OT, S>, Error> {
+        let Some(address) = &self.address else {
+            return Err(Error::illegal_argument(
+                "NetworkExecutor::builder: no address set!",
+            ));
+        };
+        if !matches!(
+            self.target_inner.input_location,
+            InputLocation::StdIn { input_file: None }
+        ) {
+            return Err(Error::illegal_argument(
+                "NetworkExecutor sends the input over the network, the input location is not supported",
+            ));
+        }
+        if self.child_env_inner.stdout_observer.is_some()
+            || self.child_env_inner.stderr_observer.is_some()
+        {
+            return Err(Error::illegal_argument(
+                "StdOut and StdError observers are not supported by the NetworkExecutor",
+            ));
+        }
+
+        Ok(NetworkExecutor {
+            address: address.clone(),
+            program: self.target_inner.program.clone(),
+            args: self.target_inner.arguments.clone(),
+            envs: self.target_inner.envs.clone(),
+            current_dir: self.child_env_inner.current_directory.clone(),
+            debug_child: self.child_env_inner.debug_child,
+            core: self.child_env_inner.core,
+            child: None,
+            timeout: self.child_env_inner.timeout,
+            message_timeout: self.message_timeout,
+            startup_timeout: self.startup_timeout,
+            require_replies: self.require_replies,
+            replies: Vec::new(),
+            observers,
+            phantom: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+    use std::{
+        io::{Read, Write},
+        net::{TcpListener, UdpSocket},
+        thread,
+    };
+
+    use libafl_bolts::StdTargetArgs;
+
+    use super::{NetworkAddress, NetworkExecutor};
+    use crate::{
+        events::NopEventManager,
+        executors::{Executor, ExitKind, StdChildArgs},
+        fuzzer::NopFuzzer,
+        inputs::BytesInput,
+        state::NopState,
+    };
+
+    #[test]
+    fn test_network_executor_tcp() {
+        // An echo server stand-in, serving two connections
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            for _ in 0..2 {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut buf = [0; 64];
+                while let Ok(len @ 1..) = stream.read(&mut buf) {
+                    stream.write_all(&buf[..len]).unwrap();
+                }
+            }
+        });
+
+        let mut executor = NetworkExecutor::builder()
+            .address(NetworkAddress::Tcp(addr))
+            .message_timeout(Duration::from_secs(1))
+            .timeout(Duration::from_millis(200))
+            .build::<BytesInput, (), NopState<BytesInput>>(())
+            .unwrap();
+        let mut state = NopState::new();
+        let mut mgr = NopEventManager::default();
+        let mut fuzzer = NopFuzzer::new();
+
+        let input = BytesInput::new(b"hello".to_vec());
+        for _ in 0..2 {
+            assert_eq!(
+                executor
+                    .run_target(&mut fuzzer, &mut state, &mut mgr, &input)
+                    .unwrap(),
+                ExitKind::Ok
+            );
+            assert_eq!(executor.last_replies(), &[b"hello".to_vec()]);
+        }
+        server.join().unwrap();
+
+        // The attached server is gone
+        assert_eq!(
+            executor
+                .run_target(&mut fuzzer, &mut state, &mut mgr, &input)
+                .unwrap(),
+            ExitKind::Crash
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "multipart_inputs")]
+    fn test_network_executor_udp_messages() {
+        use std::net::UdpSocket;
+
+        use crate::inputs::ListInput;
+
+        // An echo server stand-in, answering two datagrams
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let mut buf = [0; 64];
+            for _ in 0..2 {
+                let (len, peer) = socket.recv_from(&mut buf).unwrap();
+                socket.send_to(&buf[..len], peer).unwrap();
+            }
+        });
+
+        let mut executor = NetworkExecutor::builder()
+            .address(NetworkAddress::Udp(addr))
+            .message_timeout(Duration::from_secs(1))
+            .build::<ListInput<BytesInput>, (), NopState<ListInput<BytesInput>>>(())
+            .unwrap();
+        let input = ListInput::new(vec![
+            BytesInput::new(b"hello".to_vec()),
+            BytesInput::new(b"world".to_vec()),
+        ]);
+        assert_eq!(
+            executor
+                .run_target(
+                    &mut NopFuzzer::new(),
+                    &mut NopState::new(),
+                    &mut NopEventManager::default(),
+                    &input
+                )
+                .unwrap(),
+            ExitKind::Ok
+        );
+        assert_eq!(
+            executor.last_replies(),
+            &[b"hello".to_vec(), b"world".to_vec()]
+        );
+        server.join().unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_network_executor_spawned() {
+        // The socket stays silent, the spawned server only lives for a while
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        let mut state = NopState::new();
+        let mut mgr = NopEventManager::default();
+        let mut fuzzer = NopFuzzer::new();
+        let input = BytesInput::new(b"hello".to_vec());
+
+        // A crash right after the last message is credited to the input
+        let mut executor = NetworkExecutor::builder()
+            .address(NetworkAddress::Udp(addr))
+            .program("sh")
+            .arg("-c")
+            .arg("sleep 0.05; kill -SEGV $$")
+            .message_timeout(Duration::from_millis(500))
+            .timeout(Duration::from_secs(5))
+            .build::<BytesInput, (), NopState<BytesInput>>(())
+            .unwrap();
+        assert_eq!(
+            executor
+                .run_target(&mut fuzzer, &mut state, &mut mgr, &input)
+                .unwrap(),
+            ExitKind::Crash
+        );
+        assert!(executor.child_pid().is_none());
+
+        // A missing reply is a timeout, and the server is restarted
+        let mut executor = NetworkExecutor::builder()
+            .address(NetworkAddress::Udp(addr))
+            .program("sleep")
+            .arg("10")
+            .message_timeout(Duration::from_millis(50))
+            .require_replies(true)
+            .timeout(Duration::from_secs(5))
+            .build::<BytesInput, (), NopState<BytesInput>>(())
+            .unwrap();
+        for _ in 0..2 {
+            assert_eq!(
+                executor
+                    .run_target(&mut fuzzer, &mut state, &mut mgr, &input)
+                    .unwrap(),
+                ExitKind::Timeout
+            );
+            assert!(executor.child_pid().is_none());
+        }
+        drop(socket);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    #[cfg_attr(miri, ignore)]
+    fn test_network_executor_udp_probe() {
+        use std::time::Instant;
+
+        // An echo server stand-in, answering one datagram
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let mut buf = [0; 64];
+            let (len, peer) = socket.recv_from(&mut buf).unwrap();
+            socket.send_to(&buf[..len], peer).unwrap();
+        });
+        let mut state = NopState::new();
+        let mut mgr = NopEventManager::default();
+        let mut fuzzer = NopFuzzer::new();
+        let input = BytesInput::new(b"hello".to_vec());
+
+        // The spawned server keeps running after a reply, without waiting the message timeout for it to exit
+        let mut executor = NetworkExecutor::builder()
+            .address(NetworkAddress::Udp(addr))
+            .program("sleep")
+            .arg("10")
+            .message_timeout(Duration::from_secs(2))
+            .timeout(Duration::from_secs(5))
+            .build::<BytesInput, (), NopState<BytesInput>>(())
+            .unwrap();
+        let start = Instant::now();
+        assert_eq!(
+            executor
+                .run_target(&mut fuzzer, &mut state, &mut mgr, &input)
+                .unwrap(),
+            ExitKind::Ok
+        );
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(executor.last_replies(), &[b"hello".to_vec()]);
+        assert!(executor.child_pid().is_some());
+        server.join().unwrap();
+
+        // The attached server is gone
+        let mut executor = NetworkExecutor::builder()
+            .address(NetworkAddress::Udp(addr))
+            .timeout(Duration::from_millis(200))
+            .build::<BytesInput, (), NopState<BytesInput>>(())
+            .unwrap();
+        assert_eq!(
+            executor
+                .run_target(&mut fuzzer, &mut state, &mut mgr, &input)
+                .unwrap(),
+            ExitKind::Crash
+        );
+    }
+}
@@ -0,0 +1,169 @@
+//! The [`BranchHitsFeedback`] counts how many executed inputs hit each branch, to find the rare branches as in
+//! `FairFuzz`, for the [`crate::stages::RareBranchMaskStage`].
+
+use alloc::{borrow::Cow, vec::Vec};
+use core::marker::PhantomData;
+
+use libafl_bolts::{
+    AsIter, Named,
+    tuples::{Handle, Handled, MatchName, MatchNameRef},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Error, HasMetadata,
+    executors::ExitKind,
+    feedbacks::{Feedback, HasObserverHandle, StateInitializer},
+    observers::MapObserver,
+};
+
+/// The number of executed inputs that hit each entry of the coverage map
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BranchHitsMetadata {
+    /// The hit counts, indexed like the coverage map
+    pub hits: Vec<u64>,
+}
+
+libafl_bolts::impl_serdeany!(BranchHitsMetadata);
+
+impl BranchHitsMetadata {
+    /// The number of inputs that hit the entry at `idx`
+    #[must_use]
+    pub fn hits(&self, idx: usize) -> u64 {
+        self.hits.get(idx).copied().unwrap_or(0)
+    }
+
+    /// Branches hit at most this often are rare: the smallest power of two not below the lowest hit count of the
+    /// branches hit so far. Returns `None` if no branch was hit yet.
+    #[must_use]
+    pub fn rarity_cutoff(&self) -> Option<u64> {
+        self.hits
+            .iter()
+            .copied()
+            .filter(|hits| *hits > 0)
+            .min()
+            .map(u64::next_power_of_two)
+    }
+
+    /// The rarest of the branches at `indices` if it is rare, e.g., for the branches a testcase hits
+    #[must_use]
+    pub fn rarest<It>(&self, indices: It) -> Option<usize>
+    where
+        It: IntoIterator<Item = usize>,
+    {
+        let cutoff = self.rarity_cutoff()?;
+        indices
+            .into_iter()
+            .map(|idx| (self.hits(idx), idx))
+            .filter(|(hits, _)| *hits > 0 && *hits <= cutoff)
+            .min()
+            .map(|(_, idx)| idx)
+    }
+}
+
+/// A feedback counting the executed inputs hitting each entry of a coverage map in the [`BranchHitsMetadata`].
+#[derive(Debug, Clone)]
+pub struct BranchHitsFeedback<C, O> {
+    name: Cow<'static, str>,
+    map_ref: Handle<C>,
+    phantom: PhantomData<fn() -> O>,
+}
+
+impl<C, O> BranchHitsFeedback<C, O>
+where
+    C: Named,
+{
+    /// Creates a new [`BranchHitsFeedback`] counting the hits in the map of `map_observer`
+    #[must_use]
+    pub fn new(map_observer: &C) -> Self {
+        Self {
+            name: Cow::Borrowed("BranchHitsFeedback"),
+            map_ref: map_observer.handle(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<C, O> Named for BranchHitsFeedback<C, O> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<C, O> HasObserverHandle for BranchHitsFeedback<C, O> {
+    type Observer = C;
+
+    #[inline]
+    fn observer_handle(&self) -> &Handle<C> {
+        &self.map_ref
+    }
+}
+
+impl<C, O, S> StateInitializer<S> for BranchHitsFeedback<C, O>
+where
+    S: HasMetadata,
+{
+    fn init_state(&mut self, state: &mut S) -> Result<(), Error> {
+        state.metadata_or_insert_with(BranchHitsMetadata::default);
+        Ok(())
+    }
+}
+
+impl<C, EM, I, O, OT, S> Feedback<EM, I, OT, S> for BranchHitsFeedback<C, O>
+where
+    C: AsRef<O>,
+    O: MapObserver + for<'it> AsIter<'it, Item = O::Entry>,
+    OT: MatchName,
+    S: HasMetadata,
+{
+    fn is_interesting(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        _input: &I,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        let observer = observers
+            .get(&self.map_ref)
+            .ok_or_else(|| Error::key_not_found("MapObserver not found"))?
+            .as_ref();
+        let initial = observer.initial();
+        let meta = state.metadata_or_insert_with(BranchHitsMetadata::default);
+        if meta.hits.len() < observer.len() {
+            meta.hits.resize(observer.len(), 0);
+        }
+        for (idx, value) in observer.as_iter().enumerate() {
+            if *value != initial {
+                meta.hits[idx] = meta.hits[idx].saturating_add(1);
+            }
+        }
+        Ok(false)
+    }
+
+    #[cfg(feature = "track_hit_feedbacks")]
+    fn last_result(&self) -> Result<bool, Error> {
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BranchHitsMetadata;
+
+    #[test]
+    fn test_rarity_cutoff() {
+        let meta = BranchHitsMetadata {
+            hits: vec![0, 100, 3, 5, 4, 9],
+        };
+        // The lowest hit count is 3, so branches hit up to 4 times are rare
+        assert_eq!(meta.rarity_cutoff(), Some(4));
+        assert_eq!(meta.rarest([1, 3, 4]), Some(4));
+        assert_eq!(meta.rarest([0, 1, 5]), None);
+        assert_eq!(BranchHitsMetadata::default().rarity_cutoff(), None);
+    }
+}
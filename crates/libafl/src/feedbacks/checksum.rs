@@ -0,0 +1,132 @@
+//! The [`ChecksumFixupFeedback`] recomputes the checksums of new solutions before they are stored, so that they
+//! reproduce against a target that verifies them.
+
+use alloc::{borrow::Cow, vec::Vec};
+use core::{marker::PhantomData, ops::Range};
+
+use libafl_bolts::{Named, impl_serdeany};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Error, HasMetadata,
+    corpus::{HasCurrentCorpusId, Testcase},
+    executors::ExitKind,
+    feedbacks::{Feedback, StateInitializer},
+    inputs::HasMutatorBytes,
+    mutators::checksum::current_checksums,
+    state::HasCorpus,
+};
+
+/// Added to a testcase whose checksums were fixed, holding the bytes the checksums replaced
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChecksumFixupMetadata {
+    /// The checksum fields that changed, with their original bytes
+    pub fixups: Vec<(Range<usize>, Vec<u8>)>,
+}
+
+impl_serdeany!(ChecksumFixupMetadata);
+
+impl ChecksumFixupMetadata {
+    /// Undoes the fixup, restoring the input as it was executed
+    pub fn unfix(&self, bytes: &mut [u8]) {
+        // In reverse, in case fields were fixed more than once
+        for (range, orig) in self.fixups.iter().rev() {
+            if let Some(field) = bytes.get_mut(range.clone()) {
+                field.copy_from_slice(orig);
+            }
+        }
+    }
+}
+
+/// A feedback that fixes the checksums of the testcases it is asked to append metadata to.
+///
+/// It is never interesting by itself: combine it with the actual objective using `feedback_or`, so it only
+/// touches the solutions. This is useful when fuzzing a target with checksum verification patched out,
+/// where the mutated inputs are executed as they are, but the stored solutions should work against the original
+/// target. The replaced bytes are kept in a [`ChecksumFixupMetadata`], to get back the executed input.
+///
+/// The checksums come from the [`crate::mutators::ChecksumsMetadata`] of the testcase being fuzzed, checksums
+/// moved by inserted or deleted bytes are left alone.
+#[derive(Debug, Clone)]
+pub struct ChecksumFixupFeedback<I> {
+    name: Cow<'static, str>,
+    phantom: PhantomData<I>,
+}
+
+impl<I> Named for ChecksumFixupFeedback<I> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<I, S> StateInitializer<S> for ChecksumFixupFeedback<I> {}
+
+impl<EM, I, OT, S> Feedback<EM, I, OT, S> for ChecksumFixupFeedback<I>
+where
+    I: HasMutatorBytes + Clone,
+    S: HasCorpus<I> + HasCurrentCorpusId,
+{
+    #[inline]
+    fn is_interesting(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &I,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        Ok(false)
+    }
+
+    #[cfg(feature = "track_hit_feedbacks")]
+    fn last_result(&self) -> Result<bool, Error> {
+        Ok(false)
+    }
+
+    fn append_metadata(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        _observers: &OT,
+        testcase: &mut Testcase<I>,
+    ) -> Result<(), Error> {
+        let Some((checksums, orig)) = current_checksums(state) else {
+            return Ok(());
+        };
+        let Some(input) = testcase.input_mut() else {
+            return Err(Error::empty_optional("The testcase has no input"));
+        };
+
+        let bytes = input.mutator_bytes_mut();
+        let fixups = checksums
+            .fix(orig.mutator_bytes(), bytes)
+            .into_iter()
+            .filter(|(range, orig)| bytes[range.clone()] != orig[..])
+            .collect::<Vec<_>>();
+        if !fixups.is_empty() {
+            testcase.add_metadata(ChecksumFixupMetadata { fixups });
+        }
+        Ok(())
+    }
+}
+
+impl<I> ChecksumFixupFeedback<I> {
+    /// Creates a new [`ChecksumFixupFeedback`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            name: Cow::Borrowed("ChecksumFixupFeedback"),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<I> Default for ChecksumFixupFeedback<I> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,140 @@
+//! The [`DistanceFeedback`] records the distance of new corpus entries to the targets of directed fuzzing,
+//! for the [`crate::schedulers::DirectedScheduler`] and [`crate::schedulers::DirectedTestcaseScore`].
+
+use alloc::borrow::Cow;
+
+use libafl_bolts::{
+    Named, impl_serdeany,
+    tuples::{Handle, Handled, MatchName, MatchNameRef},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Error, HasMetadata,
+    corpus::Testcase,
+    executors::ExitKind,
+    feedbacks::{Feedback, StateInitializer},
+    observers::DistanceObserver,
+};
+
+/// The mean distance of the basic blocks a testcase executes to the targets
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct DistanceMetadata {
+    /// The distance
+    pub distance: f64,
+}
+
+impl_serdeany!(DistanceMetadata);
+
+/// The smallest and largest distance of the corpus entries, to normalize the distance of each of them
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct DistanceBoundsMetadata {
+    /// The smallest distance
+    pub min: f64,
+    /// The largest distance
+    pub max: f64,
+}
+
+impl_serdeany!(DistanceBoundsMetadata);
+
+impl DistanceBoundsMetadata {
+    /// Adds a distance to the bounds
+    pub fn update(&mut self, distance: f64) {
+        self.min = self.min.min(distance);
+        self.max = self.max.max(distance);
+    }
+
+    /// Maps `distance` to `[0, 1]`, where `0` is the closest corpus entry.
+    /// Returns `0.5` if all corpus entries are equally close.
+    #[must_use]
+    pub fn normalize(&self, distance: f64) -> f64 {
+        if self.max > self.min {
+            ((distance - self.min) / (self.max - self.min)).clamp(0.0, 1.0)
+        } else {
+            0.5
+        }
+    }
+}
+
+/// A feedback that adds the distance observed by a [`DistanceObserver`] to new corpus entries as
+/// [`DistanceMetadata`], and keeps track of the [`DistanceBoundsMetadata`] of the corpus.
+#[derive(Debug, Clone)]
+pub struct DistanceFeedback<'a> {
+    name: Cow<'static, str>,
+    observer_handle: Handle<DistanceObserver<'a>>,
+}
+
+impl<'a> DistanceFeedback<'a> {
+    /// Creates a new [`DistanceFeedback`]
+    #[must_use]
+    pub fn new(observer: &DistanceObserver<'a>) -> Self {
+        Self {
+            name: Cow::Borrowed("DistanceFeedback"),
+            observer_handle: observer.handle(),
+        }
+    }
+}
+
+impl Named for DistanceFeedback<'_> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<S> StateInitializer<S> for DistanceFeedback<'_> {}
+
+impl<EM, I, OT, S> Feedback<EM, I, OT, S> for DistanceFeedback<'_>
+where
+    OT: MatchName,
+    S: HasMetadata,
+{
+    #[inline]
+    fn is_interesting(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &I,
+        _observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        Ok(false)
+    }
+
+    #[cfg(feature = "track_hit_feedbacks")]
+    fn last_result(&self) -> Result<bool, Error> {
+        Ok(false)
+    }
+
+    fn append_metadata(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        observers: &OT,
+        testcase: &mut Testcase<I>,
+    ) -> Result<(), Error> {
+        let observer = observers
+            .get(&self.observer_handle)
+            .ok_or_else(|| Error::key_not_found("DistanceObserver not found"))?;
+        // Testcases that do not reach the targets at all get no distance
+        let Some(distance) = observer.distance() else {
+            return Ok(());
+        };
+
+        state
+            .metadata_or_insert_with(|| DistanceBoundsMetadata {
+                min: distance,
+                max: distance,
+            })
+            .update(distance);
+        testcase.add_metadata(DistanceMetadata { distance });
+        Ok(())
+    }
+}
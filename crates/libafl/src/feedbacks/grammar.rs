@@ -0,0 +1,273 @@
+//! The [`GrammarCoverageFeedback`] keeps inputs deriving from new grammar rules, or new paths of rules, for k-path
+//! grammar coverage of the Nautilus and Gramatron grammar fuzzers.
+
+use alloc::{borrow::Cow, vec::Vec};
+use core::marker::PhantomData;
+
+use hashbrown::HashSet;
+use libafl_bolts::{
+    Named, impl_serdeany,
+    tuples::{Handle, Handled, MatchName, MatchNameRef},
+};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "track_hit_feedbacks")]
+use crate::feedbacks::premature_last_result_err;
+use crate::{
+    Error, HasNamedMetadata,
+    corpus::Testcase,
+    events::{Event, EventFirer, EventWithStats},
+    executors::ExitKind,
+    feedbacks::{Feedback, HasObserverHandle, StateInitializer},
+    monitors::stats::{AggregatorOps, UserStats, UserStatsValue},
+    observers::GrammarCoverageObserver,
+    state::HasExecutions,
+};
+
+/// The grammar rules and paths of rules the corpus covers, for a [`GrammarCoverageFeedback`]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GrammarCoverageMetadata {
+    /// Whether the corpus covers each rule, by id
+    pub rules: Vec<bool>,
+    /// The number of rules the corpus covers
+    pub covered_rules: usize,
+    /// The hashes of the paths of rules the corpus covers
+    pub paths: HashSet<u64>,
+}
+
+impl_serdeany!(GrammarCoverageMetadata);
+
+impl GrammarCoverageMetadata {
+    /// Whether the corpus covers the rule
+    #[must_use]
+    pub fn covers_rule(&self, rule: usize) -> bool {
+        self.rules.get(rule).copied().unwrap_or(false)
+    }
+}
+
+/// A feedback that is interesting if an input derives from a rule, or a path of rules, no input in the corpus
+/// derived from before, as recorded by a [`GrammarCoverageObserver`].
+///
+/// Reports the share of the rules of the grammar the corpus covers as the `grammar_<observer name>` stats.
+#[derive(Debug)]
+pub struct GrammarCoverageFeedback {
+    name: Cow<'static, str>,
+    stats_name: Cow<'static, str>,
+    observer_handle: Handle<GrammarCoverageObserver>,
+    #[cfg(feature = "track_hit_feedbacks")]
+    last_result: Option<bool>,
+}
+
+impl GrammarCoverageFeedback {
+    /// Creates a new [`GrammarCoverageFeedback`] for the rules `observer` records
+    #[must_use]
+    pub fn new(observer: &GrammarCoverageObserver) -> Self {
+        Self {
+            name: observer.name().clone(),
+            stats_name: Cow::Owned(format!("grammar_{}", observer.name())),
+            observer_handle: observer.handle(),
+            #[cfg(feature = "track_hit_feedbacks")]
+            last_result: None,
+        }
+    }
+
+    fn observer<'a, OT>(&self, observers: &'a OT) -> Result<&'a GrammarCoverageObserver, Error>
+    where
+        OT: MatchName,
+    {
+        observers
+            .get(&self.observer_handle)
+            .ok_or_else(|| Error::key_not_found("GrammarCoverageObserver not found"))
+    }
+}
+
+impl Named for GrammarCoverageFeedback {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl HasObserverHandle for GrammarCoverageFeedback {
+    type Observer = GrammarCoverageObserver;
+
+    #[inline]
+    fn observer_handle(&self) -> &Handle<GrammarCoverageObserver> {
+        &self.observer_handle
+    }
+}
+
+impl<S> StateInitializer<S> for GrammarCoverageFeedback
+where
+    S: HasNamedMetadata,
+{
+    fn init_state(&mut self, state: &mut S) -> Result<(), Error> {
+        state.add_named_metadata_checked(&self.name, GrammarCoverageMetadata::default())?;
+        Ok(())
+    }
+}
+
+impl<EM, I, OT, S> Feedback<EM, I, OT, S> for GrammarCoverageFeedback
+where
+    EM: EventFirer<I, S>,
+    OT: MatchName,
+    S: HasNamedMetadata + HasExecutions,
+{
+    fn is_interesting(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        _input: &I,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        let observer = self.observer(observers)?;
+        let coverage = state.named_metadata::<GrammarCoverageMetadata>(&self.name)?;
+        let res = observer
+            .rules()
+            .iter()
+            .any(|rule| !coverage.covers_rule(*rule))
+            || observer
+                .paths()
+                .iter()
+                .any(|path| !coverage.paths.contains(path));
+
+        #[cfg(feature = "track_hit_feedbacks")]
+        {
+            self.last_result = Some(res);
+        }
+        Ok(res)
+    }
+
+    #[cfg(feature = "track_hit_feedbacks")]
+    fn last_result(&self) -> Result<bool, Error> {
+        self.last_result.ok_or(premature_last_result_err())
+    }
+
+    fn append_metadata(
+        &mut self,
+        state: &mut S,
+        manager: &mut EM,
+        observers: &OT,
+        _testcase: &mut Testcase<I>,
+    ) -> Result<(), Error> {
+        let observer = self.observer(observers)?;
+        let coverage = state.named_metadata_mut::<GrammarCoverageMetadata>(&self.name)?;
+        if let Some(last) = observer.rules().last()
+            && coverage.rules.len() <= *last
+        {
+            coverage.rules.resize(*last + 1, false);
+        }
+        for rule in observer.rules() {
+            if !coverage.rules[*rule] {
+                coverage.rules[*rule] = true;
+                coverage.covered_rules += 1;
+            }
+        }
+        coverage.paths.extend(observer.paths());
+        let covered = coverage.covered_rules;
+
+        manager.fire(
+            state,
+            EventWithStats::with_current_time(
+                Event::UpdateUserStats {
+                    name: self.stats_name.clone(),
+                    value: UserStats::new(
+                        UserStatsValue::Ratio(covered as u64, observer.rule_count() as u64),
+                        AggregatorOps::Avg,
+                    ),
+                    phantom: PhantomData,
+                },
+                *state.executions(),
+            ),
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{string::ToString, vec, vec::Vec};
+
+    use tuple_list::tuple_list;
+
+    use super::{GrammarCoverageFeedback, GrammarCoverageMetadata};
+    use crate::{
+        HasNamedMetadata,
+        corpus::Testcase,
+        events::NopEventManager,
+        executors::ExitKind,
+        feedbacks::{Feedback, StateInitializer},
+        generators::gramatron::{Automaton, Trigger},
+        inputs::{GramatronInput, Terminal},
+        observers::{GrammarCoverageObserver, Observer},
+        state::NopState,
+    };
+
+    #[test]
+    fn test_grammar_coverage_feedback() {
+        #[cfg(any(not(feature = "serdeany_autoreg"), miri))]
+        unsafe {
+            GrammarCoverageMetadata::register();
+        }
+
+        let trigger = |dest: usize, term: &str| Trigger {
+            dest,
+            term: term.to_string(),
+        };
+        let automaton = Automaton {
+            init_state: 0,
+            final_state: 1,
+            pda: vec![
+                vec![trigger(0, "a"), trigger(0, "b"), trigger(1, "c")],
+                vec![],
+            ],
+        };
+        let observer = GrammarCoverageObserver::gramatron("grammar", &automaton);
+        let mut feedback = GrammarCoverageFeedback::new(&observer);
+        let mut state: NopState<GramatronInput> = NopState::new();
+        let mut mgr = NopEventManager::default();
+        feedback.init_state(&mut state).unwrap();
+        let mut observers = tuple_list!(observer);
+
+        let mut run = |terms: &str| {
+            let input = GramatronInput::new(
+                terms
+                    .chars()
+                    .map(|c| {
+                        let trigger_idx = usize::from(c as u8 - b'a');
+                        Terminal::new(0, trigger_idx, c.to_string())
+                    })
+                    .collect::<Vec<_>>(),
+            );
+            observers.0.pre_exec(&mut state, &input).unwrap();
+            let interesting = feedback
+                .is_interesting(&mut state, &mut mgr, &input, &observers, &ExitKind::Ok)
+                .unwrap();
+            if interesting {
+                let mut testcase = Testcase::new(input);
+                feedback
+                    .append_metadata(&mut state, &mut mgr, &observers, &mut testcase)
+                    .unwrap();
+            }
+            interesting
+        };
+
+        assert!(run("ac"));
+        assert!(!run("ac"));
+        // A new rule
+        assert!(run("bc"));
+        // No new rule, but the new pair a-b
+        assert!(run("abc"));
+        assert!(!run("ab"));
+
+        let coverage = state
+            .named_metadata::<GrammarCoverageMetadata>("grammar")
+            .unwrap();
+        assert_eq!(coverage.covered_rules, 3);
+        assert!(!coverage.covers_rule(3));
+    }
+}
@@ -0,0 +1,326 @@
+//! Feedbacks on the memory usage observed by a [`MemoryObserver`]: the [`MaxMemoryFeedback`] keeps the inputs using
+//! more memory than all before, and the [`MemoryLimitFeedback`] objective reports inputs above a limit, like
+//! `-malloc_limit_mb` of libFuzzer.
+
+use alloc::borrow::Cow;
+
+use libafl_bolts::{
+    Named, impl_serdeany,
+    tuples::{Handle, Handled, MatchName, MatchNameRef},
+};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "track_hit_feedbacks")]
+use crate::feedbacks::premature_last_result_err;
+use crate::{
+    Error, HasMetadata,
+    corpus::Testcase,
+    executors::ExitKind,
+    feedbacks::{Feedback, StateInitializer},
+    observers::MemoryObserver,
+};
+
+/// The memory usage of a testcase
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct MemoryMetadata {
+    /// The peak heap usage, in bytes
+    pub peak: u64,
+    /// The number of allocations
+    pub allocations: u64,
+}
+
+impl_serdeany!(MemoryMetadata);
+
+impl MemoryMetadata {
+    fn from_observer(observer: &MemoryObserver<'_>) -> Self {
+        Self {
+            peak: observer.peak(),
+            allocations: observer.allocations(),
+        }
+    }
+}
+
+/// The largest memory usage of the corpus entries of a [`MaxMemoryFeedback`]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct MaxMemoryMetadata {
+    /// The largest peak heap usage, in bytes
+    pub peak: u64,
+    /// The largest number of allocations
+    pub allocations: u64,
+}
+
+impl_serdeany!(MaxMemoryMetadata);
+
+/// The power of two bucket of a memory usage, so the [`MaxMemoryFeedback`] does not keep every input using a byte more
+fn memory_bucket(value: u64) -> u32 {
+    u64::BITS - value.leading_zeros()
+}
+
+/// Gets the [`MemoryObserver`] of `handle` from `observers`
+fn memory_observer<'a, 'b, OT>(
+    observers: &'b OT,
+    handle: &Handle<MemoryObserver<'a>>,
+) -> Result<&'b MemoryObserver<'a>, Error>
+where
+    OT: MatchName,
+{
+    observers
+        .get(handle)
+        .ok_or_else(|| Error::key_not_found("MemoryObserver not found"))
+}
+
+/// A feedback that is interesting if an input uses more memory, or makes more allocations, than all corpus entries
+/// before, to find algorithmic complexity and memory blowups. The usages are bucketed by powers of two.
+///
+/// Adds the [`MemoryMetadata`] to new corpus entries.
+#[derive(Debug, Clone)]
+pub struct MaxMemoryFeedback<'a> {
+    name: Cow<'static, str>,
+    observer_handle: Handle<MemoryObserver<'a>>,
+    #[cfg(feature = "track_hit_feedbacks")]
+    last_result: Option<bool>,
+}
+
+impl<'a> MaxMemoryFeedback<'a> {
+    /// Creates a new [`MaxMemoryFeedback`]
+    #[must_use]
+    pub fn new(observer: &MemoryObserver<'a>) -> Self {
+        Self {
+            name: Cow::Borrowed("MaxMemoryFeedback"),
+            observer_handle: observer.handle(),
+            #[cfg(feature = "track_hit_feedbacks")]
+            last_result: None,
+        }
+    }
+}
+
+impl Named for MaxMemoryFeedback<'_> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<S> StateInitializer<S> for MaxMemoryFeedback<'_>
+where
+    S: HasMetadata,
+{
+    fn init_state(&mut self, state: &mut S) -> Result<(), Error> {
+        state.metadata_or_insert_with(MaxMemoryMetadata::default);
+        Ok(())
+    }
+}
+
+impl<EM, I, OT, S> Feedback<EM, I, OT, S> for MaxMemoryFeedback<'_>
+where
+    OT: MatchName,
+    S: HasMetadata,
+{
+    fn is_interesting(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        _input: &I,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        let observer = memory_observer(observers, &self.observer_handle)?;
+        let max = state.metadata_or_insert_with(MaxMemoryMetadata::default);
+        let res = memory_bucket(observer.peak()) > memory_bucket(max.peak)
+            || memory_bucket(observer.allocations()) > memory_bucket(max.allocations);
+
+        #[cfg(feature = "track_hit_feedbacks")]
+        {
+            self.last_result = Some(res);
+        }
+        Ok(res)
+    }
+
+    #[cfg(feature = "track_hit_feedbacks")]
+    fn last_result(&self) -> Result<bool, Error> {
+        self.last_result.ok_or(premature_last_result_err())
+    }
+
+    fn append_metadata(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        observers: &OT,
+        testcase: &mut Testcase<I>,
+    ) -> Result<(), Error> {
+        let usage =
+            MemoryMetadata::from_observer(memory_observer(observers, &self.observer_handle)?);
+        let max = state.metadata_or_insert_with(MaxMemoryMetadata::default);
+        max.peak = max.peak.max(usage.peak);
+        max.allocations = max.allocations.max(usage.allocations);
+        testcase.add_metadata(usage);
+        Ok(())
+    }
+}
+
+/// An objective that fires if an input uses more memory than a limit, or makes more allocations than a limit.
+///
+/// Adds the [`MemoryMetadata`] to the solutions.
+#[derive(Debug, Clone)]
+pub struct MemoryLimitFeedback<'a> {
+    name: Cow<'static, str>,
+    observer_handle: Handle<MemoryObserver<'a>>,
+    max_peak: u64,
+    max_allocations: Option<u64>,
+    #[cfg(feature = "track_hit_feedbacks")]
+    last_result: Option<bool>,
+}
+
+impl<'a> MemoryLimitFeedback<'a> {
+    /// Creates a new [`MemoryLimitFeedback`], firing above `max_peak` bytes
+    #[must_use]
+    pub fn new(observer: &MemoryObserver<'a>, max_peak: u64) -> Self {
+        Self {
+            name: Cow::Borrowed("MemoryLimitFeedback"),
+            observer_handle: observer.handle(),
+            max_peak,
+            max_allocations: None,
+            #[cfg(feature = "track_hit_feedbacks")]
+            last_result: None,
+        }
+    }
+
+    /// Creates a new [`MemoryLimitFeedback`], firing above `limit_mb` megabytes, as `-malloc_limit_mb` of libFuzzer
+    #[must_use]
+    pub fn with_limit_mb(observer: &MemoryObserver<'a>, limit_mb: u64) -> Self {
+        Self::new(observer, limit_mb << 20)
+    }
+
+    /// Also fires above `max_allocations` allocations
+    #[must_use]
+    pub fn with_max_allocations(mut self, max_allocations: u64) -> Self {
+        self.max_allocations = Some(max_allocations);
+        self
+    }
+}
+
+impl Named for MemoryLimitFeedback<'_> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<S> StateInitializer<S> for MemoryLimitFeedback<'_> {}
+
+impl<EM, I, OT, S> Feedback<EM, I, OT, S> for MemoryLimitFeedback<'_>
+where
+    OT: MatchName,
+{
+    fn is_interesting(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _input: &I,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        let observer = memory_observer(observers, &self.observer_handle)?;
+        let res = observer.peak() > self.max_peak
+            || self
+                .max_allocations
+                .is_some_and(|max_allocations| observer.allocations() > max_allocations);
+
+        #[cfg(feature = "track_hit_feedbacks")]
+        {
+            self.last_result = Some(res);
+        }
+        Ok(res)
+    }
+
+    #[cfg(feature = "track_hit_feedbacks")]
+    fn last_result(&self) -> Result<bool, Error> {
+        self.last_result.ok_or(premature_last_result_err())
+    }
+
+    fn append_metadata(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        observers: &OT,
+        testcase: &mut Testcase<I>,
+    ) -> Result<(), Error> {
+        let observer = memory_observer(observers, &self.observer_handle)?;
+        testcase.add_metadata(MemoryMetadata::from_observer(observer));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tuple_list::tuple_list;
+
+    use super::{MaxMemoryFeedback, MemoryLimitFeedback};
+    use crate::{
+        corpus::Testcase,
+        executors::ExitKind,
+        feedbacks::{Feedback, StateInitializer},
+        inputs::BytesInput,
+        observers::{MemoryObserver, Observer},
+        state::NopState,
+    };
+
+    #[test]
+    fn test_memory_feedbacks() {
+        #[cfg(any(not(feature = "serdeany_autoreg"), miri))]
+        unsafe {
+            super::MemoryMetadata::register();
+            super::MaxMemoryMetadata::register();
+        }
+
+        let mut state: NopState<BytesInput> = NopState::new();
+        let input = BytesInput::new(vec![0]);
+        let observer = MemoryObserver::owned("memory");
+        let mut max = MaxMemoryFeedback::new(&observer);
+        let mut limit = MemoryLimitFeedback::new(&observer, 1000).with_max_allocations(10);
+        max.init_state(&mut state).unwrap();
+        let mut observers = tuple_list!(observer);
+
+        let mut run = |observers: &mut (MemoryObserver<'_>, ()), allocs: &[u64]| {
+            observers.0.pre_exec(&mut state, &input).unwrap();
+            for size in allocs {
+                observers.0.record_alloc(*size);
+                observers.0.record_free(*size / 2);
+            }
+            observers
+                .0
+                .post_exec(&mut state, &input, &ExitKind::Ok)
+                .unwrap();
+            let new_max = max
+                .is_interesting(&mut state, &mut (), &input, &*observers, &ExitKind::Ok)
+                .unwrap();
+            if new_max {
+                max.append_metadata(
+                    &mut state,
+                    &mut (),
+                    &*observers,
+                    &mut Testcase::new(input.clone()),
+                )
+                .unwrap();
+            }
+            let over_limit = limit
+                .is_interesting(&mut state, &mut (), &input, &*observers, &ExitKind::Ok)
+                .unwrap();
+            (new_max, over_limit)
+        };
+
+        assert_eq!(run(&mut observers, &[100, 100]), (true, false));
+        assert_eq!(observers.0.peak(), 150);
+        assert_eq!(run(&mut observers, &[100]), (false, false));
+        assert_eq!(run(&mut observers, &[800, 800]), (true, true));
+        // Same bucket as the largest peak so far
+        assert_eq!(run(&mut observers, &[1300]), (false, true));
+        assert_eq!(run(&mut observers, &[1; 11]), (true, true));
+    }
+}
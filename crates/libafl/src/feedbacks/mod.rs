@@ -32,6 +32,10 @@ pub mod capture_feedback;
 
 pub mod bool;
 pub use bool::BoolValueFeedback;
+pub mod branch_hits;
+pub use branch_hits::{BranchHitsFeedback, BranchHitsMetadata};
+pub mod checksum;
+pub use checksum::{ChecksumFixupFeedback, ChecksumFixupMetadata};
 
 #[cfg(feature = "std")]
 pub mod concolic;
@@ -39,21 +43,39 @@ pub mod concolic;
 /// The module for `CustomFilenameToTestcaseFeedback`
 pub mod custom_filename;
 pub mod differential;
+pub mod distance;
+pub use distance::{DistanceBoundsMetadata, DistanceFeedback, DistanceMetadata};
+pub mod grammar;
+pub use grammar::{GrammarCoverageFeedback, GrammarCoverageMetadata};
 /// The module for list feedback
 pub mod list;
 pub mod map;
+pub mod memory;
+pub use memory::{MaxMemoryFeedback, MaxMemoryMetadata, MemoryLimitFeedback, MemoryMetadata};
 #[cfg(feature = "nautilus")]
 pub mod nautilus;
 #[cfg(feature = "std")]
 pub mod new_hash_feedback;
+pub mod patch;
+pub use patch::{PatchHitsMetadata, PatchMapFeedback};
+pub mod perf;
+pub use perf::{
+    AverageExecTimeMetadata, PerfEdgesMetadata, PerfFeedback, PerfMaxMetadata, SlowInputFeedback,
+};
+pub mod sensitivity;
+pub use sensitivity::SensitivityFeedback;
 #[cfg(feature = "simd")]
 pub mod simd;
 #[cfg(feature = "std")]
 pub mod stdio;
 pub mod transferred;
+#[cfg(feature = "std")]
+pub mod triage;
 
 #[cfg(feature = "std")]
 pub use capture_feedback::CaptureTimeoutFeedback;
+#[cfg(feature = "std")]
+pub use triage::{CrashBucketFeedback, CrashBucketMetadata, CrashBucketsMetadata};
 
 #[cfg(feature = "introspection")]
 use crate::state::HasClientPerfMonitor;
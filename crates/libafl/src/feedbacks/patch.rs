@@ -0,0 +1,279 @@
+//! The [`PatchMapFeedback`] is a map feedback that only counts the edges of the lines a patch changed,
+//! for patch-directed fuzzing with the patch pass of `libafl_cc`.
+
+use alloc::{borrow::Cow, vec::Vec};
+use core::marker::PhantomData;
+
+use libafl_bolts::{
+    AsIter, AsSlice, Named, impl_serdeany,
+    ownedref::OwnedSlice,
+    tuples::{Handle, Handled, MatchName, MatchNameRef},
+};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "track_hit_feedbacks")]
+use crate::feedbacks::premature_last_result_err;
+use crate::{
+    Error, HasMetadata, HasNamedMetadata,
+    corpus::Testcase,
+    events::{Event, EventFirer, EventWithStats},
+    executors::ExitKind,
+    feedbacks::{Feedback, HasObserverHandle, MapFeedbackMetadata, StateInitializer},
+    monitors::stats::{AggregatorOps, UserStats, UserStatsValue},
+    observers::MapObserver,
+    state::HasExecutions,
+};
+
+/// The number of edges of changed lines a testcase executes
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct PatchHitsMetadata {
+    /// The number of tagged edges
+    pub edges: usize,
+}
+
+impl_serdeany!(PatchHitsMetadata);
+
+/// A map feedback that is interesting only if a testcase covers an edge of a line changed by a patch for the first
+/// time, i.e., an edge tagged in `tags`. Also adds the [`PatchHitsMetadata`] to new corpus entries, for the
+/// [`crate::schedulers::PatchTestcaseScore`].
+///
+/// The tags come from `libafl_targets::patch_edges_tags`. They are set the first time the target executes a tagged
+/// edge, so the `patch` stats show the share of the edges of changed lines executed so far that the corpus covers.
+/// Combine it with a regular [`crate::feedbacks::MaxMapFeedback`] using `feedback_or`.
+#[derive(Debug)]
+pub struct PatchMapFeedback<'a, C, O> {
+    name: Cow<'static, str>,
+    map_ref: Handle<C>,
+    tags: OwnedSlice<'a, u8>,
+    #[cfg(feature = "track_hit_feedbacks")]
+    last_result: Option<bool>,
+    phantom: PhantomData<fn() -> O>,
+}
+
+impl<'a, C, O> PatchMapFeedback<'a, C, O>
+where
+    C: Named,
+{
+    /// Creates a new [`PatchMapFeedback`], counting the entries of the map of `map_observer` nonzero in `tags`
+    #[must_use]
+    pub fn new(map_observer: &C, tags: OwnedSlice<'a, u8>) -> Self {
+        Self {
+            name: Cow::Owned(format!("patch_{}", map_observer.name())),
+            map_ref: map_observer.handle(),
+            tags,
+            #[cfg(feature = "track_hit_feedbacks")]
+            last_result: None,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<C, O> PatchMapFeedback<'_, C, O> {
+    /// Whether the map entry at `idx` belongs to a changed line
+    #[must_use]
+    pub fn is_tagged(&self, idx: usize) -> bool {
+        self.tags.as_slice().get(idx).is_some_and(|tag| *tag != 0)
+    }
+
+    /// The number of map entries belonging to changed lines
+    #[must_use]
+    pub fn tagged_count(&self) -> usize {
+        self.tags.as_slice().iter().filter(|tag| **tag != 0).count()
+    }
+}
+
+impl<C, O> PatchMapFeedback<'_, C, O>
+where
+    C: AsRef<O>,
+    O: MapObserver + for<'it> AsIter<'it, Item = O::Entry>,
+{
+    /// The tagged entries the observer saw in the last execution
+    fn tagged_hits<OT>(&self, observers: &OT) -> Result<Vec<usize>, Error>
+    where
+        OT: MatchName,
+    {
+        let observer = observers
+            .get(&self.map_ref)
+            .ok_or_else(|| Error::key_not_found("MapObserver not found"))?
+            .as_ref();
+        let initial = observer.initial();
+        Ok(observer
+            .as_iter()
+            .enumerate()
+            .filter(|(i, value)| **value != initial && self.is_tagged(*i))
+            .map(|(i, _)| i)
+            .collect())
+    }
+}
+
+impl<C, O> Named for PatchMapFeedback<'_, C, O> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<C, O> HasObserverHandle for PatchMapFeedback<'_, C, O> {
+    type Observer = C;
+
+    #[inline]
+    fn observer_handle(&self) -> &Handle<C> {
+        &self.map_ref
+    }
+}
+
+impl<C, O, S> StateInitializer<S> for PatchMapFeedback<'_, C, O>
+where
+    S: HasNamedMetadata,
+{
+    fn init_state(&mut self, state: &mut S) -> Result<(), Error> {
+        state.add_named_metadata_checked(&self.name, MapFeedbackMetadata::<bool>::default())?;
+        Ok(())
+    }
+}
+
+impl<C, EM, I, O, OT, S> Feedback<EM, I, OT, S> for PatchMapFeedback<'_, C, O>
+where
+    C: AsRef<O>,
+    EM: EventFirer<I, S>,
+    O: MapObserver + for<'it> AsIter<'it, Item = O::Entry>,
+    OT: MatchName,
+    S: HasNamedMetadata + HasExecutions,
+{
+    fn is_interesting(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        _input: &I,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        let hits = self.tagged_hits(observers)?;
+        let history = &state
+            .named_metadata::<MapFeedbackMetadata<bool>>(&self.name)?
+            .history_map;
+        let res = hits
+            .iter()
+            .any(|i| !history.get(*i).copied().unwrap_or(false));
+
+        #[cfg(feature = "track_hit_feedbacks")]
+        {
+            self.last_result = Some(res);
+        }
+        Ok(res)
+    }
+
+    #[cfg(feature = "track_hit_feedbacks")]
+    fn last_result(&self) -> Result<bool, Error> {
+        self.last_result.ok_or(premature_last_result_err())
+    }
+
+    fn append_metadata(
+        &mut self,
+        state: &mut S,
+        manager: &mut EM,
+        observers: &OT,
+        testcase: &mut Testcase<I>,
+    ) -> Result<(), Error> {
+        let hits = self.tagged_hits(observers)?;
+        let map_state = state.named_metadata_mut::<MapFeedbackMetadata<bool>>(&self.name)?;
+        if let Some(last) = hits.last()
+            && map_state.history_map.len() <= *last
+        {
+            map_state.history_map.resize(*last + 1, false);
+        }
+        for i in &hits {
+            if !map_state.history_map[*i] {
+                map_state.history_map[*i] = true;
+                map_state.num_covered_map_indexes += 1;
+            }
+        }
+        let covered = map_state.num_covered_map_indexes;
+        testcase.add_metadata(PatchHitsMetadata { edges: hits.len() });
+
+        manager.fire(
+            state,
+            EventWithStats::with_current_time(
+                Event::UpdateUserStats {
+                    name: Cow::Borrowed("patch"),
+                    value: UserStats::new(
+                        UserStatsValue::Ratio(covered as u64, self.tagged_count() as u64),
+                        AggregatorOps::Avg,
+                    ),
+                    phantom: PhantomData,
+                },
+                *state.executions(),
+            ),
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl_bolts::ownedref::OwnedSlice;
+    use tuple_list::tuple_list;
+
+    use super::{PatchHitsMetadata, PatchMapFeedback};
+    use crate::{
+        HasMetadata,
+        corpus::Testcase,
+        events::NopEventManager,
+        executors::ExitKind,
+        feedbacks::{Feedback, StateInitializer},
+        inputs::BytesInput,
+        observers::{MapObserver, StdMapObserver},
+        state::NopState,
+    };
+
+    #[test]
+    fn test_patch_map_feedback() {
+        #[cfg(any(not(feature = "serdeany_autoreg"), miri))]
+        unsafe {
+            PatchHitsMetadata::register();
+            crate::feedbacks::MapFeedbackMetadata::<bool>::register();
+        }
+
+        let mut state: NopState<BytesInput> = NopState::new();
+        let mut mgr = NopEventManager::default();
+        let input = BytesInput::new(vec![0]);
+
+        let observer = StdMapObserver::owned("edges", vec![0_u8; 8]);
+        let mut feedback =
+            PatchMapFeedback::new(&observer, OwnedSlice::from(vec![0, 0, 1, 0, 1, 0, 0, 0]));
+        assert_eq!(feedback.tagged_count(), 2);
+        feedback.init_state(&mut state).unwrap();
+
+        let mut run = |feedback: &mut PatchMapFeedback<_, _>, edges: &[usize]| {
+            let mut observer = StdMapObserver::owned("edges", vec![0_u8; 8]);
+            for edge in edges {
+                observer.set(*edge, 1);
+            }
+            let observers = tuple_list!(observer);
+            let interesting = feedback
+                .is_interesting(&mut state, &mut mgr, &input, &observers, &ExitKind::Ok)
+                .unwrap();
+            let mut testcase = Testcase::new(input.clone());
+            if interesting {
+                feedback
+                    .append_metadata(&mut state, &mut mgr, &observers, &mut testcase)
+                    .unwrap();
+            }
+            (interesting, testcase)
+        };
+
+        // Untagged edges are not interesting
+        assert!(!run(&mut feedback, &[0, 1]).0);
+        let (interesting, testcase) = run(&mut feedback, &[1, 2]);
+        assert!(interesting);
+        assert_eq!(testcase.metadata::<PatchHitsMetadata>().unwrap().edges, 1);
+        // Only new tagged edges are
+        assert!(!run(&mut feedback, &[2, 3]).0);
+        let (interesting, testcase) = run(&mut feedback, &[2, 4]);
+        assert!(interesting);
+        assert_eq!(testcase.metadata::<PatchHitsMetadata>().unwrap().edges, 2);
+    }
+}
@@ -0,0 +1,377 @@
+//! Feedbacks for finding slow inputs, as in `PerfFuzz`: the [`PerfFeedback`] keeps the inputs executing an edge more
+//! often than all before, and the [`SlowInputFeedback`] objective reports inputs running much longer than the average.
+
+use alloc::{borrow::Cow, vec::Vec};
+use core::{marker::PhantomData, time::Duration};
+
+use libafl_bolts::{
+    AsIter, Named, impl_serdeany,
+    tuples::{Handle, Handled, MatchName, MatchNameRef},
+};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "track_hit_feedbacks")]
+use crate::feedbacks::premature_last_result_err;
+use crate::{
+    Error, HasMetadata, HasNamedMetadata,
+    corpus::Testcase,
+    executors::ExitKind,
+    feedbacks::{Feedback, HasObserverHandle, StateInitializer},
+    observers::{MapObserver, TimeObserver},
+};
+
+/// The largest hit count of each edge so far, of a [`PerfFeedback`]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PerfMaxMetadata {
+    /// The largest hit counts, indexed like the map
+    pub max: Vec<u64>,
+}
+
+impl_serdeany!(PerfMaxMetadata);
+
+/// The number of edges a testcase executed more often than all testcases before, added by the [`PerfFeedback`]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct PerfEdgesMetadata {
+    /// The number of edges with a new largest hit count
+    pub edges: usize,
+}
+
+impl_serdeany!(PerfEdgesMetadata);
+
+/// A feedback that is interesting if an input hits any entry of a map more often than all corpus entries before,
+/// as in `PerfFuzz`, to find algorithmic complexity bugs.
+///
+/// The map needs the raw hit counts, e.g., from a [`crate::observers::HitcountsMapObserver::without_bucketing`],
+/// ideally in wider entries than `u8`, as buckets hide most increases. Combine it with the coverage feedback using
+/// `feedback_or`, and with a [`crate::schedulers::PerfTestcaseScore`] to fuzz the maximizing entries more.
+#[derive(Debug, Clone)]
+pub struct PerfFeedback<C, O> {
+    name: Cow<'static, str>,
+    map_ref: Handle<C>,
+    /// The edges with a new largest hit count in the last execution
+    new_max: Vec<usize>,
+    phantom: PhantomData<fn() -> O>,
+}
+
+impl<C, O> PerfFeedback<C, O>
+where
+    C: Named,
+{
+    /// Creates a new [`PerfFeedback`], maximizing the hit counts in the map of `map_observer`
+    #[must_use]
+    pub fn new(map_observer: &C) -> Self {
+        Self {
+            name: Cow::Owned(format!("perf_{}", map_observer.name())),
+            map_ref: map_observer.handle(),
+            new_max: Vec::new(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<C, O> Named for PerfFeedback<C, O> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<C, O> HasObserverHandle for PerfFeedback<C, O> {
+    type Observer = C;
+
+    #[inline]
+    fn observer_handle(&self) -> &Handle<C> {
+        &self.map_ref
+    }
+}
+
+impl<C, O, S> StateInitializer<S> for PerfFeedback<C, O>
+where
+    S: HasNamedMetadata,
+{
+    fn init_state(&mut self, state: &mut S) -> Result<(), Error> {
+        state.named_metadata_or_insert_with(&self.name, PerfMaxMetadata::default);
+        Ok(())
+    }
+}
+
+impl<C, EM, I, O, OT, S> Feedback<EM, I, OT, S> for PerfFeedback<C, O>
+where
+    C: AsRef<O>,
+    O: MapObserver + for<'it> AsIter<'it, Item = O::Entry>,
+    O::Entry: Into<u64>,
+    OT: MatchName,
+    S: HasNamedMetadata,
+{
+    fn is_interesting(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        _input: &I,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        let observer = observers
+            .get(&self.map_ref)
+            .ok_or_else(|| Error::key_not_found("MapObserver not found"))?
+            .as_ref();
+        let meta = state.named_metadata_or_insert_with(&self.name, PerfMaxMetadata::default);
+
+        self.new_max.clear();
+        for (idx, value) in observer.as_iter().enumerate() {
+            let hits: u64 = (*value).into();
+            if hits > meta.max.get(idx).copied().unwrap_or(0) {
+                self.new_max.push(idx);
+            }
+        }
+        Ok(!self.new_max.is_empty())
+    }
+
+    #[cfg(feature = "track_hit_feedbacks")]
+    fn last_result(&self) -> Result<bool, Error> {
+        Ok(!self.new_max.is_empty())
+    }
+
+    fn append_metadata(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        observers: &OT,
+        testcase: &mut Testcase<I>,
+    ) -> Result<(), Error> {
+        let observer = observers
+            .get(&self.map_ref)
+            .ok_or_else(|| Error::key_not_found("MapObserver not found"))?
+            .as_ref();
+        let meta = state.named_metadata_or_insert_with(&self.name, PerfMaxMetadata::default);
+        if meta.max.len() < observer.len() {
+            meta.max.resize(observer.len(), 0);
+        }
+        for idx in &self.new_max {
+            meta.max[*idx] = meta.max[*idx].max(observer.get(*idx).into());
+        }
+        testcase.add_metadata(PerfEdgesMetadata {
+            edges: self.new_max.len(),
+        });
+        Ok(())
+    }
+}
+
+/// The default number of executions the average execution time has to be taken from
+/// before the [`SlowInputFeedback`] reports inputs
+pub const DEFAULT_SLOW_INPUT_MIN_RUNS: u64 = 32;
+
+/// The average execution time of the inputs that were not slow, of a [`SlowInputFeedback`]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct AverageExecTimeMetadata {
+    /// The total execution time
+    pub total: Duration,
+    /// The number of executions
+    pub runs: u64,
+}
+
+impl_serdeany!(AverageExecTimeMetadata);
+
+impl AverageExecTimeMetadata {
+    /// The average execution time, if there were any executions
+    #[must_use]
+    pub fn average(&self) -> Option<Duration> {
+        u32::try_from(self.runs)
+            .ok()
+            .filter(|runs| *runs > 0)
+            .map(|runs| self.total / runs)
+    }
+}
+
+/// An objective that fires if an input runs more than `ratio` times longer than the average input,
+/// as measured by a [`TimeObserver`], to report algorithmic complexity bugs.
+///
+/// The average is taken over all executions that are not reported, once there were enough of them.
+#[derive(Debug, Clone)]
+pub struct SlowInputFeedback {
+    name: Cow<'static, str>,
+    observer_handle: Handle<TimeObserver>,
+    ratio: f64,
+    min_runs: u64,
+    #[cfg(feature = "track_hit_feedbacks")]
+    last_result: Option<bool>,
+}
+
+impl SlowInputFeedback {
+    /// Creates a new [`SlowInputFeedback`], firing for inputs running more than `ratio` times the average
+    #[must_use]
+    pub fn new(observer: &TimeObserver, ratio: f64) -> Self {
+        Self {
+            name: Cow::Borrowed("SlowInputFeedback"),
+            observer_handle: observer.handle(),
+            ratio,
+            min_runs: DEFAULT_SLOW_INPUT_MIN_RUNS,
+            #[cfg(feature = "track_hit_feedbacks")]
+            last_result: None,
+        }
+    }
+
+    /// Only fires once the average is taken from `min_runs` executions,
+    /// [`DEFAULT_SLOW_INPUT_MIN_RUNS`] by default
+    #[must_use]
+    pub fn with_min_runs(mut self, min_runs: u64) -> Self {
+        self.min_runs = min_runs;
+        self
+    }
+}
+
+impl Named for SlowInputFeedback {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<S> StateInitializer<S> for SlowInputFeedback
+where
+    S: HasMetadata,
+{
+    fn init_state(&mut self, state: &mut S) -> Result<(), Error> {
+        state.metadata_or_insert_with(AverageExecTimeMetadata::default);
+        Ok(())
+    }
+}
+
+impl<EM, I, OT, S> Feedback<EM, I, OT, S> for SlowInputFeedback
+where
+    OT: MatchName,
+    S: HasMetadata,
+{
+    fn is_interesting(
+        &mut self,
+        state: &mut S,
+        _manager: &mut EM,
+        _input: &I,
+        observers: &OT,
+        _exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        let observer = observers
+            .get(&self.observer_handle)
+            .ok_or_else(|| Error::key_not_found("TimeObserver not found"))?;
+        let Some(runtime) = *observer.last_runtime() else {
+            return Ok(false);
+        };
+        let meta = state.metadata_or_insert_with(AverageExecTimeMetadata::default);
+        let res = meta.runs >= self.min_runs
+            && meta
+                .average()
+                .is_some_and(|average| runtime.as_secs_f64() > average.as_secs_f64() * self.ratio);
+        if !res {
+            meta.total += runtime;
+            meta.runs += 1;
+        }
+
+        #[cfg(feature = "track_hit_feedbacks")]
+        {
+            self.last_result = Some(res);
+        }
+        Ok(res)
+    }
+
+    #[cfg(feature = "track_hit_feedbacks")]
+    fn last_result(&self) -> Result<bool, Error> {
+        self.last_result.ok_or(premature_last_result_err())
+    }
+
+    fn append_metadata(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        observers: &OT,
+        testcase: &mut Testcase<I>,
+    ) -> Result<(), Error> {
+        let observer = observers
+            .get(&self.observer_handle)
+            .ok_or_else(|| Error::key_not_found("TimeObserver not found"))?;
+        *testcase.exec_time_mut() = *observer.last_runtime();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use tuple_list::tuple_list;
+
+    use super::{AverageExecTimeMetadata, PerfEdgesMetadata, PerfFeedback};
+    use crate::{
+        HasMetadata,
+        corpus::Testcase,
+        executors::ExitKind,
+        feedbacks::{Feedback, StateInitializer},
+        inputs::BytesInput,
+        observers::{HitcountsMapObserver, MapObserver, StdMapObserver},
+        state::NopState,
+    };
+
+    #[test]
+    fn test_perf_feedback() {
+        #[cfg(any(not(feature = "serdeany_autoreg"), miri))]
+        unsafe {
+            super::PerfMaxMetadata::register();
+            PerfEdgesMetadata::register();
+        }
+
+        let mut state: NopState<BytesInput> = NopState::new();
+        let input = BytesInput::new(vec![0]);
+        let observer =
+            HitcountsMapObserver::without_bucketing(StdMapObserver::owned("counts", vec![0_u8; 4]));
+        let mut feedback = PerfFeedback::new(&observer);
+        feedback.init_state(&mut state).unwrap();
+
+        let mut run = |counts: [u8; 4]| {
+            let mut observer = HitcountsMapObserver::without_bucketing(StdMapObserver::owned(
+                "counts",
+                vec![0_u8; 4],
+            ));
+            for (idx, count) in counts.into_iter().enumerate() {
+                observer.set(idx, count);
+            }
+            let observers = tuple_list!(observer);
+            let interesting = feedback
+                .is_interesting(&mut state, &mut (), &input, &observers, &ExitKind::Ok)
+                .unwrap();
+            let mut testcase = Testcase::new(input.clone());
+            if interesting {
+                feedback
+                    .append_metadata(&mut state, &mut (), &observers, &mut testcase)
+                    .unwrap();
+            }
+            testcase
+                .metadata::<PerfEdgesMetadata>()
+                .map_or(0, |meta| meta.edges)
+        };
+
+        assert_eq!(run([5, 5, 0, 0]), 2);
+        // Bucketing would put 5 and 6 into the same bucket
+        assert_eq!(run([6, 1, 0, 0]), 1);
+        assert_eq!(run([6, 5, 0, 0]), 0);
+        assert_eq!(run([1, 1, 1, 1]), 2);
+    }
+
+    #[test]
+    fn test_average_exec_time() {
+        let meta = AverageExecTimeMetadata {
+            total: Duration::from_millis(30),
+            runs: 3,
+        };
+        assert_eq!(meta.average(), Some(Duration::from_millis(10)));
+        assert_eq!(AverageExecTimeMetadata::default().average(), None);
+    }
+}
@@ -0,0 +1,272 @@
+//! The [`SensitivityFeedback`] combines the novelty of plain edges with the novelty of a more sensitive coverage map,
+//! like the n-gram or calling context maps of `libafl_targets`, as long as the sensitive map is not too full.
+
+use alloc::borrow::Cow;
+#[cfg(feature = "track_hit_feedbacks")]
+use alloc::vec::Vec;
+
+use libafl_bolts::Named;
+
+#[cfg(feature = "track_hit_feedbacks")]
+use crate::feedbacks::premature_last_result_err;
+#[cfg(feature = "introspection")]
+use crate::state::HasClientPerfMonitor;
+use crate::{
+    Error, HasNamedMetadata,
+    corpus::Testcase,
+    executors::ExitKind,
+    feedbacks::{Feedback, MapFeedbackMetadata, StateInitializer},
+};
+
+/// The default share of covered entries of the sensitive map above which its novelty is ignored
+pub const DEFAULT_MAX_PRESSURE: f64 = 0.5;
+
+/// A feedback that is interesting if the `edges` feedback is, or if the `sensitive` map feedback is while its map
+/// pressure is below the limit.
+///
+/// Sensitive coverage, like n-grams of edges, finds more paths, but also fills the corpus with entries that are
+/// hardly different, and its map with collisions. The map pressure is the share of the entries of the sensitive map
+/// covered so far. Above the limit, only new plain edges get into the corpus, while the history of the sensitive map
+/// is still updated for them. `sensitive` is a [`crate::feedbacks::MapFeedback`] over a map of `u8`, e.g., a
+/// [`crate::feedbacks::MaxMapFeedback`]. Nest [`SensitivityFeedback`]s to combine more maps.
+#[derive(Debug, Clone)]
+pub struct SensitivityFeedback<A, B> {
+    name: Cow<'static, str>,
+    edges: A,
+    sensitive: B,
+    max_pressure: f64,
+    #[cfg(feature = "track_hit_feedbacks")]
+    last_result: Option<(bool, bool)>,
+}
+
+impl<A, B> SensitivityFeedback<A, B>
+where
+    A: Named,
+    B: Named,
+{
+    /// Creates a new [`SensitivityFeedback`] with a map pressure limit of [`DEFAULT_MAX_PRESSURE`]
+    #[must_use]
+    pub fn new(edges: A, sensitive: B) -> Self {
+        Self::with_max_pressure(edges, sensitive, DEFAULT_MAX_PRESSURE)
+    }
+
+    /// Creates a new [`SensitivityFeedback`], ignoring the novelty of `sensitive` once more than `max_pressure`
+    /// of its map is covered. A limit of `1.0` never ignores it.
+    #[must_use]
+    pub fn with_max_pressure(edges: A, sensitive: B, max_pressure: f64) -> Self {
+        Self {
+            name: Cow::Owned(format!(
+                "SensitivityFeedback<{}, {}>",
+                edges.name(),
+                sensitive.name()
+            )),
+            edges,
+            sensitive,
+            max_pressure,
+            #[cfg(feature = "track_hit_feedbacks")]
+            last_result: None,
+        }
+    }
+}
+
+impl<A, B> SensitivityFeedback<A, B>
+where
+    B: Named,
+{
+    /// The share of covered entries of the sensitive map, from the history of the `sensitive` feedback
+    pub fn pressure<S>(&self, state: &S) -> Result<f64, Error>
+    where
+        S: HasNamedMetadata,
+    {
+        let meta = state.named_metadata::<MapFeedbackMetadata<u8>>(self.sensitive.name())?;
+        if meta.history_map.is_empty() {
+            return Ok(0.0);
+        }
+        #[expect(clippy::cast_precision_loss)]
+        Ok(meta.num_covered_map_indexes as f64 / meta.history_map.len() as f64)
+    }
+
+    /// The wrapped plain edges feedback
+    pub fn edges(&self) -> &A {
+        &self.edges
+    }
+
+    /// The wrapped sensitive map feedback
+    pub fn sensitive(&self) -> &B {
+        &self.sensitive
+    }
+}
+
+impl<A, B> Named for SensitivityFeedback<A, B> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<A, B, S> StateInitializer<S> for SensitivityFeedback<A, B>
+where
+    A: StateInitializer<S>,
+    B: StateInitializer<S>,
+{
+    fn init_state(&mut self, state: &mut S) -> Result<(), Error> {
+        self.edges.init_state(state)?;
+        self.sensitive.init_state(state)
+    }
+}
+
+impl<A, B, EM, I, OT, S> Feedback<EM, I, OT, S> for SensitivityFeedback<A, B>
+where
+    A: Feedback<EM, I, OT, S>,
+    B: Feedback<EM, I, OT, S> + Named,
+    S: HasNamedMetadata,
+{
+    fn is_interesting(
+        &mut self,
+        state: &mut S,
+        manager: &mut EM,
+        input: &I,
+        observers: &OT,
+        exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        // Always run both, so they keep track of the novelties for `append_metadata`
+        let new_edges = self
+            .edges
+            .is_interesting(state, manager, input, observers, exit_kind)?;
+        let new_sensitive = self
+            .sensitive
+            .is_interesting(state, manager, input, observers, exit_kind)?
+            && self.pressure(state)? < self.max_pressure;
+
+        #[cfg(feature = "track_hit_feedbacks")]
+        {
+            self.last_result = Some((new_edges, new_sensitive));
+        }
+        Ok(new_edges || new_sensitive)
+    }
+
+    #[cfg(feature = "introspection")]
+    fn is_interesting_introspection(
+        &mut self,
+        state: &mut S,
+        manager: &mut EM,
+        input: &I,
+        observers: &OT,
+        exit_kind: &ExitKind,
+    ) -> Result<bool, Error>
+    where
+        S: HasClientPerfMonitor,
+    {
+        let new_edges = self
+            .edges
+            .is_interesting_introspection(state, manager, input, observers, exit_kind)?;
+        let new_sensitive = self
+            .sensitive
+            .is_interesting_introspection(state, manager, input, observers, exit_kind)?
+            && self.pressure(state)? < self.max_pressure;
+
+        #[cfg(feature = "track_hit_feedbacks")]
+        {
+            self.last_result = Some((new_edges, new_sensitive));
+        }
+        Ok(new_edges || new_sensitive)
+    }
+
+    #[cfg(feature = "track_hit_feedbacks")]
+    fn last_result(&self) -> Result<bool, Error> {
+        self.last_result
+            .map(|(new_edges, new_sensitive)| new_edges || new_sensitive)
+            .ok_or(premature_last_result_err())
+    }
+
+    #[cfg(feature = "track_hit_feedbacks")]
+    fn append_hit_feedbacks(&self, list: &mut Vec<Cow<'static, str>>) -> Result<(), Error> {
+        let (new_edges, new_sensitive) = self.last_result.ok_or(premature_last_result_err())?;
+        if new_edges {
+            self.edges.append_hit_feedbacks(list)?;
+        }
+        if new_sensitive {
+            self.sensitive.append_hit_feedbacks(list)?;
+        }
+        Ok(())
+    }
+
+    fn append_metadata(
+        &mut self,
+        state: &mut S,
+        manager: &mut EM,
+        observers: &OT,
+        testcase: &mut Testcase<I>,
+    ) -> Result<(), Error> {
+        self.edges
+            .append_metadata(state, manager, observers, testcase)?;
+        self.sensitive
+            .append_metadata(state, manager, observers, testcase)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tuple_list::tuple_list;
+
+    use super::SensitivityFeedback;
+    use crate::{
+        corpus::Testcase,
+        events::NopEventManager,
+        executors::ExitKind,
+        feedbacks::{Feedback, MaxMapFeedback, StateInitializer},
+        inputs::BytesInput,
+        observers::{MapObserver, StdMapObserver},
+        state::NopState,
+    };
+
+    #[test]
+    fn test_sensitivity_feedback() {
+        #[cfg(any(not(feature = "serdeany_autoreg"), miri))]
+        unsafe {
+            crate::feedbacks::MapFeedbackMetadata::<u8>::register();
+        }
+
+        let mut state: NopState<BytesInput> = NopState::new();
+        let mut mgr = NopEventManager::default();
+        let input = BytesInput::new(vec![0]);
+
+        let edges = StdMapObserver::owned("edges", vec![0_u8; 4]);
+        let ngrams = StdMapObserver::owned("ngrams", vec![0_u8; 4]);
+        let mut feedback = SensitivityFeedback::with_max_pressure(
+            MaxMapFeedback::new(&edges),
+            MaxMapFeedback::new(&ngrams),
+            0.5,
+        );
+        feedback.init_state(&mut state).unwrap();
+
+        let mut run = |feedback: &mut SensitivityFeedback<_, _>, edge: usize, ngram: usize| {
+            let mut edges = StdMapObserver::owned("edges", vec![0_u8; 4]);
+            let mut ngrams = StdMapObserver::owned("ngrams", vec![0_u8; 4]);
+            edges.set(edge, 1);
+            ngrams.set(ngram, 1);
+            let observers = tuple_list!(edges, ngrams);
+            let interesting = feedback
+                .is_interesting(&mut state, &mut mgr, &input, &observers, &ExitKind::Ok)
+                .unwrap();
+            if interesting {
+                feedback
+                    .append_metadata(
+                        &mut state,
+                        &mut mgr,
+                        &observers,
+                        &mut Testcase::new(input.clone()),
+                    )
+                    .unwrap();
+            }
+            interesting
+        };
+
+        assert!(run(&mut feedback, 0, 0));
+        // A new n-gram on a known edge
+        assert!(run(&mut feedback, 0, 1));
+        // Half of the n-gram map is covered, new n-grams alone are ignored now
+        assert!(!run(&mut feedback, 0, 2));
+        assert!(run(&mut feedback, 1, 2));
+        assert!((feedback.pressure(&state).unwrap() - 0.75).abs() < f64::EPSILON);
+    }
+}
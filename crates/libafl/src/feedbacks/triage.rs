@@ -0,0 +1,528 @@
+//! The [`CrashBucketFeedback`] groups solutions into buckets, by exit kind, normalized stack trace and sanitizer report.
+//!
+//! Only the first solution of each bucket is interesting, unless duplicates are explicitly kept.
+//! The number of buckets is reported as [`UserStats`], so monitors show unique crashes instead of raw counts.
+//! Every client keeps its own buckets, so the count is reported per client; see
+//! [`CrashBucketFeedback::stats_name`].
+
+use alloc::{
+    borrow::Cow,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{fmt::Write as _, marker::PhantomData};
+use std::{fs, path::PathBuf};
+
+use hashbrown::HashMap;
+use libafl_bolts::{
+    Named, generic_hash_std, impl_serdeany,
+    tuples::{Handle, Handled, MatchName, MatchNameRef},
+};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "track_hit_feedbacks")]
+use crate::feedbacks::premature_last_result_err;
+use crate::{
+    Error, HasMetadata, HasNamedMetadata,
+    corpus::Testcase,
+    events::{Event, EventFirer, EventWithStats},
+    executors::ExitKind,
+    feedbacks::{Feedback, HasObserverHandle, StateInitializer},
+    inputs::Input,
+    monitors::stats::{AggregatorOps, UserStats, UserStatsValue},
+    observers::{ObserverWithHashField, StdErrObserver},
+    state::HasExecutions,
+};
+
+/// The prefix of the metadata names
+pub const CRASH_BUCKET_FEEDBACK_PREFIX: &str = "crashbucketfeedback_";
+
+/// The default name of the [`UserStats`] holding the number of buckets
+pub const CRASH_BUCKETS_STATS_NAME: &str = "crash_buckets";
+
+/// The default number of stack frames that make up a bucket
+pub const DEFAULT_BUCKET_FRAMES: usize = 5;
+
+/// Frames of the sanitizer runtime, which are the same for every report
+const RUNTIME_FRAME_PREFIXES: [&str; 6] = [
+    "__asan",
+    "__lsan",
+    "__msan",
+    "__ubsan",
+    "__sanitizer",
+    "__interceptor_",
+];
+
+/// A bucket of solutions sharing the same exit kind, stack trace and sanitizer report
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CrashBucket {
+    /// The id of this bucket, a hash of the other fields
+    pub id: u64,
+    /// The exit kind of the solutions
+    pub exit_kind: ExitKind,
+    /// The sanitizer and the kind of bug it reported, e.g., `AddressSanitizer: heap-buffer-overflow`
+    pub sanitizer: Option<String>,
+    /// The normalized top frames of the stack trace, parsed from the sanitizer report
+    pub frames: Vec<String>,
+    /// The hash of the stack trace observer, used if no frames could be parsed
+    pub stack_hash: Option<u64>,
+}
+
+impl CrashBucket {
+    /// Creates a bucket and computes its id
+    #[must_use]
+    pub fn new(
+        exit_kind: ExitKind,
+        sanitizer: Option<String>,
+        frames: Vec<String>,
+        stack_hash: Option<u64>,
+    ) -> Self {
+        // The frames are stable across builds and runs, prefer them over the address-based hash
+        let stack_hash = if frames.is_empty() { stack_hash } else { None };
+        let id = generic_hash_std(&(format!("{exit_kind:?}"), &sanitizer, &frames, stack_hash));
+        Self {
+            id,
+            exit_kind,
+            sanitizer,
+            frames,
+            stack_hash,
+        }
+    }
+
+    /// The name of this bucket, the hex-encoded id
+    #[must_use]
+    pub fn name(&self) -> String {
+        format!("{:016x}", self.id)
+    }
+}
+
+/// Attached to each solution by the [`CrashBucketFeedback`]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrashBucketMetadata {
+    /// The bucket of this solution
+    pub bucket: CrashBucket,
+}
+
+impl_serdeany!(CrashBucketMetadata);
+
+/// The statistics of a bucket
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrashBucketStats {
+    /// The bucket
+    pub bucket: CrashBucket,
+    /// How often a solution of this bucket was observed
+    pub hits: u64,
+    /// The number of executions when the bucket was first observed
+    pub first_seen: u64,
+}
+
+/// The state of the [`CrashBucketFeedback`]: all buckets seen so far
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CrashBucketsMetadata {
+    buckets: HashMap<u64, CrashBucketStats>,
+}
+
+impl_serdeany!(CrashBucketsMetadata);
+
+impl CrashBucketsMetadata {
+    /// Creates a new [`CrashBucketsMetadata`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of unique buckets
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.buckets.len()
+    }
+
+    /// If no bucket was seen yet
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.buckets.is_empty()
+    }
+
+    /// The statistics of a bucket, by id
+    #[must_use]
+    pub fn get(&self, id: u64) -> Option<&CrashBucketStats> {
+        self.buckets.get(&id)
+    }
+
+    /// All buckets
+    pub fn iter(&self) -> impl Iterator<Item = &CrashBucketStats> {
+        self.buckets.values()
+    }
+
+    /// Records a hit of the bucket, returns `true` if it is new
+    pub fn hit(&mut self, bucket: &CrashBucket, executions: u64) -> bool {
+        let mut is_new = false;
+        let stats = self.buckets.entry(bucket.id).or_insert_with(|| {
+            is_new = true;
+            CrashBucketStats {
+                bucket: bucket.clone(),
+                hits: 0,
+                first_seen: executions,
+            }
+        });
+        stats.hits += 1;
+        is_new
+    }
+}
+
+/// Parses a sanitizer report, as printed to stderr, into the sanitizer and bug kind, and the top `max_frames`
+/// function names of the first stack trace.
+///
+/// Addresses, offsets and frames of the sanitizer runtime are dropped, so that the result is stable across runs.
+#[must_use]
+pub fn parse_sanitizer_report(report: &str, max_frames: usize) -> (Option<String>, Vec<String>) {
+    let mut sanitizer = None;
+    let mut frames = Vec::new();
+    let mut in_trace = false;
+
+    for line in report.lines().map(str::trim) {
+        if sanitizer.is_none() {
+            // e.g. `==1234==ERROR: AddressSanitizer: heap-buffer-overflow on address ...`
+            if let Some(pos) = line.find("ERROR: ") {
+                let mut words = line[pos + 7..].split_whitespace();
+                if let (Some(name), Some(kind)) = (words.next(), words.next()) {
+                    sanitizer = Some(format!("{name} {kind}"));
+                }
+            } else if let Some(pos) = line.find("runtime error: ") {
+                // UBSan: `file.c:12:5: runtime error: signed integer overflow: ...`
+                let kind = line[pos + 15..].split(':').next().unwrap_or_default();
+                sanitizer = Some(format!("UndefinedBehaviorSanitizer: {kind}"));
+            }
+        }
+
+        // e.g. `#0 0x4f3c1b in parse_header /src/parser.c:42:7`
+        let Some(frame) = line.strip_prefix('#') else {
+            if in_trace {
+                // Only the first stack trace is part of the bucket
+                break;
+            }
+            continue;
+        };
+        in_trace = true;
+        let mut words = frame.split_whitespace().skip(2);
+        let (Some("in"), Some(function)) = (words.next(), words.next()) else {
+            continue;
+        };
+        if RUNTIME_FRAME_PREFIXES
+            .iter()
+            .any(|prefix| function.starts_with(prefix))
+        {
+            continue;
+        }
+        frames.push(function.to_string());
+        if frames.len() >= max_frames {
+            break;
+        }
+    }
+
+    (sanitizer, frames)
+}
+
+/// A [`CrashBucketFeedback`] puts each solution in a bucket and only considers solutions of new buckets interesting.
+///
+/// The bucket is derived from the [`ExitKind`], the hash of a stack trace observer (e.g. the
+/// [`crate::observers::BacktraceObserver`] or [`crate::observers::AsanBacktraceObserver`]), and, if a
+/// [`StdErrObserver`] is given, the sanitizer report parsed from stderr.
+/// Use it in the objective, combined with the crash and timeout feedbacks.
+#[derive(Debug, Clone)]
+pub struct CrashBucketFeedback<O> {
+    name: Cow<'static, str>,
+    o_ref: Handle<O>,
+    stderr_ref: Option<Handle<StdErrObserver>>,
+    max_frames: usize,
+    keep_duplicates: bool,
+    summary_dir: Option<PathBuf>,
+    stats_name: Cow<'static, str>,
+    last_bucket: Option<CrashBucket>,
+    #[cfg(feature = "track_hit_feedbacks")]
+    // The previous run's result of `Self::is_interesting`
+    last_result: Option<bool>,
+}
+
+impl<O> CrashBucketFeedback<O>
+where
+    O: Named,
+{
+    /// Creates a new [`CrashBucketFeedback`] using the hash of the given stack trace observer
+    #[must_use]
+    pub fn new(observer: &O) -> Self {
+        Self {
+            name: Cow::from(CRASH_BUCKET_FEEDBACK_PREFIX.to_string() + observer.name()),
+            o_ref: observer.handle(),
+            stderr_ref: None,
+            max_frames: DEFAULT_BUCKET_FRAMES,
+            keep_duplicates: false,
+            summary_dir: None,
+            stats_name: Cow::Borrowed(CRASH_BUCKETS_STATS_NAME),
+            last_bucket: None,
+            #[cfg(feature = "track_hit_feedbacks")]
+            last_result: None,
+        }
+    }
+
+    /// Also parse the sanitizer report from the output of the given [`StdErrObserver`]
+    #[must_use]
+    pub fn with_stderr(mut self, observer: &StdErrObserver) -> Self {
+        self.stderr_ref = Some(observer.handle());
+        self
+    }
+
+    /// The number of top stack frames that make up a bucket, [`DEFAULT_BUCKET_FRAMES`] by default
+    #[must_use]
+    pub fn max_frames(mut self, max_frames: usize) -> Self {
+        self.max_frames = max_frames;
+        self
+    }
+
+    /// Consider all solutions interesting, still bucketing them
+    #[must_use]
+    pub fn keep_duplicates(mut self, keep_duplicates: bool) -> Self {
+        self.keep_duplicates = keep_duplicates;
+        self
+    }
+
+    /// Maintain a directory per bucket in `dir`, with a `summary.txt`, the sanitizer `report.txt`,
+    /// and the first `input` of the bucket
+    #[must_use]
+    pub fn summary_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.summary_dir = Some(dir.into());
+        self
+    }
+
+    /// The name of the [`UserStats`] holding the number of buckets, [`CRASH_BUCKETS_STATS_NAME`] by default.
+    ///
+    /// The buckets of one client are not known to the others, and the same bucket may be found by several clients,
+    /// so the counts of the clients are not aggregated. Give each client a distinct name, e.g., including its core
+    /// id, to show all of them next to each other.
+    #[must_use]
+    pub fn stats_name<N: Into<Cow<'static, str>>>(mut self, name: N) -> Self {
+        self.stats_name = name.into();
+        self
+    }
+
+    fn write_summary(&self, stats: &CrashBucketStats, report: Option<&str>) -> Result<(), Error> {
+        let Some(summary_dir) = &self.summary_dir else {
+            return Ok(());
+        };
+        let bucket = &stats.bucket;
+        let dir = summary_dir.join(bucket.name());
+        fs::create_dir_all(&dir)?;
+
+        let mut summary = String::new();
+        writeln!(summary, "bucket: {}", bucket.name()).unwrap();
+        writeln!(summary, "exit_kind: {:?}", bucket.exit_kind).unwrap();
+        if let Some(sanitizer) = &bucket.sanitizer {
+            writeln!(summary, "sanitizer: {sanitizer}").unwrap();
+        }
+        if let Some(stack_hash) = bucket.stack_hash {
+            writeln!(summary, "stack_hash: {stack_hash:016x}").unwrap();
+        }
+        writeln!(summary, "hits: {}", stats.hits).unwrap();
+        writeln!(summary, "first_seen: {}", stats.first_seen).unwrap();
+        for (i, frame) in bucket.frames.iter().enumerate() {
+            writeln!(summary, "#{i} {frame}").unwrap();
+        }
+        fs::write(dir.join("summary.txt"), summary)?;
+
+        if let Some(report) = report {
+            fs::write(dir.join("report.txt"), report)?;
+        }
+        Ok(())
+    }
+}
+
+impl<O, S> StateInitializer<S> for CrashBucketFeedback<O>
+where
+    S: HasNamedMetadata,
+{
+    fn init_state(&mut self, state: &mut S) -> Result<(), Error> {
+        state.add_named_metadata_checked(&self.name, CrashBucketsMetadata::new())?;
+        Ok(())
+    }
+}
+
+impl<O, EM, I, OT, S> Feedback<EM, I, OT, S> for CrashBucketFeedback<O>
+where
+    O: ObserverWithHashField + Named,
+    EM: EventFirer<I, S>,
+    I: Input,
+    OT: MatchName,
+    S: HasNamedMetadata + HasExecutions,
+{
+    fn is_interesting(
+        &mut self,
+        state: &mut S,
+        manager: &mut EM,
+        _input: &I,
+        observers: &OT,
+        exit_kind: &ExitKind,
+    ) -> Result<bool, Error> {
+        self.last_bucket = None;
+        let res = if *exit_kind == ExitKind::Ok {
+            false
+        } else {
+            let stack_hash = observers
+                .get(&self.o_ref)
+                .ok_or(Error::illegal_state(
+                    "CrashBucketFeedback is missing its observer",
+                ))?
+                .hash();
+            let report = self
+                .stderr_ref
+                .as_ref()
+                .and_then(|stderr_ref| observers.get(stderr_ref))
+                .and_then(|observer| observer.output.as_ref())
+                .map(|output| String::from_utf8_lossy(output).into_owned());
+            let (sanitizer, frames) = report
+                .as_deref()
+                .map(|report| parse_sanitizer_report(report, self.max_frames))
+                .unwrap_or_default();
+            let bucket = CrashBucket::new(*exit_kind, sanitizer, frames, stack_hash);
+
+            let executions = *state.executions();
+            let buckets = state.named_metadata_mut::<CrashBucketsMetadata>(&self.name)?;
+            let is_new = buckets.hit(&bucket, executions);
+            let count = buckets.len();
+            let stats = buckets.get(bucket.id).unwrap().clone();
+            self.write_summary(&stats, if is_new { report.as_deref() } else { None })?;
+
+            if is_new {
+                manager.fire(
+                    state,
+                    EventWithStats::with_current_time(
+                        Event::UpdateUserStats {
+                            name: self.stats_name.clone(),
+                            value: UserStats::new(
+                                UserStatsValue::Number(count as u64),
+                                AggregatorOps::None,
+                            ),
+                            phantom: PhantomData,
+                        },
+                        executions,
+                    ),
+                )?;
+            }
+
+            self.last_bucket = Some(bucket);
+            is_new || self.keep_duplicates
+        };
+
+        #[cfg(feature = "track_hit_feedbacks")]
+        {
+            self.last_result = Some(res);
+        }
+        Ok(res)
+    }
+
+    #[cfg(feature = "track_hit_feedbacks")]
+    fn last_result(&self) -> Result<bool, Error> {
+        self.last_result.ok_or(premature_last_result_err())
+    }
+
+    fn append_metadata(
+        &mut self,
+        _state: &mut S,
+        _manager: &mut EM,
+        _observers: &OT,
+        testcase: &mut Testcase<I>,
+    ) -> Result<(), Error> {
+        let Some(bucket) = self.last_bucket.take() else {
+            return Ok(());
+        };
+        if let (Some(summary_dir), Some(input)) = (&self.summary_dir, testcase.input()) {
+            let path = summary_dir.join(bucket.name()).join("input");
+            if !path.exists() {
+                input.to_file(path)?;
+            }
+        }
+        testcase.add_metadata(CrashBucketMetadata { bucket });
+        Ok(())
+    }
+}
+
+impl<O> Named for CrashBucketFeedback<O> {
+    #[inline]
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<O> HasObserverHandle for CrashBucketFeedback<O> {
+    type Observer = O;
+
+    #[inline]
+    fn observer_handle(&self) -> &Handle<O> {
+        &self.o_ref
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CrashBucket, CrashBucketsMetadata, parse_sanitizer_report};
+    use crate::executors::ExitKind;
+
+    const REPORT: &str = "=================================================================
+==4242==ERROR: AddressSanitizer: heap-buffer-overflow on address 0x602000000011 at pc 0x4f3c1b bp 0x7ffd sp 0x7ffc
+READ of size 1 at 0x602000000011 thread T0
+    #0 0x4f3c1b in __asan_memcpy (/out/fuzzer+0x4f3c1b)
+    #1 0x4f3d2a in parse_header /src/parser.c:42:7
+    #2 0x4f3e39 in parse /src/parser.c:97:3
+    #3 0x4f3f48 in LLVMFuzzerTestOneInput /src/fuzz.c:10:3
+
+0x602000000011 is located 0 bytes to the right of 1-byte region
+allocated by thread T0 here:
+    #0 0x4a1b2c in malloc (/out/fuzzer+0x4a1b2c)
+SUMMARY: AddressSanitizer: heap-buffer-overflow /src/parser.c:42:7 in parse_header";
+
+    #[test]
+    fn test_parse_sanitizer_report() {
+        let (sanitizer, frames) = parse_sanitizer_report(REPORT, 2);
+        assert_eq!(
+            sanitizer.as_deref(),
+            Some("AddressSanitizer: heap-buffer-overflow")
+        );
+        assert_eq!(frames, ["parse_header", "parse"]);
+
+        let (sanitizer, frames) = parse_sanitizer_report(
+            "fuzz.c:3:5: runtime error: signed integer overflow: 1 + 2147483647",
+            5,
+        );
+        assert_eq!(
+            sanitizer.as_deref(),
+            Some("UndefinedBehaviorSanitizer: signed integer overflow")
+        );
+        assert!(frames.is_empty());
+    }
+
+    #[test]
+    fn test_buckets() {
+        let (sanitizer, frames) = parse_sanitizer_report(REPORT, 5);
+        // Different addresses, but the same frames end up in the same bucket
+        let first = CrashBucket::new(ExitKind::Crash, sanitizer.clone(), frames.clone(), Some(1));
+        let second = CrashBucket::new(ExitKind::Crash, sanitizer.clone(), frames.clone(), Some(2));
+        let timeout = CrashBucket::new(ExitKind::Timeout, sanitizer, frames, Some(1));
+        assert_eq!(first.id, second.id);
+        assert_ne!(first.id, timeout.id);
+
+        let mut buckets = CrashBucketsMetadata::new();
+        assert!(buckets.hit(&first, 10));
+        assert!(!buckets.hit(&second, 20));
+        assert!(buckets.hit(&timeout, 30));
+        assert_eq!(buckets.len(), 2);
+        assert_eq!(buckets.get(first.id).unwrap().hits, 2);
+        assert_eq!(buckets.get(first.id).unwrap().first_seen, 10);
+    }
+}
@@ -6,14 +6,21 @@ use alloc::{
 use core::fmt::Debug;
 use std::{fs, io::BufReader, path::Path};
 
+use hashbrown::HashMap;
 use libafl_bolts::rands::Rand;
 
 pub use crate::common::nautilus::grammartec::newtypes::NTermId;
 #[cfg(feature = "nautilus_py")]
 use crate::nautilus::grammartec::python_grammar_loader;
 use crate::{
-    Error, common::nautilus::grammartec::context::Context, generators::Generator,
-    inputs::nautilus::NautilusInput, state::HasRand,
+    Error,
+    common::{
+        grammar::{Grammar, Symbol},
+        nautilus::grammartec::context::Context,
+    },
+    generators::Generator,
+    inputs::nautilus::NautilusInput,
+    state::HasRand,
 };
 
 /// The nautilus context for a generator
@@ -85,9 +92,64 @@ impl NautilusContext {
         Some(Self { ctx })
     }
 
-    /// Create a new [`NautilusContext`] from a file
+    /// Returns a new [`NautilusContext`] for an imported ANTLR4 or EBNF [`Grammar`].
+    ///
+    /// Character classes become regex rules, terminals containing `{`, `}` or `\` become rules of their own.
+    pub fn from_grammar(tree_depth: usize, grammar: &Grammar) -> Result<Self, Error> {
+        let productions = grammar.productions()?;
+        let mut ctx = Context::new();
+        let mut helpers: HashMap<Symbol, String> = HashMap::new();
+        for production in &productions {
+            let mut format = Vec::new();
+            for symbol in &production.rhs {
+                match symbol {
+                    Symbol::NonTerminal(nt) => {
+                        format.extend_from_slice(b"{");
+                        format.extend_from_slice(nt.as_bytes());
+                        format.extend_from_slice(b"}");
+                    }
+                    Symbol::Terminal(term) if !term.contains(['{', '}', '\\']) => {
+                        format.extend_from_slice(term.as_bytes());
+                    }
+                    Symbol::Terminal(_) | Symbol::Class(_) => {
+                        // See `Grammar::productions` for the names of generated nonterminals
+                        let count = helpers.len();
+                        let nt = helpers.entry(symbol.clone()).or_insert_with(|| {
+                            let nt = format!("Symbol--{count}");
+                            match symbol {
+                                Symbol::Terminal(term) => ctx.add_term_rule(&nt, term.as_bytes()),
+                                Symbol::Class(class) => ctx.add_regex(&nt, &class.to_regex()),
+                                Symbol::NonTerminal(_) => unreachable!(),
+                            };
+                            nt
+                        });
+                        format.extend_from_slice(b"{");
+                        format.extend_from_slice(nt.as_bytes());
+                        format.extend_from_slice(b"}");
+                    }
+                }
+            }
+            ctx.add_rule(&production.lhs, &format);
+        }
+        let root = format!("{{{}}}", productions[0].lhs);
+        ctx.add_rule("START", root.as_bytes());
+        ctx.initialize(tree_depth);
+        Ok(Self { ctx })
+    }
+
+    /// Create a new [`NautilusContext`] from a file.
+    ///
+    /// Loads Python grammars (`.py`, with the `nautilus_py` feature), ANTLR4 (`.g4`) and W3C-style EBNF (`.ebnf`)
+    /// grammars, see [`Grammar`], and JSON grammars otherwise.
     pub fn from_file<P: AsRef<Path>>(tree_depth: usize, grammar_file: P) -> Result<Self, Error> {
         let grammar_file = grammar_file.as_ref();
+        if grammar_file
+            .extension()
+            .is_some_and(|ext| ext == "g4" || ext == "ebnf")
+        {
+            log::debug!("Creating NautilusContext from imported grammar");
+            return Self::from_grammar(tree_depth, &Grammar::from_file(grammar_file)?);
+        }
         if grammar_file.extension().unwrap_or_default() == "py" {
             #[cfg(feature = "nautilus_py")]
             {
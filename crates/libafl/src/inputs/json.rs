@@ -0,0 +1,461 @@
+//! Structured inputs of JSON, YAML or TOML documents, mutated as trees, see [`JsonInput`].
+
+use alloc::{
+    rc::Rc,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::cell::RefCell;
+use std::{fs, path::Path};
+
+use libafl_bolts::{HasLen, ownedref::OwnedSlice};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    Error,
+    inputs::{HasTargetBytes, Input},
+};
+
+/// The format a [`JsonInput`] is parsed from and serialized to.
+///
+/// YAML and TOML documents need the `json_input_yaml` and `json_input_toml` features, parsing and serializing them
+/// fails without.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum DocumentFormat {
+    /// JSON documents
+    #[default]
+    Json,
+    /// YAML documents
+    Yaml,
+    /// TOML documents. The root of a TOML document is a table, so other roots are serialized as the `value` key,
+    /// and `null`s, which TOML has no notion of, are left out.
+    Toml,
+}
+
+impl DocumentFormat {
+    /// The format of the file, by its extension, JSON for unknown extensions
+    #[must_use]
+    pub fn from_path<P>(path: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        match path
+            .as_ref()
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some("yaml" | "yml") => Self::Yaml,
+            Some("toml") => Self::Toml,
+            _ => Self::Json,
+        }
+    }
+
+    /// The error for a format LibAFL was built without
+    #[cfg(not(all(feature = "json_input_yaml", feature = "json_input_toml")))]
+    fn missing_feature(self) -> Error {
+        let feature = match self {
+            Self::Json => "json_input",
+            Self::Yaml => "json_input_yaml",
+            Self::Toml => "json_input_toml",
+        };
+        Error::illegal_argument(format!(
+            "{self:?} documents need the `{feature}` feature of LibAFL"
+        ))
+    }
+
+    /// Parses a document of this format
+    pub fn parse(self, bytes: &[u8]) -> Result<Value, Error> {
+        match self {
+            Self::Json => serde_json::from_slice(bytes)
+                .map_err(|err| Error::illegal_argument(format!("Invalid JSON document: {err}"))),
+            #[cfg(feature = "json_input_yaml")]
+            Self::Yaml => serde_yaml::from_slice(bytes)
+                .map_err(|err| Error::illegal_argument(format!("Invalid YAML document: {err}"))),
+            #[cfg(feature = "json_input_toml")]
+            Self::Toml => {
+                let document = core::str::from_utf8(bytes).map_err(|err| {
+                    Error::illegal_argument(format!("Invalid TOML document: {err}"))
+                })?;
+                toml::from_str(document)
+                    .map_err(|err| Error::illegal_argument(format!("Invalid TOML document: {err}")))
+            }
+            #[cfg(not(all(feature = "json_input_yaml", feature = "json_input_toml")))]
+            _ => Err(self.missing_feature()),
+        }
+    }
+
+    /// Serializes the value as a document of this format
+    pub fn serialize(self, value: &Value) -> Result<Vec<u8>, Error> {
+        match self {
+            Self::Json => Ok(value.to_string().into_bytes()),
+            #[cfg(feature = "json_input_yaml")]
+            Self::Yaml => serde_yaml::to_string(value)
+                .map(String::into_bytes)
+                .map_err(|err| Error::serialize(format!("Could not serialize YAML: {err}"))),
+            #[cfg(feature = "json_input_toml")]
+            Self::Toml => {
+                let table = match to_toml(value) {
+                    Some(toml::Value::Table(table)) => table,
+                    Some(value) => toml::Table::from_iter([("value".to_string(), value)]),
+                    None => toml::Table::new(),
+                };
+                toml::to_string(&table)
+                    .map(String::into_bytes)
+                    .map_err(|err| Error::serialize(format!("Could not serialize TOML: {err}")))
+            }
+            #[cfg(not(all(feature = "json_input_yaml", feature = "json_input_toml")))]
+            _ => Err(self.missing_feature()),
+        }
+    }
+}
+
+/// Converts the value to TOML, leaving out `null`s
+#[cfg(feature = "json_input_toml")]
+fn to_toml(value: &Value) -> Option<toml::Value> {
+    Some(match value {
+        Value::Null => return None,
+        Value::Bool(value) => toml::Value::Boolean(*value),
+        Value::Number(number) => number
+            .as_i64()
+            .map(toml::Value::Integer)
+            .or_else(|| number.as_f64().map(toml::Value::Float))?,
+        Value::String(value) => toml::Value::String(value.clone()),
+        Value::Array(values) => toml::Value::Array(values.iter().filter_map(to_toml).collect()),
+        Value::Object(members) => toml::Value::Table(
+            members
+                .iter()
+                .filter_map(|(key, value)| Some((key.clone(), to_toml(value)?)))
+                .collect(),
+        ),
+    })
+}
+
+/// A syntax error a [`JsonInput`] is serialized with, to test the error handling of the parser of the target.
+///
+/// Positions wrap around the length of the serialized document.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Malformation {
+    /// Cut the document off at the position
+    Truncate(usize),
+    /// Delete the byte at the position
+    Delete(usize),
+    /// Insert the byte at the position
+    Insert(usize, u8),
+    /// Repeat the byte at the position
+    Repeat(usize),
+}
+
+impl Malformation {
+    /// Applies the malformation to the serialized document
+    pub fn apply(self, document: &mut Vec<u8>) {
+        let wrap = |pos: usize, len: usize| pos % len.max(1);
+        match self {
+            Self::Truncate(pos) => document.truncate(wrap(pos, document.len())),
+            Self::Delete(pos) => {
+                if !document.is_empty() {
+                    document.remove(wrap(pos, document.len()));
+                }
+            }
+            Self::Insert(pos, byte) => document.insert(wrap(pos, document.len() + 1), byte),
+            Self::Repeat(pos) => {
+                if !document.is_empty() {
+                    let pos = wrap(pos, document.len());
+                    document.insert(pos, document[pos]);
+                }
+            }
+        }
+    }
+}
+
+/// An input for structure-aware fuzzing of targets parsing JSON, YAML or TOML documents, e.g., config files.
+///
+/// The document is kept as a tree of [`Value`]s, mutated by the tree mutations, e.g.,
+/// [`crate::mutators::json_mutations`], and serialized in its [`DocumentFormat`] for the target. Inputs only turn
+/// malformed, with a [`Malformation`], if the [`crate::mutators::JsonMalformMutator`] is used.
+///
+/// Corpus files store the input serialized, like other inputs, so they keep the format and the malformation. To
+/// load plain documents as seeds, use [`JsonInput::from_document_file`], e.g., as the loader of
+/// [`crate::state::StdState::load_initial_inputs_with_loader`].
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct JsonInput {
+    #[serde(with = "value_serializer")]
+    value: Value,
+    format: DocumentFormat,
+    malformation: Option<Malformation>,
+}
+
+/// Stores the [`Value`] as JSON, as the serializers of the fuzzer, e.g. `postcard`, are not self-describing
+mod value_serializer {
+    use alloc::string::{String, ToString};
+
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+    use serde_json::Value;
+
+    pub fn serialize<S>(value: &Value, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&value.to_string())
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let json = String::deserialize(deserializer)?;
+        serde_json::from_str(&json).map_err(D::Error::custom)
+    }
+}
+
+impl Input for JsonInput {}
+
+/// Rc Ref-cell from Input
+impl From<JsonInput> for Rc<RefCell<JsonInput>> {
+    fn from(input: JsonInput) -> Self {
+        Rc::new(RefCell::new(input))
+    }
+}
+
+impl JsonInput {
+    /// Creates a new [`JsonInput`] of the value, serialized in the format
+    #[must_use]
+    pub fn new(value: Value, format: DocumentFormat) -> Self {
+        Self {
+            value,
+            format,
+            malformation: None,
+        }
+    }
+
+    /// Parses a document of the format into a new [`JsonInput`]
+    pub fn parse(document: &[u8], format: DocumentFormat) -> Result<Self, Error> {
+        Ok(Self::new(format.parse(document)?, format))
+    }
+
+    /// Loads a plain document from the file, in the format of its extension, see [`DocumentFormat::from_path`]
+    pub fn from_document_file<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let format = DocumentFormat::from_path(&path);
+        Self::parse(&fs::read(path)?, format)
+    }
+
+    /// The root of the document
+    #[must_use]
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    /// The root of the document, mutable
+    #[must_use]
+    pub fn value_mut(&mut self) -> &mut Value {
+        &mut self.value
+    }
+
+    /// The format of the document
+    #[must_use]
+    pub fn format(&self) -> DocumentFormat {
+        self.format
+    }
+
+    /// The syntax error the document is serialized with, if any
+    #[must_use]
+    pub fn malformation(&self) -> Option<Malformation> {
+        self.malformation
+    }
+
+    /// Sets the syntax error the document is serialized with
+    pub fn set_malformation(&mut self, malformation: Option<Malformation>) {
+        self.malformation = malformation;
+    }
+
+    /// The document, in its format, with its malformation
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut document = self.format.serialize(&self.value)?;
+        if let Some(malformation) = self.malformation {
+            malformation.apply(&mut document);
+        }
+        Ok(document)
+    }
+
+    /// The keys of all objects in the document, e.g., to add them to the
+    /// [`crate::mutators::Tokens`] the mutators pick new keys from
+    #[must_use]
+    pub fn key_tokens(&self) -> Vec<Vec<u8>> {
+        fn collect(value: &Value, keys: &mut Vec<Vec<u8>>) {
+            match value {
+                Value::Array(values) => values.iter().for_each(|value| collect(value, keys)),
+                Value::Object(members) => {
+                    for (key, value) in members {
+                        keys.push(key.as_bytes().to_vec());
+                        collect(value, keys);
+                    }
+                }
+                _ => {}
+            }
+        }
+        let mut keys = Vec::new();
+        collect(&self.value, &mut keys);
+        keys
+    }
+}
+
+impl HasLen for JsonInput {
+    /// The number of values in the document
+    fn len(&self) -> usize {
+        fn count(value: &Value) -> usize {
+            1 + match value {
+                Value::Array(values) => values.iter().map(count).sum(),
+                Value::Object(members) => members.values().map(count).sum(),
+                _ => 0,
+            }
+        }
+        count(&self.value)
+    }
+}
+
+impl HasTargetBytes for JsonInput {
+    /// The document, see [`JsonInput::to_bytes`].
+    ///
+    /// # Panics
+    /// Panics if LibAFL was built without the feature for the format of the input.
+    fn target_bytes(&self) -> OwnedSlice<'_, u8> {
+        OwnedSlice::from(
+            self.to_bytes()
+                .unwrap_or_else(|err| panic!("Could not serialize the input: {err}")),
+        )
+    }
+}
+
+impl From<Value> for JsonInput {
+    fn from(value: Value) -> Self {
+        Self::new(value, DocumentFormat::Json)
+    }
+}
+
+impl TryFrom<&str> for JsonInput {
+    type Error = Error;
+
+    fn try_from(document: &str) -> Result<Self, Self::Error> {
+        Self::parse(document.as_bytes(), DocumentFormat::Json)
+    }
+}
+
+impl TryFrom<JsonInput> for String {
+    type Error = Error;
+
+    fn try_from(input: JsonInput) -> Result<Self, Self::Error> {
+        Ok(Self::from_utf8_lossy(&input.to_bytes()?).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+    use std::{env, fs, process};
+
+    use libafl_bolts::HasLen;
+    use serde_json::json;
+
+    use super::{DocumentFormat, JsonInput, Malformation};
+    use crate::{
+        corpus::{Corpus, OnDiskCorpus, Testcase},
+        inputs::HasTargetBytes,
+    };
+
+    #[test]
+    fn test_json_input() {
+        let input = JsonInput::try_from(r#"{"a": [1, true, null], "b": {"c": "d"}}"#).unwrap();
+        assert_eq!(input.len(), 7);
+        assert_eq!(
+            input.key_tokens(),
+            [b"a".to_vec(), b"b".to_vec(), b"c".to_vec()]
+        );
+        assert_eq!(
+            &*input.target_bytes(),
+            br#"{"a":[1,true,null],"b":{"c":"d"}}"#
+        );
+
+        // Inputs survive the non-self-describing serialization of the fuzzer
+        let serialized = postcard::to_allocvec(&input).unwrap();
+        assert_eq!(
+            postcard::from_bytes::<JsonInput>(&serialized).unwrap(),
+            input
+        );
+
+        let mut malformed = input.clone();
+        malformed.set_malformation(Some(Malformation::Truncate(5)));
+        assert_eq!(&*malformed.target_bytes(), br#"{"a":"#);
+        malformed.set_malformation(Some(Malformation::Insert(usize::MAX, b',')));
+        assert!(
+            DocumentFormat::Json
+                .parse(&malformed.to_bytes().unwrap())
+                .is_err()
+        );
+
+        assert!(JsonInput::try_from("{").is_err());
+        assert_eq!(JsonInput::from(json!([])).to_bytes().unwrap(), b"[]");
+        assert_eq!(DocumentFormat::from_path("a.yml"), DocumentFormat::Yaml);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_json_input_ondisk() {
+        let dir = env::temp_dir().join(format!("libafl_json_input_{}", process::id()));
+        let mut corpus = OnDiskCorpus::<JsonInput>::new(&dir).unwrap();
+        let mut malformed = JsonInput::new(json!({"a": [1, "x"]}), DocumentFormat::Json);
+        malformed.set_malformation(Some(Malformation::Repeat(3)));
+        let inputs = [malformed, JsonInput::from(json!(null))];
+        let ids = inputs
+            .iter()
+            .map(|input| corpus.add(Testcase::new(input.clone())).unwrap())
+            .collect::<Vec<_>>();
+
+        // The corpus only keeps the last input in memory, the first one is loaded from its file
+        for (id, input) in ids.into_iter().zip(&inputs) {
+            assert_eq!(&corpus.cloned_input_for_id(id).unwrap(), input);
+        }
+
+        // Plain documents are loaded as seeds in the format of their extension
+        let document = dir.join("seed.json");
+        fs::write(&document, br#"{"b": true}"#).unwrap();
+        assert_eq!(
+            JsonInput::from_document_file(&document).unwrap(),
+            JsonInput::from(json!({"b": true}))
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(not(feature = "json_input_yaml"))]
+    #[test]
+    fn test_json_input_missing_feature() {
+        assert!(JsonInput::parse(b"a: 1\n", DocumentFormat::Yaml).is_err());
+        assert!(
+            JsonInput::new(json!(1), DocumentFormat::Yaml)
+                .to_bytes()
+                .is_err()
+        );
+    }
+
+    #[cfg(feature = "json_input_yaml")]
+    #[test]
+    fn test_json_input_yaml() {
+        let input = JsonInput::parse(b"a:\n  - 1\n  - x\n", DocumentFormat::Yaml).unwrap();
+        assert_eq!(input.value(), &json!({"a": [1, "x"]}));
+        assert_eq!(input.to_bytes().unwrap(), b"a:\n- 1\n- x\n");
+    }
+
+    #[cfg(feature = "json_input_toml")]
+    #[test]
+    fn test_json_input_toml() {
+        let input =
+            JsonInput::parse(b"a = [1, \"x\"]\n\n[b]\nc = true\n", DocumentFormat::Toml).unwrap();
+        assert_eq!(input.value(), &json!({"a": [1, "x"], "b": {"c": true}}));
+        let mut input = JsonInput::new(json!({"a": null, "b": [1.5]}), DocumentFormat::Toml);
+        assert_eq!(input.to_bytes().unwrap(), b"b = [1.5]\n");
+        *input.value_mut() = json!(1);
+        assert_eq!(input.to_bytes().unwrap(), b"value = 1\n");
+    }
+}
@@ -30,6 +30,12 @@ pub use list::*;
 #[cfg(feature = "nautilus")]
 pub mod nautilus;
 
+#[cfg(feature = "protobuf")]
+pub mod protobuf;
+
+#[cfg(feature = "json_input")]
+pub mod json;
+
 use alloc::{
     boxed::Box,
     string::String,
@@ -45,6 +51,8 @@ use core::{
 #[cfg(feature = "std")]
 use std::{fs::File, io::Read, path::Path};
 
+#[cfg(feature = "json_input")]
+pub use json::*;
 #[cfg(feature = "std")]
 use libafl_bolts::fs::write_file_atomic;
 use libafl_bolts::{
@@ -54,6 +62,8 @@ use libafl_bolts::{
 };
 #[cfg(feature = "nautilus")]
 pub use nautilus::*;
+#[cfg(feature = "protobuf")]
+pub use protobuf::*;
 use serde::{Deserialize, Serialize};
 
 use crate::corpus::CorpusId;
@@ -4,14 +4,17 @@ use core::{
     cell::RefCell,
     hash::{Hash, Hasher},
 };
+use std::{fs, path::Path};
 
 use libafl_bolts::{HasLen, ownedref::OwnedSlice};
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    Error,
     common::nautilus::grammartec::{
-        newtypes::NodeId,
-        rule::RuleIdOrCustom,
+        newtypes::{NTermId, NodeId},
+        parser::Parser,
+        rule::{Rule, RuleIdOrCustom},
         tree::{Tree, TreeLike},
     },
     generators::nautilus::NautilusContext,
@@ -116,3 +119,132 @@ impl ToTargetBytes<NautilusInput> for NautilusBytesConverter<'_> {
         OwnedSlice::from(bytes)
     }
 }
+
+/// Parses existing inputs, e.g., the files of a test suite, into [`NautilusInput`]s,
+/// so grammar fuzzing can start from real-world seeds instead of generated trees.
+///
+/// Files are loaded into the corpus with [`crate::state::StdState::load_initial_inputs_with_loader`],
+/// seeds that do not match the grammar are reported and skipped:
+///
+/// ```rust,ignore
+/// let parser = NautilusParser::new(&context)?;
+/// state.load_initial_inputs_with_loader(
+///     &mut fuzzer,
+///     &mut executor,
+///     &mut mgr,
+///     &[PathBuf::from("./seeds")],
+///     &mut |_, _, path| parser.parse_file(path),
+/// )?;
+/// ```
+#[derive(Debug)]
+pub struct NautilusParser<'a> {
+    parser: Parser<'a>,
+    start: NTermId,
+}
+
+impl<'a> NautilusParser<'a> {
+    /// Creates a new [`NautilusParser`], parsing from the `START` nonterminal like the
+    /// [`crate::generators::NautilusGenerator`]
+    pub fn new(context: &'a NautilusContext) -> Result<Self, Error> {
+        Self::with_start(context, "START")
+    }
+
+    /// Creates a new [`NautilusParser`], parsing from the nonterminal `start`
+    pub fn with_start(context: &'a NautilusContext, start: &str) -> Result<Self, Error> {
+        let start = context
+            .ctx
+            .rules()
+            .iter()
+            .map(Rule::nonterm)
+            .find(|nt| context.ctx.nt_id_to_s(*nt) == start)
+            .ok_or_else(|| Error::key_not_found(format!("No rules for nonterminal {start}")))?;
+        Ok(Self {
+            parser: Parser::new(&context.ctx)?,
+            start,
+        })
+    }
+
+    /// Parses the bytes into a [`NautilusInput`] which unparses to the same bytes
+    pub fn parse(&self, bytes: &[u8]) -> Result<NautilusInput, Error> {
+        Ok(NautilusInput::new(self.parser.parse(self.start, bytes)?))
+    }
+
+    /// Parses the content of a file into a [`NautilusInput`]
+    pub fn parse_file<P>(&self, path: P) -> Result<NautilusInput, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        self.parse(&fs::read(path)?).map_err(|err| {
+            Error::illegal_argument(format!("Could not parse {}: {err}", path.display()))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use libafl_bolts::rands::StdRand;
+
+    use super::{NautilusInput, NautilusParser};
+    use crate::{
+        common::grammar::Grammar,
+        generators::nautilus::{NautilusContext, NautilusGenerator},
+    };
+
+    #[test]
+    fn test_nautilus_parser() {
+        let rules: &[(&str, &[u8])] = &[
+            ("LIST", b"[{ITEMS}]"),
+            ("ITEMS", b""),
+            ("ITEMS", b"{NUM}"),
+            ("ITEMS", b"{NUM},{ITEMS}"),
+            ("NUM", b"1"),
+            ("NUM", b"23"),
+        ];
+        let context = NautilusContext::with_rules(10, rules).unwrap();
+        let parser = NautilusParser::new(&context).unwrap();
+
+        let mut bytes = Vec::new();
+        for seed in [&b"[]"[..], b"[1]", b"[23,1,23]"] {
+            parser.parse(seed).unwrap().unparse(&context, &mut bytes);
+            assert_eq!(bytes, seed);
+        }
+        assert!(parser.parse(b"[1,,1]").is_err());
+        assert!(NautilusParser::with_start(&context, "NOPE").is_err());
+    }
+
+    #[test]
+    fn test_nautilus_grammar_import() {
+        let grammar = Grammar::from_antlr4(
+            r"
+grammar Obj;
+obj : '{' pair (',' pair)* '}' ;
+pair : KEY ':' VALUE ;
+KEY : [a-z]+ ;
+VALUE : '\\' [0-9] ;
+",
+        )
+        .unwrap();
+        let context = NautilusContext::from_grammar(20, &grammar).unwrap();
+        let parser = NautilusParser::new(&context).unwrap();
+
+        let mut bytes = Vec::new();
+        let seed = br"{ab:\1,c:\2}";
+        parser.parse(seed).unwrap().unparse(&context, &mut bytes);
+        assert_eq!(bytes, seed);
+        assert!(parser.parse(br"{ab:1}").is_err());
+
+        let generator = NautilusGenerator::new(&context);
+        let mut rand = StdRand::with_seed(1337);
+        for _ in 0..10 {
+            let mut input = NautilusInput::empty();
+            let start = generator.nonterminal("START");
+            generator.generate_from_nonterminal(&mut rand, &mut input, start, 20);
+            input.unparse(&context, &mut bytes);
+            assert_eq!(bytes[0], b'{');
+            assert!(parser.parse(&bytes).is_ok());
+        }
+    }
+}
@@ -0,0 +1,307 @@
+//! Structured inputs described by protobuf messages, like the inputs of libprotobuf-mutator, see [`ProtobufInput`].
+
+use alloc::{borrow::ToOwned, rc::Rc, string::String, vec::Vec};
+use core::cell::RefCell;
+use std::{fs, path::Path};
+
+use libafl_bolts::{HasLen, fs::write_file_atomic, ownedref::OwnedSlice};
+use prost_reflect::{DescriptorPool, DynamicMessage, MessageDescriptor, prost::Message};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Error,
+    inputs::{HasTargetBytes, Input, ToTargetBytes},
+};
+
+/// The protobuf message type of the inputs, loaded at runtime from a descriptor set, without generated code.
+///
+/// A descriptor set is the `FileDescriptorSet` `protoc` writes, e.g., with
+/// `protoc --include_imports --descriptor_set_out=target.desc target.proto`.
+#[derive(Debug, Clone)]
+pub struct ProtobufContext {
+    message: MessageDescriptor,
+}
+
+impl ProtobufContext {
+    /// Creates a new [`ProtobufContext`] for the message with the fully qualified `message_name`, e.g.,
+    /// `package.Message`, from the bytes of a descriptor set
+    pub fn new(descriptor_set: &[u8], message_name: &str) -> Result<Self, Error> {
+        let pool = DescriptorPool::decode(descriptor_set).map_err(|err| {
+            Error::illegal_argument(format!("Could not load the descriptor set: {err}"))
+        })?;
+        let message = pool.get_message_by_name(message_name).ok_or_else(|| {
+            Error::key_not_found(format!("No message {message_name} in the descriptor set"))
+        })?;
+        Ok(Self { message })
+    }
+
+    /// Creates a new [`ProtobufContext`] for the message with the fully qualified `message_name` from a descriptor
+    /// set file
+    pub fn from_file<P>(descriptor_set: P, message_name: &str) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        Self::new(&fs::read(descriptor_set)?, message_name)
+    }
+
+    /// The descriptor of the message type of the inputs
+    #[must_use]
+    pub fn message_descriptor(&self) -> &MessageDescriptor {
+        &self.message
+    }
+
+    /// Decodes the input into a message
+    pub fn decode(&self, input: &ProtobufInput) -> Result<DynamicMessage, Error> {
+        DynamicMessage::decode(self.message.clone(), input.bytes()).map_err(|err| {
+            Error::illegal_argument(format!(
+                "The input is no {} message: {err}",
+                self.message.full_name()
+            ))
+        })
+    }
+
+    /// A new input of the empty message
+    #[must_use]
+    pub fn empty_input(&self) -> ProtobufInput {
+        ProtobufInput::default()
+    }
+}
+
+/// An input for structure-aware fuzzing of targets parsing protobuf messages, in the binary wire format, like the
+/// inputs of the `DEFINE_BINARY_PROTO_FUZZER` harnesses of libprotobuf-mutator.
+///
+/// The fields are mutated by the protobuf mutators, e.g., [`crate::mutators::protobuf_mutations`], which decode
+/// the input with the descriptor of a [`ProtobufContext`]. Inputs are stored in the wire format, so existing
+/// binary protobuf corpora can be loaded as is, and the empty input is the empty message.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ProtobufInput {
+    bytes: Vec<u8>,
+}
+
+impl Input for ProtobufInput {
+    /// Write the wire format of this input to the file
+    fn to_file<P>(&self, path: P) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        write_file_atomic(path, &self.bytes)
+    }
+
+    /// Load an input in the wire format from a file
+    fn from_file<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(Self::new(fs::read(path)?))
+    }
+}
+
+/// Rc Ref-cell from Input
+impl From<ProtobufInput> for Rc<RefCell<ProtobufInput>> {
+    fn from(input: ProtobufInput) -> Self {
+        Rc::new(RefCell::new(input))
+    }
+}
+
+impl ProtobufInput {
+    /// Creates a new [`ProtobufInput`] from a message in the wire format
+    #[must_use]
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self { bytes }
+    }
+
+    /// Creates a new [`ProtobufInput`] of the message
+    #[must_use]
+    pub fn from_message(message: &DynamicMessage) -> Self {
+        Self::new(message.encode_to_vec())
+    }
+
+    /// The message in the wire format
+    #[must_use]
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// A description of the message, in the protobuf text format, for debugging
+    pub fn to_text(&self, context: &ProtobufContext) -> Result<String, Error> {
+        Ok(format!("{}", context.decode(self)?))
+    }
+}
+
+impl HasLen for ProtobufInput {
+    #[inline]
+    fn len(&self) -> usize {
+        self.bytes.len()
+    }
+}
+
+impl HasTargetBytes for ProtobufInput {
+    #[inline]
+    fn target_bytes(&self) -> OwnedSlice<'_, u8> {
+        OwnedSlice::from(&self.bytes)
+    }
+}
+
+impl From<&DynamicMessage> for ProtobufInput {
+    fn from(message: &DynamicMessage) -> Self {
+        Self::from_message(message)
+    }
+}
+
+impl From<&[u8]> for ProtobufInput {
+    fn from(bytes: &[u8]) -> Self {
+        Self::new(bytes.to_owned())
+    }
+}
+
+/// Convert from [`ProtobufInput`] to the wire format, e.g., for the `LLVMFuzzerTestOneInput` of a harness written
+/// with `DEFINE_BINARY_PROTO_FUZZER`
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ProtobufBytesConverter;
+
+impl ProtobufBytesConverter {
+    /// Create a new [`ProtobufBytesConverter`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl ToTargetBytes<ProtobufInput> for ProtobufBytesConverter {
+    fn to_target_bytes<'a>(&mut self, input: &'a ProtobufInput) -> OwnedSlice<'a, u8> {
+        input.target_bytes()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use alloc::{string::ToString, vec, vec::Vec};
+
+    use prost_reflect::{
+        DynamicMessage, Value,
+        prost::Message,
+        prost_types::{
+            DescriptorProto, EnumDescriptorProto, EnumValueDescriptorProto, FieldDescriptorProto,
+            FileDescriptorProto, FileDescriptorSet, OneofDescriptorProto,
+            field_descriptor_proto::{Label, Type},
+        },
+    };
+
+    use super::{ProtobufContext, ProtobufInput};
+
+    fn field(name: &str, number: i32, label: Label, kind: Type) -> FieldDescriptorProto {
+        FieldDescriptorProto {
+            name: Some(name.to_string()),
+            number: Some(number),
+            label: Some(label.into()),
+            r#type: Some(kind.into()),
+            ..FieldDescriptorProto::default()
+        }
+    }
+
+    /// The descriptor set of
+    ///
+    /// ```proto
+    /// package test;
+    /// enum Color { RED = 0; GREEN = 1; }
+    /// message Item { int32 id = 1; string name = 2; }
+    /// message Root {
+    ///   repeated Item items = 1;
+    ///   oneof kind { uint64 number = 2; bytes data = 3; Item item = 4; }
+    ///   bool flag = 5;
+    ///   Color color = 6;
+    ///   repeated sint64 values = 7;
+    ///   double ratio = 8;
+    /// }
+    /// ```
+    pub(crate) fn test_descriptor_set() -> Vec<u8> {
+        let message = |name: &str| Some(name.to_string());
+        let item = DescriptorProto {
+            name: Some("Item".to_string()),
+            field: vec![
+                field("id", 1, Label::Optional, Type::Int32),
+                field("name", 2, Label::Optional, Type::String),
+            ],
+            ..DescriptorProto::default()
+        };
+        let in_kind = |mut field: FieldDescriptorProto| {
+            field.oneof_index = Some(0);
+            field
+        };
+        let root = DescriptorProto {
+            name: Some("Root".to_string()),
+            field: vec![
+                FieldDescriptorProto {
+                    type_name: message(".test.Item"),
+                    ..field("items", 1, Label::Repeated, Type::Message)
+                },
+                in_kind(field("number", 2, Label::Optional, Type::Uint64)),
+                in_kind(field("data", 3, Label::Optional, Type::Bytes)),
+                in_kind(FieldDescriptorProto {
+                    type_name: message(".test.Item"),
+                    ..field("item", 4, Label::Optional, Type::Message)
+                }),
+                field("flag", 5, Label::Optional, Type::Bool),
+                FieldDescriptorProto {
+                    type_name: message(".test.Color"),
+                    ..field("color", 6, Label::Optional, Type::Enum)
+                },
+                field("values", 7, Label::Repeated, Type::Sint64),
+                field("ratio", 8, Label::Optional, Type::Double),
+            ],
+            oneof_decl: vec![OneofDescriptorProto {
+                name: Some("kind".to_string()),
+                ..OneofDescriptorProto::default()
+            }],
+            ..DescriptorProto::default()
+        };
+        let color = EnumDescriptorProto {
+            name: Some("Color".to_string()),
+            value: ["RED", "GREEN"]
+                .iter()
+                .zip(0..)
+                .map(|(name, number)| EnumValueDescriptorProto {
+                    name: Some((*name).to_string()),
+                    number: Some(number),
+                    ..EnumValueDescriptorProto::default()
+                })
+                .collect(),
+            ..EnumDescriptorProto::default()
+        };
+        FileDescriptorSet {
+            file: vec![FileDescriptorProto {
+                name: Some("test.proto".to_string()),
+                package: Some("test".to_string()),
+                syntax: Some("proto3".to_string()),
+                message_type: vec![item, root],
+                enum_type: vec![color],
+                ..FileDescriptorProto::default()
+            }],
+        }
+        .encode_to_vec()
+    }
+
+    #[test]
+    fn test_protobuf_input() {
+        let context = ProtobufContext::new(&test_descriptor_set(), "test.Root").unwrap();
+        assert!(ProtobufContext::new(&test_descriptor_set(), "test.Nope").is_err());
+
+        let empty = context.decode(&context.empty_input()).unwrap();
+        assert_eq!(empty.fields().count(), 0);
+
+        let descriptor = context.message_descriptor();
+        let mut message = DynamicMessage::new(descriptor.clone());
+        message.set_field_by_name("number", Value::U64(1337));
+        message.set_field_by_name("values", Value::List(vec![Value::I64(-1), Value::I64(2)]));
+        let input = ProtobufInput::from_message(&message);
+        assert_eq!(context.decode(&input).unwrap(), message);
+        assert!(input.to_text(&context).unwrap().contains("1337"));
+
+        // A length-delimited field 1 of 5 bytes, but only 1 byte follows
+        assert!(
+            context
+                .decode(&ProtobufInput::new(vec![0x0a, 5, 0]))
+                .is_err()
+        );
+    }
+}
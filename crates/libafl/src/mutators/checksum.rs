@@ -0,0 +1,420 @@
+//! Checksums found in inputs by the [`crate::stages::ChecksumDetectionStage`], and the [`ChecksumFixupMutator`]
+//! that recomputes them after each mutation, so that the target does not reject the mutated inputs early.
+
+use alloc::{borrow::Cow, format, vec::Vec};
+use core::ops::Range;
+
+use libafl_bolts::Named;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Error, HasMetadata,
+    corpus::{CorpusId, HasCurrentCorpusId},
+    inputs::HasMutatorBytes,
+    mutators::{MutationResult, Mutator},
+    state::{HasCorpus, HasCurrentTestcase},
+};
+
+/// The CRC32 (IEEE 802.3) lookup table
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// The modulus of Adler-32
+pub(crate) const ADLER32_MOD: u32 = 65521;
+
+/// A checksum algorithm commonly found in file formats and protocols
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ChecksumAlgorithm {
+    /// CRC-32 as used by zlib, PNG and Ethernet
+    Crc32,
+    /// Adler-32 as used by zlib
+    Adler32,
+    /// The sum of all bytes, truncated to 16 bits
+    Sum16,
+    /// The sum of all bytes, truncated to 32 bits
+    Sum32,
+}
+
+impl ChecksumAlgorithm {
+    /// All supported algorithms
+    pub const ALL: [Self; 4] = [Self::Crc32, Self::Adler32, Self::Sum16, Self::Sum32];
+
+    /// The width of the checksum in bytes
+    #[must_use]
+    pub fn width(self) -> usize {
+        match self {
+            Self::Crc32 | Self::Adler32 | Self::Sum32 => 4,
+            Self::Sum16 => 2,
+        }
+    }
+
+    /// Computes the checksum of `data`
+    #[must_use]
+    pub fn compute(self, data: &[u8]) -> u64 {
+        let mut hasher = ChecksumHasher::new(self);
+        hasher.update(data);
+        hasher.value()
+    }
+}
+
+/// Computes a checksum incrementally, to get the checksums of all prefixes of some data in a single pass
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ChecksumHasher {
+    algorithm: ChecksumAlgorithm,
+    /// The CRC register, the Adler-32 `a`, or the sum
+    low: u32,
+    /// The Adler-32 `b`
+    high: u32,
+}
+
+impl ChecksumHasher {
+    /// Starts the checksum of empty data
+    pub(crate) fn new(algorithm: ChecksumAlgorithm) -> Self {
+        let low = match algorithm {
+            ChecksumAlgorithm::Crc32 => !0,
+            ChecksumAlgorithm::Adler32 => 1,
+            ChecksumAlgorithm::Sum16 | ChecksumAlgorithm::Sum32 => 0,
+        };
+        Self {
+            algorithm,
+            low,
+            high: 0,
+        }
+    }
+
+    /// Appends `data`
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        for byte in data {
+            match self.algorithm {
+                ChecksumAlgorithm::Crc32 => {
+                    self.low = CRC32_TABLE[((self.low ^ u32::from(*byte)) & 0xff) as usize]
+                        ^ (self.low >> 8);
+                }
+                ChecksumAlgorithm::Adler32 => {
+                    self.low = (self.low + u32::from(*byte)) % ADLER32_MOD;
+                    self.high = (self.high + self.low) % ADLER32_MOD;
+                }
+                ChecksumAlgorithm::Sum16 | ChecksumAlgorithm::Sum32 => {
+                    self.low = self.low.wrapping_add(u32::from(*byte));
+                }
+            }
+        }
+    }
+
+    /// The checksum of the data appended so far
+    pub(crate) fn value(&self) -> u64 {
+        match self.algorithm {
+            ChecksumAlgorithm::Crc32 => u64::from(!self.low),
+            ChecksumAlgorithm::Adler32 => u64::from((self.high << 16) | self.low),
+            ChecksumAlgorithm::Sum16 => u64::from(self.low & 0xffff),
+            ChecksumAlgorithm::Sum32 => u64::from(self.low),
+        }
+    }
+}
+
+/// A checksum stored in an input
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checksum {
+    /// The algorithm
+    pub algorithm: ChecksumAlgorithm,
+    /// The bytes the checksum is computed over
+    pub data: Range<usize>,
+    /// The bytes holding the checksum
+    pub field: Range<usize>,
+    /// Whether the checksum is stored big endian
+    pub big_endian: bool,
+    /// Whether `data` runs until the end of the input, also once the input grew or shrank
+    pub to_end: bool,
+}
+
+impl Checksum {
+    /// The bytes the checksum is computed over, in an input of length `len`
+    #[must_use]
+    pub fn data_for_len(&self, len: usize) -> Range<usize> {
+        if self.to_end && self.field.end <= self.data.start {
+            self.data.start..len
+        } else {
+            self.data.clone()
+        }
+    }
+
+    /// If the checksum is still where it was in `orig` after it was mutated to `bytes`.
+    ///
+    /// Without knowing where bytes were inserted or deleted, this holds if the length did not change, or for a
+    /// checksum in front of data that runs until the end of the input, if nothing in front of the data changed.
+    #[must_use]
+    pub fn layout_holds(&self, orig: &[u8], bytes: &[u8]) -> bool {
+        if orig.len() == bytes.len() {
+            return true;
+        }
+        // The field is compared as well: a changed field cannot be told apart from bytes inserted in front of it
+        let prefix = ..self.data.start;
+        self.to_end
+            && self.field.end <= self.data.start
+            && orig
+                .get(prefix)
+                .is_some_and(|orig| bytes.get(prefix) == Some(orig))
+    }
+
+    /// Reads the checksum stored in `bytes`
+    #[must_use]
+    pub fn stored(&self, bytes: &[u8]) -> Option<u64> {
+        let field = bytes.get(self.field.clone())?;
+        let mut buf = [0; 8];
+        if self.big_endian {
+            buf[8 - field.len()..].copy_from_slice(field);
+            Some(u64::from_be_bytes(buf))
+        } else {
+            buf[..field.len()].copy_from_slice(field);
+            Some(u64::from_le_bytes(buf))
+        }
+    }
+
+    /// Recomputes the checksum and writes it to its field.
+    ///
+    /// Returns the bytes that were overwritten, or `None` if the checksum does not fit into `bytes` anymore.
+    pub fn fix(&self, bytes: &mut [u8]) -> Option<Vec<u8>> {
+        let data = self.data_for_len(bytes.len());
+        if data.start > data.end || data.end > bytes.len() || self.field.end > bytes.len() {
+            return None;
+        }
+        if data.start < self.field.end && self.field.start < data.end {
+            // The data moved over the field
+            return None;
+        }
+
+        let value = self.algorithm.compute(&bytes[data]);
+        let width = self.field.len();
+        let field = &mut bytes[self.field.clone()];
+        let orig = field.to_vec();
+        if self.big_endian {
+            field.copy_from_slice(&value.to_be_bytes()[8 - width..]);
+        } else {
+            field.copy_from_slice(&value.to_le_bytes()[..width]);
+        }
+        Some(orig)
+    }
+}
+
+/// The checksums found in a testcase, in the order they have to be fixed up
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ChecksumsMetadata {
+    /// The checksums
+    pub checksums: Vec<Checksum>,
+}
+
+libafl_bolts::impl_serdeany!(ChecksumsMetadata);
+
+impl ChecksumsMetadata {
+    /// Creates a new [`struct@ChecksumsMetadata`]
+    #[must_use]
+    pub fn new(checksums: Vec<Checksum>) -> Self {
+        Self { checksums }
+    }
+
+    /// Fixes the checksums in `bytes`, mutated from the testcase `orig` the checksums were found in, returning the
+    /// original bytes of each field that was overwritten.
+    ///
+    /// Checksums moved by inserted or deleted bytes are skipped, see [`Checksum::layout_holds`].
+    pub fn fix(&self, orig: &[u8], bytes: &mut [u8]) -> Vec<(Range<usize>, Vec<u8>)> {
+        // Check all before fixing any, fixing the nested checksums changes the bytes compared
+        let holding = self
+            .checksums
+            .iter()
+            .filter(|checksum| checksum.layout_holds(orig, bytes))
+            .collect::<Vec<_>>();
+        holding
+            .into_iter()
+            .filter_map(|checksum| {
+                checksum
+                    .fix(bytes)
+                    .map(|orig| (checksum.field.clone(), orig))
+            })
+            .collect()
+    }
+}
+
+/// Clones the checksums of the current testcase, if any, together with its input
+pub(crate) fn current_checksums<I, S>(state: &S) -> Option<(ChecksumsMetadata, I)>
+where
+    I: Clone,
+    S: HasCorpus<I> + HasCurrentCorpusId,
+{
+    let checksums = {
+        let testcase = HasCurrentTestcase::<I>::current_testcase(state).ok()?;
+        testcase.metadata::<ChecksumsMetadata>().ok()?.clone()
+    };
+    if checksums.checksums.is_empty() {
+        return None;
+    }
+    let input = HasCurrentTestcase::<I>::current_input_cloned(state).ok()?;
+    Some((checksums, input))
+}
+
+/// A mutator wrapper that recomputes the checksums of the current testcase after the inner mutator ran.
+///
+/// This is a post-mutation hook: the fixed input is the one executed and stored, so solutions found with it
+/// reproduce against the unmodified target.
+#[derive(Debug)]
+pub struct ChecksumFixupMutator<M> {
+    name: Cow<'static, str>,
+    inner: M,
+}
+
+impl<I, M, S> Mutator<I, S> for ChecksumFixupMutator<M>
+where
+    M: Mutator<I, S>,
+    I: HasMutatorBytes + Clone,
+    S: HasCorpus<I> + HasCurrentCorpusId,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        let result = self.inner.mutate(state, input)?;
+        if result == MutationResult::Mutated {
+            if let Some((checksums, orig)) = current_checksums(state) {
+                checksums.fix(orig.mutator_bytes(), input.mutator_bytes_mut());
+            }
+        }
+        Ok(result)
+    }
+
+    #[inline]
+    fn post_exec(&mut self, state: &mut S, new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
+        self.inner.post_exec(state, new_corpus_id)
+    }
+}
+
+impl<M> Named for ChecksumFixupMutator<M> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<M> ChecksumFixupMutator<M>
+where
+    M: Named,
+{
+    /// Wraps the given mutator, e.g., a [`crate::mutators::HavocScheduledMutator`]
+    pub fn new(inner: M) -> Self {
+        Self {
+            name: Cow::Owned(format!("ChecksumFixupMutator<{}>", inner.name())),
+            inner,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::{Checksum, ChecksumAlgorithm, ChecksumsMetadata};
+
+    #[test]
+    fn test_checksum_algorithms() {
+        assert_eq!(ChecksumAlgorithm::Crc32.compute(b"123456789"), 0xcbf4_3926);
+        assert_eq!(
+            ChecksumAlgorithm::Adler32.compute(b"Wikipedia"),
+            0x11e6_0398
+        );
+        assert_eq!(ChecksumAlgorithm::Sum16.compute(&[0xff, 0xff, 2]), 0x0200);
+    }
+
+    #[test]
+    fn test_checksum_fix() {
+        // A big endian CRC32 in front of the rest of the input
+        let checksum = Checksum {
+            algorithm: ChecksumAlgorithm::Crc32,
+            data: 4..13,
+            field: 0..4,
+            big_endian: true,
+            to_end: true,
+        };
+        let mut bytes = vec![0; 4];
+        bytes.extend_from_slice(b"123456789");
+        assert_eq!(checksum.fix(&mut bytes), Some(vec![0; 4]));
+        assert_eq!(&bytes[..4], &[0xcb, 0xf4, 0x39, 0x26]);
+        assert_eq!(checksum.stored(&bytes), Some(0xcbf4_3926));
+
+        // The data grew
+        bytes.push(b'0');
+        assert!(checksum.fix(&mut bytes).is_some());
+        assert_eq!(
+            checksum.stored(&bytes),
+            Some(ChecksumAlgorithm::Crc32.compute(b"1234567890"))
+        );
+
+        assert_eq!(checksum.fix(&mut bytes[..2]), None);
+    }
+
+    #[test]
+    fn test_checksum_layout() {
+        // A little endian Sum16 behind its data, which stops before the trailer
+        let checksum = Checksum {
+            algorithm: ChecksumAlgorithm::Sum16,
+            data: 0..4,
+            field: 4..6,
+            big_endian: false,
+            to_end: false,
+        };
+        let orig = *b"abcd\0\0trailer";
+        let mut bytes = orig.to_vec();
+        bytes.insert(1, b'x');
+
+        // The field moved, fixing it would overwrite the data
+        let meta = ChecksumsMetadata::new(vec![checksum]);
+        assert!(meta.fix(&orig, &mut bytes).is_empty());
+        assert_eq!(&bytes[..7], b"axbcd\0\0");
+
+        // Same length, fixed in place
+        let mut bytes = orig.to_vec();
+        bytes[0] = b'b';
+        assert_eq!(meta.fix(&orig, &mut bytes).len(), 1);
+        assert_eq!(
+            &bytes[4..6],
+            &(u16::from(b'b') * 2 + u16::from(b'c') + u16::from(b'd')).to_le_bytes()
+        );
+
+        // A checksum in front of data until the end survives data growing behind it
+        let checksum = Checksum {
+            algorithm: ChecksumAlgorithm::Sum32,
+            data: 4..9,
+            field: 0..4,
+            big_endian: true,
+            to_end: true,
+        };
+        let meta = ChecksumsMetadata::new(vec![checksum]);
+        let orig = *b"\0\0\0\0hello";
+        let mut bytes = orig.to_vec();
+        bytes.push(b'!');
+        assert_eq!(meta.fix(&orig, &mut bytes).len(), 1);
+        assert_eq!(
+            ChecksumAlgorithm::Sum32.compute(b"hello!"),
+            u64::from(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+        );
+
+        // but not bytes inserted in front of the data
+        let mut bytes = orig.to_vec();
+        bytes.insert(0, 1);
+        assert!(meta.fix(&orig, &mut bytes).is_empty());
+    }
+}
@@ -0,0 +1,449 @@
+//! Mutators that respect the fields inferred by the [`crate::stages::FieldInferenceStage`].
+//!
+//! The fields are stored in the [`InferredFieldsMetadata`] of the testcase currently being fuzzed.
+//! If the input no longer matches the fields, e.g., because another mutation moved its bytes around,
+//! the mutators skip.
+
+use alloc::{borrow::Cow, vec::Vec};
+use core::{num::NonZero, ops::Range};
+
+use libafl_bolts::{
+    Named,
+    rands::Rand,
+    tuples::{tuple_list, tuple_list_type},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Error, HasMetadata,
+    corpus::{CorpusId, HasCurrentCorpusId},
+    inputs::{HasMutatorBytes, ResizableMutator},
+    mutators::{ARITH_MAX, MutationResult, Mutator},
+    nonzero,
+    state::{HasCorpus, HasCurrentTestcase, HasMaxSize, HasRand},
+};
+
+/// The kind of an [`InferredField`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FieldKind {
+    /// The number of bytes in `governs`
+    Length {
+        /// The range of the data this length refers to
+        governs: Range<usize>,
+    },
+    /// A constant the target compares against
+    Magic,
+    /// A value computed by the target from other bytes of the input and compared against this field
+    Checksum,
+    /// A position in the input
+    Offset,
+}
+
+/// A field of an input, as inferred from the comparisons of the target
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InferredField {
+    /// The bytes of the field
+    pub range: Range<usize>,
+    /// What the field holds
+    pub kind: FieldKind,
+    /// Whether the field is a big endian integer
+    pub big_endian: bool,
+}
+
+impl InferredField {
+    /// The width of this field in bytes
+    #[must_use]
+    pub fn width(&self) -> usize {
+        self.range.len()
+    }
+
+    /// Whether this field is a number the target computes with, i.e., a length or an offset
+    #[must_use]
+    pub fn is_numeric(&self) -> bool {
+        matches!(self.kind, FieldKind::Length { .. } | FieldKind::Offset)
+    }
+
+    /// Reads the value of this field from `bytes`, if it is in bounds and at most 8 bytes wide
+    #[must_use]
+    pub fn read(&self, bytes: &[u8]) -> Option<u64> {
+        let field = bytes.get(self.range.clone())?;
+        if field.is_empty() || field.len() > 8 {
+            return None;
+        }
+        let mut buf = [0; 8];
+        if self.big_endian {
+            buf[8 - field.len()..].copy_from_slice(field);
+            Some(u64::from_be_bytes(buf))
+        } else {
+            buf[..field.len()].copy_from_slice(field);
+            Some(u64::from_le_bytes(buf))
+        }
+    }
+
+    /// Writes `value` to this field in `bytes`, truncated to the width of the field.
+    ///
+    /// Returns `false` if the field is out of bounds.
+    pub fn write(&self, bytes: &mut [u8], value: u64) -> bool {
+        let width = self.width();
+        let Some(field) = bytes.get_mut(self.range.clone()) else {
+            return false;
+        };
+        if width == 0 || width > 8 {
+            return false;
+        }
+        if self.big_endian {
+            field.copy_from_slice(&value.to_be_bytes()[8 - width..]);
+        } else {
+            field.copy_from_slice(&value.to_le_bytes()[..width]);
+        }
+        true
+    }
+
+    /// The largest value that fits into this field
+    #[must_use]
+    pub fn max_value(&self) -> u64 {
+        match self.width() {
+            0 => 0,
+            w if w >= 8 => u64::MAX,
+            w => (1 << (w * 8)) - 1,
+        }
+    }
+}
+
+/// The fields inferred for a testcase, sorted by their start
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InferredFieldsMetadata {
+    /// The fields, never overlapping each other
+    pub fields: Vec<InferredField>,
+}
+
+libafl_bolts::impl_serdeany!(InferredFieldsMetadata);
+
+impl InferredFieldsMetadata {
+    /// Creates a new [`struct@InferredFieldsMetadata`]
+    #[must_use]
+    pub fn new(fields: Vec<InferredField>) -> Self {
+        Self { fields }
+    }
+
+    /// Returns the field containing the byte at `idx`, if any
+    #[must_use]
+    pub fn field_at(&self, idx: usize) -> Option<&InferredField> {
+        self.fields.iter().find(|field| field.range.contains(&idx))
+    }
+}
+
+/// Clones the fields of the current testcase, matching them against the input length
+fn current_fields<I, S>(state: &S, input_len: usize) -> Option<Vec<InferredField>>
+where
+    I: Clone,
+    S: HasCorpus<I> + HasCurrentCorpusId,
+{
+    let testcase = HasCurrentTestcase::<I>::current_testcase(state).ok()?;
+    let meta = testcase.metadata::<InferredFieldsMetadata>().ok()?;
+    Some(
+        meta.fields
+            .iter()
+            .filter(|field| field.range.end <= input_len)
+            .cloned()
+            .collect(),
+    )
+}
+
+/// Picks one of the `fields` matching `filter`
+fn choose_field<'a, S, F>(
+    state: &mut S,
+    fields: &'a [InferredField],
+    filter: F,
+) -> Option<&'a InferredField>
+where
+    S: HasRand,
+    F: Fn(&InferredField) -> bool,
+{
+    let candidates = fields.iter().filter(|field| filter(field));
+    state.rand_mut().choose(candidates)
+}
+
+/// Resizes the data governed by a length field and updates the length field to match
+#[derive(Default, Debug)]
+pub struct FieldLengthMutator;
+
+impl<I, S> Mutator<I, S> for FieldLengthMutator
+where
+    S: HasRand + HasMaxSize + HasCorpus<I> + HasCurrentCorpusId,
+    I: ResizableMutator<u8> + HasMutatorBytes + Clone,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        let size = input.mutator_bytes().len();
+        let Some(fields) = current_fields(state, size) else {
+            return Ok(MutationResult::Skipped);
+        };
+        let Some(field) = choose_field(state, &fields, |field| match &field.kind {
+            FieldKind::Length { governs } => {
+                governs.end <= size
+                    && field.read(input.mutator_bytes()) == Some(governs.len() as u64)
+            }
+            _ => false,
+        }) else {
+            return Ok(MutationResult::Skipped);
+        };
+        let FieldKind::Length { governs } = &field.kind else {
+            unreachable!()
+        };
+
+        let old_len = governs.len();
+        let max_len = (state.max_size().saturating_sub(size - old_len))
+            .min(old_len * 2 + 16)
+            .min(usize::try_from(field.max_value()).unwrap_or(usize::MAX));
+        let new_len = state.rand_mut().between(0, max_len);
+        if new_len == old_len {
+            return Ok(MutationResult::Skipped);
+        }
+
+        if new_len > old_len {
+            // Grow the data with a copy of some of its own bytes, or with random bytes if it was empty
+            let grow = new_len - old_len;
+            let pos = governs.start + state.rand_mut().below_or_zero(old_len + 1);
+            let bytes: Vec<u8> = if old_len == 0 {
+                (0..grow).map(|_| state.rand_mut().next() as u8).collect()
+            } else {
+                let from = governs.start + state.rand_mut().below_or_zero(old_len);
+                let data = &input.mutator_bytes()[from..governs.end];
+                data.iter().cycle().take(grow).copied().collect()
+            };
+            drop(input.splice(pos..pos, bytes));
+        } else {
+            let shrink = old_len - new_len;
+            let pos = governs.start + state.rand_mut().below_or_zero(old_len - shrink + 1);
+            input.drain(pos..pos + shrink);
+        }
+
+        field.write(input.mutator_bytes_mut(), new_len as u64);
+        Ok(MutationResult::Mutated)
+    }
+
+    #[inline]
+    fn post_exec(&mut self, _state: &mut S, _new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Named for FieldLengthMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("FieldLengthMutator");
+        &NAME
+    }
+}
+
+impl FieldLengthMutator {
+    /// Creates a new [`FieldLengthMutator`].
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+/// Adds or subtracts a small value to a length or offset field, in the width and endianness of the field
+#[derive(Default, Debug)]
+pub struct FieldArithMutator;
+
+impl<I, S> Mutator<I, S> for FieldArithMutator
+where
+    S: HasRand + HasCorpus<I> + HasCurrentCorpusId,
+    I: HasMutatorBytes + Clone,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        let Some(fields) = current_fields(state, input.mutator_bytes().len()) else {
+            return Ok(MutationResult::Skipped);
+        };
+        let Some(field) = choose_field(state, &fields, InferredField::is_numeric) else {
+            return Ok(MutationResult::Skipped);
+        };
+        let Some(value) = field.read(input.mutator_bytes()) else {
+            return Ok(MutationResult::Skipped);
+        };
+
+        let delta = 1 + state.rand_mut().below(nonzero!(ARITH_MAX)) as u64;
+        let new_value = if state.rand_mut().coinflip(0.5) {
+            value.wrapping_add(delta)
+        } else {
+            value.wrapping_sub(delta)
+        } & field.max_value();
+
+        field.write(input.mutator_bytes_mut(), new_value);
+        Ok(MutationResult::Mutated)
+    }
+
+    #[inline]
+    fn post_exec(&mut self, _state: &mut S, _new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Named for FieldArithMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("FieldArithMutator");
+        &NAME
+    }
+}
+
+impl FieldArithMutator {
+    /// Creates a new [`FieldArithMutator`].
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+/// Randomizes a byte outside of all inferred fields, so that the structure of the input stays intact
+#[derive(Default, Debug)]
+pub struct FieldDataMutator;
+
+/// How often the [`FieldDataMutator`] tries to find a byte outside of the fields
+const FIELD_DATA_TRIES: usize = 16;
+
+impl<I, S> Mutator<I, S> for FieldDataMutator
+where
+    S: HasRand + HasCorpus<I> + HasCurrentCorpusId,
+    I: HasMutatorBytes + Clone,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        let size = input.mutator_bytes().len();
+        let Some(size) = NonZero::new(size) else {
+            return Ok(MutationResult::Skipped);
+        };
+        let Some(fields) = current_fields(state, size.get()) else {
+            return Ok(MutationResult::Skipped);
+        };
+
+        for _ in 0..FIELD_DATA_TRIES {
+            let idx = state.rand_mut().below(size);
+            if fields.iter().any(|field| field.range.contains(&idx)) {
+                continue;
+            }
+            input.mutator_bytes_mut()[idx] ^= 1 + state.rand_mut().below(nonzero!(255)) as u8;
+            return Ok(MutationResult::Mutated);
+        }
+
+        Ok(MutationResult::Skipped)
+    }
+
+    #[inline]
+    fn post_exec(&mut self, _state: &mut S, _new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Named for FieldDataMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("FieldDataMutator");
+        &NAME
+    }
+}
+
+impl FieldDataMutator {
+    /// Creates a new [`FieldDataMutator`].
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+/// Tuple type of the mutations that respect the inferred fields
+pub type FieldMutationsType =
+    tuple_list_type!(FieldLengthMutator, FieldArithMutator, FieldDataMutator);
+
+/// Get the mutations that respect the fields in the [`InferredFieldsMetadata`] of the current testcase
+#[must_use]
+pub fn field_mutations() -> FieldMutationsType {
+    tuple_list!(
+        FieldLengthMutator::new(),
+        FieldArithMutator::new(),
+        FieldDataMutator::new()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use libafl_bolts::rands::StdRand;
+
+    use super::{FieldKind, FieldLengthMutator, InferredField, InferredFieldsMetadata};
+    use crate::{
+        HasMetadata,
+        corpus::{Corpus, HasCurrentCorpusId, InMemoryCorpus, Testcase},
+        feedbacks::ConstFeedback,
+        inputs::{BytesInput, HasMutatorBytes},
+        mutators::{MutationResult, Mutator},
+        state::{HasCorpus, StdState},
+    };
+
+    #[test]
+    fn test_field_read_write() {
+        let field = InferredField {
+            range: 1..3,
+            kind: FieldKind::Offset,
+            big_endian: true,
+        };
+        let mut bytes = vec![0xaa, 0x12, 0x34, 0xbb];
+        assert_eq!(field.read(&bytes), Some(0x1234));
+        assert!(field.write(&mut bytes, 0x1_5678));
+        assert_eq!(bytes, [0xaa, 0x56, 0x78, 0xbb]);
+        assert_eq!(field.max_value(), 0xffff);
+
+        let field = InferredField {
+            big_endian: false,
+            ..field
+        };
+        assert_eq!(field.read(&bytes), Some(0x7856));
+        assert!(!field.write(&mut bytes[..2], 0));
+    }
+
+    #[test]
+    fn test_field_length_mutator() {
+        // A 2 byte little endian length, followed by the 5 bytes it covers
+        let bytes = vec![5, 0, b'h', b'e', b'l', b'l', b'o'];
+        let mut testcase = Testcase::new(BytesInput::new(bytes.clone()));
+        testcase.add_metadata(InferredFieldsMetadata::new(vec![InferredField {
+            range: 0..2,
+            kind: FieldKind::Length { governs: 2..7 },
+            big_endian: false,
+        }]));
+
+        let mut corpus = InMemoryCorpus::new();
+        let id = corpus.add(testcase).unwrap();
+        let mut feedback = ConstFeedback::new(false);
+        let mut objective = ConstFeedback::new(false);
+        let mut state = StdState::new(
+            StdRand::with_seed(1337),
+            corpus,
+            InMemoryCorpus::new(),
+            &mut feedback,
+            &mut objective,
+        )
+        .unwrap();
+        state.set_corpus_id(id).unwrap();
+        assert_eq!(state.corpus().count(), 1);
+
+        let mut mutator = FieldLengthMutator::new();
+        let mut mutated = 0;
+        for _ in 0..32 {
+            let mut input = BytesInput::new(bytes.clone());
+            if mutator.mutate(&mut state, &mut input).unwrap() == MutationResult::Skipped {
+                continue;
+            }
+            mutated += 1;
+            let out = input.mutator_bytes();
+            assert_eq!(
+                usize::from(u16::from_le_bytes([out[0], out[1]])),
+                out.len() - 2
+            );
+        }
+        assert!(mutated > 0);
+    }
+}
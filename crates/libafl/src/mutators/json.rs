@@ -0,0 +1,806 @@
+//! Tree mutators for [`JsonInput`]s, replacing values, duplicating and deleting members and items, renaming keys
+//! and splicing subtrees of other inputs, so the documents stay well-formed.
+
+use alloc::{
+    borrow::Cow,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::num::NonZero;
+
+use libafl_bolts::{
+    Named,
+    rands::Rand,
+    tuples::{tuple_list, tuple_list_type},
+};
+use serde_json::{Map, Number, Value};
+
+use crate::{
+    Error, HasMetadata,
+    corpus::{Corpus, CorpusId},
+    inputs::{JsonInput, Malformation},
+    mutators::{MutationResult, Mutator, Tokens, mutations::INTERESTING_32},
+    nonzero, random_corpus_id,
+    state::{HasCorpus, HasMaxSize, HasRand},
+};
+
+const INTERESTING_FLOATS: [f64; 8] = [
+    0.5,
+    -0.0,
+    1e-7,
+    1e300,
+    -1e300,
+    5e-324,
+    1.7976931348623157e308,
+    3.4e38,
+];
+
+const INTERESTING_STRINGS: [&str; 16] = [
+    "",
+    " ",
+    "0",
+    "-1",
+    "1e999",
+    "true",
+    "null",
+    "%s%n%x",
+    "\\",
+    "\"",
+    "\0",
+    "\u{feff}",
+    "\u{fffd}",
+    "\u{1f600}",
+    "../../../../../../etc/passwd",
+    "${HOME}",
+];
+
+/// Bytes inserted into documents by the [`JsonMalformMutator`]
+const MALFORMED_BYTES: &[u8] = b"{}[]\",:\\'#-\n\0";
+
+/// Mutates a copy of the root of the document with `mutate`, and keeps it if it was mutated and the document still
+/// fits into the max size
+fn mutate_tree<S, F>(
+    state: &mut S,
+    input: &mut JsonInput,
+    mutate: F,
+) -> Result<MutationResult, Error>
+where
+    S: HasMaxSize,
+    F: FnOnce(&mut S, &mut Value) -> Result<MutationResult, Error>,
+{
+    let mut value = input.value().clone();
+    if mutate(state, &mut value)? == MutationResult::Skipped {
+        return Ok(MutationResult::Skipped);
+    }
+    let original = core::mem::replace(input.value_mut(), value);
+    if input.to_bytes()?.len() > state.max_size() {
+        *input.value_mut() = original;
+        return Ok(MutationResult::Skipped);
+    }
+    Ok(MutationResult::Mutated)
+}
+
+/// The number of values of the tree matching `filter`
+fn count_values<F>(value: &Value, filter: &F) -> usize
+where
+    F: Fn(&Value) -> bool,
+{
+    usize::from(filter(value))
+        + match value {
+            Value::Array(values) => values.iter().map(|value| count_values(value, filter)).sum(),
+            Value::Object(members) => members
+                .values()
+                .map(|value| count_values(value, filter))
+                .sum(),
+            _ => 0,
+        }
+}
+
+/// The `idx`th value of the tree, in preorder, matching `filter`
+fn value_at<'a, F>(value: &'a Value, idx: &mut usize, filter: &F) -> Option<&'a Value>
+where
+    F: Fn(&Value) -> bool,
+{
+    if filter(value) {
+        if *idx == 0 {
+            return Some(value);
+        }
+        *idx -= 1;
+    }
+    match value {
+        Value::Array(values) => values.iter().find_map(|value| value_at(value, idx, filter)),
+        Value::Object(members) => members
+            .values()
+            .find_map(|value| value_at(value, idx, filter)),
+        _ => None,
+    }
+}
+
+/// The `idx`th value of the tree, in preorder, matching `filter`, mutable
+fn value_at_mut<'a, F>(value: &'a mut Value, idx: &mut usize, filter: &F) -> Option<&'a mut Value>
+where
+    F: Fn(&Value) -> bool,
+{
+    if filter(value) {
+        if *idx == 0 {
+            return Some(value);
+        }
+        *idx -= 1;
+    }
+    match value {
+        Value::Array(values) => values
+            .iter_mut()
+            .find_map(|value| value_at_mut(value, idx, filter)),
+        Value::Object(members) => members
+            .values_mut()
+            .find_map(|value| value_at_mut(value, idx, filter)),
+        _ => None,
+    }
+}
+
+/// A random value of the tree matching `filter`
+fn random_value<'a, R, F>(rand: &mut R, value: &'a mut Value, filter: F) -> Option<&'a mut Value>
+where
+    R: Rand,
+    F: Fn(&Value) -> bool,
+{
+    let count = NonZero::new(count_values(value, &filter))?;
+    let mut idx = rand.below(count);
+    value_at_mut(value, &mut idx, &filter)
+}
+
+/// Whether the value is an array or object with items or members
+fn is_filled_container(value: &Value) -> bool {
+    match value {
+        Value::Array(values) => !values.is_empty(),
+        Value::Object(members) => !members.is_empty(),
+        _ => false,
+    }
+}
+
+/// A random token of the [`Tokens`] in the state, as string
+fn random_token<S>(state: &mut S) -> Option<String>
+where
+    S: HasMetadata + HasRand,
+{
+    let count = NonZero::new(state.metadata_map().get::<Tokens>()?.len())?;
+    let idx = state.rand_mut().below(count);
+    let token = &state.metadata_map().get::<Tokens>()?.tokens()[idx];
+    Some(String::from_utf8_lossy(token).into_owned())
+}
+
+/// An interesting number, or the number changed a bit
+fn interesting_number<R>(rand: &mut R, number: &Number) -> Number
+where
+    R: Rand,
+{
+    match rand.below(nonzero!(5)) {
+        0 => Number::from(*rand.choose(&INTERESTING_32).unwrap()),
+        1 => rand
+            .choose([
+                Number::from(i64::MIN),
+                Number::from(i64::MAX),
+                Number::from(u64::MAX),
+            ])
+            .unwrap(),
+        2 => Number::from_f64(*rand.choose(&INTERESTING_FLOATS).unwrap()).unwrap(),
+        3 => {
+            let delta = (rand.next() % 35 + 1).cast_signed();
+            match number.as_i64() {
+                Some(value) if rand.coinflip(0.5) => Number::from(value.wrapping_add(delta)),
+                Some(value) => Number::from(value.wrapping_sub(delta)),
+                None => Number::from(delta),
+            }
+        }
+        _ => Number::from(rand.next()),
+    }
+}
+
+/// An interesting string, a token of the state, or the string changed a bit
+fn interesting_string<S>(state: &mut S, string: &str) -> String
+where
+    S: HasMetadata + HasRand,
+{
+    match state.rand_mut().below(nonzero!(4)) {
+        0 => (*state.rand_mut().choose(&INTERESTING_STRINGS).unwrap()).to_string(),
+        1 => {
+            if let Some(token) = random_token(state) {
+                token
+            } else {
+                string.chars().rev().collect()
+            }
+        }
+        2 => {
+            let count = state.rand_mut().between(2, 256);
+            if string.is_empty() {
+                "A".repeat(count)
+            } else {
+                string.repeat(count)
+            }
+        }
+        _ => {
+            let mut chars: Vec<char> = string.chars().collect();
+            let pos = state.rand_mut().below_or_zero(chars.len() + 1);
+            let inserted = *state
+                .rand_mut()
+                .choose(&['"', '\\', '\0', '\n', '%', '\u{fffd}', '\u{202e}'])
+                .unwrap();
+            chars.insert(pos, inserted);
+            chars.into_iter().collect()
+        }
+    }
+}
+
+/// A random scalar, or an empty array or object
+fn random_scalar<R>(rand: &mut R) -> Value
+where
+    R: Rand,
+{
+    match rand.below(nonzero!(6)) {
+        0 => Value::Null,
+        1 => Value::Bool(rand.coinflip(0.5)),
+        2 => Value::Number(interesting_number(rand, &Number::from(0))),
+        3 => Value::String((*rand.choose(&INTERESTING_STRINGS).unwrap()).to_string()),
+        4 => Value::Array(Vec::new()),
+        _ => Value::Object(Map::new()),
+    }
+}
+
+/// A [`Mutator`] that replaces a value of a [`JsonInput`] with an interesting value of the same type, a token of
+/// the [`Tokens`] for strings, or, rarely, a value of another type
+#[derive(Debug, Default)]
+pub struct JsonValueMutator;
+
+impl<S> Mutator<JsonInput, S> for JsonValueMutator
+where
+    S: HasMetadata + HasRand + HasMaxSize,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut JsonInput) -> Result<MutationResult, Error> {
+        mutate_tree(state, input, |state, root| {
+            let value = random_value(state.rand_mut(), root, |_| true).unwrap();
+            if state.rand_mut().coinflip(0.1) {
+                *value = random_scalar(state.rand_mut());
+                return Ok(MutationResult::Mutated);
+            }
+            match value {
+                Value::Null => *value = random_scalar(state.rand_mut()),
+                Value::Bool(value) => *value = !*value,
+                Value::Number(number) => *number = interesting_number(state.rand_mut(), number),
+                Value::String(string) => *string = interesting_string(state, string),
+                Value::Array(values) => values.clear(),
+                Value::Object(members) => members.clear(),
+            }
+            Ok(MutationResult::Mutated)
+        })
+    }
+
+    #[inline]
+    fn post_exec(&mut self, _state: &mut S, _new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Named for JsonValueMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("JsonValueMutator");
+        &NAME
+    }
+}
+
+impl JsonValueMutator {
+    /// Creates a new [`JsonValueMutator`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+/// A [`Mutator`] that duplicates an item of an array, or a member of an object of a [`JsonInput`], under a key of
+/// the [`Tokens`] or a variation of its key
+#[derive(Debug, Default)]
+pub struct JsonDuplicateMutator;
+
+impl<S> Mutator<JsonInput, S> for JsonDuplicateMutator
+where
+    S: HasMetadata + HasRand + HasMaxSize,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut JsonInput) -> Result<MutationResult, Error> {
+        mutate_tree(state, input, |state, root| {
+            let Some(container) = random_value(state.rand_mut(), root, is_filled_container) else {
+                return Ok(MutationResult::Skipped);
+            };
+            match container {
+                Value::Array(values) => {
+                    let item = values[state.rand_mut().below_or_zero(values.len())].clone();
+                    let pos = state.rand_mut().below_or_zero(values.len() + 1);
+                    values.insert(pos, item);
+                }
+                Value::Object(members) => {
+                    let (key, value) = state.rand_mut().choose(members.iter()).unwrap();
+                    let value = value.clone();
+                    let key = match random_token(state) {
+                        Some(token) if state.rand_mut().coinflip(0.5) => token,
+                        _ => format!("{key}{}", state.rand_mut().below(nonzero!(10))),
+                    };
+                    if members.contains_key(&key) {
+                        return Ok(MutationResult::Skipped);
+                    }
+                    members.insert(key, value);
+                }
+                _ => return Ok(MutationResult::Skipped),
+            }
+            Ok(MutationResult::Mutated)
+        })
+    }
+
+    #[inline]
+    fn post_exec(&mut self, _state: &mut S, _new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Named for JsonDuplicateMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("JsonDuplicateMutator");
+        &NAME
+    }
+}
+
+impl JsonDuplicateMutator {
+    /// Creates a new [`JsonDuplicateMutator`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+/// A [`Mutator`] that deletes an item of an array, or a member of an object of a [`JsonInput`]
+#[derive(Debug, Default)]
+pub struct JsonDeleteMutator;
+
+impl<S> Mutator<JsonInput, S> for JsonDeleteMutator
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut JsonInput) -> Result<MutationResult, Error> {
+        mutate_tree(state, input, |state, root| {
+            let Some(container) = random_value(state.rand_mut(), root, is_filled_container) else {
+                return Ok(MutationResult::Skipped);
+            };
+            match container {
+                Value::Array(values) => {
+                    values.remove(state.rand_mut().below_or_zero(values.len()));
+                }
+                Value::Object(members) => {
+                    let key = state.rand_mut().choose(members.keys()).unwrap().clone();
+                    members.remove(&key);
+                }
+                _ => return Ok(MutationResult::Skipped),
+            }
+            Ok(MutationResult::Mutated)
+        })
+    }
+
+    #[inline]
+    fn post_exec(&mut self, _state: &mut S, _new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Named for JsonDeleteMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("JsonDeleteMutator");
+        &NAME
+    }
+}
+
+impl JsonDeleteMutator {
+    /// Creates a new [`JsonDeleteMutator`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+/// A [`Mutator`] that renames a member of an object of a [`JsonInput`] to a key of the [`Tokens`], e.g., the key
+/// names the target looks up, or to an interesting string
+#[derive(Debug, Default)]
+pub struct JsonKeyMutator;
+
+impl<S> Mutator<JsonInput, S> for JsonKeyMutator
+where
+    S: HasMetadata + HasRand + HasMaxSize,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut JsonInput) -> Result<MutationResult, Error> {
+        mutate_tree(state, input, |state, root| {
+            let Some(Value::Object(members)) = random_value(
+                state.rand_mut(),
+                root,
+                |value| matches!(value, Value::Object(members) if !members.is_empty()),
+            ) else {
+                return Ok(MutationResult::Skipped);
+            };
+            let key = state.rand_mut().choose(members.keys()).unwrap().clone();
+            let renamed = match random_token(state) {
+                Some(token) if !state.rand_mut().coinflip(0.2) => token,
+                _ => interesting_string(state, &key),
+            };
+            if members.contains_key(&renamed) {
+                return Ok(MutationResult::Skipped);
+            }
+            let value = members.remove(&key).unwrap();
+            members.insert(renamed, value);
+            Ok(MutationResult::Mutated)
+        })
+    }
+
+    #[inline]
+    fn post_exec(&mut self, _state: &mut S, _new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Named for JsonKeyMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("JsonKeyMutator");
+        &NAME
+    }
+}
+
+impl JsonKeyMutator {
+    /// Creates a new [`JsonKeyMutator`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+/// A [`Mutator`] that replaces a value of a [`JsonInput`] with a subtree of another input of the corpus
+#[derive(Debug, Default)]
+pub struct JsonSpliceMutator;
+
+impl<S> Mutator<JsonInput, S> for JsonSpliceMutator
+where
+    S: HasRand + HasMaxSize + HasCorpus<JsonInput>,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut JsonInput) -> Result<MutationResult, Error> {
+        mutate_tree(state, input, |state, root| {
+            let id = random_corpus_id!(state.corpus(), state.rand_mut());
+            let rand_num = state.rand_mut().next() as usize;
+            let subtree = {
+                let mut other_testcase = state.corpus().get(id)?.borrow_mut();
+                let other = other_testcase.load_input(state.corpus())?.value();
+                let mut idx = rand_num % count_values(other, &|_| true);
+                value_at(other, &mut idx, &|_| true).unwrap().clone()
+            };
+            let value = random_value(state.rand_mut(), root, |_| true).unwrap();
+            if *value == subtree {
+                return Ok(MutationResult::Skipped);
+            }
+            *value = subtree;
+            Ok(MutationResult::Mutated)
+        })
+    }
+
+    #[inline]
+    fn post_exec(&mut self, _state: &mut S, _new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Named for JsonSpliceMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("JsonSpliceMutator");
+        &NAME
+    }
+}
+
+impl JsonSpliceMutator {
+    /// Creates a new [`JsonSpliceMutator`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+/// A [`Mutator`] that makes a [`JsonInput`] serialize to a slightly malformed document, with a random
+/// [`Malformation`], or well-formed again.
+///
+/// Not part of the [`json_mutations`], add it to test the error handling of the parser of the target.
+#[derive(Debug, Default)]
+pub struct JsonMalformMutator;
+
+impl<S> Mutator<JsonInput, S> for JsonMalformMutator
+where
+    S: HasRand,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut JsonInput) -> Result<MutationResult, Error> {
+        let rand = state.rand_mut();
+        if input.malformation().is_some() && rand.coinflip(0.5) {
+            input.set_malformation(None);
+            return Ok(MutationResult::Mutated);
+        }
+        let pos = rand.next() as usize;
+        let malformation = match rand.below(nonzero!(4)) {
+            0 => Malformation::Truncate(pos),
+            1 => Malformation::Delete(pos),
+            2 => Malformation::Insert(pos, *rand.choose(MALFORMED_BYTES).unwrap()),
+            _ => Malformation::Repeat(pos),
+        };
+        input.set_malformation(Some(malformation));
+        Ok(MutationResult::Mutated)
+    }
+
+    #[inline]
+    fn post_exec(&mut self, _state: &mut S, _new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Named for JsonMalformMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("JsonMalformMutator");
+        &NAME
+    }
+}
+
+impl JsonMalformMutator {
+    /// Creates a new [`JsonMalformMutator`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+/// Tuple type of the tree mutations for [`JsonInput`]s
+pub type JsonMutationsType = tuple_list_type!(
+    JsonValueMutator,
+    JsonDuplicateMutator,
+    JsonDeleteMutator,
+    JsonKeyMutator,
+    JsonSpliceMutator,
+);
+
+/// Get the tree mutations for [`JsonInput`]s, which keep the documents well-formed
+#[must_use]
+pub fn json_mutations() -> JsonMutationsType {
+    tuple_list!(
+        JsonValueMutator::new(),
+        JsonDuplicateMutator::new(),
+        JsonDeleteMutator::new(),
+        JsonKeyMutator::new(),
+        JsonSpliceMutator::new(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{string::String, vec::Vec};
+
+    use libafl_bolts::rands::StdRand;
+    use serde_json::{Value, json};
+
+    use super::{
+        INTERESTING_STRINGS, JsonDeleteMutator, JsonDuplicateMutator, JsonKeyMutator,
+        JsonMalformMutator, JsonSpliceMutator, JsonValueMutator,
+    };
+    use crate::{
+        HasMetadata,
+        corpus::{Corpus, InMemoryCorpus, Testcase},
+        feedbacks::ConstFeedback,
+        inputs::JsonInput,
+        mutators::{MutationResult, Mutator, Tokens},
+        state::StdState,
+    };
+
+    type JsonState =
+        StdState<InMemoryCorpus<JsonInput>, JsonInput, StdRand, InMemoryCorpus<JsonInput>>;
+
+    /// A state with the `donor` in the corpus and `timeout` as token
+    fn state(donor: Value) -> JsonState {
+        let mut corpus = InMemoryCorpus::new();
+        corpus.add(Testcase::new(JsonInput::from(donor))).unwrap();
+        let mut feedback = ConstFeedback::new(false);
+        let mut objective = ConstFeedback::new(false);
+        let mut state = StdState::new(
+            StdRand::with_seed(1337),
+            corpus,
+            InMemoryCorpus::new(),
+            &mut feedback,
+            &mut objective,
+        )
+        .unwrap();
+        state.add_metadata(Tokens::from([b"timeout".to_vec()]));
+        state
+    }
+
+    /// Mutates fresh copies of `seed` with `mutator`, returning the values it mutated them to
+    fn mutate_all<M>(state: &mut JsonState, mutator: &mut M, seed: &Value) -> Vec<Value>
+    where
+        M: Mutator<JsonInput, JsonState>,
+    {
+        (0..100)
+            .filter_map(|_| {
+                let mut input = JsonInput::from(seed.clone());
+                (mutator.mutate(state, &mut input).unwrap() == MutationResult::Mutated)
+                    .then(|| input.value().clone())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_json_key_mutator() {
+        let mut state = state(json!(null));
+        let mutated = mutate_all(
+            &mut state,
+            &mut JsonKeyMutator::new(),
+            &json!({"retries": 3}),
+        );
+        assert!(!mutated.is_empty());
+        for value in &mutated {
+            // The member is renamed, keeping its value
+            let members = value.as_object().unwrap();
+            assert_eq!(members.len(), 1);
+            assert!(!members.contains_key("retries"));
+            assert_eq!(members.values().next(), Some(&json!(3)));
+        }
+        assert!(mutated.contains(&json!({"timeout": 3})));
+
+        // Nothing to rename
+        let mut input = JsonInput::from(json!([{}, 1]));
+        assert_eq!(
+            JsonKeyMutator::new()
+                .mutate(&mut state, &mut input)
+                .unwrap(),
+            MutationResult::Skipped
+        );
+    }
+
+    #[test]
+    fn test_json_value_mutator() {
+        let mut state = state(json!(null));
+        let mutated = mutate_all(&mut state, &mut JsonValueMutator::new(), &json!(3));
+        assert_eq!(mutated.len(), 100);
+        // A number mostly stays a number, and rarely becomes a value of another type
+        assert!(mutated.iter().filter(|value| value.is_number()).count() > 50);
+        assert!(mutated.iter().any(|value| !value.is_number()));
+
+        let mutated = mutate_all(&mut state, &mut JsonValueMutator::new(), &json!("abc"));
+        assert_eq!(mutated.len(), 100);
+        for value in &mutated {
+            let Some(string) = value.as_str() else {
+                continue;
+            };
+            // An interesting string, a token, a repetition of the string, or the string with a character inserted
+            let chars = string.chars().collect::<Vec<_>>();
+            let inserted = chars.len() == 4
+                && (0..4).any(|pos| {
+                    let mut chars = chars.clone();
+                    chars.remove(pos);
+                    chars.into_iter().collect::<String>() == "abc"
+                });
+            assert!(
+                INTERESTING_STRINGS.contains(&string)
+                    || string == "timeout"
+                    || string == "abc".repeat(string.len() / 3)
+                    || inserted,
+                "{string:?}"
+            );
+        }
+        assert!(mutated.contains(&json!("timeout")));
+        assert!(mutated.iter().any(|value| {
+            value
+                .as_str()
+                .is_some_and(|s| INTERESTING_STRINGS.contains(&s))
+        }));
+
+        // A bool is flipped, containers are emptied
+        let mutated = mutate_all(&mut state, &mut JsonValueMutator::new(), &json!(true));
+        assert!(mutated.contains(&json!(false)));
+        let mutated = mutate_all(&mut state, &mut JsonValueMutator::new(), &json!({"a": [1]}));
+        assert!(mutated.contains(&json!({})));
+        assert!(mutated.contains(&json!({"a": []})));
+    }
+
+    #[test]
+    fn test_json_delete_mutator() {
+        let mut state = state(json!(null));
+        let mutated = mutate_all(&mut state, &mut JsonDeleteMutator::new(), &json!([1, 2, 3]));
+        assert_eq!(mutated.len(), 100);
+        for value in &mutated {
+            assert!([json!([2, 3]), json!([1, 3]), json!([1, 2])].contains(value));
+        }
+
+        let mutated = mutate_all(
+            &mut state,
+            &mut JsonDeleteMutator::new(),
+            &json!({"a": 1, "b": [2]}),
+        );
+        for value in &mutated {
+            // Either the member of the root, or the item of `b`, is deleted
+            assert!([json!({"a": 1}), json!({"b": [2]}), json!({"a": 1, "b": []})].contains(value));
+        }
+    }
+
+    #[test]
+    fn test_json_duplicate_mutator() {
+        let mut state = state(json!(null));
+        let mutated = mutate_all(
+            &mut state,
+            &mut JsonDuplicateMutator::new(),
+            &json!([1, 2, 3]),
+        );
+        assert_eq!(mutated.len(), 100);
+        for value in &mutated {
+            let mut items = value
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|item| item.as_u64().unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(items.len(), 4);
+            items.sort_unstable();
+            items.dedup();
+            assert_eq!(items, [1, 2, 3]);
+        }
+
+        let mutated = mutate_all(
+            &mut state,
+            &mut JsonDuplicateMutator::new(),
+            &json!({"a": 1}),
+        );
+        assert_eq!(mutated.len(), 100);
+        for value in &mutated {
+            // The member is copied under a token or a variation of its key
+            let members = value.as_object().unwrap();
+            assert_eq!(members.len(), 2);
+            assert_eq!(members.get("a"), Some(&json!(1)));
+            assert!(members.values().all(|value| *value == json!(1)));
+            assert!(
+                members
+                    .keys()
+                    .all(|key| key == "timeout" || key.starts_with('a'))
+            );
+        }
+        assert!(mutated.contains(&json!({"a": 1, "timeout": 1})));
+    }
+
+    #[test]
+    fn test_json_splice_mutator() {
+        let donor = json!({"donor": ["x", "y"]});
+        let subtrees = [donor.clone(), json!(["x", "y"]), json!("x"), json!("y")];
+        let mut state = state(donor);
+        let mutated = mutate_all(&mut state, &mut JsonSpliceMutator::new(), &json!([1]));
+        assert!(!mutated.is_empty());
+        for value in &mutated {
+            // Either the root or its item is replaced by a subtree of the donor
+            let spliced = match value.as_array() {
+                Some(items) if items.len() == 1 && !subtrees.contains(value) => &items[0],
+                _ => value,
+            };
+            assert!(subtrees.contains(spliced), "{value}");
+        }
+    }
+
+    #[test]
+    fn test_json_malform_mutator() {
+        let mut state = state(json!(null));
+        let mut input = JsonInput::from(json!({"a": [1, "x"]}));
+        let mut malformed = 0;
+        for _ in 0..100 {
+            JsonMalformMutator::new()
+                .mutate(&mut state, &mut input)
+                .unwrap();
+            if input.malformation().is_some() {
+                malformed += 1;
+                assert_ne!(
+                    input.to_bytes().unwrap(),
+                    input.format().serialize(input.value()).unwrap()
+                );
+            }
+            // The value itself is left alone
+            assert_eq!(input.value(), &json!({"a": [1, "x"]}));
+        }
+        assert!(malformed > 0);
+    }
+}
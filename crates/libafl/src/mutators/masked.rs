@@ -0,0 +1,312 @@
+//! Mutation masks as in `FairFuzz`: the [`crate::stages::RareBranchMaskStage`] finds the bytes of a testcase that
+//! can be changed without losing the rare branch it hits, and the [`MaskedHavocScheduledMutator`] only changes those.
+
+use alloc::{borrow::Cow, format, vec::Vec};
+
+use libafl_bolts::{Named, tuples::NamedTuple};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Error, HasMetadata,
+    corpus::{Corpus, CorpusId, HasCurrentCorpusId},
+    inputs::{HasMutatorBytes, ResizableMutator},
+    mutators::{
+        ComposedByMutations, HavocScheduledMutator, MutationId, MutationResult, Mutator,
+        MutatorsTuple, ScheduledMutator,
+    },
+    state::{HasCorpus, HasRand},
+};
+
+/// The bytes of a testcase that may be mutated without losing the rare branch it hits
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MutationMaskMetadata {
+    /// The rare branch, as index into the coverage map, or `None` if the testcase hits no rare branch
+    pub edge: Option<usize>,
+    /// Whether each byte may be overwritten. Bytes past the end of the mask may not.
+    pub allowed: Vec<bool>,
+}
+
+libafl_bolts::impl_serdeany!(MutationMaskMetadata);
+
+impl MutationMaskMetadata {
+    /// A mask for a testcase that hits no rare branch, allowing all mutations
+    #[must_use]
+    pub fn unmasked() -> Self {
+        Self::default()
+    }
+
+    /// A mask for a testcase hitting the rare branch `edge`
+    #[must_use]
+    pub fn new(edge: usize, allowed: Vec<bool>) -> Self {
+        Self {
+            edge: Some(edge),
+            allowed,
+        }
+    }
+
+    /// Whether the mask restricts mutations at all
+    #[must_use]
+    pub fn is_masked(&self) -> bool {
+        self.edge.is_some()
+    }
+
+    /// Whether the byte at `idx` may be overwritten
+    #[must_use]
+    pub fn is_allowed(&self, idx: usize) -> bool {
+        !self.is_masked() || self.allowed.get(idx).copied().unwrap_or(false)
+    }
+
+    /// Adjusts the mask to a single mutation from `original` to `mutated`, as in `FairFuzz`.
+    ///
+    /// Overwrites of protected bytes are reverted in `mutated`. If the mutation changed the length, the mask is
+    /// shifted along, and the inserted bytes may be mutated further. Returns `false` if the mutation deleted
+    /// protected bytes or inserted bytes between two protected ones, leaving `mutated` to be reverted.
+    pub fn realign(&mut self, original: &[u8], mutated: &mut [u8]) -> bool {
+        if !self.is_masked() {
+            return true;
+        }
+        if original.len() == mutated.len() {
+            for (idx, (byte, orig)) in mutated.iter_mut().zip(original).enumerate() {
+                if !self.is_allowed(idx) {
+                    *byte = *orig;
+                }
+            }
+            return true;
+        }
+
+        // The bytes in between the common prefix and suffix were replaced
+        let prefix = original
+            .iter()
+            .zip(mutated.iter())
+            .take_while(|(orig, byte)| orig == byte)
+            .count();
+        let shortest = original.len().min(mutated.len());
+        let suffix = original
+            .iter()
+            .rev()
+            .zip(mutated.iter().rev())
+            .take(shortest - prefix)
+            .take_while(|(orig, byte)| orig == byte)
+            .count();
+        let removed = prefix..original.len() - suffix;
+        let inserted = mutated.len() - suffix - prefix;
+
+        let allowed = if removed.is_empty() {
+            self.is_allowed(prefix)
+                || prefix
+                    .checked_sub(1)
+                    .is_some_and(|idx| self.is_allowed(idx))
+        } else {
+            removed.clone().all(|idx| self.is_allowed(idx))
+        };
+        if !allowed {
+            return false;
+        }
+
+        let shifted = (0..prefix)
+            .map(|idx| self.is_allowed(idx))
+            .chain(core::iter::repeat_n(true, inserted))
+            .chain((removed.end..original.len()).map(|idx| self.is_allowed(idx)))
+            .collect();
+        self.allowed = shifted;
+        true
+    }
+}
+
+/// A [`HavocScheduledMutator`] that keeps the bytes the [`MutationMaskMetadata`] of the current testcase
+/// protects, so that the mutated inputs still hit the rare branch.
+///
+/// The stacked mutations are applied one by one, and the mask follows each of them: overwrites of protected bytes
+/// are reverted, inserts and deletes shift the mask, and mutations deleting protected bytes are dropped.
+#[derive(Debug)]
+pub struct MaskedHavocScheduledMutator<MT> {
+    name: Cow<'static, str>,
+    havoc: HavocScheduledMutator<MT>,
+    /// The mask of the last testcase that had one
+    mask: Option<(CorpusId, MutationMaskMetadata)>,
+}
+
+impl<MT> Named for MaskedHavocScheduledMutator<MT> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<MT> MaskedHavocScheduledMutator<MT> {
+    /// Loads the mask of the current testcase, if it changed
+    fn update_mask<I, S>(&mut self, state: &S) -> Result<(), Error>
+    where
+        S: HasCorpus<I> + HasCurrentCorpusId,
+    {
+        let Some(id) = state.current_corpus_id()? else {
+            self.mask = None;
+            return Ok(());
+        };
+        if self
+            .mask
+            .as_ref()
+            .is_some_and(|(mask_id, _)| *mask_id == id)
+        {
+            return Ok(());
+        }
+        let testcase = state.corpus().get(id)?.borrow();
+        self.mask = testcase
+            .metadata::<MutationMaskMetadata>()
+            .ok()
+            .map(|mask| (id, mask.clone()));
+        Ok(())
+    }
+}
+
+impl<I, MT, S> Mutator<I, S> for MaskedHavocScheduledMutator<MT>
+where
+    I: HasMutatorBytes + ResizableMutator<u8>,
+    MT: MutatorsTuple<I, S>,
+    S: HasRand + HasCorpus<I> + HasCurrentCorpusId,
+{
+    fn mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        self.scheduled_mutate(state, input)
+    }
+
+    #[inline]
+    fn post_exec(&mut self, state: &mut S, new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
+        Mutator::<I, S>::post_exec(&mut self.havoc, state, new_corpus_id)
+    }
+}
+
+impl<MT> ComposedByMutations for MaskedHavocScheduledMutator<MT> {
+    type Mutations = MT;
+
+    #[inline]
+    fn mutations(&self) -> &MT {
+        self.havoc.mutations()
+    }
+
+    #[inline]
+    fn mutations_mut(&mut self) -> &mut MT {
+        self.havoc.mutations_mut()
+    }
+}
+
+impl<I, MT, S> ScheduledMutator<I, S> for MaskedHavocScheduledMutator<MT>
+where
+    I: HasMutatorBytes + ResizableMutator<u8>,
+    MT: MutatorsTuple<I, S>,
+    S: HasRand + HasCorpus<I> + HasCurrentCorpusId,
+{
+    fn iterations(&self, state: &mut S, input: &I) -> u64 {
+        self.havoc.iterations(state, input)
+    }
+
+    fn schedule(&self, state: &mut S, input: &I) -> MutationId {
+        self.havoc.schedule(state, input)
+    }
+
+    fn scheduled_mutate(&mut self, state: &mut S, input: &mut I) -> Result<MutationResult, Error> {
+        self.update_mask(state)?;
+        let Some(mut mask) = self
+            .mask
+            .as_ref()
+            .filter(|(_, mask)| mask.is_masked())
+            .map(|(_, mask)| mask.clone())
+        else {
+            return self.havoc.scheduled_mutate(state, input);
+        };
+
+        let original = input.mutator_bytes().to_vec();
+        let mut before = original.clone();
+        let num = self.iterations(state, input);
+        for _ in 0..num {
+            let idx = self.schedule(state, input);
+            if self.mutations_mut().get_and_mutate(idx, state, input)? == MutationResult::Skipped {
+                continue;
+            }
+            if mask.realign(&before, input.mutator_bytes_mut()) {
+                before.clear();
+                before.extend_from_slice(input.mutator_bytes());
+            } else {
+                restore(input, &before);
+            }
+        }
+
+        if input.mutator_bytes() == original {
+            Ok(MutationResult::Skipped)
+        } else {
+            Ok(MutationResult::Mutated)
+        }
+    }
+}
+
+/// Resets the bytes of `input` to `bytes`
+fn restore<I>(input: &mut I, bytes: &[u8])
+where
+    I: HasMutatorBytes + ResizableMutator<u8>,
+{
+    input.resize(bytes.len(), 0);
+    input.mutator_bytes_mut().copy_from_slice(bytes);
+}
+
+impl<MT> MaskedHavocScheduledMutator<MT>
+where
+    MT: NamedTuple,
+{
+    /// Creates a new [`MaskedHavocScheduledMutator`] with the given mutations, e.g., [`crate::mutators::havoc_mutations()`]
+    pub fn new(mutations: MT) -> Self {
+        Self::from_havoc(HavocScheduledMutator::new(mutations))
+    }
+
+    /// Creates a new [`MaskedHavocScheduledMutator`] stacking up to `2^max_stack_pow` mutations
+    pub fn with_max_stack_pow(mutations: MT, max_stack_pow: usize) -> Self {
+        Self::from_havoc(HavocScheduledMutator::with_max_stack_pow(
+            mutations,
+            max_stack_pow,
+        ))
+    }
+
+    fn from_havoc(havoc: HavocScheduledMutator<MT>) -> Self {
+        Self {
+            name: Cow::Owned(format!("Masked{}", havoc.name())),
+            havoc,
+            mask: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MutationMaskMetadata;
+
+    #[test]
+    fn test_mutation_mask() {
+        let mut mask = MutationMaskMetadata::new(3, vec![true, false, true]);
+        // Overwrites of protected bytes are reverted
+        let mut mutated = *b"XYZ";
+        assert!(mask.realign(b"abc", &mut mutated));
+        assert_eq!(&mutated, b"XbZ");
+        // Bytes past the end of the mask are protected too
+        let mut mutated = *b"XYZW";
+        assert!(mask.clone().realign(b"abcd", &mut mutated));
+        assert_eq!(&mutated, b"XbZd");
+        // Deleting protected bytes is rejected
+        assert!(!mask.clone().realign(b"abc", &mut b"ac".to_vec()));
+
+        // Inserting at an allowed position shifts the mask
+        assert!(mask.realign(b"abc", &mut b"xyabc".to_vec()));
+        assert_eq!(mask.allowed, vec![true, true, true, false, true]);
+        assert!(!mask.is_allowed(3));
+        // Deleting allowed bytes shifts it back
+        assert!(mask.realign(b"xyabc", &mut b"bc".to_vec()));
+        assert_eq!(mask.allowed, vec![false, true]);
+        // Inserting in between protected bytes is rejected
+        let mut mask = MutationMaskMetadata::new(3, vec![false, false]);
+        assert!(!mask.realign(b"ab", &mut b"axb".to_vec()));
+
+        let mut unmasked = MutationMaskMetadata::unmasked();
+        assert!(unmasked.is_allowed(100));
+        assert!(unmasked.realign(b"abc", &mut b"ab".to_vec()));
+    }
+}
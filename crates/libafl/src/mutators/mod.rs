@@ -27,6 +27,12 @@ pub mod mapping;
 pub use mapping::*;
 pub mod tuneable;
 pub use tuneable::*;
+pub mod fields;
+pub use fields::*;
+pub mod checksum;
+pub use checksum::*;
+pub mod masked;
+pub use masked::*;
 
 #[cfg(feature = "lua_mutator")]
 pub mod lua;
@@ -49,11 +55,21 @@ pub mod multi;
 #[cfg(feature = "nautilus")]
 pub mod nautilus;
 
+#[cfg(feature = "protobuf")]
+pub mod protobuf;
+
+#[cfg(feature = "json_input")]
+pub mod json;
+
 use alloc::{borrow::Cow, boxed::Box, vec::Vec};
 
+#[cfg(feature = "json_input")]
+pub use json::*;
 use libafl_bolts::{HasLen, Named, tuples::IntoVec};
 #[cfg(feature = "nautilus")]
 pub use nautilus::*;
+#[cfg(feature = "protobuf")]
+pub use protobuf::*;
 use tuple_list::NonEmptyTuple;
 
 use crate::{Error, corpus::CorpusId};
@@ -0,0 +1,691 @@
+//! Field-aware mutators for [`ProtobufInput`]s, like the mutators of libprotobuf-mutator.
+//!
+//! Each mutator decodes the input with the message descriptor of a [`ProtobufContext`], mutates a field of a random
+//! (sub)message, and encodes the message again, so the input stays a valid message.
+
+use alloc::{borrow::Cow, vec, vec::Vec};
+use core::num::NonZero;
+use std::collections::HashMap;
+
+use libafl_bolts::{
+    Named,
+    rands::Rand,
+    tuples::{tuple_list, tuple_list_type},
+};
+use prost_reflect::{
+    DynamicMessage, Kind, MessageDescriptor, ReflectMessage, Value, prost::bytes::Bytes,
+};
+
+use crate::{
+    Error,
+    corpus::{Corpus, CorpusId},
+    inputs::{ProtobufContext, ProtobufInput},
+    mutators::{MutationResult, Mutator, mutations::INTERESTING_32},
+    nonzero, random_corpus_id,
+    state::{HasCorpus, HasMaxSize, HasRand},
+};
+
+/// The maximum number of elements inserted into strings and bytes at once
+const MAX_INSERT_LEN: usize = 16;
+
+const INTERESTING_FLOATS: [f64; 11] = [
+    0.0,
+    -0.0,
+    1.0,
+    -1.0,
+    f64::EPSILON,
+    f64::MIN_POSITIVE,
+    f64::MAX,
+    f64::MIN,
+    f64::INFINITY,
+    f64::NEG_INFINITY,
+    f64::NAN,
+];
+
+/// Decodes the input, mutates the message with `mutate`, and encodes it again if it was mutated and still fits
+/// into the max size
+fn mutate_message<S, F>(
+    descriptor: &MessageDescriptor,
+    state: &mut S,
+    input: &mut ProtobufInput,
+    mutate: F,
+) -> Result<MutationResult, Error>
+where
+    S: HasMaxSize,
+    F: FnOnce(&mut S, &mut DynamicMessage) -> Result<MutationResult, Error>,
+{
+    let Ok(mut message) = DynamicMessage::decode(descriptor.clone(), input.bytes()) else {
+        return Ok(MutationResult::Skipped);
+    };
+    if mutate(state, &mut message)? == MutationResult::Skipped {
+        return Ok(MutationResult::Skipped);
+    }
+    let mutated = ProtobufInput::from_message(&message);
+    if mutated.bytes().len() > state.max_size() {
+        return Ok(MutationResult::Skipped);
+    }
+    *input = mutated;
+    Ok(MutationResult::Mutated)
+}
+
+/// Calls `f` for the message and all its submessages, in preorder
+fn for_each_message<'a, F>(message: &'a DynamicMessage, f: &mut F)
+where
+    F: FnMut(&'a DynamicMessage),
+{
+    f(message);
+    for (_, value) in message.fields() {
+        match value {
+            Value::Message(message) => for_each_message(message, f),
+            Value::List(list) => {
+                for value in list {
+                    if let Value::Message(message) = value {
+                        for_each_message(message, f);
+                    }
+                }
+            }
+            Value::Map(map) => {
+                for value in map.values() {
+                    if let Value::Message(message) = value {
+                        for_each_message(message, f);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The `idx`th message, in preorder, of the message and its submessages
+fn message_at_mut<'a>(
+    message: &'a mut DynamicMessage,
+    idx: &mut usize,
+) -> Option<&'a mut DynamicMessage> {
+    if *idx == 0 {
+        return Some(message);
+    }
+    *idx -= 1;
+    for (_, value) in message.fields_mut() {
+        let found = match value {
+            Value::Message(message) => message_at_mut(message, idx),
+            Value::List(list) => list.iter_mut().find_map(|value| match value {
+                Value::Message(message) => message_at_mut(message, idx),
+                _ => None,
+            }),
+            Value::Map(map) => map.values_mut().find_map(|value| match value {
+                Value::Message(message) => message_at_mut(message, idx),
+                _ => None,
+            }),
+            _ => None,
+        };
+        if found.is_some() {
+            return found;
+        }
+    }
+    None
+}
+
+/// A random message of the message and its submessages
+fn random_message<'a, R>(rand: &mut R, message: &'a mut DynamicMessage) -> &'a mut DynamicMessage
+where
+    R: Rand,
+{
+    let mut count = 0;
+    for_each_message(message, &mut |_| count += 1);
+    let mut idx = rand.below(NonZero::new(count).unwrap());
+    message_at_mut(message, &mut idx).unwrap()
+}
+
+/// Mutates an integer, as `bits` wide integer
+fn mutate_int<R>(rand: &mut R, value: i64, bits: usize) -> i64
+where
+    R: Rand,
+{
+    match rand.below(nonzero!(4)) {
+        0 => i64::from(*rand.choose(&INTERESTING_32).unwrap()),
+        1 => {
+            let delta = (rand.next() % 35 + 1).cast_signed();
+            if rand.coinflip(0.5) {
+                value.wrapping_add(delta)
+            } else {
+                value.wrapping_sub(delta)
+            }
+        }
+        2 => value ^ (1 << rand.below(NonZero::new(bits).unwrap())),
+        _ => rand.next().cast_signed(),
+    }
+}
+
+/// Mutates a float
+fn mutate_float<R>(rand: &mut R, value: f64) -> f64
+where
+    R: Rand,
+{
+    match rand.below(nonzero!(3)) {
+        0 => *rand.choose(&INTERESTING_FLOATS).unwrap(),
+        1 => f64::from_bits(value.to_bits() ^ (1 << rand.below(nonzero!(64)))),
+        _ => f64::from(mutate_int(rand, value as i64, 32) as i32),
+    }
+}
+
+/// Inserts random elements into, deletes, replaces or duplicates elements of `seq`
+fn mutate_seq<R, T, F>(rand: &mut R, seq: &mut Vec<T>, mut random: F)
+where
+    R: Rand,
+    T: Clone,
+    F: FnMut(&mut R) -> T,
+{
+    let Some(len) = NonZero::new(seq.len()) else {
+        let count = rand.between(1, MAX_INSERT_LEN);
+        seq.extend((0..count).map(|_| random(rand)));
+        return;
+    };
+    match rand.below(nonzero!(4)) {
+        0 => {
+            let pos = rand.below_or_zero(seq.len() + 1);
+            let count = rand.between(1, MAX_INSERT_LEN);
+            let inserted: Vec<T> = (0..count).map(|_| random(rand)).collect();
+            seq.splice(pos..pos, inserted);
+        }
+        1 => {
+            let start = rand.below(len);
+            let end = rand.between(start + 1, seq.len());
+            seq.drain(start..end);
+        }
+        2 => {
+            let pos = rand.below(len);
+            seq[pos] = random(rand);
+        }
+        _ => {
+            let start = rand.below(len);
+            let end = rand.between(start + 1, seq.len().min(start + MAX_INSERT_LEN));
+            let pos = rand.below_or_zero(seq.len() + 1);
+            let duplicated = seq[start..end].to_vec();
+            seq.splice(pos..pos, duplicated);
+        }
+    }
+}
+
+/// Mutates a value of a scalar field of the kind; messages, lists and maps are left as they are
+fn mutate_value<R>(rand: &mut R, value: &mut Value, kind: &Kind)
+where
+    R: Rand,
+{
+    match value {
+        Value::Bool(value) => *value = !*value,
+        Value::I32(value) => *value = mutate_int(rand, i64::from(*value), 32) as i32,
+        Value::I64(value) => *value = mutate_int(rand, *value, 64),
+        Value::U32(value) => {
+            *value = mutate_int(rand, i64::from(*value), 32).cast_unsigned() as u32;
+        }
+        Value::U64(value) => *value = mutate_int(rand, value.cast_signed(), 64).cast_unsigned(),
+        Value::F32(value) => *value = mutate_float(rand, f64::from(*value)) as f32,
+        Value::F64(value) => *value = mutate_float(rand, *value),
+        Value::String(value) => {
+            let mut chars: Vec<char> = value.chars().collect();
+            mutate_seq(rand, &mut chars, |rand| {
+                if rand.coinflip(0.9) {
+                    char::from(rand.between(0x20, 0x7e) as u8)
+                } else {
+                    char::from_u32(rand.next() as u32 % 0x11_0000).unwrap_or('\u{fffd}')
+                }
+            });
+            *value = chars.into_iter().collect();
+        }
+        Value::Bytes(value) => {
+            let mut bytes = value.to_vec();
+            mutate_seq(rand, &mut bytes, |rand| rand.next() as u8);
+            *value = Bytes::from(bytes);
+        }
+        Value::EnumNumber(value) => {
+            if let Kind::Enum(descriptor) = kind
+                && !rand.coinflip(0.1)
+            {
+                *value = rand
+                    .choose(descriptor.values())
+                    .map_or(*value, |value| value.number());
+            } else {
+                // Unknown values of open enums are still valid messages
+                *value = mutate_int(rand, i64::from(*value), 32) as i32;
+            }
+        }
+        Value::Message(_) | Value::List(_) | Value::Map(_) => {}
+    }
+}
+
+/// A random value of the kind, an empty message for message kinds
+fn random_value<R>(rand: &mut R, kind: &Kind) -> Value
+where
+    R: Rand,
+{
+    let mut value = Value::default_value(kind);
+    mutate_value(rand, &mut value, kind);
+    value
+}
+
+/// A [`Mutator`] that mutates a scalar field of a [`ProtobufInput`]: integers, floats, bools, strings, bytes and
+/// enums, or an element of a repeated scalar field
+#[derive(Debug, Clone)]
+pub struct ProtobufScalarMutator {
+    descriptor: MessageDescriptor,
+}
+
+impl<S> Mutator<ProtobufInput, S> for ProtobufScalarMutator
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut ProtobufInput,
+    ) -> Result<MutationResult, Error> {
+        mutate_message(&self.descriptor, state, input, |state, message| {
+            let rand = state.rand_mut();
+            let message = random_message(rand, message);
+            let fields = message
+                .descriptor()
+                .fields()
+                .filter(|field| !field.is_map() && !matches!(field.kind(), Kind::Message(_)))
+                .collect::<Vec<_>>();
+            let Some(field) = rand.choose(fields) else {
+                return Ok(MutationResult::Skipped);
+            };
+            let kind = field.kind();
+            if field.is_list() {
+                let Value::List(list) = message.get_field_mut(&field) else {
+                    return Ok(MutationResult::Skipped);
+                };
+                match NonZero::new(list.len()) {
+                    Some(len) if !rand.coinflip(0.1) => {
+                        let idx = rand.below(len);
+                        mutate_value(rand, &mut list[idx], &kind);
+                    }
+                    _ => list.push(random_value(rand, &kind)),
+                }
+            } else {
+                let mut value = message.get_field(&field).into_owned();
+                mutate_value(rand, &mut value, &kind);
+                message.set_field(&field, value);
+            }
+            Ok(MutationResult::Mutated)
+        })
+    }
+    #[inline]
+    fn post_exec(&mut self, _state: &mut S, _new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Named for ProtobufScalarMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("ProtobufScalarMutator");
+        &NAME
+    }
+}
+
+impl ProtobufScalarMutator {
+    /// Creates a new [`ProtobufScalarMutator`] for the messages of `context`
+    #[must_use]
+    pub fn new(context: &ProtobufContext) -> Self {
+        Self {
+            descriptor: context.message_descriptor().clone(),
+        }
+    }
+}
+
+/// A [`Mutator`] that sets an unset field of a [`ProtobufInput`] to a random value, or an empty message, or
+/// clears a set field
+#[derive(Debug, Clone)]
+pub struct ProtobufFieldMutator {
+    descriptor: MessageDescriptor,
+}
+
+impl<S> Mutator<ProtobufInput, S> for ProtobufFieldMutator
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut ProtobufInput,
+    ) -> Result<MutationResult, Error> {
+        mutate_message(&self.descriptor, state, input, |state, message| {
+            let rand = state.rand_mut();
+            let message = random_message(rand, message);
+            let Some(field) = rand.choose(message.descriptor().fields()) else {
+                return Ok(MutationResult::Skipped);
+            };
+            if message.has_field(&field) {
+                message.clear_field(&field);
+                return Ok(MutationResult::Mutated);
+            }
+            let kind = field.kind();
+            let value = match &kind {
+                Kind::Message(entry) if field.is_map() => {
+                    let key_kind = entry.map_entry_key_field().kind();
+                    let Some(key) = random_value(rand, &key_kind).into_map_key() else {
+                        return Ok(MutationResult::Skipped);
+                    };
+                    let value = Value::default_value_for_field(&entry.map_entry_value_field());
+                    Value::Map(HashMap::from([(key, value)]))
+                }
+                _ if field.is_list() => Value::List(vec![random_value(rand, &kind)]),
+                _ => random_value(rand, &kind),
+            };
+            message.set_field(&field, value);
+            Ok(MutationResult::Mutated)
+        })
+    }
+    #[inline]
+    fn post_exec(&mut self, _state: &mut S, _new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Named for ProtobufFieldMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("ProtobufFieldMutator");
+        &NAME
+    }
+}
+
+impl ProtobufFieldMutator {
+    /// Creates a new [`ProtobufFieldMutator`] for the messages of `context`
+    #[must_use]
+    pub fn new(context: &ProtobufContext) -> Self {
+        Self {
+            descriptor: context.message_descriptor().clone(),
+        }
+    }
+}
+
+/// A [`Mutator`] that inserts an element into, or deletes an element from, a repeated or map field of a
+/// [`ProtobufInput`]. Inserted elements are copies of other elements, or random values.
+#[derive(Debug, Clone)]
+pub struct ProtobufRepeatedMutator {
+    descriptor: MessageDescriptor,
+}
+
+impl<S> Mutator<ProtobufInput, S> for ProtobufRepeatedMutator
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut ProtobufInput,
+    ) -> Result<MutationResult, Error> {
+        mutate_message(&self.descriptor, state, input, |state, message| {
+            let rand = state.rand_mut();
+            let message = random_message(rand, message);
+            let fields = message
+                .descriptor()
+                .fields()
+                .filter(|field| field.is_list() || field.is_map())
+                .collect::<Vec<_>>();
+            let Some(field) = rand.choose(fields) else {
+                return Ok(MutationResult::Skipped);
+            };
+            let kind = field.kind();
+            match message.get_field_mut(&field) {
+                Value::List(list) => match NonZero::new(list.len()) {
+                    Some(len) if rand.coinflip(0.5) => {
+                        list.remove(rand.below(len));
+                    }
+                    len => {
+                        let element = match len {
+                            Some(len) if rand.coinflip(0.5) => list[rand.below(len)].clone(),
+                            _ => random_value(rand, &kind),
+                        };
+                        list.insert(rand.below_or_zero(list.len() + 1), element);
+                    }
+                },
+                Value::Map(map) => {
+                    if !map.is_empty() && rand.coinflip(0.5) {
+                        let key = rand.choose(map.keys()).unwrap().clone();
+                        map.remove(&key);
+                    } else {
+                        let Kind::Message(entry) = &kind else {
+                            return Ok(MutationResult::Skipped);
+                        };
+                        let key_kind = entry.map_entry_key_field().kind();
+                        let Some(key) = random_value(rand, &key_kind).into_map_key() else {
+                            return Ok(MutationResult::Skipped);
+                        };
+                        let value = match rand.choose(map.values()) {
+                            Some(value) if rand.coinflip(0.5) => value.clone(),
+                            _ => random_value(rand, &entry.map_entry_value_field().kind()),
+                        };
+                        map.insert(key, value);
+                    }
+                }
+                _ => return Ok(MutationResult::Skipped),
+            }
+            Ok(MutationResult::Mutated)
+        })
+    }
+    #[inline]
+    fn post_exec(&mut self, _state: &mut S, _new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Named for ProtobufRepeatedMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("ProtobufRepeatedMutator");
+        &NAME
+    }
+}
+
+impl ProtobufRepeatedMutator {
+    /// Creates a new [`ProtobufRepeatedMutator`] for the messages of `context`
+    #[must_use]
+    pub fn new(context: &ProtobufContext) -> Self {
+        Self {
+            descriptor: context.message_descriptor().clone(),
+        }
+    }
+}
+
+/// A [`Mutator`] that switches a `oneof` of a [`ProtobufInput`] to another of its fields, set to a random value
+#[derive(Debug, Clone)]
+pub struct ProtobufOneofMutator {
+    descriptor: MessageDescriptor,
+}
+
+impl<S> Mutator<ProtobufInput, S> for ProtobufOneofMutator
+where
+    S: HasRand + HasMaxSize,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut ProtobufInput,
+    ) -> Result<MutationResult, Error> {
+        mutate_message(&self.descriptor, state, input, |state, message| {
+            let rand = state.rand_mut();
+            let message = random_message(rand, message);
+            // Synthetic oneofs only track the presence of proto3 `optional` fields
+            let oneofs = message
+                .descriptor()
+                .oneofs()
+                .filter(|oneof| !oneof.is_synthetic())
+                .collect::<Vec<_>>();
+            let Some(oneof) = rand.choose(oneofs) else {
+                return Ok(MutationResult::Skipped);
+            };
+            let fields = oneof
+                .fields()
+                .filter(|field| !message.has_field(field))
+                .collect::<Vec<_>>();
+            let Some(field) = rand.choose(fields) else {
+                return Ok(MutationResult::Skipped);
+            };
+            // Setting a field of a oneof clears the others
+            message.set_field(&field, random_value(rand, &field.kind()));
+            Ok(MutationResult::Mutated)
+        })
+    }
+    #[inline]
+    fn post_exec(&mut self, _state: &mut S, _new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Named for ProtobufOneofMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("ProtobufOneofMutator");
+        &NAME
+    }
+}
+
+impl ProtobufOneofMutator {
+    /// Creates a new [`ProtobufOneofMutator`] for the messages of `context`
+    #[must_use]
+    pub fn new(context: &ProtobufContext) -> Self {
+        Self {
+            descriptor: context.message_descriptor().clone(),
+        }
+    }
+}
+
+/// A [`Mutator`] that replaces a (sub)message of a [`ProtobufInput`] with a message of the same type from another
+/// input of the corpus
+#[derive(Debug, Clone)]
+pub struct ProtobufSpliceMutator {
+    descriptor: MessageDescriptor,
+}
+
+impl<S> Mutator<ProtobufInput, S> for ProtobufSpliceMutator
+where
+    S: HasRand + HasMaxSize + HasCorpus<ProtobufInput>,
+{
+    fn mutate(
+        &mut self,
+        state: &mut S,
+        input: &mut ProtobufInput,
+    ) -> Result<MutationResult, Error> {
+        let descriptor = &self.descriptor;
+        mutate_message(descriptor, state, input, |state, message| {
+            let id = random_corpus_id!(state.corpus(), state.rand_mut());
+            let other = {
+                let mut other_testcase = state.corpus().get(id)?.borrow_mut();
+                let other = other_testcase.load_input(state.corpus())?;
+                DynamicMessage::decode(descriptor.clone(), other.bytes())
+            };
+            let Ok(other) = other else {
+                return Ok(MutationResult::Skipped);
+            };
+
+            let target = random_message(state.rand_mut(), message);
+            let mut candidates = Vec::new();
+            for_each_message(&other, &mut |other| {
+                if other.descriptor() == target.descriptor() && other != &*target {
+                    candidates.push(other);
+                }
+            });
+            let Some(replacement) = state.rand_mut().choose(candidates) else {
+                return Ok(MutationResult::Skipped);
+            };
+            *target = replacement.clone();
+            Ok(MutationResult::Mutated)
+        })
+    }
+    #[inline]
+    fn post_exec(&mut self, _state: &mut S, _new_corpus_id: Option<CorpusId>) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Named for ProtobufSpliceMutator {
+    fn name(&self) -> &Cow<'static, str> {
+        static NAME: Cow<'static, str> = Cow::Borrowed("ProtobufSpliceMutator");
+        &NAME
+    }
+}
+
+impl ProtobufSpliceMutator {
+    /// Creates a new [`ProtobufSpliceMutator`] for the messages of `context`
+    #[must_use]
+    pub fn new(context: &ProtobufContext) -> Self {
+        Self {
+            descriptor: context.message_descriptor().clone(),
+        }
+    }
+}
+
+/// Tuple type of the mutations for [`ProtobufInput`]s
+pub type ProtobufMutationsType = tuple_list_type!(
+    ProtobufScalarMutator,
+    ProtobufFieldMutator,
+    ProtobufRepeatedMutator,
+    ProtobufOneofMutator,
+    ProtobufSpliceMutator,
+);
+
+/// Get the field-aware mutations for the [`ProtobufInput`]s of `context`
+#[must_use]
+pub fn protobuf_mutations(context: &ProtobufContext) -> ProtobufMutationsType {
+    tuple_list!(
+        ProtobufScalarMutator::new(context),
+        ProtobufFieldMutator::new(context),
+        ProtobufRepeatedMutator::new(context),
+        ProtobufOneofMutator::new(context),
+        ProtobufSpliceMutator::new(context),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use libafl_bolts::rands::StdRand;
+
+    use super::protobuf_mutations;
+    use crate::{
+        corpus::{Corpus, InMemoryCorpus, Testcase},
+        feedbacks::ConstFeedback,
+        inputs::{ProtobufContext, protobuf::tests::test_descriptor_set},
+        mutators::{MutationResult, MutatorsTuple},
+        state::{HasCorpus, StdState},
+    };
+
+    #[test]
+    fn test_protobuf_mutations() {
+        let context = ProtobufContext::new(&test_descriptor_set(), "test.Root").unwrap();
+        let mut corpus = InMemoryCorpus::new();
+        corpus.add(Testcase::new(context.empty_input())).unwrap();
+        let mut feedback = ConstFeedback::new(false);
+        let mut objective = ConstFeedback::new(false);
+        let mut state = StdState::new(
+            StdRand::with_seed(1337),
+            corpus,
+            InMemoryCorpus::new(),
+            &mut feedback,
+            &mut objective,
+        )
+        .unwrap();
+
+        let mut mutations = protobuf_mutations(&context);
+        let mut input = context.empty_input();
+        let mut mutated = [0; 5];
+        for i in 0..2000 {
+            let idx = i % mutated.len();
+            if mutations
+                .get_and_mutate(idx.into(), &mut state, &mut input)
+                .unwrap()
+                == MutationResult::Mutated
+            {
+                mutated[idx] += 1;
+                // Every mutation keeps the input a valid message
+                context.decode(&input).unwrap();
+                if i % 10 == 0 {
+                    state
+                        .corpus_mut()
+                        .add(Testcase::new(input.clone()))
+                        .unwrap();
+                }
+            }
+        }
+        assert!(mutated.iter().all(|count| *count > 0), "{mutated:?}");
+        assert!(!input.bytes().is_empty());
+    }
+}
@@ -0,0 +1,101 @@
+//! The [`DistanceObserver`] observes the distance of an execution to the targets of directed fuzzing.
+
+use alloc::{borrow::Cow, vec};
+
+use libafl_bolts::{AsSlice, AsSliceMut, Named, ownedref::OwnedMutSlice};
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, observers::Observer};
+
+/// Observes the distances of the basic blocks executed by the target, as instrumented by the distance pass of `libafl_cc`.
+///
+/// The target adds the distance of each executed basic block, in hundredths, to the first entry of the map,
+/// and counts the executed basic blocks in the second one.
+#[derive(Serialize, Deserialize, Debug)]
+#[expect(clippy::unsafe_derive_deserialize)]
+pub struct DistanceObserver<'a> {
+    name: Cow<'static, str>,
+    map: OwnedMutSlice<'a, u64>,
+}
+
+impl<'a> DistanceObserver<'a> {
+    /// Creates a new [`DistanceObserver`] over the `[sum, count]` map the target writes to
+    #[must_use]
+    pub fn new<S>(name: S, map: OwnedMutSlice<'a, u64>) -> Self
+    where
+        S: Into<Cow<'static, str>>,
+    {
+        assert!(
+            map.as_slice().len() >= 2,
+            "The distance map needs two entries"
+        );
+        Self {
+            name: name.into(),
+            map,
+        }
+    }
+
+    /// Creates a new [`DistanceObserver`] over the `[sum, count]` map at `map_ptr`
+    ///
+    /// # Safety
+    /// `map_ptr` has to point to two `u64`s that outlive this observer.
+    #[must_use]
+    pub unsafe fn from_mut_ptr<S>(name: S, map_ptr: *mut u64) -> Self
+    where
+        S: Into<Cow<'static, str>>,
+    {
+        Self::new(name, unsafe {
+            OwnedMutSlice::from_raw_parts_mut(map_ptr, 2)
+        })
+    }
+
+    /// Creates a new [`DistanceObserver`] with an owned map, e.g., for a target written in Rust
+    #[must_use]
+    pub fn owned<S>(name: S) -> Self
+    where
+        S: Into<Cow<'static, str>>,
+    {
+        Self::new(name, OwnedMutSlice::from(vec![0; 2]))
+    }
+
+    /// The sum of the distances of the executed basic blocks, in hundredths
+    #[must_use]
+    pub fn sum(&self) -> u64 {
+        self.map.as_slice()[0]
+    }
+
+    /// The number of executed basic blocks with a distance
+    #[must_use]
+    pub fn count(&self) -> u64 {
+        self.map.as_slice()[1]
+    }
+
+    /// Adds an executed basic block, e.g., from a target written in Rust
+    pub fn add(&mut self, distance: u64) {
+        let map = self.map.as_slice_mut();
+        map[0] = map[0].saturating_add(distance);
+        map[1] += 1;
+    }
+
+    /// The mean distance of the executed basic blocks to the targets,
+    /// or `None` if no basic block reaching the targets was executed
+    #[must_use]
+    #[expect(clippy::cast_precision_loss)]
+    pub fn distance(&self) -> Option<f64> {
+        let count = self.count();
+        (count > 0).then(|| self.sum() as f64 / count as f64 / 100.0)
+    }
+}
+
+impl Named for DistanceObserver<'_> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<I, S> Observer<I, S> for DistanceObserver<'_> {
+    fn pre_exec(&mut self, _state: &mut S, _input: &I) -> Result<(), Error> {
+        self.map.as_slice_mut()[..2].fill(0);
+        Ok(())
+    }
+}
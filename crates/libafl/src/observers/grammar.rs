@@ -0,0 +1,242 @@
+//! The [`GrammarCoverageObserver`] observes the grammar rules the inputs of a grammar fuzzer derive from.
+
+use alloc::{borrow::Cow, vec::Vec};
+use core::hash::{BuildHasher, Hasher};
+
+use ahash::RandomState;
+use libafl_bolts::Named;
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, generators::gramatron::Automaton, inputs::GramatronInput, observers::Observer};
+#[cfg(feature = "nautilus")]
+use crate::{generators::NautilusContext, inputs::NautilusInput};
+
+/// The default length of the paths of rules a [`GrammarCoverageObserver`] records
+pub const DEFAULT_K_PATH_LEN: usize = 2;
+
+/// Inputs derived from the rules of a grammar, for the [`GrammarCoverageObserver`]
+pub trait HasGrammarRules {
+    /// Appends the ids of the rules the input derives from to `rules`, and for each the index of the rule it is
+    /// derived in to `parents`, `None` for the first one.
+    ///
+    /// Nautilus rules have their `RuleId`, Gramatron rules are
+    /// the transitions of the automaton, numbered `state_offsets[state] + trigger_idx`, each derived in the
+    /// previous transition.
+    fn grammar_rules(
+        &self,
+        state_offsets: &[usize],
+        rules: &mut Vec<usize>,
+        parents: &mut Vec<Option<usize>>,
+    );
+}
+
+#[cfg(feature = "nautilus")]
+impl HasGrammarRules for NautilusInput {
+    fn grammar_rules(
+        &self,
+        _state_offsets: &[usize],
+        rules: &mut Vec<usize>,
+        parents: &mut Vec<Option<usize>>,
+    ) {
+        rules.extend(self.tree.rules.iter().map(|rule| rule.id().to_i()));
+        parents.extend(
+            self.tree
+                .paren
+                .iter()
+                .enumerate()
+                .map(|(i, parent)| (i != 0).then(|| parent.to_i())),
+        );
+    }
+}
+
+impl HasGrammarRules for GramatronInput {
+    fn grammar_rules(
+        &self,
+        state_offsets: &[usize],
+        rules: &mut Vec<usize>,
+        parents: &mut Vec<Option<usize>>,
+    ) {
+        let start = rules.len();
+        for (i, term) in self.terminals().iter().enumerate() {
+            rules.push(
+                state_offsets.get(term.state).copied().unwrap_or_default() + term.trigger_idx,
+            );
+            parents.push(i.checked_sub(1).map(|previous| start + previous));
+        }
+    }
+}
+
+/// Observes the grammar rules the executed input derives from, and the paths of up to `k` rules, each derived in
+/// the one before, for k-path grammar coverage.
+///
+/// A path of length `k` is a rule and the `k - 1` rules it is nested in: its ancestors in the Nautilus derivation
+/// tree, or the previous transitions of the Gramatron automaton. Use it with the
+/// [`crate::feedbacks::GrammarCoverageFeedback`].
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GrammarCoverageObserver {
+    name: Cow<'static, str>,
+    rule_count: usize,
+    /// The id of the first transition of each state, for Gramatron
+    state_offsets: Vec<usize>,
+    k: usize,
+    rules: Vec<usize>,
+    paths: Vec<u64>,
+}
+
+impl GrammarCoverageObserver {
+    fn new<S>(name: S, rule_count: usize, state_offsets: Vec<usize>) -> Self
+    where
+        S: Into<Cow<'static, str>>,
+    {
+        Self {
+            name: name.into(),
+            rule_count,
+            state_offsets,
+            k: DEFAULT_K_PATH_LEN,
+            rules: Vec::new(),
+            paths: Vec::new(),
+        }
+    }
+
+    /// Creates a new [`GrammarCoverageObserver`] for [`NautilusInput`]s of the grammar of `context`
+    #[cfg(feature = "nautilus")]
+    #[must_use]
+    pub fn nautilus<S>(name: S, context: &NautilusContext) -> Self
+    where
+        S: Into<Cow<'static, str>>,
+    {
+        Self::new(name, context.ctx.rules().len(), Vec::new())
+    }
+
+    /// Creates a new [`GrammarCoverageObserver`] for [`GramatronInput`]s of `automaton`
+    #[must_use]
+    pub fn gramatron<S>(name: S, automaton: &Automaton) -> Self
+    where
+        S: Into<Cow<'static, str>>,
+    {
+        let mut state_offsets = Vec::with_capacity(automaton.pda.len());
+        let mut rule_count = 0;
+        for triggers in &automaton.pda {
+            state_offsets.push(rule_count);
+            rule_count += triggers.len();
+        }
+        Self::new(name, rule_count, state_offsets)
+    }
+
+    /// Sets the length of the longest paths of rules to record, `1` to only record the rules
+    #[must_use]
+    pub fn with_k(mut self, k: usize) -> Self {
+        self.k = k.max(1);
+        self
+    }
+
+    /// The length of the longest paths of rules recorded
+    #[must_use]
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    /// The number of rules of the grammar
+    #[must_use]
+    pub fn rule_count(&self) -> usize {
+        self.rule_count
+    }
+
+    /// The rules the last input derived from, sorted and without duplicates
+    #[must_use]
+    pub fn rules(&self) -> &[usize] {
+        &self.rules
+    }
+
+    /// The hashes of the paths of `2` up to `k` rules the last input derived, sorted and without duplicates
+    #[must_use]
+    pub fn paths(&self) -> &[u64] {
+        &self.paths
+    }
+}
+
+impl Named for GrammarCoverageObserver {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<I, S> Observer<I, S> for GrammarCoverageObserver
+where
+    I: HasGrammarRules,
+{
+    fn pre_exec(&mut self, _state: &mut S, input: &I) -> Result<(), Error> {
+        self.rules.clear();
+        self.paths.clear();
+        let mut parents = Vec::new();
+        input.grammar_rules(&self.state_offsets, &mut self.rules, &mut parents);
+
+        let hasher = RandomState::with_seeds(0, 0, 0, 0);
+        for (node, rule) in self.rules.iter().enumerate() {
+            let mut path = hasher.build_hasher();
+            path.write_usize(*rule);
+            let mut current = node;
+            for _ in 1..self.k {
+                let Some(parent) = parents.get(current).copied().flatten() else {
+                    break;
+                };
+                path.write_usize(self.rules[parent]);
+                self.paths.push(path.finish());
+                current = parent;
+            }
+        }
+        self.paths.sort_unstable();
+        self.paths.dedup();
+        self.rules.sort_unstable();
+        self.rules.dedup();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{string::ToString, vec};
+
+    use super::GrammarCoverageObserver;
+    use crate::{
+        generators::gramatron::{Automaton, Trigger},
+        inputs::{GramatronInput, Terminal},
+        observers::Observer,
+        state::NopState,
+    };
+
+    #[test]
+    fn test_grammar_coverage_observer() {
+        let trigger = |dest: usize, term: &str| Trigger {
+            dest,
+            term: term.to_string(),
+        };
+        let automaton = Automaton {
+            init_state: 0,
+            final_state: 2,
+            pda: vec![
+                vec![trigger(1, "a"), trigger(1, "b")],
+                vec![trigger(2, "c"), trigger(0, "d")],
+                vec![],
+            ],
+        };
+        let mut observer = GrammarCoverageObserver::gramatron("grammar", &automaton).with_k(3);
+        assert_eq!(observer.rule_count(), 4);
+
+        let mut state = NopState::<GramatronInput>::new();
+        let input = GramatronInput::new(vec![
+            Terminal::new(0, 1, "b".to_string()),
+            Terminal::new(1, 1, "d".to_string()),
+            Terminal::new(0, 1, "b".to_string()),
+            Terminal::new(1, 0, "c".to_string()),
+        ]);
+        observer.pre_exec(&mut state, &input).unwrap();
+        assert_eq!(observer.rules(), &[1, 2, 3]);
+        // b-d, d-b, b-c, b-d-b, d-b-c
+        assert_eq!(observer.paths().len(), 5);
+
+        let mut observer = observer.with_k(1);
+        observer.pre_exec(&mut state, &input).unwrap();
+        assert!(observer.paths().is_empty());
+    }
+}
@@ -113,6 +113,14 @@ pub(crate) fn classify_counts(map: &mut [u8]) {
 #[derive(Serialize, Deserialize, Debug, Clone, Hash)]
 pub struct HitcountsMapObserver<M> {
     base: M,
+    /// Missing in observers serialized before bucketing could be turned off
+    #[serde(default = "default_bucketing")]
+    bucketing: bool,
+}
+
+/// Observers bucket the hit counts unless told otherwise
+fn default_bucketing() -> bool {
+    true
 }
 
 impl<M> Deref for HitcountsMapObserver<M> {
@@ -140,7 +148,9 @@ where
 
     #[inline]
     fn post_exec(&mut self, state: &mut S, input: &I, exit_kind: &ExitKind) -> Result<(), Error> {
-        classify_counts(&mut self.as_slice_mut());
+        if self.bucketing {
+            classify_counts(&mut self.as_slice_mut());
+        }
         self.base.post_exec(state, input, exit_kind)
     }
 }
@@ -159,7 +169,25 @@ impl<M> HitcountsMapObserver<M> {
     /// Creates a new [`MapObserver`]
     pub fn new(base: M) -> Self {
         init_count_class_16();
-        Self { base }
+        Self {
+            base,
+            bucketing: true,
+        }
+    }
+
+    /// Creates a new [`MapObserver`] keeping the raw hit counts, without sorting them into buckets,
+    /// e.g., for the [`crate::feedbacks::PerfFeedback`] to maximize them
+    pub fn without_bucketing(base: M) -> Self {
+        Self {
+            base,
+            bucketing: false,
+        }
+    }
+
+    /// Whether the hit counts are sorted into AFL-like buckets after each execution
+    #[must_use]
+    pub fn bucketing(&self) -> bool {
+        self.bucketing
     }
 }
 
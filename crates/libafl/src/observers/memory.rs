@@ -0,0 +1,138 @@
+//! The [`MemoryObserver`] observes the peak heap usage and the number of allocations of an execution.
+
+use alloc::{borrow::Cow, vec};
+
+use libafl_bolts::{AsSlice, AsSliceMut, Named, ownedref::OwnedMutSlice};
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, executors::ExitKind, observers::Observer};
+
+/// The number of `u64`s in the map of a [`MemoryObserver`]: the peak, the allocations, the current heap usage,
+/// and whether the target is running.
+pub const MEMORY_MAP_LEN: usize = 4;
+
+/// Observes the memory usage of an execution.
+///
+/// In-process, the malloc hooks of `libafl_targets` write to its map: the peak heap usage in bytes, the number of
+/// allocations, the current heap usage, and whether an execution is running, so the fuzzer's own allocations are not
+/// counted. Allocations made before the execution and freed in it do not lower the usage below zero.
+///
+/// For targets the [`crate::executors::CommandExecutor`] runs, use a [`MemoryObserver::owned`] one and pass it to
+/// [`crate::executors::command::CommandExecutorBuilder::memory_observer`]: the executor then reports the peak resident
+/// set size of each child process, from `wait4`. The [`crate::executors::ForkserverExecutor`] reports it on Linux, see
+/// [`crate::executors::forkserver::ForkserverExecutorBuilder::memory_observer`].
+#[derive(Serialize, Deserialize, Debug)]
+#[expect(clippy::unsafe_derive_deserialize)]
+pub struct MemoryObserver<'a> {
+    name: Cow<'static, str>,
+    map: OwnedMutSlice<'a, u64>,
+}
+
+impl<'a> MemoryObserver<'a> {
+    /// Creates a new [`MemoryObserver`] over the map the target writes to
+    #[must_use]
+    pub fn new<S>(name: S, map: OwnedMutSlice<'a, u64>) -> Self
+    where
+        S: Into<Cow<'static, str>>,
+    {
+        assert!(
+            map.as_slice().len() >= MEMORY_MAP_LEN,
+            "The memory map needs {MEMORY_MAP_LEN} entries"
+        );
+        Self {
+            name: name.into(),
+            map,
+        }
+    }
+
+    /// Creates a new [`MemoryObserver`] over the map at `map_ptr`
+    ///
+    /// # Safety
+    /// `map_ptr` has to point to [`MEMORY_MAP_LEN`] `u64`s that outlive this observer.
+    #[must_use]
+    pub unsafe fn from_mut_ptr<S>(name: S, map_ptr: *mut u64) -> Self
+    where
+        S: Into<Cow<'static, str>>,
+    {
+        Self::new(name, unsafe {
+            OwnedMutSlice::from_raw_parts_mut(map_ptr, MEMORY_MAP_LEN)
+        })
+    }
+
+    /// Creates a new [`MemoryObserver`] with an owned map, e.g., for a target written in Rust
+    #[must_use]
+    pub fn owned<S>(name: S) -> Self
+    where
+        S: Into<Cow<'static, str>>,
+    {
+        Self::new(name, OwnedMutSlice::from(vec![0; MEMORY_MAP_LEN]))
+    }
+
+    /// The peak heap usage of the last execution, in bytes
+    #[must_use]
+    pub fn peak(&self) -> u64 {
+        self.map.as_slice()[0]
+    }
+
+    /// The number of allocations of the last execution
+    #[must_use]
+    pub fn allocations(&self) -> u64 {
+        self.map.as_slice()[1]
+    }
+
+    /// Records an allocation, e.g., from a target written in Rust
+    pub fn record_alloc(&mut self, size: u64) {
+        let map = self.map.as_slice_mut();
+        map[1] += 1;
+        map[2] = map[2].saturating_add(size);
+        map[0] = map[0].max(map[2]);
+    }
+
+    /// Records a deallocation, e.g., from a target written in Rust
+    pub fn record_free(&mut self, size: u64) {
+        let map = self.map.as_slice_mut();
+        map[2] = map[2].saturating_sub(size);
+    }
+
+    /// Records the peak resident set size of a child process in bytes, e.g., as read from `/proc`
+    pub fn record_peak_rss(&mut self, bytes: u64) {
+        self.map.as_slice_mut()[0] = bytes;
+    }
+
+    /// Records the peak resident set size of a child process, as reported by `wait4`
+    #[cfg(all(unix, feature = "std"))]
+    pub fn record_rusage(&mut self, usage: &libc::rusage) {
+        let max_rss = u64::try_from(usage.ru_maxrss).unwrap_or(0);
+        // Apple reports bytes, all others kilobytes
+        self.map.as_slice_mut()[0] = if cfg!(target_vendor = "apple") {
+            max_rss
+        } else {
+            max_rss * 1024
+        };
+    }
+}
+
+impl Named for MemoryObserver<'_> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<I, S> Observer<I, S> for MemoryObserver<'_> {
+    fn pre_exec(&mut self, _state: &mut S, _input: &I) -> Result<(), Error> {
+        let map = self.map.as_slice_mut();
+        map[..MEMORY_MAP_LEN - 1].fill(0);
+        map[MEMORY_MAP_LEN - 1] = 1;
+        Ok(())
+    }
+
+    fn post_exec(
+        &mut self,
+        _state: &mut S,
+        _input: &I,
+        _exit_kind: &ExitKind,
+    ) -> Result<(), Error> {
+        self.map.as_slice_mut()[MEMORY_MAP_LEN - 1] = 0;
+        Ok(())
+    }
+}
@@ -15,6 +15,12 @@ pub mod stacktrace;
 pub use stacktrace::*;
 
 pub mod concolic;
+pub mod distance;
+pub use distance::DistanceObserver;
+pub mod grammar;
+pub use grammar::{GrammarCoverageObserver, HasGrammarRules};
+pub mod memory;
+pub use memory::MemoryObserver;
 pub mod map;
 pub use map::*;
 
@@ -0,0 +1,348 @@
+//! The [`AdaptivePowerScheduler`] switches the [`BaseSchedule`] of the power schedules at runtime,
+//! whenever the coverage growth under the active one plateaus.
+
+use alloc::{vec, vec::Vec};
+use core::time::Duration;
+
+use libafl_bolts::{current_time, tuples::MatchName};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Error, HasMetadata,
+    corpus::{CorpusId, Testcase},
+    schedulers::{
+        HasQueueCycles, RemovableScheduler, Scheduler,
+        powersched::{BaseSchedule, PowerSchedule, SchedulerMetadata},
+    },
+};
+
+/// The default length of the windows the coverage growth is measured over
+pub const DEFAULT_ADAPTIVE_WINDOW: Duration = Duration::from_secs(5 * 60);
+
+/// The default share of the previous window's finds below which the growth is considered a plateau
+pub const DEFAULT_PLATEAU_RATIO: f64 = 0.5;
+
+/// How well a [`BaseSchedule`] did so far
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct ScheduleStats {
+    /// The schedule
+    pub schedule: BaseSchedule,
+    /// The number of windows it was active for
+    pub windows: u64,
+    /// The number of new corpus entries found while it was active
+    pub finds: u64,
+    /// The number of new corpus entries found in its last window
+    pub last_finds: Option<u64>,
+}
+
+impl ScheduleStats {
+    /// The average number of new corpus entries per window
+    #[must_use]
+    #[expect(clippy::cast_precision_loss)]
+    pub fn rate(&self) -> f64 {
+        if self.windows == 0 {
+            0.0
+        } else {
+            self.finds as f64 / self.windows as f64
+        }
+    }
+}
+
+/// A switch of the [`BaseSchedule`], not yet reported by the [`crate::stages::AdaptivePowerStatsStage`]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct ScheduleSwitch {
+    /// The previous schedule
+    pub from: BaseSchedule,
+    /// The new schedule
+    pub to: BaseSchedule,
+    /// The number of new corpus entries found in the last window of the previous schedule
+    pub finds: u64,
+}
+
+/// The state of the [`AdaptivePowerScheduler`]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AdaptivePowerMetadata {
+    /// The statistics of each schedule, in the order they are tried in
+    pub stats: Vec<ScheduleStats>,
+    /// The index of the active schedule in `stats`
+    pub active: usize,
+    /// When the current window started
+    pub window_start: Duration,
+    /// The number of new corpus entries found in the current window
+    pub window_finds: u64,
+    /// The switches that were not reported yet
+    pub pending: Vec<ScheduleSwitch>,
+}
+
+libafl_bolts::impl_serdeany!(AdaptivePowerMetadata);
+
+impl AdaptivePowerMetadata {
+    /// Creates a new [`struct@AdaptivePowerMetadata`], starting with the first of the `schedules`
+    #[must_use]
+    pub fn new(schedules: &[BaseSchedule]) -> Self {
+        Self {
+            stats: schedules
+                .iter()
+                .map(|schedule| ScheduleStats {
+                    schedule: *schedule,
+                    windows: 0,
+                    finds: 0,
+                    last_finds: None,
+                })
+                .collect(),
+            active: 0,
+            window_start: current_time(),
+            window_finds: 0,
+            pending: vec![],
+        }
+    }
+
+    /// The active schedule
+    #[must_use]
+    pub fn active(&self) -> BaseSchedule {
+        self.stats[self.active].schedule
+    }
+
+    /// Closes the current window and returns the index of the schedule to use for the next one.
+    ///
+    /// The active schedule is kept as long as it finds something, and at least `plateau_ratio` times
+    /// as much as in its previous window. Otherwise, schedules that were never tried come first,
+    /// then the one with the best average.
+    fn end_window(&mut self, plateau_ratio: f64) -> usize {
+        let finds = self.window_finds;
+        self.window_finds = 0;
+
+        let stats = &mut self.stats[self.active];
+        #[expect(clippy::cast_precision_loss)]
+        let plateau = finds == 0
+            || stats
+                .last_finds
+                .is_some_and(|last| (finds as f64) < last as f64 * plateau_ratio);
+        stats.windows += 1;
+        stats.finds += finds;
+        stats.last_finds = Some(finds);
+        if !plateau || self.stats.len() < 2 {
+            return self.active;
+        }
+
+        let len = self.stats.len();
+        let others = (1..len).map(|offset| (self.active + offset) % len);
+        if let Some(untried) = others.clone().find(|idx| self.stats[*idx].windows == 0) {
+            return untried;
+        }
+        // Ties go to the next schedule in order
+        others
+            .rev()
+            .max_by(|a, b| self.stats[*a].rate().total_cmp(&self.stats[*b].rate()))
+            .unwrap()
+    }
+}
+
+/// A scheduler wrapper that monitors the coverage growth, i.e., the number of new corpus entries,
+/// over fixed windows of time, and switches the [`BaseSchedule`] of the power schedules once it plateaus.
+///
+/// The active schedule is written to the [`struct@SchedulerMetadata`], so it is picked up by the power
+/// mutational stages, and by the wrapped scheduler, e.g., a [`crate::schedulers::WeightedScheduler`],
+/// the next time it computes its weights.
+/// Add an [`crate::stages::AdaptivePowerStatsStage`] to log each switch and report the active schedule.
+#[derive(Debug, Clone)]
+pub struct AdaptivePowerScheduler<CS> {
+    inner: CS,
+    window: Duration,
+    plateau_ratio: f64,
+}
+
+impl<CS> AdaptivePowerScheduler<CS> {
+    /// Wraps `inner`, cycling through EXPLORE, FAST, COE, LIN, QUAD and EXPLOIT
+    pub fn new<S>(state: &mut S, inner: CS) -> Self
+    where
+        S: HasMetadata,
+    {
+        Self::with_schedules(
+            state,
+            inner,
+            &[
+                BaseSchedule::EXPLORE,
+                BaseSchedule::FAST,
+                BaseSchedule::COE,
+                BaseSchedule::LIN,
+                BaseSchedule::QUAD,
+                BaseSchedule::EXPLOIT,
+            ],
+        )
+    }
+
+    /// Wraps `inner`, switching between the given schedules, starting with the first one
+    pub fn with_schedules<S>(state: &mut S, inner: CS, schedules: &[BaseSchedule]) -> Self
+    where
+        S: HasMetadata,
+    {
+        assert!(!schedules.is_empty(), "No schedules to switch between");
+        // After a restart, continue with the schedule that was active before
+        let active = state
+            .metadata_or_insert_with(|| AdaptivePowerMetadata::new(schedules))
+            .active();
+        let psmeta = state
+            .metadata_or_insert_with(|| SchedulerMetadata::new(Some(PowerSchedule::new(active))));
+        let mut strat = psmeta.strat().unwrap_or_else(|| PowerSchedule::new(active));
+        strat.set_base(active);
+        psmeta.set_strat(Some(strat));
+
+        Self {
+            inner,
+            window: DEFAULT_ADAPTIVE_WINDOW,
+            plateau_ratio: DEFAULT_PLATEAU_RATIO,
+        }
+    }
+
+    /// Sets the length of the windows the coverage growth is measured over
+    #[must_use]
+    pub fn window(mut self, window: Duration) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Sets the share of the previous window's finds below which the growth is considered a plateau
+    #[must_use]
+    pub fn plateau_ratio(mut self, plateau_ratio: f64) -> Self {
+        self.plateau_ratio = plateau_ratio;
+        self
+    }
+
+    /// The wrapped scheduler
+    pub fn inner(&self) -> &CS {
+        &self.inner
+    }
+
+    /// The wrapped scheduler (mutable)
+    pub fn inner_mut(&mut self) -> &mut CS {
+        &mut self.inner
+    }
+
+    /// Ends the current window if it is over, and switches the schedule if needed
+    fn maybe_switch<S>(&self, state: &mut S) -> Result<(), Error>
+    where
+        S: HasMetadata,
+    {
+        let now = current_time();
+        let meta = state.metadata_mut::<AdaptivePowerMetadata>()?;
+        if now.saturating_sub(meta.window_start) < self.window {
+            return Ok(());
+        }
+        meta.window_start = now;
+
+        let finds = meta.window_finds;
+        let next = meta.end_window(self.plateau_ratio);
+        if next == meta.active {
+            return Ok(());
+        }
+        let from = meta.active();
+        meta.active = next;
+        let to = meta.active();
+        meta.pending.push(ScheduleSwitch { from, to, finds });
+
+        let psmeta = state.metadata_mut::<SchedulerMetadata>()?;
+        let mut strat = psmeta.strat().unwrap_or_else(|| PowerSchedule::new(to));
+        strat.set_base(to);
+        psmeta.set_strat(Some(strat));
+        Ok(())
+    }
+}
+
+impl<CS, I, S> RemovableScheduler<I, S> for AdaptivePowerScheduler<CS>
+where
+    CS: RemovableScheduler<I, S>,
+{
+    fn on_remove(
+        &mut self,
+        state: &mut S,
+        id: CorpusId,
+        testcase: &Option<Testcase<I>>,
+    ) -> Result<(), Error> {
+        self.inner.on_remove(state, id, testcase)
+    }
+
+    fn on_replace(&mut self, state: &mut S, id: CorpusId, prev: &Testcase<I>) -> Result<(), Error> {
+        self.inner.on_replace(state, id, prev)
+    }
+}
+
+impl<CS> HasQueueCycles for AdaptivePowerScheduler<CS>
+where
+    CS: HasQueueCycles,
+{
+    fn queue_cycles(&self) -> u64 {
+        self.inner.queue_cycles()
+    }
+}
+
+impl<CS, I, S> Scheduler<I, S> for AdaptivePowerScheduler<CS>
+where
+    CS: Scheduler<I, S>,
+    S: HasMetadata,
+{
+    fn on_add(&mut self, state: &mut S, id: CorpusId) -> Result<(), Error> {
+        self.inner.on_add(state, id)?;
+        state.metadata_mut::<AdaptivePowerMetadata>()?.window_finds += 1;
+        Ok(())
+    }
+
+    fn on_evaluation<OT>(&mut self, state: &mut S, input: &I, observers: &OT) -> Result<(), Error>
+    where
+        OT: MatchName,
+    {
+        self.inner.on_evaluation(state, input, observers)
+    }
+
+    fn next(&mut self, state: &mut S) -> Result<CorpusId, Error> {
+        self.maybe_switch(state)?;
+        self.inner.next(state)
+    }
+
+    fn set_current_scheduled(
+        &mut self,
+        state: &mut S,
+        next_id: Option<CorpusId>,
+    ) -> Result<(), Error> {
+        self.inner.set_current_scheduled(state, next_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AdaptivePowerMetadata;
+    use crate::schedulers::powersched::BaseSchedule;
+
+    #[test]
+    fn test_adaptive_schedule_switching() {
+        let mut meta = AdaptivePowerMetadata::new(&[
+            BaseSchedule::EXPLORE,
+            BaseSchedule::FAST,
+            BaseSchedule::EXPLOIT,
+        ]);
+
+        // Growing, or only slightly slower, keeps the schedule
+        meta.window_finds = 10;
+        assert_eq!(meta.end_window(0.5), 0);
+        meta.window_finds = 6;
+        assert_eq!(meta.end_window(0.5), 0);
+
+        // A plateau moves on to the schedules that were not tried yet
+        meta.window_finds = 2;
+        assert_eq!(meta.end_window(0.5), 1);
+        meta.active = 1;
+        assert_eq!(meta.end_window(0.5), 2);
+        meta.active = 2;
+
+        // Once all were tried, the best one on average wins
+        meta.window_finds = 1;
+        assert_eq!(meta.end_window(0.5), 2);
+        assert_eq!(meta.end_window(0.5), 0);
+        assert_eq!(meta.stats[0].finds, 18);
+        assert_eq!(meta.stats[2].windows, 2);
+    }
+}
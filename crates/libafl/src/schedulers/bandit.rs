@@ -0,0 +1,631 @@
+//! A scheduler that treats the corpus entries as the arms of a multi-armed bandit.
+//!
+//! Instead of a fixed formula, it learns which entries pay off: an entry is rewarded whenever fuzzing it
+//! finds a new corpus entry, and the next entry is picked using UCB1 or Thompson sampling.
+
+use alloc::{collections::BTreeSet, vec::Vec};
+use core::{cmp::Reverse, f64::consts::PI, hash::Hash, marker::PhantomData};
+
+use hashbrown::HashMap;
+use libafl_bolts::{
+    Named, impl_serdeany,
+    rands::Rand,
+    tuples::{Handle, Handled, MatchName},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Error, HasMetadata,
+    corpus::{Corpus, CorpusId, HasTestcase, Testcase},
+    schedulers::{
+        AflScheduler, HasQueueCycles, RemovableScheduler, Scheduler, on_add_metadata_default,
+        on_evaluation_metadata_default, on_next_metadata_default,
+        powersched::{PowerSchedule, SchedulerMetadata},
+    },
+    state::{HasCorpus, HasImported, HasRand},
+};
+
+/// The statistics of a single arm, i.e., corpus entry
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BanditArm {
+    /// How often the entry was scheduled
+    pulls: u64,
+    /// How many of the pulls found at least one new corpus entry
+    rewards: u64,
+    /// How many new corpus entries were found in total
+    finds: u64,
+    /// The bits of the cached score, if the arm is in the score index
+    #[serde(skip)]
+    score: Option<u64>,
+}
+
+impl BanditArm {
+    /// How often the entry was scheduled
+    #[must_use]
+    pub fn pulls(&self) -> u64 {
+        self.pulls
+    }
+
+    /// How many of the pulls found at least one new corpus entry
+    #[must_use]
+    pub fn rewards(&self) -> u64 {
+        self.rewards
+    }
+
+    /// How many new corpus entries were found in total
+    #[must_use]
+    pub fn finds(&self) -> u64 {
+        self.finds
+    }
+
+    /// The share of pulls that were rewarded
+    #[must_use]
+    #[expect(clippy::cast_precision_loss)]
+    pub fn mean_reward(&self) -> f64 {
+        if self.pulls == 0 {
+            0.0
+        } else {
+            self.rewards as f64 / self.pulls as f64
+        }
+    }
+}
+
+/// The statistics of the [`BanditScheduler`]
+///
+/// They are kept next to the [`struct@SchedulerMetadata`] rather than in it: that metadata is shared with the power
+/// schedules of all other schedulers, so adding the arms to it would change the layout of every saved state, and
+/// make all fuzzers carry the bookkeeping of this one.
+///
+/// The score of each arm is cached in an ordered index, so that picking the next arm does not touch all of them.
+/// The score of an arm is recomputed when it is pulled or rewarded. In addition, the UCB1 scores of all arms are
+/// refreshed whenever the total number of pulls doubled, and the Thompson samples of all arms are drawn again once
+/// per queue cycle, so an arm that drew a low sample gets another chance in the next cycle.
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct BanditMetadata {
+    /// The arm of each corpus entry
+    arms: HashMap<CorpusId, BanditArm>,
+    /// The total number of pulls
+    pulls: u64,
+    /// The number of pulls in the current queue cycle
+    cycle_pulls: u64,
+    /// Whether the current pull was rewarded already
+    rewarded: bool,
+    /// The entry that was fuzzed when the last entry was added, with the imported count at that time.
+    ///
+    /// It is rewarded once it is clear that the new entry was not imported from another fuzzer.
+    pending_reward: Option<(CorpusId, usize)>,
+    /// The cached scores, with the oldest entry first among equal scores; the last one is pulled next
+    #[serde(skip)]
+    scores: BTreeSet<(u64, Reverse<CorpusId>)>,
+    /// The total number of pulls when all scores were last computed
+    #[serde(skip)]
+    refreshed_at: u64,
+}
+
+impl_serdeany!(BanditMetadata);
+
+impl BanditMetadata {
+    /// The arm of the given corpus entry
+    #[must_use]
+    pub fn arm(&self, id: CorpusId) -> Option<&BanditArm> {
+        self.arms.get(&id)
+    }
+
+    /// The arms of all corpus entries
+    #[must_use]
+    pub fn arms(&self) -> &HashMap<CorpusId, BanditArm> {
+        &self.arms
+    }
+
+    /// The total number of pulls
+    #[must_use]
+    pub fn pulls(&self) -> u64 {
+        self.pulls
+    }
+
+    /// Recomputes the cached score of `id`
+    fn rescore<R: Rand>(&mut self, policy: &BanditPolicy, rand: &mut R, id: CorpusId) {
+        let Some(arm) = self.arms.get_mut(&id) else {
+            return;
+        };
+        if let Some(score) = arm.score.take() {
+            self.scores.remove(&(score, Reverse(id)));
+        }
+        // The scores are never negative, so their bits are ordered like them
+        let score = policy.score(rand, arm, self.pulls).to_bits();
+        arm.score = Some(score);
+        self.scores.insert((score, Reverse(id)));
+    }
+
+    /// Recomputes all cached scores
+    fn refresh<R: Rand>(&mut self, policy: &BanditPolicy, rand: &mut R) {
+        self.scores.clear();
+        let ids = self.arms.keys().copied().collect::<Vec<_>>();
+        for id in ids {
+            if let Some(arm) = self.arms.get_mut(&id) {
+                arm.score = None;
+            }
+            self.rescore(policy, rand, id);
+        }
+        self.refreshed_at = self.pulls;
+    }
+
+    /// Adds a fresh arm for `id`
+    fn add_arm<R: Rand>(&mut self, policy: &BanditPolicy, rand: &mut R, id: CorpusId) {
+        self.remove_arm(id);
+        self.arms.insert(id, BanditArm::default());
+        self.rescore(policy, rand, id);
+    }
+
+    /// Forgets the arm of `id`
+    fn remove_arm(&mut self, id: CorpusId) {
+        if let Some(score) = self.arms.remove(&id).and_then(|arm| arm.score) {
+            self.scores.remove(&(score, Reverse(id)));
+        }
+    }
+
+    /// The arm with the best cached score
+    fn best(&self) -> Option<CorpusId> {
+        self.scores.last().map(|(_, Reverse(id))| *id)
+    }
+
+    /// Records that `id` was scheduled
+    fn pull<R: Rand>(&mut self, policy: &BanditPolicy, rand: &mut R, id: CorpusId) {
+        self.arms.entry(id).or_default().pulls += 1;
+        self.pulls += 1;
+        self.cycle_pulls += 1;
+        self.rewarded = false;
+        self.rescore(policy, rand, id);
+    }
+
+    /// Rewards the pending entry, unless the entry added since was imported
+    fn settle_reward<R: Rand>(&mut self, policy: &BanditPolicy, rand: &mut R, imported: usize) {
+        let Some((id, imported_before)) = self.pending_reward.take() else {
+            return;
+        };
+        if imported != imported_before {
+            return;
+        }
+        let Some(arm) = self.arms.get_mut(&id) else {
+            return;
+        };
+        arm.finds += 1;
+        if !self.rewarded {
+            arm.rewards += 1;
+            self.rewarded = true;
+            self.rescore(policy, rand, id);
+        }
+    }
+}
+
+/// The policy used to pick the next arm
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum BanditPolicy {
+    /// Upper confidence bound: the mean reward plus `exploration * sqrt(ln(total pulls) / pulls)`.
+    ///
+    /// Deterministic, entries that were never scheduled are picked first.
+    Ucb1 {
+        /// The weight of the exploration term, `sqrt(2)` in the original formulation
+        exploration: f64,
+    },
+    /// Samples the reward of each arm from its beta posterior and picks the best sample.
+    ///
+    /// An arm is sampled again when it is pulled or rewarded, and all arms are sampled again once per queue cycle.
+    ThompsonSampling,
+}
+
+impl Default for BanditPolicy {
+    fn default() -> Self {
+        Self::Ucb1 {
+            exploration: core::f64::consts::SQRT_2,
+        }
+    }
+}
+
+/// Samples a standard normal distribution, using the Box-Muller transform
+fn sample_normal<R: Rand>(rand: &mut R) -> f64 {
+    let u1 = 1.0 - rand.next_float();
+    let u2 = rand.next_float();
+    libm::sqrt(-2.0 * libm::log(u1)) * libm::cos(2.0 * PI * u2)
+}
+
+/// Samples a gamma distribution with `shape >= 1` and scale 1, using the method of Marsaglia and Tsang
+fn sample_gamma<R: Rand>(rand: &mut R, shape: f64) -> f64 {
+    let d = shape - 1.0 / 3.0;
+    let c = 1.0 / libm::sqrt(9.0 * d);
+    loop {
+        let normal = sample_normal(rand);
+        let v = 1.0 + c * normal;
+        if v <= 0.0 {
+            continue;
+        }
+        let v = v * v * v;
+        let uniform = 1.0 - rand.next_float();
+        if libm::log(uniform) < 0.5 * normal * normal + d - d * v + d * libm::log(v) {
+            return d * v;
+        }
+    }
+}
+
+/// Samples a beta distribution with `alpha, beta >= 1`
+fn sample_beta<R: Rand>(rand: &mut R, alpha: f64, beta: f64) -> f64 {
+    let x = sample_gamma(rand, alpha);
+    let y = sample_gamma(rand, beta);
+    x / (x + y)
+}
+
+impl BanditPolicy {
+    /// The score of `arm`, the arm with the highest score is pulled next
+    #[expect(clippy::cast_precision_loss)]
+    fn score<R: Rand>(&self, rand: &mut R, arm: &BanditArm, total_pulls: u64) -> f64 {
+        match self {
+            Self::Ucb1 { exploration } => {
+                if arm.pulls == 0 {
+                    return f64::INFINITY;
+                }
+                let bonus = libm::sqrt(libm::log(total_pulls.max(1) as f64) / arm.pulls as f64);
+                arm.mean_reward() + exploration * bonus
+            }
+            Self::ThompsonSampling => sample_beta(
+                rand,
+                (arm.rewards + 1) as f64,
+                (arm.pulls.saturating_sub(arm.rewards) + 1) as f64,
+            ),
+        }
+    }
+}
+
+/// A corpus scheduler that learns which entries pay off, treating them as the arms of a multi-armed bandit.
+///
+/// Every time an entry is scheduled, its arm is pulled. The pull is rewarded if fuzzing the entry adds a new
+/// entry to the corpus, i.e., finds new coverage; entries imported from other fuzzers through the event manager
+/// reward nothing. The statistics are kept in their own [`struct@BanditMetadata`]. The scheduler also maintains the
+/// [`struct@SchedulerMetadata`], which makes it work with the calibration and power mutational stages,
+/// and entries removed through the [`RemovableScheduler`] hooks are forgotten,
+/// so it can be wrapped in a [`crate::schedulers::MinimizerScheduler`].
+#[derive(Debug, Clone)]
+pub struct BanditScheduler<C, O> {
+    policy: BanditPolicy,
+    strat: Option<PowerSchedule>,
+    observer_handle: Handle<C>,
+    last_hash: usize,
+    queue_cycles: u64,
+    phantom: PhantomData<O>,
+}
+
+impl<C, O> BanditScheduler<C, O>
+where
+    C: Named,
+{
+    /// Create a new [`BanditScheduler`] without any power schedule
+    #[must_use]
+    pub fn new<S>(state: &mut S, observer: &C, policy: BanditPolicy) -> Self
+    where
+        S: HasMetadata,
+    {
+        Self::with_schedule(state, observer, policy, None)
+    }
+
+    /// Create a new [`BanditScheduler`], with the power schedule used by the power mutational stages
+    #[must_use]
+    pub fn with_schedule<S>(
+        state: &mut S,
+        observer: &C,
+        policy: BanditPolicy,
+        strat: Option<PowerSchedule>,
+    ) -> Self
+    where
+        S: HasMetadata,
+    {
+        let _ = state.metadata_or_insert_with(|| SchedulerMetadata::new(strat));
+        let _ = state.metadata_or_insert_with(BanditMetadata::default);
+
+        Self {
+            policy,
+            strat,
+            observer_handle: observer.handle(),
+            last_hash: 0,
+            queue_cycles: 0,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Getter for `policy`
+    #[must_use]
+    pub fn policy(&self) -> &BanditPolicy {
+        &self.policy
+    }
+
+    /// Getter for `strat`
+    #[must_use]
+    pub fn strat(&self) -> &Option<PowerSchedule> {
+        &self.strat
+    }
+}
+
+/// Runs `f` on the [`struct@BanditMetadata`], taken out of the state, so that `f` can use the rest of it
+fn with_bandit_metadata<S, T, F>(state: &mut S, f: F) -> T
+where
+    S: HasMetadata,
+    F: FnOnce(&mut S, &mut BanditMetadata) -> T,
+{
+    let mut meta = state
+        .metadata_map_mut()
+        .remove::<BanditMetadata>()
+        .unwrap_or_default();
+    let res = f(state, &mut meta);
+    state.metadata_map_mut().insert_boxed(meta);
+    res
+}
+
+impl<C, I, O, S> RemovableScheduler<I, S> for BanditScheduler<C, O>
+where
+    S: HasMetadata + HasRand,
+{
+    fn on_remove(
+        &mut self,
+        state: &mut S,
+        id: CorpusId,
+        _prev: &Option<Testcase<I>>,
+    ) -> Result<(), Error> {
+        state
+            .metadata_or_insert_with(BanditMetadata::default)
+            .remove_arm(id);
+        Ok(())
+    }
+
+    /// The new testcase starts over with a fresh arm
+    fn on_replace(
+        &mut self,
+        state: &mut S,
+        id: CorpusId,
+        _prev: &Testcase<I>,
+    ) -> Result<(), Error> {
+        let policy = self.policy;
+        with_bandit_metadata(state, |state, meta| {
+            meta.add_arm(&policy, state.rand_mut(), id);
+        });
+        Ok(())
+    }
+}
+
+impl<C, O> AflScheduler for BanditScheduler<C, O> {
+    type ObserverRef = C;
+
+    fn last_hash(&self) -> usize {
+        self.last_hash
+    }
+
+    fn set_last_hash(&mut self, hash: usize) {
+        self.last_hash = hash;
+    }
+
+    fn observer_handle(&self) -> &Handle<C> {
+        &self.observer_handle
+    }
+}
+
+impl<C, O> HasQueueCycles for BanditScheduler<C, O> {
+    fn queue_cycles(&self) -> u64 {
+        self.queue_cycles
+    }
+}
+
+impl<C, I, O, S> Scheduler<I, S> for BanditScheduler<C, O>
+where
+    C: AsRef<O>,
+    O: Hash,
+    S: HasCorpus<I> + HasMetadata + HasRand + HasTestcase<I> + HasImported,
+{
+    /// Called when a [`Testcase`] is added to the corpus, rewards the entry it was found by
+    fn on_add(&mut self, state: &mut S, id: CorpusId) -> Result<(), Error> {
+        on_add_metadata_default(self, state, id)?;
+
+        let policy = self.policy;
+        let parent_id = *state.corpus().current();
+        let imported = *state.imported();
+        with_bandit_metadata(state, |state, meta| {
+            // The previous entry was found locally if nothing was imported since
+            meta.settle_reward(&policy, state.rand_mut(), imported);
+            meta.add_arm(&policy, state.rand_mut(), id);
+            meta.pending_reward = parent_id.map(|parent_id| (parent_id, imported));
+        });
+        Ok(())
+    }
+
+    fn on_evaluation<OT>(&mut self, state: &mut S, _input: &I, observers: &OT) -> Result<(), Error>
+    where
+        OT: MatchName,
+    {
+        on_evaluation_metadata_default(self, state, observers)
+    }
+
+    fn next(&mut self, state: &mut S) -> Result<CorpusId, Error> {
+        let corpus_counts = state.corpus().count();
+        if corpus_counts == 0 {
+            return Err(Error::empty(
+                "No entries in corpus. This often implies the target is not properly instrumented.",
+            ));
+        }
+
+        let policy = self.policy;
+        let imported = *state.imported();
+        let (id, new_cycle) = with_bandit_metadata(state, |state, meta| {
+            meta.settle_reward(&policy, state.rand_mut(), imported);
+
+            let id = loop {
+                if meta.arms.len() != corpus_counts {
+                    // Entries were added or removed behind the back of the scheduler, e.g., before it existed
+                    meta.arms.retain(|id, _| state.corpus().get(*id).is_ok());
+                    let mut id = state.corpus().first();
+                    while let Some(current) = id {
+                        meta.arms.entry(current).or_default();
+                        id = state.corpus().next(current);
+                    }
+                    meta.refresh(&policy, state.rand_mut());
+                } else if meta.scores.len() != meta.arms.len()
+                    || match policy {
+                        BanditPolicy::Ucb1 { .. } => meta.pulls >= meta.refreshed_at.max(1) * 2,
+                        BanditPolicy::ThompsonSampling => {
+                            meta.pulls >= meta.refreshed_at + corpus_counts as u64
+                        }
+                    }
+                {
+                    meta.refresh(&policy, state.rand_mut());
+                }
+
+                let id = meta
+                    .best()
+                    .ok_or_else(|| Error::empty("No arms for the corpus entries"))?;
+                if state.corpus().get(id).is_ok() {
+                    break id;
+                }
+                meta.remove_arm(id);
+            };
+
+            meta.pull(&policy, state.rand_mut(), id);
+            let new_cycle = meta.cycle_pulls >= corpus_counts as u64;
+            if new_cycle {
+                meta.cycle_pulls = 0;
+            }
+            Ok::<_, Error>((id, new_cycle))
+        })?;
+
+        if new_cycle {
+            self.queue_cycles += 1;
+            state
+                .metadata_mut::<SchedulerMetadata>()?
+                .set_queue_cycles(self.queue_cycles);
+        }
+
+        self.set_current_scheduled(state, Some(id))?;
+        Ok(id)
+    }
+
+    /// Set current fuzzed corpus id and `scheduled_count`
+    fn set_current_scheduled(
+        &mut self,
+        state: &mut S,
+        next_id: Option<CorpusId>,
+    ) -> Result<(), Error> {
+        on_next_metadata_default(state)?;
+
+        *state.corpus_mut().current_mut() = next_id;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "std")]
+mod tests {
+    use alloc::{vec, vec::Vec};
+    use core::cmp::Reverse;
+
+    use libafl_bolts::rands::StdRand;
+
+    use super::{BanditMetadata, BanditPolicy, BanditScheduler};
+    use crate::{
+        HasMetadata,
+        corpus::{Corpus, CorpusId, InMemoryCorpus, Testcase},
+        feedbacks::ConstFeedback,
+        inputs::BytesInput,
+        observers::StdMapObserver,
+        schedulers::Scheduler,
+        state::{HasCorpus, HasImported, StdState},
+    };
+
+    #[test]
+    fn test_bandit_scheduler() {
+        let observer = StdMapObserver::owned("edges", vec![0_u8; 16]);
+        let mut feedback = ConstFeedback::new(false);
+        let mut objective = ConstFeedback::new(false);
+        let mut state = StdState::new(
+            StdRand::with_seed(1337),
+            InMemoryCorpus::<BytesInput>::new(),
+            InMemoryCorpus::new(),
+            &mut feedback,
+            &mut objective,
+        )
+        .unwrap();
+        let mut scheduler = BanditScheduler::new(&mut state, &observer, BanditPolicy::default());
+
+        for input in [b"a", b"b"] {
+            let id = state
+                .corpus_mut()
+                .add(Testcase::new(BytesInput::new(input.to_vec())))
+                .unwrap();
+            scheduler.on_add(&mut state, id).unwrap();
+        }
+
+        // Entries that were never scheduled go first
+        assert_eq!(scheduler.next(&mut state).unwrap(), CorpusId(0));
+
+        // Fuzzing the first entry pays off
+        let id = state
+            .corpus_mut()
+            .add(Testcase::new(BytesInput::new(b"c".to_vec())))
+            .unwrap();
+        scheduler.on_add(&mut state, id).unwrap();
+        assert_eq!(scheduler.next(&mut state).unwrap(), CorpusId(1));
+        assert_eq!(scheduler.next(&mut state).unwrap(), CorpusId(2));
+
+        // All entries were pulled once now, only the first one was rewarded
+        assert_eq!(scheduler.next(&mut state).unwrap(), CorpusId(0));
+
+        // An entry imported from another fuzzer rewards nothing
+        let id = state
+            .corpus_mut()
+            .add(Testcase::new(BytesInput::new(b"d".to_vec())))
+            .unwrap();
+        scheduler.on_add(&mut state, id).unwrap();
+        *state.imported_mut() += 1;
+        assert_eq!(scheduler.next(&mut state).unwrap(), CorpusId(3));
+
+        let stats = state.metadata::<BanditMetadata>().unwrap();
+        assert_eq!(stats.pulls(), 5);
+        let arm = stats.arm(CorpusId(0)).unwrap();
+        assert_eq!((arm.pulls(), arm.rewards(), arm.finds()), (2, 1, 1));
+    }
+
+    #[test]
+    fn test_bandit_thompson_resampling() {
+        let observer = StdMapObserver::owned("edges", vec![0_u8; 16]);
+        let mut feedback = ConstFeedback::new(false);
+        let mut objective = ConstFeedback::new(false);
+        let mut state = StdState::new(
+            StdRand::with_seed(1337),
+            InMemoryCorpus::<BytesInput>::new(),
+            InMemoryCorpus::new(),
+            &mut feedback,
+            &mut objective,
+        )
+        .unwrap();
+        let mut scheduler =
+            BanditScheduler::new(&mut state, &observer, BanditPolicy::ThompsonSampling);
+        for input in [b"a", b"b", b"c"] {
+            let id = state
+                .corpus_mut()
+                .add(Testcase::new(BytesInput::new(input.to_vec())))
+                .unwrap();
+            scheduler.on_add(&mut state, id).unwrap();
+        }
+
+        // The last entry drew the lowest possible sample, it is never pulled unless it is sampled again
+        let meta = state.metadata_mut::<BanditMetadata>().unwrap();
+        let arm = meta.arms.get_mut(&CorpusId(2)).unwrap();
+        let score = arm.score.replace(0).unwrap();
+        meta.scores.remove(&(score, Reverse(CorpusId(2))));
+        meta.scores.insert((0, Reverse(CorpusId(2))));
+
+        let pulled = (0..30)
+            .map(|_| scheduler.next(&mut state).unwrap())
+            .collect::<Vec<_>>();
+        assert!(pulled.contains(&CorpusId(2)));
+        let meta = state.metadata::<BanditMetadata>().unwrap();
+        assert_eq!(meta.refreshed_at, 27);
+    }
+}
@@ -0,0 +1,247 @@
+//! Directed greybox fuzzing as in `AFLGo`: the [`DirectedTestcaseScore`], set up by the [`DirectedScheduler`], favors
+//! the corpus entries closest to the targets, more and more over time, following a simulated annealing schedule.
+//!
+//! The distances come from the [`crate::feedbacks::DistanceFeedback`].
+
+use core::time::Duration;
+
+use libafl_bolts::{current_time, tuples::MatchName};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Error, HasMetadata,
+    corpus::{CorpusId, Testcase},
+    feedbacks::{DistanceBoundsMetadata, DistanceMetadata},
+    schedulers::{
+        HasQueueCycles, RemovableScheduler, Scheduler,
+        testcase_score::{
+            CorpusPowerTestcaseScore, CorpusWeightTestcaseScore, FactorTestcaseScore,
+            TestcaseScoreFactor,
+        },
+    },
+    state::HasStartTime,
+};
+
+/// The default time after which the fuzzer mostly exploits the corpus entries closest to the targets
+pub const DEFAULT_TIME_TO_EXPLOIT: Duration = Duration::from_secs(45 * 60);
+
+/// The temperature after [`DirectedMetadata::time_to_exploit`]
+const EXPLOIT_TEMPERATURE: f64 = 0.05;
+
+/// The largest factor the power of a corpus entry is multiplied or divided by
+const MAX_POWER_FACTOR: f64 = 32.0;
+
+/// The state of the simulated annealing of the [`DirectedScheduler`]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct DirectedMetadata {
+    /// The time after which the temperature dropped to 5%, i.e., the fuzzer mostly exploits
+    pub time_to_exploit: Duration,
+}
+
+libafl_bolts::impl_serdeany!(DirectedMetadata);
+
+impl DirectedMetadata {
+    /// The temperature after fuzzing for `elapsed`, cooling down exponentially from `1` (exploration)
+    /// to `0` (exploitation)
+    #[must_use]
+    pub fn temperature(&self, elapsed: Duration) -> f64 {
+        if self.time_to_exploit.is_zero() {
+            return 0.0;
+        }
+        let progress = elapsed.as_secs_f64() / self.time_to_exploit.as_secs_f64();
+        libm::pow(EXPLOIT_TEMPERATURE, progress)
+    }
+}
+
+/// The priority of a corpus entry in `[0, 1]`, given its normalized distance and the temperature.
+///
+/// While the temperature is high, all entries get about the same priority. As it cools down, the entries close
+/// to the targets get a higher priority, and the distant ones a lower one.
+#[must_use]
+pub fn annealed_priority(normalized_distance: f64, temperature: f64) -> f64 {
+    (1.0 - normalized_distance) * (1.0 - temperature) + 0.5 * temperature
+}
+
+/// The factor to multiply the power of a corpus entry with, between `1/32` and `32`
+#[must_use]
+pub fn annealed_power_factor(priority: f64) -> f64 {
+    libm::pow(2.0, 2.0 * libm::log2(MAX_POWER_FACTOR) * (priority - 0.5))
+}
+
+/// The current temperature and the normalized distance of `entry`.
+///
+/// Entries without a distance are treated as average ones.
+fn annealing_params<I, S>(state: &S, entry: &Testcase<I>) -> Result<(f64, f64), Error>
+where
+    S: HasMetadata + HasStartTime,
+{
+    let meta = state.metadata::<DirectedMetadata>()?;
+    let elapsed = current_time().saturating_sub(*state.start_time());
+    let temperature = meta.temperature(elapsed);
+
+    let normalized = match (
+        entry.metadata::<DistanceMetadata>(),
+        state.metadata::<DistanceBoundsMetadata>(),
+    ) {
+        (Ok(distance), Ok(bounds)) => bounds.normalize(distance.distance),
+        _ => 0.5,
+    };
+    Ok((temperature, normalized))
+}
+
+/// The [`annealed_power_factor`] of a corpus entry. Needs a [`DirectedScheduler`] to set up the annealing.
+#[derive(Debug, Clone)]
+pub struct DirectedScoreFactor;
+
+impl<I, S> TestcaseScoreFactor<I, S> for DirectedScoreFactor
+where
+    S: HasMetadata + HasStartTime,
+{
+    fn factor(state: &S, entry: &Testcase<I>) -> Result<f64, Error> {
+        let (temperature, normalized) = annealing_params(state, entry)?;
+        Ok(annealed_power_factor(annealed_priority(
+            normalized,
+            temperature,
+        )))
+    }
+}
+
+/// Multiplies the score of `F` with the [`annealed_power_factor`] of the entry,
+/// e.g., to use in a [`crate::stages::PowerMutationalStage`] or a [`crate::schedulers::WeightedScheduler`].
+pub type DirectedTestcaseScore<F> = FactorTestcaseScore<F, DirectedScoreFactor>;
+
+/// The power of the power schedules, with the annealing of `AFLGo` on top
+pub type DirectedPowerTestcaseScore = DirectedTestcaseScore<CorpusPowerTestcaseScore>;
+
+/// The weight of the weighted scheduler, with the annealing of `AFLGo` on top
+pub type DirectedWeightTestcaseScore = DirectedTestcaseScore<CorpusWeightTestcaseScore>;
+
+/// A scheduler wrapper for directed fuzzing.
+///
+/// It sets up the simulated annealing used by the [`DirectedTestcaseScore`], and otherwise leaves the scheduling to
+/// the wrapped scheduler. Use it with a [`crate::feedbacks::DistanceFeedback`], a
+/// [`crate::stages::PowerMutationalStage`] using a [`DirectedPowerTestcaseScore`], and, to also pick the entries
+/// close to the targets more often, a [`crate::schedulers::WeightedScheduler`] using a
+/// [`DirectedWeightTestcaseScore`]. The weights follow the annealing whenever the corpus changes.
+#[derive(Debug, Clone)]
+pub struct DirectedScheduler<CS> {
+    inner: CS,
+}
+
+impl<CS> DirectedScheduler<CS> {
+    /// Wraps `inner`, exploiting after [`DEFAULT_TIME_TO_EXPLOIT`]
+    pub fn new<S>(state: &mut S, inner: CS) -> Self
+    where
+        S: HasMetadata,
+    {
+        Self::with_time_to_exploit(state, inner, DEFAULT_TIME_TO_EXPLOIT)
+    }
+
+    /// Wraps `inner`, exploiting after `time_to_exploit`, as set with `-c` in `AFLGo`
+    pub fn with_time_to_exploit<S>(state: &mut S, inner: CS, time_to_exploit: Duration) -> Self
+    where
+        S: HasMetadata,
+    {
+        state.add_metadata(DirectedMetadata { time_to_exploit });
+        Self { inner }
+    }
+
+    /// The wrapped scheduler
+    pub fn inner(&self) -> &CS {
+        &self.inner
+    }
+
+    /// The wrapped scheduler (mutable)
+    pub fn inner_mut(&mut self) -> &mut CS {
+        &mut self.inner
+    }
+}
+
+impl<CS, I, S> RemovableScheduler<I, S> for DirectedScheduler<CS>
+where
+    CS: RemovableScheduler<I, S>,
+{
+    fn on_remove(
+        &mut self,
+        state: &mut S,
+        id: CorpusId,
+        testcase: &Option<Testcase<I>>,
+    ) -> Result<(), Error> {
+        self.inner.on_remove(state, id, testcase)
+    }
+
+    fn on_replace(&mut self, state: &mut S, id: CorpusId, prev: &Testcase<I>) -> Result<(), Error> {
+        self.inner.on_replace(state, id, prev)
+    }
+}
+
+impl<CS> HasQueueCycles for DirectedScheduler<CS>
+where
+    CS: HasQueueCycles,
+{
+    fn queue_cycles(&self) -> u64 {
+        self.inner.queue_cycles()
+    }
+}
+
+impl<CS, I, S> Scheduler<I, S> for DirectedScheduler<CS>
+where
+    CS: Scheduler<I, S>,
+{
+    fn on_add(&mut self, state: &mut S, id: CorpusId) -> Result<(), Error> {
+        self.inner.on_add(state, id)
+    }
+
+    fn on_evaluation<OT>(&mut self, state: &mut S, input: &I, observers: &OT) -> Result<(), Error>
+    where
+        OT: MatchName,
+    {
+        self.inner.on_evaluation(state, input, observers)
+    }
+
+    fn next(&mut self, state: &mut S) -> Result<CorpusId, Error> {
+        self.inner.next(state)
+    }
+
+    fn set_current_scheduled(
+        &mut self,
+        state: &mut S,
+        next_id: Option<CorpusId>,
+    ) -> Result<(), Error> {
+        self.inner.set_current_scheduled(state, next_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use super::{DirectedMetadata, annealed_power_factor, annealed_priority};
+
+    #[test]
+    fn test_annealing() {
+        let meta = DirectedMetadata {
+            time_to_exploit: Duration::from_secs(60),
+        };
+        assert!((meta.temperature(Duration::ZERO) - 1.0).abs() < 1e-9);
+        assert!((meta.temperature(Duration::from_secs(60)) - 0.05).abs() < 1e-9);
+
+        // Exploring: the distance does not matter
+        let hot = meta.temperature(Duration::ZERO);
+        assert!((annealed_power_factor(annealed_priority(0.0, hot)) - 1.0).abs() < 1e-9);
+        assert!((annealed_power_factor(annealed_priority(1.0, hot)) - 1.0).abs() < 1e-9);
+
+        // Exploiting: close entries get up to 32 times the power, distant ones down to 1/32
+        let cold = meta.temperature(Duration::from_secs(600));
+        assert!(annealed_power_factor(annealed_priority(0.0, cold)) > 31.0);
+        assert!(annealed_power_factor(annealed_priority(1.0, cold)) < 1.0 / 31.0);
+        assert!(
+            annealed_power_factor(annealed_priority(0.5, cold)) > 0.99
+                && annealed_power_factor(annealed_priority(0.5, cold)) < 1.01
+        );
+    }
+}
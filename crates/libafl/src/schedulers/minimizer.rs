@@ -114,6 +114,10 @@ where
             } else {
                 return Ok(());
             };
+        if entries.is_empty() {
+            // The entry was not top rated for any index, there is nothing to re-rate
+            return Ok(());
+        }
         entries.sort_unstable(); // this should already be sorted, but just in case
         let mut map = HashMap::new();
         for current_id in state.corpus().ids() {
@@ -4,7 +4,9 @@ use alloc::{borrow::ToOwned, string::ToString};
 use core::{hash::Hash, marker::PhantomData};
 
 pub mod testcase_score;
-pub use testcase_score::{LenTimeMulTestcaseScore, TestcaseScore};
+pub use testcase_score::{
+    FactorTestcaseScore, LenTimeMulTestcaseScore, TestcaseScore, TestcaseScoreFactor,
+};
 
 pub mod queue;
 pub use queue::QueueScheduler;
@@ -26,6 +28,24 @@ pub use accounting::CoverageAccountingScheduler;
 pub mod weighted;
 pub use weighted::{StdWeightedScheduler, WeightedScheduler};
 
+pub mod bandit;
+pub use bandit::{BanditMetadata, BanditPolicy, BanditScheduler};
+
+pub mod adaptive;
+pub use adaptive::AdaptivePowerScheduler;
+
+pub mod directed;
+pub use directed::{
+    DirectedPowerTestcaseScore, DirectedScheduler, DirectedScoreFactor, DirectedTestcaseScore,
+    DirectedWeightTestcaseScore,
+};
+
+pub mod patch;
+pub use patch::{PatchPowerTestcaseScore, PatchScoreFactor, PatchTestcaseScore};
+
+pub mod perf;
+pub use perf::{PerfPowerTestcaseScore, PerfScoreFactor, PerfTestcaseScore};
+
 pub mod tuneable;
 use libafl_bolts::{
     generic_hash_std,
@@ -0,0 +1,42 @@
+//! The [`PatchTestcaseScore`] favors the corpus entries executing the edges of the lines a patch changed,
+//! as counted by the [`crate::feedbacks::PatchMapFeedback`].
+
+use crate::{
+    Error, HasMetadata,
+    corpus::Testcase,
+    feedbacks::PatchHitsMetadata,
+    schedulers::testcase_score::{
+        CorpusPowerTestcaseScore, FactorTestcaseScore, TestcaseScoreFactor,
+    },
+};
+
+/// The largest factor the score of a corpus entry is multiplied by
+pub const MAX_PATCH_FACTOR: f64 = 16.0;
+
+/// The factor to multiply the score of a corpus entry executing `edges` edges of changed lines with,
+/// from `1` for none up to [`MAX_PATCH_FACTOR`]
+#[must_use]
+#[expect(clippy::cast_precision_loss)]
+pub fn patch_factor(edges: usize) -> f64 {
+    (1.0 + edges as f64).min(MAX_PATCH_FACTOR)
+}
+
+/// The [`patch_factor`] of a corpus entry, from its [`PatchHitsMetadata`]. Entries without it keep their score.
+#[derive(Debug, Clone)]
+pub struct PatchScoreFactor;
+
+impl<I, S> TestcaseScoreFactor<I, S> for PatchScoreFactor {
+    fn factor(_state: &S, entry: &Testcase<I>) -> Result<f64, Error> {
+        let edges = entry
+            .metadata::<PatchHitsMetadata>()
+            .map_or(0, |meta| meta.edges);
+        Ok(patch_factor(edges))
+    }
+}
+
+/// Multiplies the score of `F` with the [`patch_factor`] of the entry,
+/// e.g., to use in a [`crate::stages::PowerMutationalStage`] or a [`crate::schedulers::WeightedScheduler`].
+pub type PatchTestcaseScore<F> = FactorTestcaseScore<F, PatchScoreFactor>;
+
+/// The power of the power schedules, favoring the entries executing changed lines
+pub type PatchPowerTestcaseScore = PatchTestcaseScore<CorpusPowerTestcaseScore>;
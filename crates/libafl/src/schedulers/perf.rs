@@ -0,0 +1,42 @@
+//! The [`PerfTestcaseScore`] favors the corpus entries executing edges more often than all entries before them,
+//! as counted by the [`crate::feedbacks::PerfFeedback`], to fuzz towards slow inputs as in `PerfFuzz`.
+
+use crate::{
+    Error, HasMetadata,
+    corpus::Testcase,
+    feedbacks::PerfEdgesMetadata,
+    schedulers::testcase_score::{
+        CorpusPowerTestcaseScore, FactorTestcaseScore, TestcaseScoreFactor,
+    },
+};
+
+/// The largest factor the score of a corpus entry is multiplied by
+pub const MAX_PERF_FACTOR: f64 = 8.0;
+
+/// The factor to multiply the score of a corpus entry maximizing the hit counts of `edges` edges with,
+/// growing logarithmically from `1` for none up to [`MAX_PERF_FACTOR`]
+#[must_use]
+#[expect(clippy::cast_precision_loss)]
+pub fn perf_factor(edges: usize) -> f64 {
+    (1.0 + libm::log2(1.0 + edges as f64)).min(MAX_PERF_FACTOR)
+}
+
+/// The [`perf_factor`] of a corpus entry, from its [`PerfEdgesMetadata`]. Entries without it keep their score.
+#[derive(Debug, Clone)]
+pub struct PerfScoreFactor;
+
+impl<I, S> TestcaseScoreFactor<I, S> for PerfScoreFactor {
+    fn factor(_state: &S, entry: &Testcase<I>) -> Result<f64, Error> {
+        let edges = entry
+            .metadata::<PerfEdgesMetadata>()
+            .map_or(0, |meta| meta.edges);
+        Ok(perf_factor(edges))
+    }
+}
+
+/// Multiplies the score of `F` with the [`perf_factor`] of the entry,
+/// e.g., to use in a [`crate::stages::PowerMutationalStage`] or a [`crate::schedulers::WeightedScheduler`].
+pub type PerfTestcaseScore<F> = FactorTestcaseScore<F, PerfScoreFactor>;
+
+/// The power of the power schedules, favoring the entries maximizing hit counts
+pub type PerfPowerTestcaseScore = PerfTestcaseScore<CorpusPowerTestcaseScore>;
@@ -1,5 +1,6 @@
 //! The `TestcaseScore` is an evaluator providing scores of corpus items.
 use alloc::string::{String, ToString};
+use core::marker::PhantomData;
 
 use libafl_bolts::{HasLen, HasRefCnt};
 use num_traits::Zero;
@@ -21,6 +22,29 @@ pub trait TestcaseScore<I, S> {
     fn compute(state: &S, entry: &mut Testcase<I>) -> Result<f64, Error>;
 }
 
+/// A factor to multiply the score of a [`Testcase`] with, usually computed from its metadata
+pub trait TestcaseScoreFactor<I, S> {
+    /// Computes the factor for a [`Testcase`]
+    fn factor(state: &S, entry: &Testcase<I>) -> Result<f64, Error>;
+}
+
+/// Multiplies the score of `F` with the [`TestcaseScoreFactor`] `M` of the entry,
+/// e.g., to use in a [`crate::stages::PowerMutationalStage`] or a [`crate::schedulers::WeightedScheduler`].
+#[derive(Debug, Clone)]
+pub struct FactorTestcaseScore<F, M> {
+    phantom: PhantomData<(F, M)>,
+}
+
+impl<F, I, M, S> TestcaseScore<I, S> for FactorTestcaseScore<F, M>
+where
+    F: TestcaseScore<I, S>,
+    M: TestcaseScoreFactor<I, S>,
+{
+    fn compute(state: &S, entry: &mut Testcase<I>) -> Result<f64, Error> {
+        Ok(F::compute(state, entry)? * M::factor(state, entry)?)
+    }
+}
+
 /// Multiply the testcase size with the execution time.
 /// This favors small and quick testcases.
 #[derive(Debug, Clone)]
@@ -346,3 +370,58 @@ where
         Ok(weight)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::TestcaseScore;
+    use crate::{
+        Error, HasMetadata,
+        corpus::Testcase,
+        feedbacks::{PatchHitsMetadata, PerfEdgesMetadata},
+        inputs::BytesInput,
+        schedulers::{PatchTestcaseScore, PerfTestcaseScore},
+        state::NopState,
+    };
+
+    /// Scores every entry with `2`
+    struct TwoTestcaseScore;
+
+    impl<I, S> TestcaseScore<I, S> for TwoTestcaseScore {
+        fn compute(_state: &S, _entry: &mut Testcase<I>) -> Result<f64, Error> {
+            Ok(2.0)
+        }
+    }
+
+    #[test]
+    fn test_factor_testcase_score() {
+        #[cfg(any(not(feature = "serdeany_autoreg"), miri))]
+        unsafe {
+            PerfEdgesMetadata::register();
+            PatchHitsMetadata::register();
+        }
+
+        let state: NopState<BytesInput> = NopState::new();
+        let mut entry = Testcase::new(BytesInput::new(vec![0]));
+        let perf = |entry: &mut Testcase<BytesInput>| {
+            PerfTestcaseScore::<TwoTestcaseScore>::compute(&state, entry).unwrap()
+        };
+        let patch = |entry: &mut Testcase<BytesInput>| {
+            PatchTestcaseScore::<TwoTestcaseScore>::compute(&state, entry).unwrap()
+        };
+
+        // Entries without metadata keep their score
+        assert!((perf(&mut entry) - 2.0).abs() < f64::EPSILON);
+        assert!((patch(&mut entry) - 2.0).abs() < f64::EPSILON);
+
+        entry.add_metadata(PerfEdgesMetadata { edges: 3 });
+        entry.add_metadata(PatchHitsMetadata { edges: 3 });
+        assert!((perf(&mut entry) - 6.0).abs() < f64::EPSILON);
+        assert!((patch(&mut entry) - 8.0).abs() < f64::EPSILON);
+
+        // The factors are capped
+        entry.add_metadata(PerfEdgesMetadata { edges: 1 << 20 });
+        entry.add_metadata(PatchHitsMetadata { edges: 1000 });
+        assert!((perf(&mut entry) - 16.0).abs() < f64::EPSILON);
+        assert!((patch(&mut entry) - 32.0).abs() < f64::EPSILON);
+    }
+}
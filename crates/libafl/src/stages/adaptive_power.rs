@@ -0,0 +1,123 @@
+//! The [`AdaptivePowerStatsStage`] reports the schedule switches of the [`crate::schedulers::AdaptivePowerScheduler`].
+//!
+//! Schedulers cannot fire events, so the scheduler queues its switches in the [`AdaptivePowerMetadata`]
+//! and this stage reports them.
+
+use alloc::{borrow::Cow, format};
+use core::marker::PhantomData;
+
+use libafl_bolts::Named;
+
+use crate::{
+    Error, HasMetadata,
+    events::{Event, EventFirer, EventWithStats, LogSeverity},
+    monitors::stats::{AggregatorOps, UserStats, UserStatsValue},
+    schedulers::{adaptive::AdaptivePowerMetadata, powersched::BaseSchedule},
+    stages::{Restartable, Stage},
+    state::HasExecutions,
+};
+
+/// Default name for `AdaptivePowerStatsStage`
+pub const ADAPTIVE_POWER_STATS_STAGE_NAME: &str = "adaptive_power_stats";
+
+/// The name of the user stat holding the active schedule
+pub const ACTIVE_SCHEDULE_STAT_NAME: &str = "schedule";
+
+/// A stage that logs each switch of the [`crate::schedulers::AdaptivePowerScheduler`] as an [`Event::Log`],
+/// and reports the active schedule as the [`ACTIVE_SCHEDULE_STAT_NAME`] user stat.
+#[derive(Debug, Clone)]
+pub struct AdaptivePowerStatsStage<I> {
+    name: Cow<'static, str>,
+    reported: Option<BaseSchedule>,
+    phantom: PhantomData<I>,
+}
+
+impl<I> Named for AdaptivePowerStatsStage<I> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<I, S> Restartable<S> for AdaptivePowerStatsStage<I> {
+    #[inline]
+    fn should_restart(&mut self, _state: &mut S) -> Result<bool, Error> {
+        // Reporting does not execute the target
+        Ok(true)
+    }
+
+    #[inline]
+    fn clear_progress(&mut self, _state: &mut S) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<E, EM, I, S, Z> Stage<E, EM, S, Z> for AdaptivePowerStatsStage<I>
+where
+    EM: EventFirer<I, S>,
+    S: HasMetadata + HasExecutions,
+{
+    fn perform(
+        &mut self,
+        _fuzzer: &mut Z,
+        _executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        let Ok(meta) = state.metadata_mut::<AdaptivePowerMetadata>() else {
+            return Ok(());
+        };
+        let switches = core::mem::take(&mut meta.pending);
+        let active = meta.active();
+
+        for switch in switches {
+            manager.log(
+                state,
+                LogSeverity::Info,
+                format!(
+                    "Power schedule plateaued with {} new entries in the last window, switching from {:?} to {:?}",
+                    switch.finds, switch.from, switch.to
+                ),
+            )?;
+        }
+
+        if self.reported != Some(active) {
+            self.reported = Some(active);
+            let executions = *state.executions();
+            manager.fire(
+                state,
+                EventWithStats::with_current_time(
+                    Event::UpdateUserStats {
+                        name: Cow::Borrowed(ACTIVE_SCHEDULE_STAT_NAME),
+                        value: UserStats::new(
+                            UserStatsValue::String(Cow::Owned(
+                                format!("{active:?}").to_lowercase(),
+                            )),
+                            AggregatorOps::None,
+                        ),
+                        phantom: PhantomData,
+                    },
+                    executions,
+                ),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl<I> AdaptivePowerStatsStage<I> {
+    /// Creates a new [`AdaptivePowerStatsStage`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            name: Cow::Borrowed(ADAPTIVE_POWER_STATS_STAGE_NAME),
+            reported: None,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<I> Default for AdaptivePowerStatsStage<I> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
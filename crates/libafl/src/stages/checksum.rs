@@ -0,0 +1,366 @@
+//! The [`ChecksumDetectionStage`] finds checksums in inputs, by matching the values the target compares against
+//! checksums of ranges of the input.
+//!
+//! The checksums end up in the [`ChecksumsMetadata`] of the testcase, where the [`crate::mutators::ChecksumFixupMutator`]
+//! and the [`crate::feedbacks::ChecksumFixupFeedback`] pick them up.
+
+use alloc::{borrow::Cow, vec::Vec};
+use core::{marker::PhantomData, ops::Range};
+
+use libafl_bolts::Named;
+
+use crate::{
+    Error, HasMetadata,
+    corpus::HasCurrentCorpusId,
+    inputs::HasMutatorBytes,
+    mutators::{
+        Checksum, ChecksumAlgorithm, ChecksumsMetadata,
+        checksum::{ADLER32_MOD, ChecksumHasher},
+    },
+    observers::CmpValues,
+    stages::{
+        Restartable, Stage,
+        field_inference::{cmp_width, encode, find_all, logged_cmps},
+    },
+    state::{HasCorpus, HasCurrentTestcase},
+};
+
+/// Default name for `ChecksumDetectionStage`
+pub const CHECKSUM_DETECTION_STAGE_NAME: &str = "checksum_detection";
+
+/// Checksums over fewer bytes than this match by chance too often
+const CHECKSUM_MIN_DATA: usize = 4;
+
+/// Only inputs up to this length are searched for checksums over arbitrary ranges next to the field
+const CHECKSUM_MAX_SCAN: usize = 4096;
+
+/// At most this many occurrences of a compared value in the input are probed as checksum field
+const CHECKSUM_MAX_POSITIONS: usize = 16;
+
+/// The number of bytes fed to CRC computations per input, after which only the sums are probed
+const CHECKSUM_CRC_BUDGET: usize = 1 << 20;
+
+/// Computes the checksums of ranges of an input.
+///
+/// The sums and Adler-32 come from prefix sums in constant time, while CRC32 is computed over the range and
+/// charged to a budget, so that large inputs cannot stall the stage.
+struct RangeChecksums<'a> {
+    bytes: &'a [u8],
+    /// The wrapping sums of all prefixes
+    sums: Vec<u32>,
+    /// The sums of all prefixes, modulo the Adler-32 modulus
+    adler_sums: Vec<u64>,
+    /// The sums of all prefixes, each byte weighted with its position, modulo the Adler-32 modulus
+    adler_weighted: Vec<u64>,
+    crc_budget: usize,
+}
+
+impl<'a> RangeChecksums<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        let modulus = u64::from(ADLER32_MOD);
+        let mut sums = Vec::with_capacity(bytes.len() + 1);
+        let mut adler_sums = Vec::with_capacity(bytes.len() + 1);
+        let mut adler_weighted = Vec::with_capacity(bytes.len() + 1);
+        let (mut sum, mut adler_sum, mut weighted) = (0_u32, 0_u64, 0_u64);
+        sums.push(sum);
+        adler_sums.push(adler_sum);
+        adler_weighted.push(weighted);
+        for (pos, byte) in bytes.iter().enumerate() {
+            sum = sum.wrapping_add(u32::from(*byte));
+            adler_sum = (adler_sum + u64::from(*byte)) % modulus;
+            weighted = (weighted + (pos as u64 % modulus) * u64::from(*byte)) % modulus;
+            sums.push(sum);
+            adler_sums.push(adler_sum);
+            adler_weighted.push(weighted);
+        }
+        Self {
+            bytes,
+            sums,
+            adler_sums,
+            adler_weighted,
+            crc_budget: CHECKSUM_CRC_BUDGET,
+        }
+    }
+
+    /// The checksum of `range`, or `None` if the CRC budget is used up
+    fn compute(&mut self, algorithm: ChecksumAlgorithm, range: Range<usize>) -> Option<u64> {
+        let Range { start, end } = range;
+        Some(match algorithm {
+            ChecksumAlgorithm::Crc32 => {
+                self.crc_budget = self.crc_budget.checked_sub(end - start)?;
+                algorithm.compute(&self.bytes[start..end])
+            }
+            ChecksumAlgorithm::Adler32 => {
+                // b is the sum of a after each byte: the length, plus each byte weighted with its distance to the end
+                let modulus = u64::from(ADLER32_MOD);
+                let sum = (self.adler_sums[end] + modulus - self.adler_sums[start]) % modulus;
+                let weighted =
+                    (self.adler_weighted[end] + modulus - self.adler_weighted[start]) % modulus;
+                let a = (1 + sum) % modulus;
+                let b = ((end - start) as u64 % modulus
+                    + (end as u64 % modulus) * sum % modulus
+                    + modulus
+                    - weighted)
+                    % modulus;
+                (b << 16) | a
+            }
+            ChecksumAlgorithm::Sum16 => {
+                u64::from(self.sums[end].wrapping_sub(self.sums[start]) & 0xffff)
+            }
+            ChecksumAlgorithm::Sum32 => u64::from(self.sums[end].wrapping_sub(self.sums[start])),
+        })
+    }
+
+    /// The first end in `ends` for which `start..end` has the checksum `value`, in a single pass
+    fn find_end(
+        &mut self,
+        algorithm: ChecksumAlgorithm,
+        start: usize,
+        ends: Range<usize>,
+        value: u64,
+    ) -> Option<usize> {
+        if algorithm != ChecksumAlgorithm::Crc32 {
+            return ends
+                .into_iter()
+                .find(|&end| self.compute(algorithm, start..end) == Some(value));
+        }
+        let mut hasher = ChecksumHasher::new(algorithm);
+        let mut pos = start;
+        for end in ends {
+            self.crc_budget = self.crc_budget.checked_sub(end - pos)?;
+            hasher.update(&self.bytes[pos..end]);
+            pos = end;
+            if hasher.value() == value {
+                return Some(end);
+            }
+        }
+        None
+    }
+}
+
+/// The data a checksum stored in `field` with the value `computed` is computed over, if any.
+///
+/// The whole data behind or in front of the field is tried first, then, for short inputs, the data right in
+/// front of the checksum, e.g., PNG chunks, or right behind it.
+fn find_data(
+    checksums: &mut RangeChecksums<'_>,
+    algorithm: ChecksumAlgorithm,
+    field: &Range<usize>,
+    computed: u64,
+) -> Option<Range<usize>> {
+    let len = checksums.bytes.len();
+    for data in [field.end..len, 0..field.start] {
+        if data.len() >= CHECKSUM_MIN_DATA
+            && checksums.compute(algorithm, data.clone()) == Some(computed)
+        {
+            return Some(data);
+        }
+    }
+    if len > CHECKSUM_MAX_SCAN {
+        return None;
+    }
+    for start in 1..=field.start.saturating_sub(CHECKSUM_MIN_DATA) {
+        if checksums.compute(algorithm, start..field.start)? == computed {
+            return Some(start..field.start);
+        }
+    }
+    let ends = field.end + CHECKSUM_MIN_DATA..len;
+    checksums
+        .find_end(algorithm, field.end, ends, computed)
+        .map(|end| field.end..end)
+}
+
+/// Finds the checksums in `bytes`, using the comparisons `cmps` the target made while processing it.
+///
+/// A checksum is a comparison operand that is stored in the input, while the other operand is the checksum of
+/// some range of the input, computed with one of the [`ChecksumAlgorithm::ALL`].
+/// Stored values that are all zeros or all ones are skipped, they match padding everywhere.
+/// The checksums are sorted so that the ones nested in the data of others come first.
+#[must_use]
+pub fn detect_checksums(bytes: &[u8], cmps: &[CmpValues]) -> Vec<Checksum> {
+    let mut range_checksums = RangeChecksums::new(bytes);
+    let mut checksums: Vec<Checksum> = Vec::new();
+
+    for cmp in cmps {
+        let Some((v0, v1, _)) = cmp.to_u64_tuple() else {
+            continue;
+        };
+        let width = cmp_width(cmp);
+        if !(1..=8).contains(&width) {
+            continue;
+        }
+        let all_ones = u64::MAX >> (64 - 8 * width);
+        for (stored, computed) in [(v0, v1), (v1, v0)] {
+            if stored == 0 || stored == all_ones {
+                continue;
+            }
+            for algorithm in ChecksumAlgorithm::ALL {
+                if algorithm.width() != width {
+                    continue;
+                }
+                for big_endian in [false, true] {
+                    let needle = encode(stored, width, big_endian);
+                    for pos in find_all(bytes, &needle).take(CHECKSUM_MAX_POSITIONS) {
+                        let field = pos..pos + width;
+                        if checksums.iter().any(|checksum| checksum.field == field) {
+                            continue;
+                        }
+                        let Some(data) =
+                            find_data(&mut range_checksums, algorithm, &field, computed)
+                        else {
+                            continue;
+                        };
+                        checksums.push(Checksum {
+                            algorithm,
+                            to_end: data.end == bytes.len(),
+                            data,
+                            field,
+                            big_endian,
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    checksums.sort_by_key(|checksum| checksum.data.len());
+    checksums
+}
+
+/// A stage that detects the checksums of each corpus entry once, and stores them as [`ChecksumsMetadata`].
+///
+/// Like [`crate::stages::FieldInferenceStage`], it executes nothing itself, and needs a cmplog tracing stage
+/// run on the current input before it.
+#[derive(Debug, Clone)]
+pub struct ChecksumDetectionStage<I> {
+    name: Cow<'static, str>,
+    phantom: PhantomData<I>,
+}
+
+impl<I> Named for ChecksumDetectionStage<I> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<I, S> Restartable<S> for ChecksumDetectionStage<I> {
+    #[inline]
+    fn should_restart(&mut self, _state: &mut S) -> Result<bool, Error> {
+        // Detection does not execute the target
+        Ok(true)
+    }
+
+    #[inline]
+    fn clear_progress(&mut self, _state: &mut S) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<E, EM, I, S, Z> Stage<E, EM, S, Z> for ChecksumDetectionStage<I>
+where
+    I: HasMutatorBytes + Clone,
+    S: HasCorpus<I> + HasMetadata + HasCurrentCorpusId,
+{
+    fn perform(
+        &mut self,
+        _fuzzer: &mut Z,
+        _executor: &mut E,
+        state: &mut S,
+        _manager: &mut EM,
+    ) -> Result<(), Error> {
+        if state
+            .current_testcase()?
+            .has_metadata::<ChecksumsMetadata>()
+        {
+            return Ok(());
+        }
+
+        let input = state.current_input_cloned()?;
+        let checksums = detect_checksums(input.mutator_bytes(), &logged_cmps(state));
+        if !checksums.is_empty() {
+            log::info!(
+                "Detected {} checksum(s) in the current testcase",
+                checksums.len()
+            );
+        }
+        state
+            .current_testcase_mut()?
+            .add_metadata(ChecksumsMetadata::new(checksums));
+        Ok(())
+    }
+}
+
+impl<I> ChecksumDetectionStage<I> {
+    /// Creates a new [`ChecksumDetectionStage`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            name: Cow::Borrowed(CHECKSUM_DETECTION_STAGE_NAME),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<I> Default for ChecksumDetectionStage<I> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::{RangeChecksums, detect_checksums};
+    use crate::{
+        mutators::{Checksum, ChecksumAlgorithm},
+        observers::CmpValues,
+    };
+
+    #[test]
+    fn test_range_checksums() {
+        let bytes: Vec<u8> = (0..300_u32).map(|i| (i * 37 % 251) as u8).collect();
+        let mut checksums = RangeChecksums::new(&bytes);
+        for algorithm in ChecksumAlgorithm::ALL {
+            for range in [0..300, 0..1, 7..7, 13..290, 299..300] {
+                assert_eq!(
+                    checksums.compute(algorithm, range.clone()),
+                    Some(algorithm.compute(&bytes[range]))
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_detect_checksums_trivial() {
+        // Zeros would be a valid sum of zeros anywhere
+        let bytes = [0; 64];
+        let cmps = [CmpValues::U32((0, 0, false))];
+        assert!(detect_checksums(&bytes, &cmps).is_empty());
+    }
+
+    #[test]
+    fn test_detect_checksums() {
+        // A header, then the payload followed by a big endian CRC32 of it with a stale value
+        let mut bytes = Vec::from(*b"HDR!");
+        bytes.extend_from_slice(b"some payload");
+        bytes.extend_from_slice(&0x1234_5678_u32.to_be_bytes());
+
+        let crc = ChecksumAlgorithm::Crc32.compute(b"some payload");
+        let cmps = [
+            CmpValues::U32((crc as u32, 0x1234_5678, false)),
+            CmpValues::U16((1, 2, false)),
+        ];
+
+        assert_eq!(
+            detect_checksums(&bytes, &cmps),
+            [Checksum {
+                algorithm: ChecksumAlgorithm::Crc32,
+                data: 4..16,
+                field: 16..20,
+                big_endian: true,
+                to_end: false,
+            }]
+        );
+    }
+}
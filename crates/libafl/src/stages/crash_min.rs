@@ -0,0 +1,378 @@
+//! The [`CrashMinimizationStage`] minimizes new solutions while the fuzzer is running, instead of in a separate
+//! minimization campaign.
+//!
+//! The minimized input is added to the solutions next to the original, and the two are linked through
+//! [`MinimizedFromMetadata`] and [`MinimizedToMetadata`].
+
+use alloc::borrow::{Cow, ToOwned};
+use core::marker::PhantomData;
+
+use libafl_bolts::{
+    HasLen, Named, impl_serdeany,
+    tuples::{Handle, Handled},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Error, HasMetadata, HasNamedMetadata,
+    corpus::{Corpus, CorpusId, Testcase},
+    executors::{Executor, ExitKind, HasObservers},
+    inputs::Input,
+    mutators::{MutationResult, Mutator},
+    observers::{ObserverWithHashField, ObserversTuple},
+    stages::{Restartable, Stage},
+    state::{HasExecutions, HasMaxSize, HasSolutions},
+};
+
+/// Default name for `CrashMinimizationStage`
+pub const CRASH_MIN_STAGE_NAME: &str = "crash_min";
+
+/// Added to a minimized solution, pointing to the solution it was minimized from
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct MinimizedFromMetadata {
+    /// The id of the original solution
+    pub original: CorpusId,
+    /// The length of the original input
+    pub orig_len: usize,
+}
+
+impl_serdeany!(MinimizedFromMetadata);
+
+/// Added to a solution once it was minimized, pointing to the minimized solution
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct MinimizedToMetadata {
+    /// The id of the minimized solution
+    pub minimized: CorpusId,
+}
+
+impl_serdeany!(MinimizedToMetadata);
+
+/// The progress of a [`CrashMinimizationStage`], stored as named metadata in the state
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct CrashMinimizationMetadata {
+    /// The first solution id not looked at yet
+    pub next_solution_id: usize,
+}
+
+impl_serdeany!(CrashMinimizationMetadata);
+
+/// Mutates `input` up to `runs` times in a row, keeping every shorter mutant for which `is_equivalent` holds.
+///
+/// Like in [`crate::stages::StdTMinMutationalStage`], the counter is reset after each reduction.
+/// Returns `true` if the input got shorter.
+fn minimize_input<I, M, S, F>(
+    state: &mut S,
+    mutator: &mut M,
+    input: &mut I,
+    runs: usize,
+    mut is_equivalent: F,
+) -> Result<bool, Error>
+where
+    I: Clone + HasLen,
+    M: Mutator<I, S>,
+    S: HasMaxSize,
+    F: FnMut(&mut S, &I) -> Result<bool, Error>,
+{
+    let orig_len = input.len();
+    let orig_max_size = state.max_size();
+
+    let mut run = || -> Result<(), Error> {
+        let mut i = 0;
+        while i < runs {
+            i += 1;
+
+            let before_len = input.len();
+            state.set_max_size(before_len);
+
+            let mut candidate = input.clone();
+            if mutator.mutate(state, &mut candidate)? == MutationResult::Skipped {
+                continue;
+            }
+
+            // Mutators are not guaranteed to shrink, don't waste executions on the others
+            if candidate.len() < before_len && is_equivalent(state, &candidate)? {
+                *input = candidate;
+                i = 0;
+            }
+            mutator.post_exec(state, None)?;
+        }
+        Ok(())
+    };
+    let res = run();
+
+    // Restore the max size before propagating any error
+    state.set_max_size(orig_max_size);
+    res?;
+    Ok(input.len() < orig_len)
+}
+
+/// A stage that minimizes each new solution, as long as it crashes the same way.
+///
+/// An input counts as the same crash if it results in the same [`ExitKind`] and the same hash of the given
+/// [`ObserverWithHashField`], e.g., a backtrace observer.
+/// The minimized input is added to the solutions with a [`MinimizedFromMetadata`], while the original gets a
+/// [`MinimizedToMetadata`]. The minimized solution keeps the metadata of the original, including the crash bucket.
+///
+/// The candidates are executed without evaluating the feedbacks or objectives, so they never end up in the corpus.
+/// Since every candidate crashes, this stage is best used with executors that survive crashes,
+/// like the forkserver or command executors.
+/// Timeouts are not minimized by default, since every attempt costs a full timeout.
+#[derive(Debug, Clone)]
+pub struct CrashMinimizationStage<C, E, EM, I, M, S, Z> {
+    name: Cow<'static, str>,
+    mutator: M,
+    observer_handle: Handle<C>,
+    runs: usize,
+    minimize_timeouts: bool,
+    phantom: PhantomData<(E, EM, I, S, Z)>,
+}
+
+impl<C, E, EM, I, M, S, Z> Named for CrashMinimizationStage<C, E, EM, I, M, S, Z> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<C, E, EM, I, M, S, Z> Restartable<S> for CrashMinimizationStage<C, E, EM, I, M, S, Z> {
+    #[inline]
+    fn should_restart(&mut self, _state: &mut S) -> Result<bool, Error> {
+        // The progress is stored before each solution is minimized, a crashing solution won't be retried.
+        Ok(true)
+    }
+
+    #[inline]
+    fn clear_progress(&mut self, _state: &mut S) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<C, E, EM, I, M, S, Z> Stage<E, EM, S, Z> for CrashMinimizationStage<C, E, EM, I, M, S, Z>
+where
+    C: ObserverWithHashField + Named,
+    E: Executor<EM, I, S, Z> + HasObservers,
+    E::Observers: ObserversTuple<I, S>,
+    I: Input + HasLen,
+    M: Mutator<I, S>,
+    S: HasSolutions<I> + HasNamedMetadata + HasExecutions + HasMaxSize,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        let next_solution_id = state.solutions().peek_free_id().0;
+        let first_id = state
+            .named_metadata_or_insert_with(&self.name, CrashMinimizationMetadata::default)
+            .next_solution_id;
+
+        for id in first_id..next_solution_id {
+            // Store the progress first, so that a solution that takes us down is not minimized again
+            state
+                .named_metadata_mut::<CrashMinimizationMetadata>(&self.name)?
+                .next_solution_id = id + 1;
+            self.minimize_solution(fuzzer, executor, state, manager, CorpusId(id))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<C, E, EM, I, M, S, Z> CrashMinimizationStage<C, E, EM, I, M, S, Z>
+where
+    C: ObserverWithHashField + Named,
+    E: Executor<EM, I, S, Z> + HasObservers,
+    E::Observers: ObserversTuple<I, S>,
+    I: Input + HasLen,
+    M: Mutator<I, S>,
+    S: HasSolutions<I> + HasNamedMetadata + HasExecutions + HasMaxSize,
+{
+    /// Creates a new [`CrashMinimizationStage`], comparing the hash of the given observer (e.g., a backtrace observer)
+    /// and trying `runs` mutations in a row before giving up on a solution
+    #[must_use]
+    pub fn new(mutator: M, observer: &C, runs: usize) -> Self {
+        let obs_name = observer.name().clone().into_owned();
+        Self {
+            name: Cow::Owned(CRASH_MIN_STAGE_NAME.to_owned() + ":" + obs_name.as_str()),
+            mutator,
+            observer_handle: observer.handle(),
+            runs,
+            minimize_timeouts: false,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Also minimize solutions that time out
+    #[must_use]
+    pub fn minimize_timeouts(mut self, minimize_timeouts: bool) -> Self {
+        self.minimize_timeouts = minimize_timeouts;
+        self
+    }
+
+    fn minimize_solution(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+        id: CorpusId,
+    ) -> Result<(), Error> {
+        let (mut input, metadata, parent_id) = {
+            let Ok(testcase) = state.solutions().get(id) else {
+                // Removed or disabled in the meantime
+                return Ok(());
+            };
+            let mut testcase = testcase.borrow_mut();
+            if testcase.has_metadata::<MinimizedFromMetadata>()
+                || testcase.has_metadata::<MinimizedToMetadata>()
+            {
+                return Ok(());
+            }
+            let input = testcase.load_input(state.solutions())?.clone();
+            (input, testcase.metadata_map().clone(), testcase.parent_id())
+        };
+        let orig_len = input.len();
+
+        // Reproduce the crash first, flaky solutions can not be minimized
+        let (orig_exit_kind, orig_hash) = Self::run_and_hash(
+            &self.observer_handle,
+            fuzzer,
+            executor,
+            state,
+            manager,
+            &input,
+        )?;
+        let minimizable = match orig_exit_kind {
+            ExitKind::Crash | ExitKind::Oom => true,
+            ExitKind::Timeout => self.minimize_timeouts,
+            _ => false,
+        };
+        if !minimizable || orig_hash.is_none() {
+            return Ok(());
+        }
+
+        let minimized = minimize_input(
+            state,
+            &mut self.mutator,
+            &mut input,
+            self.runs,
+            |state, candidate| {
+                let (exit_kind, hash) = Self::run_and_hash(
+                    &self.observer_handle,
+                    fuzzer,
+                    executor,
+                    state,
+                    manager,
+                    candidate,
+                )?;
+                Ok(exit_kind == orig_exit_kind && hash == orig_hash)
+            },
+        )?;
+        if !minimized {
+            return Ok(());
+        }
+
+        let mut testcase = Testcase::from(input);
+        *testcase.metadata_map_mut() = metadata;
+        testcase.set_parent_id_optional(parent_id);
+        testcase.set_executions(*state.executions());
+        testcase.add_metadata(MinimizedFromMetadata {
+            original: id,
+            orig_len,
+        });
+        let minimized = state.solutions_mut().add(testcase)?;
+
+        state
+            .solutions()
+            .get(id)?
+            .borrow_mut()
+            .add_metadata(MinimizedToMetadata { minimized });
+
+        Ok(())
+    }
+
+    /// Runs the target without evaluating the input and returns the hash of the observer
+    fn run_and_hash(
+        observer_handle: &Handle<C>,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+        input: &I,
+    ) -> Result<(ExitKind, Option<u64>), Error> {
+        executor.observers_mut().pre_exec_all(state, input)?;
+        let exit_kind = executor.run_target(fuzzer, state, manager, input)?;
+        executor
+            .observers_mut()
+            .post_exec_all(state, input, &exit_kind)?;
+
+        let observers = executor.observers();
+        Ok((exit_kind, observers[observer_handle].hash()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use libafl_bolts::{HasLen, rands::StdRand};
+
+    use super::minimize_input;
+    use crate::{
+        corpus::InMemoryCorpus,
+        feedbacks::ConstFeedback,
+        inputs::{BytesInput, HasMutatorBytes},
+        mutators::BytesDeleteMutator,
+        state::StdState,
+    };
+
+    #[test]
+    fn test_minimize_input() {
+        let mut feedback = ConstFeedback::new(false);
+        let mut objective = ConstFeedback::new(false);
+        let mut state = StdState::new(
+            StdRand::with_seed(1337),
+            InMemoryCorpus::<BytesInput>::new(),
+            InMemoryCorpus::new(),
+            &mut feedback,
+            &mut objective,
+        )
+        .unwrap();
+        let mut mutator = BytesDeleteMutator::new();
+        let mut input = BytesInput::new(Vec::from(*b"xxxAxxxxxxBxxxxx"));
+
+        // The "crash" needs an `A` somewhere before a `B`
+        let minimized = minimize_input(&mut state, &mut mutator, &mut input, 1000, |_, c| {
+            let bytes = c.mutator_bytes();
+            let a = bytes.iter().position(|b| *b == b'A');
+            let b = bytes.iter().rposition(|b| *b == b'B');
+            Ok(matches!((a, b), (Some(a), Some(b)) if a < b))
+        })
+        .unwrap();
+
+        assert!(minimized);
+        assert_eq!(input.mutator_bytes(), b"AB");
+
+        // Already minimal
+        assert!(
+            !minimize_input(&mut state, &mut mutator, &mut input, 100, |_, c| Ok(c
+                .len()
+                == 2))
+            .unwrap()
+        );
+        assert_eq!(input.len(), 2);
+    }
+}
@@ -0,0 +1,577 @@
+//! The [`DeterministicStage`] walks each corpus entry once with the deterministic mutations of AFL++'s `-D` mode:
+//! walking bitflips, arithmetics, interesting values and dictionary overwrites.
+
+use alloc::{
+    borrow::{Cow, ToOwned},
+    vec,
+    vec::Vec,
+};
+use core::{fmt::Debug, hash::Hash, marker::PhantomData, ops::Range};
+
+use libafl_bolts::{
+    Named, generic_hash_std, impl_serdeany,
+    tuples::{Handle, Handled},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Error, HasMetadata,
+    corpus::HasCurrentCorpusId,
+    executors::HasObservers,
+    fuzzer::Evaluator,
+    inputs::HasMutatorBytes,
+    mutators::{
+        Tokens,
+        mutations::{ARITH_MAX, INTERESTING_8, INTERESTING_16, INTERESTING_32},
+    },
+    observers::ObserversTuple,
+    stages::{Restartable, Stage},
+    state::{HasCorpus, HasCurrentTestcase},
+};
+
+/// Default name for `DeterministicStage`; derived from AFL++
+pub const DETERMINISTIC_STAGE_NAME: &str = "deterministic";
+
+/// AFL++'s `EFF_MAX_PERC`: if more than this percentage of the input is effective, consider all of it.
+const EFF_MAX_PERC: usize = 90;
+
+/// The passes of the [`DeterministicStage`], in the order they are performed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeterministicPass {
+    /// Walking single bit flips
+    Flip1,
+    /// Walking two bits flips
+    Flip2,
+    /// Walking four bits flips
+    Flip4,
+    /// Walking byte flips, also used to compute the effector map
+    Flip8,
+    /// Walking word flips
+    Flip16,
+    /// Walking dword flips
+    Flip32,
+    /// Byte additions and subtractions up to [`ARITH_MAX`]
+    Arith8,
+    /// Word additions and subtractions up to [`ARITH_MAX`], both endians
+    Arith16,
+    /// Dword additions and subtractions up to [`ARITH_MAX`], both endians
+    Arith32,
+    /// Byte overwrites with [`INTERESTING_8`]
+    Interest8,
+    /// Word overwrites with [`INTERESTING_16`], both endians
+    Interest16,
+    /// Dword overwrites with [`INTERESTING_32`], both endians
+    Interest32,
+    /// Overwrites with the [`Tokens`] in the state
+    DictOverwrite,
+    /// All passes are done for this testcase
+    Done,
+}
+
+impl DeterministicPass {
+    /// The pass following this one
+    #[must_use]
+    pub fn next(self) -> Self {
+        match self {
+            Self::Flip1 => Self::Flip2,
+            Self::Flip2 => Self::Flip4,
+            Self::Flip4 => Self::Flip8,
+            Self::Flip8 => Self::Flip16,
+            Self::Flip16 => Self::Flip32,
+            Self::Flip32 => Self::Arith8,
+            Self::Arith8 => Self::Arith16,
+            Self::Arith16 => Self::Arith32,
+            Self::Arith32 => Self::Interest8,
+            Self::Interest8 => Self::Interest16,
+            Self::Interest16 => Self::Interest32,
+            Self::Interest32 => Self::DictOverwrite,
+            Self::DictOverwrite | Self::Done => Self::Done,
+        }
+    }
+
+    /// If this pass may skip bytes that are not set in the effector map
+    #[must_use]
+    pub fn uses_effector_map(self) -> bool {
+        !matches!(
+            self,
+            Self::Flip1 | Self::Flip2 | Self::Flip4 | Self::Flip8 | Self::Done
+        )
+    }
+
+    /// The number of steps of this pass for an input of length `len`
+    #[must_use]
+    pub fn steps(self, len: usize, tokens: &[Vec<u8>]) -> usize {
+        match self {
+            Self::Flip1 => len * 8,
+            Self::Flip2 => (len * 8).saturating_sub(1),
+            Self::Flip4 => (len * 8).saturating_sub(3),
+            Self::Flip8 => len,
+            Self::Flip16 => len.saturating_sub(1),
+            Self::Flip32 => len.saturating_sub(3),
+            Self::Arith8 => len * 2 * ARITH_MAX,
+            Self::Arith16 => len.saturating_sub(1) * 4 * ARITH_MAX,
+            Self::Arith32 => len.saturating_sub(3) * 4 * ARITH_MAX,
+            Self::Interest8 => len * INTERESTING_8.len(),
+            Self::Interest16 => len.saturating_sub(1) * 2 * INTERESTING_16.len(),
+            Self::Interest32 => len.saturating_sub(3) * 2 * INTERESTING_32.len(),
+            Self::DictOverwrite => len * tokens.len(),
+            Self::Done => 0,
+        }
+    }
+
+    /// Applies the mutation of `step` to `bytes`.
+    ///
+    /// Returns the range of bytes that was changed, or `None` if this step is redundant
+    /// (it would not change the input or it repeats a mutation of an earlier pass).
+    #[must_use]
+    pub fn apply(self, step: usize, bytes: &mut [u8], tokens: &[Vec<u8>]) -> Option<Range<usize>> {
+        match self {
+            Self::Flip1 => Some(flip_bits(bytes, step, 1)),
+            Self::Flip2 => Some(flip_bits(bytes, step, 2)),
+            Self::Flip4 => Some(flip_bits(bytes, step, 4)),
+            Self::Flip8 => {
+                bytes[step] ^= 0xff;
+                Some(step..step + 1)
+            }
+            Self::Flip16 => {
+                bytes[step..step + 2].iter_mut().for_each(|b| *b ^= 0xff);
+                Some(step..step + 2)
+            }
+            Self::Flip32 => {
+                bytes[step..step + 4].iter_mut().for_each(|b| *b ^= 0xff);
+                Some(step..step + 4)
+            }
+            Self::Arith8 => arith(bytes, step, 1),
+            Self::Arith16 => arith(bytes, step, 2),
+            Self::Arith32 => arith(bytes, step, 4),
+            Self::Interest8 => {
+                let pos = step / INTERESTING_8.len();
+                #[expect(clippy::cast_sign_loss)]
+                let val = INTERESTING_8[step % INTERESTING_8.len()] as u8;
+                let orig = bytes[pos];
+                if could_be_bitflip(u64::from(orig ^ val))
+                    || orig.abs_diff(val) as usize <= ARITH_MAX
+                {
+                    return None;
+                }
+                bytes[pos] = val;
+                Some(pos..pos + 1)
+            }
+            Self::Interest16 => {
+                let per_pos = 2 * INTERESTING_16.len();
+                let (pos, variant) = (step / per_pos, step % per_pos);
+                #[expect(clippy::cast_sign_loss)]
+                let val = INTERESTING_16[variant / 2] as u16;
+                let new = if variant % 2 == 0 {
+                    val.to_le_bytes()
+                } else {
+                    val.to_be_bytes()
+                };
+                overwrite(bytes, pos, &new)
+            }
+            Self::Interest32 => {
+                let per_pos = 2 * INTERESTING_32.len();
+                let (pos, variant) = (step / per_pos, step % per_pos);
+                #[expect(clippy::cast_sign_loss)]
+                let val = INTERESTING_32[variant / 2] as u32;
+                let new = if variant % 2 == 0 {
+                    val.to_le_bytes()
+                } else {
+                    val.to_be_bytes()
+                };
+                overwrite(bytes, pos, &new)
+            }
+            Self::DictOverwrite => {
+                // Token-major, so tokens added while we walk this testcase only append steps.
+                let token = &tokens[step / bytes.len()];
+                let pos = step % bytes.len();
+                if token.is_empty() || pos + token.len() > bytes.len() {
+                    return None;
+                }
+                overwrite(bytes, pos, token)
+            }
+            Self::Done => None,
+        }
+    }
+}
+
+/// AFL++'s `could_be_bitflip`: whether the given xor of old and new value is reachable through the walking bitflips.
+#[must_use]
+pub fn could_be_bitflip(mut xor_val: u64) -> bool {
+    if xor_val == 0 {
+        return true;
+    }
+    let sh = xor_val.trailing_zeros();
+    xor_val >>= sh;
+
+    // 1-, 2-, and 4-bit patterns are OK anywhere.
+    if xor_val == 1 || xor_val == 3 || xor_val == 15 {
+        return true;
+    }
+    // 8-, 16-, and 32-bit patterns are OK only if the shift factor is divisible by 8, since that's the stepover for these ops.
+    if sh & 7 != 0 {
+        return false;
+    }
+    xor_val == 0xff || xor_val == 0xffff || xor_val == 0xffff_ffff
+}
+
+fn flip_bits(bytes: &mut [u8], bit: usize, count: usize) -> Range<usize> {
+    for b in bit..bit + count {
+        bytes[b >> 3] ^= 128 >> (b & 7);
+    }
+    (bit >> 3)..((bit + count - 1) >> 3) + 1
+}
+
+fn overwrite(bytes: &mut [u8], pos: usize, new: &[u8]) -> Option<Range<usize>> {
+    let range = pos..pos + new.len();
+    let old = &mut bytes[range.clone()];
+    if old == new {
+        return None;
+    }
+    old.copy_from_slice(new);
+    Some(range)
+}
+
+/// Adds or subtracts small values to `width` bytes, in little and big endian for `width > 1`.
+fn arith(bytes: &mut [u8], step: usize, width: usize) -> Option<Range<usize>> {
+    let variants = if width == 1 { 2 } else { 4 };
+    let per_pos = variants * ARITH_MAX;
+    let (pos, variant) = (step / per_pos, step % per_pos);
+    let delta = (variant / variants + 1) as u64;
+    let subtract = variant % 2 == 1;
+    let big_endian = variant % 4 >= 2;
+
+    let range = pos..pos + width;
+    let mut buf = [0_u8; 8];
+    buf[..width].copy_from_slice(&bytes[range.clone()]);
+    if big_endian {
+        buf[..width].reverse();
+    }
+    let orig = u64::from_le_bytes(buf);
+    let mask = u64::MAX >> (64 - 8 * width);
+    let new = if subtract {
+        orig.wrapping_sub(delta)
+    } else {
+        orig.wrapping_add(delta)
+    } & mask;
+
+    // Skip values a smaller arith already produced, i.e. when no carry leaves the lower half.
+    if width > 1 && (orig ^ new) >> (4 * width) == 0 {
+        return None;
+    }
+    let mut new_bytes = new.to_le_bytes();
+    if big_endian {
+        new_bytes[..width].reverse();
+    }
+    let xor = (0..width).fold(0_u64, |acc, i| {
+        acc | (u64::from(bytes[pos + i] ^ new_bytes[i]) << (8 * i))
+    });
+    if could_be_bitflip(xor) {
+        return None;
+    }
+    bytes[range.clone()].copy_from_slice(&new_bytes[..width]);
+    Some(range)
+}
+
+/// The progress of the [`DeterministicStage`] for a testcase, so that it resumes after a restart.
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeterministicStageMetadata {
+    pass: DeterministicPass,
+    step: usize,
+    orig_hash: Option<u64>,
+    /// Which bytes changed the coverage when flipped, filled during [`DeterministicPass::Flip8`]
+    effector_map: Vec<bool>,
+}
+
+impl_serdeany!(DeterministicStageMetadata);
+
+impl DeterministicStageMetadata {
+    /// Create a new [`struct@DeterministicStageMetadata`] for an input of length `len`
+    #[must_use]
+    pub fn new(len: usize) -> Self {
+        let mut effector_map = vec![false; len];
+        // Like AFL++, always fuzz the first and the last byte
+        if let Some(first) = effector_map.first_mut() {
+            *first = true;
+        }
+        if let Some(last) = effector_map.last_mut() {
+            *last = true;
+        }
+        Self {
+            pass: DeterministicPass::Flip1,
+            step: 0,
+            orig_hash: None,
+            effector_map,
+        }
+    }
+
+    /// The pass this testcase is currently in
+    #[must_use]
+    pub fn pass(&self) -> DeterministicPass {
+        self.pass
+    }
+
+    /// The next step to perform in the current pass
+    #[must_use]
+    pub fn step(&self) -> usize {
+        self.step
+    }
+
+    /// If all deterministic passes were performed for this testcase
+    #[must_use]
+    pub fn is_done(&self) -> bool {
+        self.pass == DeterministicPass::Done
+    }
+
+    /// The effector map, meaningful once [`DeterministicPass::Flip8`] is completed
+    #[must_use]
+    pub fn effector_map(&self) -> &[bool] {
+        &self.effector_map
+    }
+
+    /// If any byte in `range` was found to be effective
+    #[must_use]
+    pub fn is_effective(&self, range: Range<usize>) -> bool {
+        self.effector_map
+            .get(range)
+            .is_none_or(|bytes| bytes.iter().any(|&b| b))
+    }
+}
+
+/// A stage performing AFL++'s deterministic mutations once per corpus entry.
+///
+/// The progress is stored in the [`DeterministicStageMetadata`] of the testcase before each execution,
+/// hence after a crash or timeout the stage resumes right after the input that killed the target.
+#[derive(Debug, Clone)]
+pub struct DeterministicStage<C, E, EM, I, O, S, Z> {
+    name: Cow<'static, str>,
+    map_observer_handle: Handle<C>,
+    use_effector_map: bool,
+    phantom: PhantomData<(E, EM, I, O, S, Z)>,
+}
+
+impl<C, E, EM, I, O, S, Z> Named for DeterministicStage<C, E, EM, I, O, S, Z> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<C, E, EM, I, O, S, Z> Restartable<S> for DeterministicStage<C, E, EM, I, O, S, Z> {
+    #[inline]
+    fn should_restart(&mut self, _state: &mut S) -> Result<bool, Error> {
+        // The progress lives in the testcase and always moves past the input that killed us.
+        Ok(true)
+    }
+
+    #[inline]
+    fn clear_progress(&mut self, _state: &mut S) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<C, E, EM, I, O, S, Z> Stage<E, EM, S, Z> for DeterministicStage<C, E, EM, I, O, S, Z>
+where
+    C: AsRef<O> + Named,
+    O: Hash,
+    E: HasObservers,
+    E::Observers: ObserversTuple<I, S>,
+    I: HasMutatorBytes + Clone,
+    S: HasCorpus<I> + HasMetadata + HasCurrentTestcase<I> + HasCurrentCorpusId,
+    Z: Evaluator<E, EM, I, S>,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        let mut input = state.current_input_cloned()?;
+        let len = input.mutator_bytes().len();
+        let (mut pass, mut step, orig_hash) = {
+            let mut testcase = state.current_testcase_mut()?;
+            let meta = testcase.metadata_or_insert_with(|| DeterministicStageMetadata::new(len));
+            if meta.effector_map.len() != len {
+                // The input changed since the progress was stored, e.g., it was trimmed: start over
+                *meta = DeterministicStageMetadata::new(len);
+            }
+            if meta.is_done() {
+                return Ok(());
+            }
+            (meta.pass, meta.step, meta.orig_hash)
+        };
+
+        if len == 0 {
+            state
+                .current_testcase_mut()?
+                .metadata_mut::<DeterministicStageMetadata>()?
+                .pass = DeterministicPass::Done;
+            return Ok(());
+        }
+
+        let tokens = state
+            .metadata::<Tokens>()
+            .map(|t| t.tokens().to_vec())
+            .unwrap_or_default();
+        let orig = input.clone();
+
+        let orig_hash = if let Some(hash) = orig_hash {
+            hash
+        } else {
+            let hash = self.run_and_hash(fuzzer, executor, state, manager, &input)?;
+            state
+                .current_testcase_mut()?
+                .metadata_mut::<DeterministicStageMetadata>()?
+                .orig_hash = Some(hash);
+            hash
+        };
+
+        while pass != DeterministicPass::Done {
+            let steps = pass.steps(len, &tokens);
+            while step < steps {
+                let Some(range) = pass.apply(step, input.mutator_bytes_mut(), &tokens) else {
+                    step += 1;
+                    continue;
+                };
+
+                let skip = self.use_effector_map
+                    && pass.uses_effector_map()
+                    && !state
+                        .current_testcase()?
+                        .metadata::<DeterministicStageMetadata>()?
+                        .is_effective(range.clone());
+
+                if !skip {
+                    // Store the progress first, so that a restart continues after this input
+                    Self::store_progress(state, pass, step + 1)?;
+
+                    let hash = self.run_and_hash(fuzzer, executor, state, manager, &input)?;
+                    if pass == DeterministicPass::Flip8 && hash != orig_hash {
+                        state
+                            .current_testcase_mut()?
+                            .metadata_mut::<DeterministicStageMetadata>()?
+                            .effector_map[step] = true;
+                    }
+                }
+
+                input.mutator_bytes_mut()[range.clone()]
+                    .copy_from_slice(&orig.mutator_bytes()[range]);
+                step += 1;
+            }
+
+            if pass == DeterministicPass::Flip8 {
+                let mut testcase = state.current_testcase_mut()?;
+                let meta = testcase.metadata_mut::<DeterministicStageMetadata>()?;
+                let effective = meta.effector_map.iter().filter(|&&b| b).count();
+                // If the map is too dense, there is nothing to gain from it
+                if effective * 100 > len * EFF_MAX_PERC {
+                    meta.effector_map.fill(true);
+                }
+            }
+
+            pass = pass.next();
+            step = 0;
+            Self::store_progress(state, pass, step)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<C, E, EM, I, O, S, Z> DeterministicStage<C, E, EM, I, O, S, Z>
+where
+    C: AsRef<O> + Named,
+    O: Hash,
+    E: HasObservers,
+    E::Observers: ObserversTuple<I, S>,
+    I: HasMutatorBytes + Clone,
+    S: HasCorpus<I> + HasMetadata + HasCurrentTestcase<I> + HasCurrentCorpusId,
+    Z: Evaluator<E, EM, I, S>,
+{
+    /// Creates a new [`DeterministicStage`] using the given map observer for the effector map
+    #[must_use]
+    pub fn new(map_observer: &C) -> Self {
+        let obs_name = map_observer.name().clone().into_owned();
+        Self {
+            name: Cow::Owned(DETERMINISTIC_STAGE_NAME.to_owned() + ":" + obs_name.as_str()),
+            map_observer_handle: map_observer.handle(),
+            use_effector_map: true,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Enable or disable skipping of bytes that did not change the coverage in the byte flip pass
+    #[must_use]
+    pub fn effector_map(mut self, use_effector_map: bool) -> Self {
+        self.use_effector_map = use_effector_map;
+        self
+    }
+
+    fn store_progress(state: &mut S, pass: DeterministicPass, step: usize) -> Result<(), Error> {
+        let mut testcase = state.current_testcase_mut()?;
+        let meta = testcase.metadata_mut::<DeterministicStageMetadata>()?;
+        meta.pass = pass;
+        meta.step = step;
+        Ok(())
+    }
+
+    /// Evaluates the input, potentially adding it to the corpus, and hashes the map afterwards
+    fn run_and_hash(
+        &self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+        input: &I,
+    ) -> Result<u64, Error> {
+        fuzzer.evaluate_input(state, executor, manager, input)?;
+        let observers = executor.observers();
+        Ok(generic_hash_std(
+            observers[&self.map_observer_handle].as_ref(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::{DeterministicPass, could_be_bitflip};
+
+    #[test]
+    fn test_could_be_bitflip() {
+        assert!(could_be_bitflip(0));
+        assert!(could_be_bitflip(0b1));
+        assert!(could_be_bitflip(0b1100));
+        assert!(could_be_bitflip(0xf0));
+        assert!(could_be_bitflip(0xff00));
+        assert!(could_be_bitflip(0xffff_ffff));
+        assert!(!could_be_bitflip(0b101));
+        assert!(!could_be_bitflip(0xff0));
+    }
+
+    #[test]
+    fn test_passes_restore() {
+        let orig = vec![0x41_u8, 0x00, 0xff, 0x10, 0x7f];
+        let tokens = vec![b"ab".to_vec(), b"toolong".to_vec()];
+        let mut pass = DeterministicPass::Flip1;
+        while pass != DeterministicPass::Done {
+            for step in 0..pass.steps(orig.len(), &tokens) {
+                let mut bytes = orig.clone();
+                match pass.apply(step, &mut bytes, &tokens) {
+                    Some(range) => {
+                        assert_ne!(bytes, orig, "{pass:?} step {step} did not mutate");
+                        assert_eq!(bytes[..range.start], orig[..range.start]);
+                        assert_eq!(bytes[range.end..], orig[range.end..]);
+                    }
+                    None => assert_eq!(bytes, orig, "{pass:?} step {step} mutated but was skipped"),
+                }
+            }
+            pass = pass.next();
+        }
+    }
+}
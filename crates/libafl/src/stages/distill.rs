@@ -0,0 +1,364 @@
+//! The [`CorpusDistillationStage`] periodically disables corpus entries that add no coverage,
+//! like an online `afl-cmin` that does not need z3 or a separate run.
+
+use alloc::{borrow::Cow, collections::BinaryHeap, vec::Vec};
+use core::{cmp::Reverse, marker::PhantomData, time::Duration};
+
+use hashbrown::{HashMap, HashSet};
+use libafl_bolts::{AsIter, HasRefCnt, Named, current_time, serdeany::SerdeAny};
+
+use crate::{
+    Error, HasMetadata, HasScheduler,
+    corpus::{Corpus, CorpusId, EnableDisableCorpus, HasCurrentCorpusId},
+    feedbacks::MapIndexesMetadata,
+    schedulers::{RemovableScheduler, minimizer::TopRatedsMetadata},
+    stages::{Restartable, Stage},
+    state::HasCorpus,
+};
+
+/// Default name for `CorpusDistillationStage`
+pub const DISTILL_STAGE_NAME: &str = "distill";
+
+/// The default time between two distillations
+pub const DEFAULT_DISTILL_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// Picks a subset of `entries` covering all of their indexes, using the greedy set cover heuristic.
+///
+/// The entries in `keep` are always part of the result, ties go to the lower [`CorpusId`].
+/// Returns the picked entries and, for each index, the picked entry that covers it.
+fn greedy_cover(
+    entries: &[(CorpusId, Vec<usize>)],
+    keep: &[CorpusId],
+) -> (HashSet<CorpusId>, HashMap<usize, CorpusId>) {
+    let mut picked = HashSet::new();
+    let mut cover = HashMap::new();
+
+    let mut pick = |id: CorpusId, indexes: &[usize], cover: &mut HashMap<usize, CorpusId>| {
+        picked.insert(id);
+        for idx in indexes {
+            cover.entry(*idx).or_insert(id);
+        }
+    };
+
+    for (id, indexes) in entries {
+        if keep.contains(id) {
+            pick(*id, indexes, &mut cover);
+        }
+    }
+
+    // Lazy greedy: the gain of an entry can only shrink, so it only needs to be recomputed when it is on top
+    let mut heap = entries
+        .iter()
+        .enumerate()
+        .filter(|(_, (id, _))| !keep.contains(id))
+        .map(|(i, (id, indexes))| (indexes.len(), Reverse(*id), i))
+        .collect::<BinaryHeap<_>>();
+    while let Some((gain, id, i)) = heap.pop() {
+        let indexes = &entries[i].1;
+        let new_gain = indexes
+            .iter()
+            .filter(|idx| !cover.contains_key(*idx))
+            .count();
+        if new_gain == 0 {
+            continue;
+        }
+        if new_gain < gain {
+            heap.push((new_gain, id, i));
+            continue;
+        }
+        pick(id.0, indexes, &mut cover);
+    }
+
+    (picked, cover)
+}
+
+/// A stage that periodically disables redundant corpus entries, to keep the queue of long campaigns lean.
+///
+/// The coverage of each entry is taken from its `M` metadata, [`MapIndexesMetadata`] by default,
+/// and a subset of entries covering the same indexes is picked with the greedy set cover heuristic.
+/// All other entries are disabled through [`EnableDisableCorpus`], so they can still be enabled again later.
+/// The [`TopRatedsMetadata`] of a minimizer scheduler is updated to only point to the remaining entries,
+/// and the scheduler is notified of every disabled entry.
+///
+/// Entries without the metadata are considered redundant: the minimizer schedulers only remove it
+/// from entries that are not top rated for any index. If no entry has it, nothing is disabled.
+#[derive(Debug, Clone)]
+pub struct CorpusDistillationStage<I, M> {
+    name: Cow<'static, str>,
+    interval: Duration,
+    min_corpus_size: usize,
+    last_run: Duration,
+    phantom: PhantomData<(I, M)>,
+}
+
+/// A [`CorpusDistillationStage`] using the [`MapIndexesMetadata`] of the entries
+pub type IndexesDistillationStage<I> = CorpusDistillationStage<I, MapIndexesMetadata>;
+
+impl<I, M> Named for CorpusDistillationStage<I, M> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<I, M, S> Restartable<S> for CorpusDistillationStage<I, M> {
+    #[inline]
+    fn should_restart(&mut self, _state: &mut S) -> Result<bool, Error> {
+        // Distilling does not execute the target
+        Ok(true)
+    }
+
+    #[inline]
+    fn clear_progress(&mut self, _state: &mut S) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<E, EM, I, M, S, Z> Stage<E, EM, S, Z> for CorpusDistillationStage<I, M>
+where
+    M: for<'a> AsIter<'a, Item = usize> + SerdeAny + HasRefCnt,
+    S: HasCorpus<I> + HasMetadata + HasCurrentCorpusId,
+    S::Corpus: EnableDisableCorpus,
+    Z: HasScheduler<I, S>,
+    Z::Scheduler: RemovableScheduler<I, S>,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        _executor: &mut E,
+        state: &mut S,
+        _manager: &mut EM,
+    ) -> Result<(), Error> {
+        let now = current_time();
+        if now.saturating_sub(self.last_run) < self.interval
+            || state.corpus().count() < self.min_corpus_size
+        {
+            return Ok(());
+        }
+        self.last_run = now;
+
+        let disabled = self.distill(fuzzer, state)?;
+        log::info!(
+            "Distilled corpus: disabled {disabled} entries, {} left",
+            state.corpus().count()
+        );
+        Ok(())
+    }
+}
+
+impl<I, M> CorpusDistillationStage<I, M>
+where
+    M: for<'a> AsIter<'a, Item = usize> + SerdeAny + HasRefCnt,
+{
+    /// Creates a new [`CorpusDistillationStage`], distilling every [`DEFAULT_DISTILL_INTERVAL`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            name: Cow::Borrowed(DISTILL_STAGE_NAME),
+            interval: DEFAULT_DISTILL_INTERVAL,
+            min_corpus_size: 0,
+            last_run: current_time(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Sets the minimum time between two distillations
+    #[must_use]
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Only distill once the corpus holds at least this many enabled entries
+    #[must_use]
+    pub fn min_corpus_size(mut self, min_corpus_size: usize) -> Self {
+        self.min_corpus_size = min_corpus_size;
+        self
+    }
+
+    /// Disables all redundant entries right away and returns how many were disabled.
+    ///
+    /// The entry currently being fuzzed, if any, is always kept.
+    pub fn distill<S, Z>(&self, fuzzer: &mut Z, state: &mut S) -> Result<usize, Error>
+    where
+        S: HasCorpus<I> + HasMetadata + HasCurrentCorpusId,
+        S::Corpus: EnableDisableCorpus,
+        Z: HasScheduler<I, S>,
+        Z::Scheduler: RemovableScheduler<I, S>,
+    {
+        let keep = state.current_corpus_id()?.into_iter().collect::<Vec<_>>();
+
+        let mut entries = Vec::with_capacity(state.corpus().count());
+        let mut redundant = Vec::new();
+        for id in state.corpus().ids() {
+            let testcase = state.corpus().get(id)?.borrow();
+            match testcase.metadata_map().get::<M>() {
+                Some(meta) => entries.push((id, meta.as_iter().map(|idx| *idx).collect())),
+                None if !keep.contains(&id) => redundant.push(id),
+                None => {}
+            }
+        }
+
+        if entries.is_empty() {
+            // Nothing tracks the coverage of the entries, there is nothing to distill
+            return Ok(0);
+        }
+
+        let (picked, cover) = greedy_cover(&entries, &keep);
+        redundant.extend(
+            entries
+                .iter()
+                .map(|(id, _)| *id)
+                .filter(|id| !picked.contains(id)),
+        );
+        if redundant.is_empty() {
+            return Ok(0);
+        }
+
+        // Point the top rateds to the remaining entries, before disabling anything
+        if let Some(top_rateds) = state.metadata_map_mut().get_mut::<TopRatedsMetadata>() {
+            top_rateds.map.retain(|idx, id| {
+                if !picked.contains(id) {
+                    match cover.get(idx) {
+                        Some(new_id) => *id = *new_id,
+                        None => return false,
+                    }
+                }
+                true
+            });
+
+            let mut refcnts = HashMap::<CorpusId, isize>::new();
+            for id in top_rateds.map.values() {
+                *refcnts.entry(*id).or_default() += 1;
+            }
+            for (id, refcnt) in refcnts {
+                let mut testcase = state.corpus().get(id)?.borrow_mut();
+                if let Some(meta) = testcase.metadata_map_mut().get_mut::<M>() {
+                    *meta.refcnt_mut() = refcnt;
+                }
+            }
+        }
+
+        // No top rated points to the redundant entries anymore, so the minimizer schedulers skip re-rating the corpus
+        for id in &redundant {
+            state.corpus_mut().disable(*id)?;
+            fuzzer.scheduler_mut().on_remove(state, *id, &None)?;
+        }
+
+        Ok(redundant.len())
+    }
+}
+
+impl<I, M> Default for CorpusDistillationStage<I, M>
+where
+    M: for<'a> AsIter<'a, Item = usize> + SerdeAny + HasRefCnt,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{vec, vec::Vec};
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use libafl_bolts::rands::StdRand;
+
+    use super::{IndexesDistillationStage, greedy_cover};
+    use crate::{
+        Error, HasMetadata, StdFuzzer,
+        corpus::{Corpus, CorpusId, InMemoryCorpus, Testcase},
+        feedbacks::{ConstFeedback, MapIndexesMetadata},
+        inputs::BytesInput,
+        observers::{CanTrack, StdMapObserver},
+        schedulers::{
+            MinimizerScheduler, QueueScheduler, Scheduler, TestcaseScore,
+            minimizer::TopRatedsMetadata,
+        },
+        state::{HasCorpus, StdState},
+    };
+
+    static SCORE_COMPUTATIONS: AtomicUsize = AtomicUsize::new(0);
+
+    /// Scores all entries the same, counting how often it was asked to
+    struct CountingScore;
+
+    impl<I, S> TestcaseScore<I, S> for CountingScore {
+        fn compute(_state: &S, _entry: &mut Testcase<I>) -> Result<f64, Error> {
+            SCORE_COMPUTATIONS.fetch_add(1, Ordering::Relaxed);
+            Ok(1.0)
+        }
+    }
+
+    #[test]
+    fn test_distill() {
+        let observer = StdMapObserver::owned("map", vec![0_u8; 8]).track_indices();
+        let mut scheduler =
+            MinimizerScheduler::<_, CountingScore, BytesInput, MapIndexesMetadata, _>::new(
+                &observer,
+                QueueScheduler::new(),
+            );
+        let mut feedback = ConstFeedback::new(false);
+        let mut objective = ConstFeedback::new(false);
+        let mut state = StdState::new(
+            StdRand::with_seed(0),
+            InMemoryCorpus::new(),
+            InMemoryCorpus::new(),
+            &mut feedback,
+            &mut objective,
+        )
+        .unwrap();
+        for (i, indexes) in [vec![0, 1], vec![0, 1, 2, 3], vec![2, 3], vec![4]]
+            .into_iter()
+            .enumerate()
+        {
+            let mut testcase = Testcase::new(BytesInput::new(vec![i as u8]));
+            testcase.add_metadata(MapIndexesMetadata::new(indexes));
+            let id = state.corpus_mut().add(testcase).unwrap();
+            scheduler.on_add(&mut state, id).unwrap();
+        }
+        let mut fuzzer = StdFuzzer::new(scheduler, feedback, objective);
+
+        SCORE_COMPUTATIONS.store(0, Ordering::Relaxed);
+        let disabled = IndexesDistillationStage::new()
+            .distill(&mut fuzzer, &mut state)
+            .unwrap();
+        assert_eq!(disabled, 2);
+        assert_eq!(state.corpus().count(), 2);
+        let top_rateds = state.metadata::<TopRatedsMetadata>().unwrap();
+        assert!(
+            top_rateds
+                .map()
+                .values()
+                .all(|id| [CorpusId(1), CorpusId(3)].contains(id))
+        );
+        // The top rateds are re-pointed by the stage, the scheduler does not need to re-rate the corpus
+        assert_eq!(SCORE_COMPUTATIONS.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_greedy_cover() {
+        let entries = vec![
+            (CorpusId(0), vec![1, 2]),
+            (CorpusId(1), vec![1, 2, 3, 4]),
+            (CorpusId(2), vec![3, 4]),
+            (CorpusId(3), vec![5]),
+            (CorpusId(4), vec![5]),
+        ];
+
+        let (picked, cover) = greedy_cover(&entries, &[]);
+        let mut picked = picked.into_iter().collect::<Vec<_>>();
+        picked.sort_unstable();
+        assert_eq!(picked, [CorpusId(1), CorpusId(3)]);
+        assert_eq!(cover.len(), 5);
+        assert_eq!(cover[&1], CorpusId(1));
+        assert_eq!(cover[&5], CorpusId(3));
+
+        // The entry being fuzzed is never dropped, even if it is dominated
+        let (picked, cover) = greedy_cover(&entries, &[CorpusId(2)]);
+        assert_eq!(picked.len(), 3);
+        assert!(picked.contains(&CorpusId(2)));
+        assert_eq!(cover[&3], CorpusId(2));
+        assert_eq!(cover[&1], CorpusId(0));
+    }
+}
@@ -0,0 +1,351 @@
+//! The [`FieldInferenceStage`] groups the bytes of an input into fields, using the comparisons logged by cmplog
+//! and the ranges found by colorization.
+//!
+//! The fields end up in the [`InferredFieldsMetadata`] of the testcase, where the field-aware mutators in
+//! [`crate::mutators::fields`] pick them up.
+
+use alloc::{borrow::Cow, vec::Vec};
+use core::{marker::PhantomData, ops::Range};
+
+use hashbrown::HashSet;
+use libafl_bolts::{AsSlice, Named};
+
+use crate::{
+    Error, HasMetadata,
+    corpus::HasCurrentCorpusId,
+    inputs::HasMutatorBytes,
+    mutators::{FieldKind, InferredField, InferredFieldsMetadata},
+    observers::{AflppCmpValuesMetadata, CmpValues, CmpValuesMetadata},
+    stages::{Restartable, Stage, TaintMetadata},
+    state::{HasCorpus, HasCurrentTestcase},
+};
+
+/// Default name for `FieldInferenceStage`
+pub const FIELD_INFERENCE_STAGE_NAME: &str = "field_inference";
+
+/// The widths of the length fields searched for in the input
+const LENGTH_WIDTHS: [usize; 3] = [1, 2, 4];
+
+/// Returns the positions of all occurrences of `needle` in `haystack`
+pub(crate) fn find_all<'a>(
+    haystack: &'a [u8],
+    needle: &'a [u8],
+) -> impl Iterator<Item = usize> + 'a {
+    haystack
+        .windows(needle.len())
+        .enumerate()
+        .filter(move |(_, window)| *window == needle)
+        .map(|(pos, _)| pos)
+}
+
+/// Encodes the lowest `width` bytes of `value`
+pub(crate) fn encode(value: u64, width: usize, big_endian: bool) -> Vec<u8> {
+    if big_endian {
+        value.to_be_bytes()[8 - width..].to_vec()
+    } else {
+        value.to_le_bytes()[..width].to_vec()
+    }
+}
+
+/// The width of a numeric comparison in bytes
+pub(crate) fn cmp_width(cmp: &CmpValues) -> usize {
+    match cmp {
+        CmpValues::U8(_) => 1,
+        CmpValues::U16(_) => 2,
+        CmpValues::U32(_) => 4,
+        CmpValues::U64(_) => 8,
+        CmpValues::Bytes(_) => 0,
+    }
+}
+
+/// Collects the comparisons logged by the last cmplog tracing run
+pub(crate) fn logged_cmps<S: HasMetadata>(state: &S) -> Vec<CmpValues> {
+    let mut cmps = Vec::new();
+    if let Ok(meta) = state.metadata::<CmpValuesMetadata>() {
+        cmps.extend(meta.list.iter().cloned());
+    }
+    if let Ok(meta) = state.metadata::<AflppCmpValuesMetadata>() {
+        cmps.extend(meta.orig_cmpvals().values().flatten().cloned());
+    }
+    cmps
+}
+
+/// The order in which overlapping fields are kept, lower is kept first
+fn kind_priority(kind: &FieldKind) -> u8 {
+    match kind {
+        FieldKind::Magic => 0,
+        FieldKind::Checksum => 1,
+        FieldKind::Length { .. } => 2,
+        FieldKind::Offset => 3,
+    }
+}
+
+/// Infers the fields of `bytes` from the comparisons `cmps` the target made while processing it.
+///
+/// - operands compared against a constant are magic values
+/// - operands compared against the input length are offsets
+/// - large operands compared against a value not found in the input are checksums
+/// - numbers equal to the size of the data following them are lengths
+///
+/// Fields that lie completely in one of the `taint` ranges are dropped, since colorization showed that
+/// changing them does not change the behavior of the target.
+/// The returned fields do not overlap and are sorted by their start.
+#[must_use]
+pub fn infer_fields(
+    bytes: &[u8],
+    cmps: &[CmpValues],
+    taint: &[Range<usize>],
+) -> Vec<InferredField> {
+    let len = bytes.len();
+    let mut candidates = Vec::new();
+    let mut numbers = HashSet::new();
+
+    for cmp in cmps {
+        if let CmpValues::Bytes((a, b)) = cmp {
+            for operand in [a.as_slice(), b.as_slice()] {
+                if operand.len() < 2 {
+                    continue;
+                }
+                for pos in find_all(bytes, operand) {
+                    candidates.push(InferredField {
+                        range: pos..pos + operand.len(),
+                        kind: FieldKind::Magic,
+                        big_endian: false,
+                    });
+                }
+            }
+            continue;
+        }
+
+        let Some((v0, v1, v0_is_const)) = cmp.to_u64_tuple() else {
+            continue;
+        };
+        numbers.insert(v0);
+        numbers.insert(v1);
+
+        // Single bytes are found everywhere, only look for them as lengths below
+        let width = cmp_width(cmp);
+        if width < 2 {
+            continue;
+        }
+        for (value, other, value_is_const, other_is_const) in
+            [(v0, v1, v0_is_const, false), (v1, v0, false, v0_is_const)]
+        {
+            if value == 0 || value_is_const {
+                continue;
+            }
+            for big_endian in [false, true] {
+                let kind = if other == len as u64 && value < len as u64 {
+                    FieldKind::Offset
+                } else if other_is_const {
+                    FieldKind::Magic
+                } else if value > len as u64
+                    && find_all(bytes, &encode(other, width, big_endian))
+                        .next()
+                        .is_none()
+                {
+                    FieldKind::Checksum
+                } else {
+                    continue;
+                };
+                for pos in find_all(bytes, &encode(value, width, big_endian)) {
+                    candidates.push(InferredField {
+                        range: pos..pos + width,
+                        kind: kind.clone(),
+                        big_endian,
+                    });
+                }
+            }
+        }
+    }
+
+    for width in LENGTH_WIDTHS {
+        for big_endian in [false, true] {
+            if width == 1 && big_endian {
+                continue;
+            }
+            for pos in 0..=len.saturating_sub(width) {
+                let field = InferredField {
+                    range: pos..pos + width,
+                    kind: FieldKind::Offset,
+                    big_endian,
+                };
+                let Some(value) = field.read(bytes) else {
+                    continue;
+                };
+                let rest = (len - field.range.end) as u64;
+                let compared = numbers.contains(&value);
+                let governs = if value != 0 && value == rest && (width > 1 || compared) {
+                    field.range.end..len
+                } else if width > 1 && compared && value >= 4 && value < rest {
+                    field.range.end..field.range.end + value as usize
+                } else {
+                    continue;
+                };
+                candidates.push(InferredField {
+                    kind: FieldKind::Length { governs },
+                    ..field
+                });
+            }
+        }
+    }
+
+    candidates.retain(|field| {
+        !taint
+            .iter()
+            .any(|range| range.start <= field.range.start && field.range.end <= range.end)
+    });
+    // Stable, so that little endian and earlier candidates win
+    candidates.sort_by_key(|field| (kind_priority(&field.kind), usize::MAX - field.width()));
+
+    let mut fields: Vec<InferredField> = Vec::new();
+    for candidate in candidates {
+        if fields.iter().all(|field| {
+            field.range.end <= candidate.range.start || candidate.range.end <= field.range.start
+        }) {
+            fields.push(candidate);
+        }
+    }
+    fields.sort_by_key(|field| field.range.start);
+    fields
+}
+
+/// A stage that infers the fields of each corpus entry once, and stores them as [`InferredFieldsMetadata`].
+///
+/// It executes nothing itself, but uses the results of the stages before it:
+/// the [`CmpValuesMetadata`] or [`AflppCmpValuesMetadata`] of a cmplog tracing stage run on the current input,
+/// and the [`TaintMetadata`] of a [`crate::stages::ColorizationStage`], if present.
+#[derive(Debug, Clone)]
+pub struct FieldInferenceStage<I> {
+    name: Cow<'static, str>,
+    phantom: PhantomData<I>,
+}
+
+impl<I> Named for FieldInferenceStage<I> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<I, S> Restartable<S> for FieldInferenceStage<I> {
+    #[inline]
+    fn should_restart(&mut self, _state: &mut S) -> Result<bool, Error> {
+        // Inference does not execute the target
+        Ok(true)
+    }
+
+    #[inline]
+    fn clear_progress(&mut self, _state: &mut S) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<E, EM, I, S, Z> Stage<E, EM, S, Z> for FieldInferenceStage<I>
+where
+    I: HasMutatorBytes + Clone,
+    S: HasCorpus<I> + HasMetadata + HasCurrentCorpusId,
+{
+    fn perform(
+        &mut self,
+        _fuzzer: &mut Z,
+        _executor: &mut E,
+        state: &mut S,
+        _manager: &mut EM,
+    ) -> Result<(), Error> {
+        if state
+            .current_testcase()?
+            .has_metadata::<InferredFieldsMetadata>()
+        {
+            return Ok(());
+        }
+
+        let input = state.current_input_cloned()?;
+        let bytes = input.mutator_bytes();
+
+        let cmps = logged_cmps(state);
+        let taint = state
+            .metadata::<TaintMetadata>()
+            .ok()
+            .filter(|meta| meta.input_vec().len() == bytes.len())
+            .map(|meta| meta.ranges().clone())
+            .unwrap_or_default();
+
+        let fields = infer_fields(bytes, &cmps, &taint);
+        state
+            .current_testcase_mut()?
+            .add_metadata(InferredFieldsMetadata::new(fields));
+        Ok(())
+    }
+}
+
+impl<I> FieldInferenceStage<I> {
+    /// Creates a new [`FieldInferenceStage`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            name: Cow::Borrowed(FIELD_INFERENCE_STAGE_NAME),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<I> Default for FieldInferenceStage<I> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{vec, vec::Vec};
+
+    use super::infer_fields;
+    use crate::{
+        mutators::{FieldKind, InferredField},
+        observers::CmpValues,
+    };
+
+    #[test]
+    fn test_infer_fields() {
+        // "MAGI" magic, big endian checksum, little endian length of the rest, payload
+        let mut bytes = Vec::from(*b"MAGI");
+        bytes.extend_from_slice(&0xdead_beef_u32.to_be_bytes());
+        bytes.extend_from_slice(&5_u16.to_le_bytes());
+        bytes.extend_from_slice(b"hello");
+
+        let cmps = vec![
+            CmpValues::U32((
+                u32::from_le_bytes(*b"MAGI"),
+                u32::from_le_bytes(*b"MAGI"),
+                true,
+            )),
+            CmpValues::U32((0x1234_5678, 0xdead_beef, false)),
+        ];
+
+        let fields = infer_fields(&bytes, &cmps, &[]);
+        assert_eq!(
+            fields,
+            [
+                InferredField {
+                    range: 0..4,
+                    kind: FieldKind::Magic,
+                    big_endian: false,
+                },
+                InferredField {
+                    range: 4..8,
+                    kind: FieldKind::Checksum,
+                    big_endian: true,
+                },
+                InferredField {
+                    range: 8..10,
+                    kind: FieldKind::Length { governs: 10..15 },
+                    big_endian: false,
+                },
+            ]
+        );
+
+        // Colorization showed the checksum does not matter
+        let fields = infer_fields(&bytes, &cmps, core::slice::from_ref(&(3..9)));
+        assert!(fields.iter().all(|field| field.kind != FieldKind::Checksum));
+    }
+}
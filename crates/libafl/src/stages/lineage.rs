@@ -0,0 +1,486 @@
+//! The [`LineageStage`] records the mutation tree of a run: which corpus entry every new entry or solution was derived
+//! from, and which mutations (from the [`LogMutationMetadata`]) produced it.
+//!
+//! The graph lives in the [`LineageMetadata`] of the state, so it survives restarts, and can be exported to DOT or JSON.
+
+use alloc::{
+    borrow::Cow,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{fmt::Write, marker::PhantomData, time::Duration};
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
+
+use hashbrown::HashMap;
+use libafl_bolts::{Named, impl_serdeany};
+#[cfg(feature = "std")]
+use libafl_bolts::{current_time, fs::write_file_atomic};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Error, HasMetadata,
+    corpus::{Corpus, CorpusId, Testcase},
+    mutators::scheduled::LogMutationMetadata,
+    stages::{Restartable, Stage},
+    state::{HasCorpus, HasExecutions, HasSolutions},
+};
+
+/// Default name for `LineageStage`
+pub const LINEAGE_STAGE_NAME: &str = "lineage";
+
+/// Default interval between two exports of the lineage graph
+#[cfg(feature = "std")]
+pub const LINEAGE_EXPORT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// A node of the lineage graph: a corpus entry or a solution
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LineageNode {
+    /// The id of the testcase, in the corpus or in the solutions, depending on `is_solution`
+    pub id: CorpusId,
+    /// Whether this node is in the solutions
+    pub is_solution: bool,
+    /// The corpus entry this testcase was derived from, if any
+    pub parent_id: Option<CorpusId>,
+    /// The names of the mutations that produced this testcase from its parent, if logged
+    pub mutations: Vec<Cow<'static, str>>,
+    /// The names of the feedbacks that considered this testcase interesting
+    pub hit_feedbacks: Vec<Cow<'static, str>>,
+    /// The number of executions at the time this testcase was found
+    pub executions: u64,
+    /// The exec time of this testcase, if known when recorded
+    pub exec_time: Option<Duration>,
+}
+
+impl LineageNode {
+    fn new<I>(id: CorpusId, is_solution: bool, testcase: &Testcase<I>) -> Self {
+        let mutations = testcase
+            .metadata::<LogMutationMetadata>()
+            .map(|log| log.list.clone())
+            .unwrap_or_default();
+        #[cfg(feature = "track_hit_feedbacks")]
+        let hit_feedbacks = if is_solution {
+            testcase.hit_objectives().clone()
+        } else {
+            testcase.hit_feedbacks().clone()
+        };
+        #[cfg(not(feature = "track_hit_feedbacks"))]
+        let hit_feedbacks = Vec::new();
+
+        Self {
+            id,
+            is_solution,
+            parent_id: testcase.parent_id(),
+            mutations,
+            hit_feedbacks,
+            executions: *testcase.executions(),
+            exec_time: *testcase.exec_time(),
+        }
+    }
+
+    fn dot_name(&self) -> String {
+        if self.is_solution {
+            format!("s{}", self.id)
+        } else {
+            format!("c{}", self.id)
+        }
+    }
+}
+
+/// The lineage graph of a run, stored in the state's metadata by the [`LineageStage`]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LineageMetadata {
+    entries: HashMap<CorpusId, LineageNode>,
+    solutions: HashMap<CorpusId, LineageNode>,
+    children: HashMap<CorpusId, Vec<CorpusId>>,
+    next_entry_id: usize,
+    next_solution_id: usize,
+}
+
+impl_serdeany!(LineageMetadata);
+
+impl LineageMetadata {
+    /// Creates an empty [`LineageMetadata`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a corpus entry, or a solution if `is_solution` is set
+    pub fn record<I>(&mut self, id: CorpusId, is_solution: bool, testcase: &Testcase<I>) {
+        let node = LineageNode::new(id, is_solution, testcase);
+        if is_solution {
+            self.solutions.insert(id, node);
+        } else {
+            if let Some(parent_id) = node.parent_id {
+                self.children.entry(parent_id).or_default().push(id);
+            }
+            self.entries.insert(id, node);
+        }
+    }
+
+    /// The node of a corpus entry
+    #[must_use]
+    pub fn entry(&self, id: CorpusId) -> Option<&LineageNode> {
+        self.entries.get(&id)
+    }
+
+    /// The node of a solution
+    #[must_use]
+    pub fn solution(&self, id: CorpusId) -> Option<&LineageNode> {
+        self.solutions.get(&id)
+    }
+
+    /// The number of recorded corpus entries
+    #[must_use]
+    pub fn entries_len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// The number of recorded solutions
+    #[must_use]
+    pub fn solutions_len(&self) -> usize {
+        self.solutions.len()
+    }
+
+    /// The corpus entry the given entry was derived from
+    #[must_use]
+    pub fn parent(&self, id: CorpusId) -> Option<CorpusId> {
+        self.entries.get(&id).and_then(|node| node.parent_id)
+    }
+
+    /// The corpus entries derived from the given entry
+    #[must_use]
+    pub fn children(&self, id: CorpusId) -> &[CorpusId] {
+        self.children.get(&id).map_or(&[], Vec::as_slice)
+    }
+
+    /// The solutions derived from the given entry
+    pub fn solutions_of(&self, id: CorpusId) -> impl Iterator<Item = &LineageNode> {
+        self.solutions
+            .values()
+            .filter(move |node| node.parent_id == Some(id))
+    }
+
+    /// The path from the given entry up to its seed, starting with the parent of the entry
+    #[must_use]
+    pub fn ancestors(&self, id: CorpusId) -> Vec<CorpusId> {
+        let mut ancestors = Vec::new();
+        let mut cur = self.parent(id);
+        while let Some(parent_id) = cur {
+            // Replaced entries may point anywhere, don't loop forever
+            if parent_id == id || ancestors.contains(&parent_id) {
+                break;
+            }
+            ancestors.push(parent_id);
+            cur = self.parent(parent_id);
+        }
+        ancestors
+    }
+
+    /// The number of corpus entries transitively derived from the given entry
+    #[must_use]
+    pub fn descendants_len(&self, id: CorpusId) -> usize {
+        let mut count = 0;
+        let mut stack = self.children(id).to_vec();
+        while let Some(cur) = stack.pop() {
+            count += 1;
+            stack.extend_from_slice(self.children(cur));
+        }
+        count
+    }
+
+    /// The corpus entries without a known parent, i.e., the initial seeds and imported entries
+    pub fn roots(&self) -> impl Iterator<Item = CorpusId> + '_ {
+        self.entries
+            .values()
+            .filter(|node| node.parent_id.is_none())
+            .map(|node| node.id)
+    }
+
+    /// For each mutation, how many corpus entries and solutions it took part in producing
+    #[must_use]
+    pub fn mutation_stats(&self) -> HashMap<Cow<'static, str>, (usize, usize)> {
+        let mut stats = HashMap::<Cow<'static, str>, (usize, usize)>::new();
+        for node in self.entries.values().chain(self.solutions.values()) {
+            for (i, mutation) in node.mutations.iter().enumerate() {
+                // Stacked mutations may repeat, count each once per testcase
+                if node.mutations[..i].contains(mutation) {
+                    continue;
+                }
+                let entry = stats.entry(mutation.clone()).or_default();
+                if node.is_solution {
+                    entry.1 += 1;
+                } else {
+                    entry.0 += 1;
+                }
+            }
+        }
+        stats
+    }
+
+    /// All nodes, corpus entries first, sorted by id
+    #[must_use]
+    pub fn nodes(&self) -> Vec<&LineageNode> {
+        let mut nodes = self
+            .entries
+            .values()
+            .chain(self.solutions.values())
+            .collect::<Vec<_>>();
+        nodes.sort_by_key(|node| (node.is_solution, node.id));
+        nodes
+    }
+
+    /// Renders the graph in the DOT format of graphviz.
+    ///
+    /// Edges are labeled with the mutations that produced the child, solutions are drawn as red boxes.
+    #[must_use]
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph lineage {\n");
+        for node in self.nodes() {
+            let label = if node.is_solution {
+                format!("solution {}", node.id)
+            } else {
+                node.id.to_string()
+            };
+            let style = if node.is_solution {
+                ", shape=box, color=red"
+            } else {
+                ""
+            };
+            writeln!(dot, "  {} [label=\"{label}\"{style}];", node.dot_name()).unwrap();
+
+            if let Some(parent_id) = node.parent_id {
+                writeln!(
+                    dot,
+                    "  c{parent_id} -> {} [label=\"{}\"];",
+                    node.dot_name(),
+                    node.mutations.join(",")
+                )
+                .unwrap();
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Renders all nodes, each with its parent and mutations, as a JSON array
+    #[cfg(feature = "std")]
+    pub fn to_json(&self) -> Result<String, Error> {
+        serde_json::to_string_pretty(&self.nodes())
+            .map_err(|err| Error::serialize(format!("Failed to json-ify lineage: {err:?}")))
+    }
+}
+
+/// A stage that records new corpus entries and solutions in the [`LineageMetadata`] of the state.
+///
+/// Place it after the mutational stages, so that the [`LogMutationMetadata`] of a
+/// [`crate::mutators::LoggerScheduledMutator`] is already attached to the new entries.
+#[derive(Debug, Clone)]
+pub struct LineageStage<I> {
+    name: Cow<'static, str>,
+    #[cfg(feature = "std")]
+    dot_file: Option<PathBuf>,
+    #[cfg(feature = "std")]
+    json_file: Option<PathBuf>,
+    #[cfg(feature = "std")]
+    export_interval: Duration,
+    #[cfg(feature = "std")]
+    last_export: Duration,
+    /// If nodes were recorded since the last export
+    #[cfg(feature = "std")]
+    export_pending: bool,
+    phantom: PhantomData<I>,
+}
+
+impl<I> Named for LineageStage<I> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<I, S> Restartable<S> for LineageStage<I> {
+    #[inline]
+    fn should_restart(&mut self, _state: &mut S) -> Result<bool, Error> {
+        // Recording is idempotent
+        Ok(true)
+    }
+
+    #[inline]
+    fn clear_progress(&mut self, _state: &mut S) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<E, EM, I, S, Z> Stage<E, EM, S, Z> for LineageStage<I>
+where
+    S: HasCorpus<I> + HasSolutions<I> + HasExecutions + HasMetadata,
+{
+    fn perform(
+        &mut self,
+        _fuzzer: &mut Z,
+        _executor: &mut E,
+        state: &mut S,
+        _manager: &mut EM,
+    ) -> Result<(), Error> {
+        let mut lineage = state
+            .metadata_map_mut()
+            .remove::<LineageMetadata>()
+            .map_or_else(LineageMetadata::new, |meta| *meta);
+
+        let mut changed = false;
+        let next_entry_id = state.corpus().peek_free_id().0;
+        for id in lineage.next_entry_id..next_entry_id {
+            if let Ok(testcase) = state.corpus().get_from_all(id.into()) {
+                lineage.record(id.into(), false, &testcase.borrow());
+                changed = true;
+            }
+        }
+        lineage.next_entry_id = next_entry_id;
+
+        let next_solution_id = state.solutions().peek_free_id().0;
+        for id in lineage.next_solution_id..next_solution_id {
+            if let Ok(testcase) = state.solutions().get_from_all(id.into()) {
+                lineage.record(id.into(), true, &testcase.borrow());
+                changed = true;
+            }
+        }
+        lineage.next_solution_id = next_solution_id;
+
+        #[cfg(feature = "std")]
+        {
+            self.export_pending |= changed;
+            if self.export_pending {
+                let now = current_time();
+                if now.checked_sub(self.last_export).unwrap_or_default() >= self.export_interval {
+                    self.export(&lineage)?;
+                    self.last_export = now;
+                    self.export_pending = false;
+                }
+            }
+        }
+        #[cfg(not(feature = "std"))]
+        let _ = changed;
+
+        state.add_metadata(lineage);
+        Ok(())
+    }
+}
+
+impl<I> LineageStage<I> {
+    /// Creates a new [`LineageStage`]
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            name: Cow::Borrowed(LINEAGE_STAGE_NAME),
+            #[cfg(feature = "std")]
+            dot_file: None,
+            #[cfg(feature = "std")]
+            json_file: None,
+            #[cfg(feature = "std")]
+            export_interval: LINEAGE_EXPORT_INTERVAL,
+            #[cfg(feature = "std")]
+            last_export: Duration::ZERO,
+            #[cfg(feature = "std")]
+            export_pending: false,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Rewrites the graph in DOT format to the given file when new nodes were recorded, at most once per export interval
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn dot_file<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.dot_file = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Rewrites the graph in JSON format to the given file when new nodes were recorded, at most once per export interval
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn json_file<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.json_file = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Sets the minimum interval between two exports, [`LINEAGE_EXPORT_INTERVAL`] by default
+    #[cfg(feature = "std")]
+    #[must_use]
+    pub fn export_interval(mut self, interval: Duration) -> Self {
+        self.export_interval = interval;
+        self
+    }
+
+    #[cfg(feature = "std")]
+    fn export(&self, lineage: &LineageMetadata) -> Result<(), Error> {
+        if let Some(path) = &self.dot_file {
+            write_file_atomic(path, lineage.to_dot().as_bytes())?;
+        }
+        if let Some(path) = &self.json_file {
+            write_file_atomic(path, lineage.to_json()?.as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+impl<I> Default for LineageStage<I> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{borrow::Cow, vec, vec::Vec};
+
+    use super::LineageMetadata;
+    use crate::{
+        HasMetadata,
+        corpus::{CorpusId, Testcase},
+        inputs::BytesInput,
+        mutators::scheduled::LogMutationMetadata,
+    };
+
+    fn testcase(parent: Option<usize>, mutations: &[&'static str]) -> Testcase<BytesInput> {
+        let mut testcase = Testcase::new(BytesInput::new(vec![0]));
+        testcase.set_parent_id_optional(parent.map(CorpusId));
+        testcase.add_metadata(LogMutationMetadata::new(
+            mutations.iter().map(|m| Cow::Borrowed(*m)).collect(),
+        ));
+        testcase
+    }
+
+    #[test]
+    fn test_lineage_queries() {
+        let mut lineage = LineageMetadata::new();
+        lineage.record(CorpusId(0), false, &testcase(None, &[]));
+        lineage.record(CorpusId(1), false, &testcase(Some(0), &["BitFlipMutator"]));
+        lineage.record(
+            CorpusId(2),
+            false,
+            &testcase(
+                Some(1),
+                &["BitFlipMutator", "ByteIncMutator", "BitFlipMutator"],
+            ),
+        );
+        lineage.record(CorpusId(0), true, &testcase(Some(2), &["ByteIncMutator"]));
+
+        assert_eq!(lineage.children(CorpusId(0)), &[CorpusId(1)]);
+        assert_eq!(lineage.ancestors(CorpusId(2)), [CorpusId(1), CorpusId(0)]);
+        assert_eq!(lineage.descendants_len(CorpusId(0)), 2);
+        assert_eq!(lineage.roots().collect::<Vec<_>>(), [CorpusId(0)]);
+        assert_eq!(lineage.solutions_of(CorpusId(2)).count(), 1);
+
+        let stats = lineage.mutation_stats();
+        assert_eq!(stats[&Cow::Borrowed("BitFlipMutator")], (2, 0));
+        assert_eq!(stats[&Cow::Borrowed("ByteIncMutator")], (1, 1));
+
+        let dot = lineage.to_dot();
+        assert!(dot.contains("c1 -> c2 [label=\"BitFlipMutator,ByteIncMutator,BitFlipMutator\"];"));
+        assert!(dot.contains("c2 -> s0"));
+    }
+}
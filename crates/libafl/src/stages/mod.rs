@@ -12,25 +12,35 @@ use alloc::{
 };
 use core::{fmt, marker::PhantomData};
 
+pub use adaptive_power::AdaptivePowerStatsStage;
 #[cfg(feature = "std")]
 pub use afl_stats::{AflStatsStage, CalibrationTime, FuzzTime, SyncTime};
 pub use calibrate::CalibrationStage;
+pub use checksum::ChecksumDetectionStage;
 pub use colorization::*;
 #[cfg(all(feature = "std", unix))]
 pub use concolic::ConcolicTracingStage;
 #[cfg(all(feature = "std", feature = "concolic_mutation", unix))]
 pub use concolic::SimpleConcolicMutationalStage;
+pub use crash_min::{
+    CrashMinimizationMetadata, CrashMinimizationStage, MinimizedFromMetadata, MinimizedToMetadata,
+};
+pub use deterministic::{DeterministicPass, DeterministicStage, DeterministicStageMetadata};
+pub use distill::{CorpusDistillationStage, IndexesDistillationStage};
 #[cfg(feature = "std")]
 pub use dump::*;
+pub use field_inference::FieldInferenceStage;
 pub use generalization::GeneralizationStage;
 use hashbrown::HashSet;
 use libafl_bolts::{
     Named, impl_serdeany,
     tuples::{HasConstLen, IntoVec},
 };
+pub use lineage::*;
 pub use logics::*;
 pub use mutational::{MutationalStage, StdMutationalStage};
 pub use power::{PowerMutationalStage, StdPowerMutationalStage};
+pub use rare_branch::RareBranchMaskStage;
 use serde::{Deserialize, Serialize};
 #[cfg(feature = "std")]
 pub use sync::*;
@@ -38,6 +48,7 @@ pub use sync::*;
 pub use time_tracker::TimeTrackingStageWrapper;
 pub use tmin::{ObserverEqualityFactory, ObserverEqualityFeedback, StdTMinMutationalStage};
 pub use tracing::TracingStage;
+pub use trim::{TrimStage, TrimmedMetadata};
 pub use tuneable::*;
 use tuple_list::NonEmptyTuple;
 #[cfg(feature = "unicode")]
@@ -63,25 +74,34 @@ pub use shadow::*;
 pub mod replay;
 pub use replay::*;
 
+pub mod adaptive_power;
 #[cfg(feature = "std")]
 pub mod afl_stats;
 pub mod calibrate;
+pub mod checksum;
 pub mod colorization;
 #[cfg(all(feature = "std", unix))]
 pub mod concolic;
+pub mod crash_min;
+pub mod deterministic;
+pub mod distill;
 #[cfg(feature = "std")]
 pub mod dump;
 pub mod dynamic;
+pub mod field_inference;
 pub mod generalization;
 pub mod generation;
+pub mod lineage;
 pub mod logics;
 pub mod nop;
 pub mod power;
+pub mod rare_branch;
 #[cfg(feature = "std")]
 pub mod sync;
 #[cfg(feature = "std")]
 pub mod time_tracker;
 pub mod tracing;
+pub mod trim;
 pub mod tuneable;
 #[cfg(feature = "unicode")]
 pub mod unicode;
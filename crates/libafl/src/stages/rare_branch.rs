@@ -0,0 +1,312 @@
+//! The rare branch stage from `FairFuzz`: finds the rarest branch the current testcase hits, and the bytes
+//! of the testcase that can be overwritten while still hitting it.
+//!
+//! The result is the [`MutationMaskMetadata`] of the testcase, used by the [`crate::mutators::MaskedHavocScheduledMutator`].
+
+use alloc::{borrow::Cow, vec::Vec};
+use core::marker::PhantomData;
+
+use libafl_bolts::{
+    Named,
+    tuples::{Handle, Handled},
+};
+
+use crate::{
+    Error, HasMetadata, HasNamedMetadata,
+    corpus::HasCurrentCorpusId,
+    executors::{Executor, HasObservers},
+    feedbacks::BranchHitsMetadata,
+    fuzzer::ExecutionProcessor,
+    inputs::HasMutatorBytes,
+    mutators::MutationMaskMetadata,
+    observers::{MapObserver, ObserversTuple},
+    stages::{Restartable, RetryCountRestartHelper, Stage},
+    state::{HasCorpus, HasCurrentTestcase},
+};
+
+/// Default name for `RareBranchMaskStage`
+pub const RARE_BRANCH_MASK_STAGE_NAME: &str = "rare_branch_mask";
+
+/// Only the first bytes of larger inputs are probed, the rest is protected
+pub const RARE_BRANCH_MAX_PROBES: usize = 4096;
+
+/// A stage computing the [`MutationMaskMetadata`] of each corpus entry once.
+///
+/// It picks the rarest branch the entry hits according to the [`BranchHitsMetadata`] of a
+/// [`crate::feedbacks::BranchHitsFeedback`], then flips each byte of the entry in turn and executes it, to see
+/// which bytes the branch does not depend on. Entries hitting no rare branch are not masked.
+///
+/// Every probe is evaluated by the fuzzer like any other execution, so probes that crash, time out or find new
+/// coverage are not lost.
+#[derive(Debug, Clone)]
+pub struct RareBranchMaskStage<C, E, I, O> {
+    name: Cow<'static, str>,
+    map_observer_handle: Handle<C>,
+    phantom: PhantomData<(E, I, O)>,
+}
+
+impl<C, E, I, O> Named for RareBranchMaskStage<C, E, I, O> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<C, E, I, O, S> Restartable<S> for RareBranchMaskStage<C, E, I, O>
+where
+    S: HasMetadata + HasNamedMetadata + HasCurrentCorpusId,
+{
+    fn should_restart(&mut self, state: &mut S) -> Result<bool, Error> {
+        // Probing an input that crashes the fuzzer would crash it again
+        RetryCountRestartHelper::no_retry(state, &self.name)
+    }
+
+    fn clear_progress(&mut self, state: &mut S) -> Result<(), Error> {
+        RetryCountRestartHelper::clear_progress::<S>(state, &self.name)
+    }
+}
+
+impl<C, E, EM, I, O, S, Z> Stage<E, EM, S, Z> for RareBranchMaskStage<C, E, I, O>
+where
+    C: AsRef<O>,
+    E: HasObservers + Executor<EM, I, S, Z>,
+    E::Observers: ObserversTuple<I, S>,
+    I: HasMutatorBytes + Clone,
+    O: MapObserver,
+    S: HasCorpus<I> + HasMetadata + HasNamedMetadata + HasCurrentCorpusId,
+    Z: ExecutionProcessor<EM, I, E::Observers, S>,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        if state
+            .current_testcase()?
+            .has_metadata::<MutationMaskMetadata>()
+        {
+            return Ok(());
+        }
+
+        let mut input = state.current_input_cloned()?;
+        let hit = self.run_hit_indices(fuzzer, executor, state, manager, &input)?;
+        let rare_edge = state
+            .metadata::<BranchHitsMetadata>()
+            .ok()
+            .and_then(|meta| meta.rarest(hit));
+
+        let mask = match rare_edge {
+            None => MutationMaskMetadata::unmasked(),
+            Some(edge) => {
+                let probes = input.mutator_bytes().len().min(RARE_BRANCH_MAX_PROBES);
+                let mut allowed = Vec::with_capacity(probes);
+                for idx in 0..probes {
+                    input.mutator_bytes_mut()[idx] ^= 0xff;
+                    allowed.push(self.run_hits(fuzzer, executor, state, manager, &input, edge)?);
+                    input.mutator_bytes_mut()[idx] ^= 0xff;
+                }
+                log::debug!(
+                    "Rare branch {edge}: {} of {probes} bytes can be mutated",
+                    allowed.iter().filter(|allowed| **allowed).count()
+                );
+                MutationMaskMetadata::new(edge, allowed)
+            }
+        };
+        state.current_testcase_mut()?.add_metadata(mask);
+        Ok(())
+    }
+}
+
+impl<C, E, I, O> RareBranchMaskStage<C, E, I, O>
+where
+    C: AsRef<O> + Named,
+    O: MapObserver,
+{
+    /// Creates a new [`RareBranchMaskStage`] probing the coverage map of `map_observer`
+    #[must_use]
+    pub fn new(map_observer: &C) -> Self {
+        Self {
+            name: Cow::Owned(format!(
+                "{RARE_BRANCH_MASK_STAGE_NAME}:{}",
+                map_observer.name()
+            )),
+            map_observer_handle: map_observer.handle(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<C, E, I, O> RareBranchMaskStage<C, E, I, O>
+where
+    C: AsRef<O>,
+    O: MapObserver,
+{
+    /// Runs `input`, and calls `f` with the map observer before its `post_exec` classifies the map.
+    ///
+    /// The fuzzer evaluates the execution afterwards, to keep probes that are solutions or corpus entries.
+    fn run_with<EM, S, Z, T>(
+        &self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+        input: &I,
+        f: impl FnOnce(&O) -> T,
+    ) -> Result<T, Error>
+    where
+        E: HasObservers + Executor<EM, I, S, Z>,
+        E::Observers: ObserversTuple<I, S>,
+        Z: ExecutionProcessor<EM, I, E::Observers, S>,
+    {
+        executor.observers_mut().pre_exec_all(state, input)?;
+        let exit_kind = executor.run_target(fuzzer, state, manager, input)?;
+        let res = f(executor.observers()[&self.map_observer_handle].as_ref());
+        executor
+            .observers_mut()
+            .post_exec_all(state, input, &exit_kind)?;
+        fuzzer.evaluate_execution(
+            state,
+            manager,
+            input,
+            &*executor.observers(),
+            &exit_kind,
+            true,
+        )?;
+        Ok(res)
+    }
+
+    /// The map entries `input` hits
+    fn run_hit_indices<EM, S, Z>(
+        &self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+        input: &I,
+    ) -> Result<Vec<usize>, Error>
+    where
+        E: HasObservers + Executor<EM, I, S, Z>,
+        E::Observers: ObserversTuple<I, S>,
+        Z: ExecutionProcessor<EM, I, E::Observers, S>,
+    {
+        self.run_with(fuzzer, executor, state, manager, input, |observer| {
+            let initial = observer.initial();
+            (0..observer.usable_count())
+                .filter(|idx| observer.get(*idx) != initial)
+                .collect()
+        })
+    }
+
+    /// Whether `input` hits the map entry at `edge`
+    fn run_hits<EM, S, Z>(
+        &self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+        input: &I,
+        edge: usize,
+    ) -> Result<bool, Error>
+    where
+        E: HasObservers + Executor<EM, I, S, Z>,
+        E::Observers: ObserversTuple<I, S>,
+        Z: ExecutionProcessor<EM, I, E::Observers, S>,
+    {
+        self.run_with(fuzzer, executor, state, manager, input, |observer| {
+            observer.get(edge) != observer.initial()
+        })
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use libafl_bolts::{ownedref::OwnedMutSlice, rands::StdRand, tuples::tuple_list};
+
+    use super::RareBranchMaskStage;
+    use crate::{
+        HasMetadata, StdFuzzer,
+        corpus::{Corpus, HasCurrentCorpusId, InMemoryCorpus, Testcase},
+        events::NopEventManager,
+        executors::{ExitKind, InProcessExecutor},
+        feedbacks::{BranchHitsMetadata, ConstFeedback, CrashFeedback},
+        inputs::{BytesInput, HasMutatorBytes},
+        mutators::MutationMaskMetadata,
+        observers::StdMapObserver,
+        schedulers::QueueScheduler,
+        stages::Stage,
+        state::{HasCorpus, HasSolutions, StdState},
+    };
+
+    static mut MAP: [u8; 4] = [0; 4];
+
+    #[test]
+    fn test_rare_branch_mask_stage() {
+        let map = &raw mut MAP;
+        let observer = unsafe {
+            StdMapObserver::from_ownedref(
+                "map",
+                OwnedMutSlice::from_raw_parts_mut(map.cast::<u8>(), 4),
+            )
+        };
+        let mut stage = RareBranchMaskStage::new(&observer);
+
+        let mut feedback = ConstFeedback::new(false);
+        let mut objective = CrashFeedback::new();
+        let mut state = StdState::new(
+            StdRand::with_seed(0),
+            InMemoryCorpus::new(),
+            InMemoryCorpus::new(),
+            &mut feedback,
+            &mut objective,
+        )
+        .unwrap();
+        // The second edge is the rare one
+        state.add_metadata(BranchHitsMetadata {
+            hits: vec![100, 1, 0, 0],
+        });
+        let id = state
+            .corpus_mut()
+            .add(Testcase::new(BytesInput::new(b"ab".to_vec())))
+            .unwrap();
+        state.set_corpus_id(id).unwrap();
+
+        let mut fuzzer = StdFuzzer::new(QueueScheduler::new(), feedback, objective);
+        let mut manager = NopEventManager::new();
+        // The rare edge depends on the first byte, and changing the second one crashes the target
+        let mut harness = |input: &BytesInput| {
+            let bytes = input.mutator_bytes();
+            unsafe {
+                (*map)[0] = 1;
+                if bytes[0] == b'a' {
+                    (*map)[1] = 1;
+                }
+            }
+            if bytes[1] == b'b' {
+                ExitKind::Ok
+            } else {
+                ExitKind::Crash
+            }
+        };
+        let mut executor = InProcessExecutor::new(
+            &mut harness,
+            tuple_list!(observer),
+            &mut fuzzer,
+            &mut state,
+            &mut manager,
+        )
+        .unwrap();
+        stage
+            .perform(&mut fuzzer, &mut executor, &mut state, &mut manager)
+            .unwrap();
+
+        {
+            let testcase = state.corpus().get(id).unwrap().borrow();
+            let mask = testcase.metadata::<MutationMaskMetadata>().unwrap();
+            assert!(!mask.is_allowed(0));
+            assert!(mask.is_allowed(1));
+        }
+        // The crashing probe is kept as a solution
+        assert_eq!(state.solutions().count(), 1);
+    }
+}
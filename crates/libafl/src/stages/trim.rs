@@ -0,0 +1,284 @@
+//! The [`TrimStage`] shrinks new corpus entries the way AFL's `trim_case` does:
+//! it removes power-of-two sized chunks as long as the coverage map stays the same.
+
+use alloc::borrow::{Cow, ToOwned};
+use core::{fmt::Debug, hash::Hash, marker::PhantomData, time::Duration};
+
+use libafl_bolts::{
+    Named, current_time, generic_hash_std, impl_serdeany,
+    tuples::{Handle, Handled},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Error, HasMetadata, HasScheduler,
+    corpus::{Corpus, HasCurrentCorpusId, SchedulerTestcaseMetadata, Testcase},
+    executors::{Executor, ExitKind, HasObservers},
+    inputs::{HasMutatorBytes, ResizableMutator},
+    observers::ObserversTuple,
+    schedulers::RemovableScheduler,
+    stages::{Restartable, Stage},
+    state::{HasCorpus, HasCurrentTestcase},
+};
+
+/// Default name for `TrimStage`; derived from AFL
+pub const TRIM_STAGE_NAME: &str = "trim";
+
+/// AFL's `TRIM_MIN_BYTES`: never remove chunks smaller than this
+const TRIM_MIN_BYTES: usize = 4;
+/// AFL's `TRIM_START_STEPS`: the first chunk size is `len / TRIM_START_STEPS`
+const TRIM_START_STEPS: usize = 16;
+/// AFL's `TRIM_END_STEPS`: the last chunk size is `len / TRIM_END_STEPS`
+const TRIM_END_STEPS: usize = 1024;
+
+/// Marks a [`Testcase`] as already processed by the [`TrimStage`]
+#[cfg_attr(
+    any(not(feature = "serdeany_autoreg"), miri),
+    expect(clippy::unsafe_derive_deserialize)
+)] // for SerdeAny
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct TrimmedMetadata {
+    /// The length of the input before trimming
+    pub orig_len: usize,
+}
+
+impl_serdeany!(TrimmedMetadata);
+
+/// Removes chunks from `input` for as long as `is_equivalent` holds for the result.
+///
+/// This is AFL's trimming algorithm: the chunk size starts at 1/16th of the (power of two rounded) input length
+/// and is halved each round until it is 1/1024th of it, but never below [`TRIM_MIN_BYTES`].
+/// Returns `true` if anything was removed.
+fn trim_input<I, F>(input: &mut I, mut is_equivalent: F) -> Result<bool, Error>
+where
+    I: HasMutatorBytes + ResizableMutator<u8> + Clone,
+    F: FnMut(&I) -> Result<bool, Error>,
+{
+    let orig_len = input.mutator_bytes().len();
+    if orig_len <= TRIM_MIN_BYTES {
+        return Ok(false);
+    }
+
+    let mut len_p2 = orig_len.next_power_of_two();
+    let mut remove_len = (len_p2 / TRIM_START_STEPS).max(TRIM_MIN_BYTES);
+
+    while remove_len >= (len_p2 / TRIM_END_STEPS).max(TRIM_MIN_BYTES) {
+        let mut remove_pos = remove_len;
+
+        while remove_pos < input.mutator_bytes().len() {
+            let len = input.mutator_bytes().len();
+            let trim_avail = remove_len.min(len - remove_pos);
+
+            let mut candidate = input.clone();
+            candidate.drain(remove_pos..remove_pos + trim_avail);
+
+            if is_equivalent(&candidate)? {
+                // Keep `remove_pos` as is, the next chunk moved into its place
+                *input = candidate;
+                len_p2 = input.mutator_bytes().len().next_power_of_two();
+            } else {
+                remove_pos += remove_len;
+            }
+        }
+
+        remove_len >>= 1;
+    }
+
+    Ok(input.mutator_bytes().len() != orig_len)
+}
+
+/// A stage that trims each corpus entry once, removing chunks that do not change the map observer's hash.
+///
+/// On success the entry is replaced in the [`Corpus`], keeping its metadata, and the scheduler is notified.
+/// Long seeds spend most of the fuzzing time in the target, so the trimmed entries speed up all later stages.
+#[derive(Debug, Clone)]
+pub struct TrimStage<C, E, EM, I, O, S, Z> {
+    name: Cow<'static, str>,
+    map_observer_handle: Handle<C>,
+    phantom: PhantomData<(E, EM, I, O, S, Z)>,
+}
+
+impl<C, E, EM, I, O, S, Z> Named for TrimStage<C, E, EM, I, O, S, Z> {
+    fn name(&self) -> &Cow<'static, str> {
+        &self.name
+    }
+}
+
+impl<C, E, EM, I, O, S, Z> Restartable<S> for TrimStage<C, E, EM, I, O, S, Z> {
+    #[inline]
+    fn should_restart(&mut self, _state: &mut S) -> Result<bool, Error> {
+        // The testcase is marked as trimmed before the first execution, it won't be retried.
+        Ok(true)
+    }
+
+    #[inline]
+    fn clear_progress(&mut self, _state: &mut S) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl<C, E, EM, I, O, S, Z> Stage<E, EM, S, Z> for TrimStage<C, E, EM, I, O, S, Z>
+where
+    C: AsRef<O> + Named,
+    O: Hash,
+    E: Executor<EM, I, S, Z> + HasObservers,
+    E::Observers: ObserversTuple<I, S>,
+    I: HasMutatorBytes + ResizableMutator<u8> + Clone,
+    S: HasCorpus<I> + HasMetadata + HasCurrentTestcase<I> + HasCurrentCorpusId,
+    Z: HasScheduler<I, S>,
+    Z::Scheduler: RemovableScheduler<I, S>,
+{
+    fn perform(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+    ) -> Result<(), Error> {
+        let Some(corpus_id) = state.current_corpus_id()? else {
+            return Err(Error::illegal_state(
+                "state is not currently processing a corpus index",
+            ));
+        };
+
+        let mut input = state.current_input_cloned()?;
+        let orig_len = input.mutator_bytes().len();
+        {
+            let mut testcase = state.current_testcase_mut()?;
+            if testcase.has_metadata::<TrimmedMetadata>() {
+                return Ok(());
+            }
+            // Mark it before running, so that a crash or timeout in here will not make us try again
+            testcase.add_metadata(TrimmedMetadata { orig_len });
+        }
+
+        let (exit_kind, orig_hash, _) =
+            self.run_and_hash(fuzzer, executor, state, manager, &input)?;
+        if exit_kind != ExitKind::Ok {
+            return Ok(());
+        }
+
+        let mut exec_time = None;
+        let trimmed = trim_input(&mut input, |candidate| {
+            let (exit_kind, hash, time) =
+                self.run_and_hash(fuzzer, executor, state, manager, candidate)?;
+            let equivalent = exit_kind == ExitKind::Ok && hash == orig_hash;
+            if equivalent {
+                exec_time = Some(time);
+            }
+            Ok(equivalent)
+        })?;
+
+        if !trimmed {
+            return Ok(());
+        }
+
+        let mut testcase = Testcase::from(input);
+        {
+            let prev = state.current_testcase()?;
+            *testcase.metadata_map_mut() = prev.metadata_map().clone();
+            testcase.set_executions(*prev.executions());
+            testcase.set_scheduled_count(prev.scheduled_count());
+            testcase.set_parent_id_optional(prev.parent_id());
+            *testcase.exec_time_mut() = exec_time.or(*prev.exec_time());
+        }
+        if let (Some(time), Ok(meta)) = (
+            exec_time,
+            testcase.metadata_mut::<SchedulerTestcaseMetadata>(),
+        ) {
+            // The calibrated runtime no longer matches the shorter input
+            meta.set_cycle_and_time((time, 1));
+        }
+
+        let prev = state.corpus_mut().replace(corpus_id, testcase)?;
+        fuzzer.scheduler_mut().on_replace(state, corpus_id, &prev)?;
+
+        Ok(())
+    }
+}
+
+impl<C, E, EM, I, O, S, Z> TrimStage<C, E, EM, I, O, S, Z>
+where
+    C: AsRef<O> + Named,
+    O: Hash,
+    E: Executor<EM, I, S, Z> + HasObservers,
+    E::Observers: ObserversTuple<I, S>,
+{
+    /// Creates a new [`TrimStage`] comparing the hash of the given map observer
+    #[must_use]
+    pub fn new(map_observer: &C) -> Self {
+        let obs_name = map_observer.name().clone().into_owned();
+        Self {
+            name: Cow::Owned(TRIM_STAGE_NAME.to_owned() + ":" + obs_name.as_str()),
+            map_observer_handle: map_observer.handle(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Runs the target without evaluating the input and hashes the map afterwards
+    fn run_and_hash(
+        &self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        state: &mut S,
+        manager: &mut EM,
+        input: &I,
+    ) -> Result<(ExitKind, u64, Duration), Error> {
+        executor.observers_mut().pre_exec_all(state, input)?;
+
+        let start = current_time();
+        let exit_kind = executor.run_target(fuzzer, state, manager, input)?;
+        let time = current_time() - start;
+
+        executor
+            .observers_mut()
+            .post_exec_all(state, input, &exit_kind)?;
+
+        // Hash after post_exec, so that classified observers (e.g. hitcounts) are compared
+        let observers = executor.observers();
+        let hash = generic_hash_std(observers[&self.map_observer_handle].as_ref());
+        Ok((exit_kind, hash, time))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{vec, vec::Vec};
+
+    use super::trim_input;
+    use crate::inputs::{BytesInput, HasMutatorBytes};
+
+    #[test]
+    fn test_trim_input() {
+        // Only the bytes at the start and at the end are relevant
+        let mut bytes = vec![0; 100];
+        bytes[0] = b'A';
+        bytes[99] = b'Z';
+        let mut input = BytesInput::new(bytes);
+
+        let trimmed = trim_input(&mut input, |candidate| {
+            let bytes = candidate.mutator_bytes();
+            Ok(bytes.first() == Some(&b'A') && bytes.last() == Some(&b'Z'))
+        })
+        .unwrap();
+
+        assert!(trimmed);
+        let bytes = input.mutator_bytes();
+        assert_eq!(bytes.first(), Some(&b'A'));
+        assert_eq!(bytes.last(), Some(&b'Z'));
+        assert!(bytes.len() <= 8);
+
+        // Nothing can be removed
+        let orig = input.mutator_bytes().to_vec();
+        let trimmed = trim_input(&mut input, |_| Ok(false)).unwrap();
+        assert!(!trimmed);
+        assert_eq!(input.mutator_bytes(), orig.as_slice());
+    }
+
+    #[test]
+    fn test_trim_input_short() {
+        let mut input = BytesInput::new(Vec::from(*b"abcd"));
+        assert!(!trim_input(&mut input, |_| Ok(true)).unwrap());
+        assert_eq!(input.mutator_bytes(), b"abcd");
+    }
+}
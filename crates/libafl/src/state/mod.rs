@@ -935,6 +935,34 @@ where
         )
     }
 
+    /// Loads initial inputs from the passed-in `in_dirs`, creating them from the files with `loader`,
+    /// e.g., to parse seeds into structured inputs.
+    /// Files the loader fails on are logged and skipped.
+    pub fn load_initial_inputs_with_loader<E, EM, Z>(
+        &mut self,
+        fuzzer: &mut Z,
+        executor: &mut E,
+        manager: &mut EM,
+        in_dirs: &[PathBuf],
+        loader: &mut dyn FnMut(&mut Z, &mut Self, &Path) -> Result<I, Error>,
+    ) -> Result<(), Error>
+    where
+        EM: EventFirer<I, Self>,
+        Z: Evaluator<E, EM, I, Self>,
+    {
+        self.canonicalize_input_dirs(in_dirs)?;
+        self.continue_loading_initial_inputs_custom(
+            fuzzer,
+            executor,
+            manager,
+            LoadConfig {
+                loader,
+                forced: false,
+                exit_on_solution: false,
+            },
+        )
+    }
+
     fn calculate_corpus_size(&mut self) -> Result<usize, Error> {
         let mut count: usize = 0;
         loop {
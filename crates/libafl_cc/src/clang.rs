@@ -32,6 +32,10 @@ pub enum LLVMPasses {
     CoverageAccounting,
     /// The dump cfg pass
     DumpCfg,
+    /// The pass instrumenting basic blocks with their distance to the targets, see [`crate::distance`]
+    Distance,
+    /// The pass tagging the coverage edges of the lines a patch changed, see [`crate::patch`]
+    Patch,
     #[cfg(unix)]
     /// The `CmpLog` Instruction pass
     CmpLogInstructions,
@@ -56,6 +60,12 @@ impl LLVMPasses {
             LLVMPasses::DumpCfg => {
                 PathBuf::from(env!("OUT_DIR")).join(format!("dump-cfg-pass.{}", dll_extension()))
             }
+            LLVMPasses::Distance => {
+                PathBuf::from(env!("OUT_DIR")).join(format!("distance-pass.{}", dll_extension()))
+            }
+            LLVMPasses::Patch => {
+                PathBuf::from(env!("OUT_DIR")).join(format!("patch-pass.{}", dll_extension()))
+            }
             #[cfg(unix)]
             LLVMPasses::CmpLogInstructions => PathBuf::from(env!("OUT_DIR"))
                 .join(format!("cmplog-instructions-pass.{}", dll_extension())),
@@ -0,0 +1,160 @@
+/*
+   LibAFL - Distance LLVM pass
+   --------------------------------------------------
+
+   Copyright 2024 AFLplusplus Project. All rights reserved.
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at:
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+*/
+
+#include "common-llvm.h"
+
+#include <fstream>
+#include <map>
+#include <string>
+
+/*
+  Instruments every basic block with a known distance to the targets, as
+  computed by `libafl_cc::distance` from the dumps of the DumpCfg pass.
+  The distance file is read from LIBAFL_DISTANCE_FILE, one
+  `function,basic block index,distance` triple per line, with the distance in
+  hundredths. Each instrumented block adds its distance to
+  `__libafl_distance[0]` and increments `__libafl_distance[1]`.
+
+  The basic blocks are indexed the same way as in the DumpCfg pass, so both
+  builds need to use the same compiler flags.
+*/
+
+using namespace llvm;
+
+static cl::opt<bool> Debug("debug-distance", cl::desc("Debug prints"),
+                           cl::init(false), cl::NotHidden);
+
+namespace {
+
+class DistancePass : public PassInfoMixin<DistancePass> {
+ public:
+  DistancePass() {
+  }
+
+  PreservedAnalyses run(Module &M, ModuleAnalysisManager &MAM);
+
+ protected:
+  std::map<std::string, std::map<uint32_t, uint64_t>> distances;
+
+ private:
+  void loadDistances(const char *path);
+};
+
+}  // namespace
+
+extern "C" ::llvm::PassPluginLibraryInfo LLVM_ATTRIBUTE_WEAK
+llvmGetPassPluginInfo() {
+  return {LLVM_PLUGIN_API_VERSION, "DistancePass", "v0.1",
+          /* lambda to insert our pass into the pass pipeline. */
+          [](PassBuilder &PB) {
+            PB.registerOptimizerLastEPCallback(
+                [](ModulePassManager &MPM, OptimizationLevel OL
+#if LLVM_VERSION_MAJOR >= 20
+                   ,
+                   ThinOrFullLTOPhase Phase
+#endif
+
+                ) { MPM.addPass(DistancePass()); });
+          }};
+}
+
+void DistancePass::loadDistances(const char *path) {
+  std::ifstream in(path);
+  if (!in.is_open()) { FATAL("Could not open the distance file %s\n", path); }
+
+  std::string line;
+  while (std::getline(in, line)) {
+    // Function names may contain commas, the numbers may not
+    size_t dist_sep = line.rfind(',');
+    if (dist_sep == std::string::npos || dist_sep == 0) { continue; }
+    size_t bb_sep = line.rfind(',', dist_sep - 1);
+    if (bb_sep == std::string::npos) { continue; }
+
+    std::string func = line.substr(0, bb_sep);
+    uint32_t    bb =
+        std::stoul(line.substr(bb_sep + 1, dist_sep - bb_sep - 1));
+    uint64_t distance = std::stoull(line.substr(dist_sep + 1));
+    distances[func][bb] = distance;
+  }
+}
+
+PreservedAnalyses DistancePass::run(Module &M, ModuleAnalysisManager &MAM) {
+  LLVMContext &C = M.getContext();
+  IntegerType *Int64Ty = IntegerType::getInt64Ty(C);
+  ArrayType   *DistanceTy = ArrayType::get(Int64Ty, 2);
+
+  auto PA = PreservedAnalyses::all();
+
+  const char *path = getenv("LIBAFL_DISTANCE_FILE");
+  if (!path) { FATAL("LIBAFL_DISTANCE_FILE not set!\n"); }
+  loadDistances(path);
+
+  GlobalVariable *DistanceMap =
+      new GlobalVariable(M, DistanceTy, false, GlobalValue::ExternalLinkage,
+                         0, "__libafl_distance");
+
+  Value *DistanceSum = ConstantExpr::getInBoundsGetElementPtr(
+      DistanceTy, DistanceMap,
+      ArrayRef<Constant *>{ConstantInt::get(Int64Ty, 0),
+                           ConstantInt::get(Int64Ty, 0)});
+  Value *DistanceCount = ConstantExpr::getInBoundsGetElementPtr(
+      DistanceTy, DistanceMap,
+      ArrayRef<Constant *>{ConstantInt::get(Int64Ty, 0),
+                           ConstantInt::get(Int64Ty, 1)});
+
+  int inst_blocks = 0;
+
+  for (auto &F : M) {
+    auto func = distances.find(std::string(F.getName()));
+    if (func == distances.end()) { continue; }
+
+    // Collect first, the instrumentation must not shift the indices
+    std::vector<std::pair<BasicBlock *, uint64_t>> todo;
+    uint32_t                                       bb_cnt = 0;
+    for (auto &BB : F) {
+      auto distance = func->second.find(bb_cnt);
+      if (distance != func->second.end()) {
+        todo.push_back({&BB, distance->second});
+      }
+      bb_cnt++;
+    }
+
+    for (auto &[BB, distance] : todo) {
+      BasicBlock::iterator IP = BB->getFirstInsertionPt();
+      IRBuilder<>          IRB(&(*IP));
+
+      LoadInst *Sum = IRB.CreateLoad(Int64Ty, DistanceSum);
+      Sum->setMetadata(M.getMDKindID("nosanitize"), MDNode::get(C, None));
+      IRB.CreateStore(IRB.CreateAdd(Sum, ConstantInt::get(Int64Ty, distance)),
+                      DistanceSum)
+          ->setMetadata(M.getMDKindID("nosanitize"), MDNode::get(C, None));
+
+      LoadInst *Count = IRB.CreateLoad(Int64Ty, DistanceCount);
+      Count->setMetadata(M.getMDKindID("nosanitize"), MDNode::get(C, None));
+      IRB.CreateStore(IRB.CreateAdd(Count, ConstantInt::get(Int64Ty, 1)),
+                      DistanceCount)
+          ->setMetadata(M.getMDKindID("nosanitize"), MDNode::get(C, None));
+
+      inst_blocks++;
+    }
+  }
+
+  if (Debug) {
+    fprintf(stderr, "Instrumented %d basic blocks with their distance.\n",
+            inst_blocks);
+  }
+
+  if (inst_blocks) { PA = PreservedAnalyses::none(); }
+  return PA;
+}
@@ -0,0 +1,485 @@
+//! Distances of basic blocks to target locations, for AFLGo-style directed fuzzing.
+//!
+//! Directed builds take two compilations with the same flags:
+//! 1. With [`crate::LLVMPasses::DumpCfg`] and `CFG_OUTPUT_PATH` set, to dump the control flow graph of each module.
+//! 2. After computing a [`DistanceMap`] from the dumps and writing it to a file, with [`crate::LLVMPasses::Distance`]
+//!    and [`DISTANCE_FILE_VAR`] pointing to that file. The pass instruments each basic block with its distance.
+//!
+//! The targets are function names or `file:line` locations, for example the stack frames of a crash report,
+//! see [`DistanceTarget::from_report`].
+
+extern crate alloc;
+
+use alloc::collections::{BTreeMap, VecDeque};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::Write,
+    path::Path,
+};
+
+use serde::Deserialize;
+
+use crate::Error;
+
+/// The environment variable the [`crate::LLVMPasses::Distance`] pass reads the distance file from
+pub const DISTANCE_FILE_VAR: &str = "LIBAFL_DISTANCE_FILE";
+
+/// The factor the function distance of a callee is weighted with at the call site, as in `AFLGo`
+pub const CALL_DISTANCE_FACTOR: f64 = 10.0;
+
+/// Functions that run the harness; stack frames from there on are not targets
+const HARNESS_ENTRIES: [&str; 3] = ["LLVMFuzzerTestOneInput", "main", "__libc_start_main"];
+
+/// A location to steer the fuzzer towards
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DistanceTarget {
+    /// All basic blocks of a function, by its (mangled) name
+    Function(String),
+    /// The basic blocks with instructions from a source line. Needs debug info (`-g`).
+    Line {
+        /// The source file, matched against the end of the paths in the debug info
+        file: String,
+        /// The line in the source file
+        line: u32,
+    },
+}
+
+impl DistanceTarget {
+    /// Parses a `file:line` location or a function name
+    #[must_use]
+    pub fn parse(target: &str) -> Option<Self> {
+        let target = target.trim();
+        if target.is_empty() {
+            return None;
+        }
+        Some(Self::parse_location(target).unwrap_or_else(|| Self::Function(target.into())))
+    }
+
+    /// Parses a `file:line` or `file:line:column` location
+    fn parse_location(location: &str) -> Option<Self> {
+        let mut parts = location.split(':');
+        let file = parts.next()?;
+        let line = parts.next()?.parse().ok()?;
+        if file.is_empty() || line == 0 {
+            return None;
+        }
+        Some(Self::Line {
+            file: file.into(),
+            line,
+        })
+    }
+
+    /// Extracts the source locations of the stack frames in a crash report,
+    /// e.g., from `AddressSanitizer` or gdb, stopping at the harness entry of each stack trace.
+    #[must_use]
+    pub fn from_report(report: &str) -> Vec<Self> {
+        let mut targets = vec![];
+        let mut in_harness = false;
+        for line in report.lines() {
+            let line = line.trim();
+            let mut tokens = line.split_whitespace();
+            let Some(frame) = tokens.next().filter(|token| token.starts_with('#')) else {
+                continue;
+            };
+            if frame == "#0" {
+                // A new stack trace
+                in_harness = false;
+            }
+            let Some(func) = tokens.by_ref().skip_while(|token| *token != "in").nth(1) else {
+                continue;
+            };
+            let func = func.split('(').next().unwrap_or(func);
+            in_harness |= HARNESS_ENTRIES.contains(&func);
+            if in_harness {
+                continue;
+            }
+            if let Some(target) = tokens.filter_map(Self::parse_location).next_back()
+                && !targets.contains(&target)
+            {
+                targets.push(target);
+            }
+        }
+        targets
+    }
+
+    /// Whether a `file:line` location from the CFG dumps matches this target
+    fn matches_location(&self, location: &str) -> bool {
+        let Self::Line { file, line } = self else {
+            return false;
+        };
+        let Some((loc_file, loc_line)) = location.rsplit_once(':') else {
+            return false;
+        };
+        loc_line.parse::<u32>().ok() == Some(*line) && same_source_file(file, loc_file)
+    }
+}
+
+/// Whether two paths name the same source file, i.e., one is a suffix of the other.
+///
+/// The paths in the debug info may be absolute or relative to the build directory, the ones in crash reports
+/// and diffs relative to somewhere else.
+pub(crate) fn same_source_file(a: &str, b: &str) -> bool {
+    let a = a.trim_start_matches("./");
+    let b = b.trim_start_matches("./");
+    let (long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    long == short || long.ends_with(&format!("/{short}"))
+}
+
+/// A module's CFG as dumped by [`crate::LLVMPasses::DumpCfg`]
+#[derive(Debug, Default, Deserialize)]
+struct CfgDump {
+    /// The successors of each basic block, per function
+    #[serde(default)]
+    edges: HashMap<String, Vec<Option<Vec<usize>>>>,
+    /// The functions called from each basic block, per function
+    #[serde(default)]
+    calls: HashMap<String, HashMap<String, Vec<String>>>,
+    /// The `file:line` locations of each basic block, per function
+    #[serde(default)]
+    lines: HashMap<String, HashMap<String, Vec<String>>>,
+}
+
+/// The merged CFGs of all modules
+#[derive(Debug, Default)]
+struct Cfg {
+    /// The successors of each basic block, per function
+    successors: HashMap<String, Vec<Vec<usize>>>,
+    /// The functions called from each basic block, per function
+    calls: HashMap<String, HashMap<usize, Vec<String>>>,
+    /// The `file:line` locations of each basic block, per function
+    lines: HashMap<String, HashMap<usize, Vec<String>>>,
+}
+
+impl Cfg {
+    /// Merges a module's dump. Functions defined in several modules, e.g., `static` ones, are merged into one.
+    fn add(&mut self, dump: CfgDump) {
+        for (func, blocks) in dump.edges {
+            let successors = self.successors.entry(func).or_default();
+            if successors.len() < blocks.len() {
+                successors.resize(blocks.len(), vec![]);
+            }
+            for (bb, succs) in blocks.into_iter().enumerate() {
+                successors[bb].extend(succs.unwrap_or_default());
+            }
+        }
+        for (field, dumped) in [(&mut self.calls, dump.calls), (&mut self.lines, dump.lines)] {
+            for (func, blocks) in dumped {
+                let merged = field.entry(func).or_default();
+                for (bb, values) in blocks {
+                    if let Ok(bb) = bb.parse() {
+                        merged.entry(bb).or_default().extend(values);
+                    }
+                }
+            }
+        }
+    }
+
+    /// The basic blocks matching any of the targets, per function
+    fn target_blocks(&self, targets: &[DistanceTarget]) -> HashMap<&str, HashSet<usize>> {
+        let mut found: HashMap<&str, HashSet<usize>> = HashMap::new();
+        for target in targets {
+            if let DistanceTarget::Function(name) = target
+                && let Some((func, blocks)) = self.successors.get_key_value(name)
+            {
+                found.entry(func).or_default().extend(0..blocks.len());
+            }
+        }
+        for (func, blocks) in &self.lines {
+            for (bb, locations) in blocks {
+                if locations
+                    .iter()
+                    .any(|loc| targets.iter().any(|target| target.matches_location(loc)))
+                {
+                    found.entry(func).or_default().insert(*bb);
+                }
+            }
+        }
+        found.retain(|_, blocks| !blocks.is_empty());
+        found
+    }
+
+    /// The harmonic mean of the call graph distances of each function to the target functions
+    fn function_distances(&self, target_funcs: &HashSet<&str>) -> HashMap<String, f64> {
+        let mut callers: HashMap<&str, HashSet<&str>> = HashMap::new();
+        for (caller, blocks) in &self.calls {
+            for callee in blocks.values().flatten() {
+                callers.entry(callee).or_default().insert(caller);
+            }
+        }
+
+        let mut inverse_sums: HashMap<&str, f64> = HashMap::new();
+        for target in target_funcs {
+            for (func, hops) in bfs(*target, |func| {
+                callers.get(func).into_iter().flatten().copied()
+            }) {
+                if hops > 0 {
+                    *inverse_sums.entry(func).or_default() += 1.0 / f64::from(hops);
+                }
+            }
+        }
+
+        let mut distances: HashMap<String, f64> = inverse_sums
+            .into_iter()
+            .filter(|(func, _)| !target_funcs.contains(func))
+            .map(|(func, sum)| (func.to_string(), 1.0 / sum))
+            .collect();
+        distances.extend(target_funcs.iter().map(|func| (func.to_string(), 0.0)));
+        distances
+    }
+}
+
+/// Breadth-first search from `start`, returning the number of hops to each reached node
+fn bfs<T, F, N>(start: T, mut next: F) -> HashMap<T, u32>
+where
+    T: Copy + Eq + core::hash::Hash,
+    F: FnMut(T) -> N,
+    N: Iterator<Item = T>,
+{
+    let mut hops = HashMap::from([(start, 0)]);
+    let mut queue = VecDeque::from([start]);
+    while let Some(node) = queue.pop_front() {
+        let distance = hops[&node] + 1;
+        for neighbour in next(node) {
+            hops.entry(neighbour).or_insert_with(|| {
+                queue.push_back(neighbour);
+                distance
+            });
+        }
+    }
+    hops
+}
+
+/// The distance of each basic block to the targets, computed as in `AFLGo`.
+///
+/// - Target basic blocks have distance 0.
+/// - Basic blocks calling functions that reach a target have [`CALL_DISTANCE_FACTOR`] times one plus the
+///   smallest function distance of the callees, the function distance being the harmonic mean of the
+///   call graph distances to the target functions.
+/// - Other basic blocks have the harmonic mean of the distances to the above blocks reachable in their function,
+///   or no distance at all.
+#[derive(Debug, Clone, Default)]
+pub struct DistanceMap {
+    functions: BTreeMap<String, f64>,
+    blocks: BTreeMap<String, BTreeMap<usize, f64>>,
+}
+
+impl DistanceMap {
+    /// Computes the distances from all `.cfg` dumps in `dir`, i.e., the `CFG_OUTPUT_PATH` of the
+    /// [`crate::LLVMPasses::DumpCfg`] pass.
+    pub fn from_cfg_dir<P>(dir: P, targets: &[DistanceTarget]) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let mut dumps = vec![];
+        for entry in fs::read_dir(dir).map_err(Error::Io)? {
+            let path = entry.map_err(Error::Io)?.path();
+            if path.extension().is_some_and(|ext| ext == "cfg") {
+                dumps.push(fs::read_to_string(path).map_err(Error::Io)?);
+            }
+        }
+        Self::from_cfg_dumps(dumps.iter().map(String::as_str), targets)
+    }
+
+    /// Computes the distances from the contents of the CFG dumps
+    pub fn from_cfg_dumps<'a, D>(dumps: D, targets: &[DistanceTarget]) -> Result<Self, Error>
+    where
+        D: IntoIterator<Item = &'a str>,
+    {
+        let mut cfg = Cfg::default();
+        for dump in dumps {
+            cfg.add(
+                serde_json::from_str(dump).map_err(|err| {
+                    Error::Unknown(format!("Could not parse the CFG dump: {err}"))
+                })?,
+            );
+        }
+
+        let targets = cfg.target_blocks(targets);
+        if targets.is_empty() {
+            return Err(Error::InvalidArguments(
+                "None of the targets were found in the CFG dumps".into(),
+            ));
+        }
+        let functions = cfg.function_distances(&targets.keys().copied().collect());
+
+        let mut blocks = BTreeMap::new();
+        for (func, successors) in &cfg.successors {
+            let mut seeds = BTreeMap::new();
+            if let Some(target_blocks) = targets.get(func.as_str()) {
+                seeds.extend(target_blocks.iter().map(|bb| (*bb, 0.0)));
+            }
+            for (bb, callees) in cfg.calls.get(func).into_iter().flatten() {
+                let closest = callees
+                    .iter()
+                    .filter_map(|callee| functions.get(callee))
+                    .copied()
+                    .min_by(f64::total_cmp);
+                if let Some(closest) = closest
+                    && !seeds.contains_key(bb)
+                {
+                    seeds.insert(*bb, CALL_DISTANCE_FACTOR * (closest + 1.0));
+                }
+            }
+            if seeds.is_empty() {
+                continue;
+            }
+
+            let mut predecessors = vec![vec![]; successors.len()];
+            for (bb, succs) in successors.iter().enumerate() {
+                for succ in succs {
+                    if let Some(preds) = predecessors.get_mut(*succ) {
+                        preds.push(bb);
+                    }
+                }
+            }
+            let mut inverse_sums: HashMap<usize, f64> = HashMap::new();
+            for (seed, seed_distance) in &seeds {
+                for (bb, hops) in bfs(*seed, |bb| predecessors[bb].iter().copied()) {
+                    if !seeds.contains_key(&bb) {
+                        *inverse_sums.entry(bb).or_default() +=
+                            1.0 / (f64::from(hops) + seed_distance);
+                    }
+                }
+            }
+            seeds.extend(inverse_sums.into_iter().map(|(bb, sum)| (bb, 1.0 / sum)));
+            blocks.insert(func.clone(), seeds);
+        }
+
+        Ok(Self {
+            functions: functions.into_iter().collect(),
+            blocks,
+        })
+    }
+
+    /// The distance of a function to the targets in the call graph, if it reaches any
+    #[must_use]
+    pub fn function_distance(&self, func: &str) -> Option<f64> {
+        self.functions.get(func).copied()
+    }
+
+    /// The distance of the `bb`-th basic block of a function to the targets, if it reaches any
+    #[must_use]
+    pub fn block_distance(&self, func: &str, bb: usize) -> Option<f64> {
+        self.blocks.get(func)?.get(&bb).copied()
+    }
+
+    /// The number of basic blocks with a distance
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.blocks.values().map(BTreeMap::len).sum()
+    }
+
+    /// Whether no basic block reaches the targets
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Writes the distance file read by the [`crate::LLVMPasses::Distance`] pass,
+    /// one `function,basic block index,distance` line per basic block, with the distance in hundredths.
+    pub fn write<W>(&self, mut writer: W) -> Result<(), Error>
+    where
+        W: Write,
+    {
+        for (func, blocks) in &self.blocks {
+            for (bb, distance) in blocks {
+                #[expect(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                let distance = (distance * 100.0).round() as u64;
+                writeln!(writer, "{func},{bb},{distance}").map_err(Error::Io)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the distance file read by the [`crate::LLVMPasses::Distance`] pass to `path`,
+    /// to be passed in [`DISTANCE_FILE_VAR`].
+    pub fn write_to_file<P>(&self, path: P) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        let mut out = vec![];
+        self.write(&mut out)?;
+        fs::write(path, out).map_err(Error::Io)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::distance::{DistanceMap, DistanceTarget};
+
+    // main: 0 -> 1 -> 2, 0 -> 3; block 1 calls parse, block 3 calls log
+    // parse: 0 -> 1 -> 2; block 1 is at parse.c:10
+    const MAIN_DUMP: &str =
+        r#"{"edges":{"main":[[1,3],[2],[],[]]},"calls":{"main":{"1":["parse"],"3":["log"]}}}"#;
+    const PARSE_DUMP: &str = r#"{"edges":{"parse":[[1],[2],[]],"log":[[]]},"lines":{"parse":{"1":["src/parse.c:10"],"2":["src/parse.c:11"]}}}"#;
+
+    #[test]
+    fn test_target_parsing() {
+        assert_eq!(
+            DistanceTarget::parse("parse.c:10"),
+            Some(DistanceTarget::Line {
+                file: "parse.c".into(),
+                line: 10
+            })
+        );
+        assert_eq!(
+            DistanceTarget::parse("png_read_row"),
+            Some(DistanceTarget::Function("png_read_row".into()))
+        );
+
+        let report = "==1==ERROR: AddressSanitizer: heap-buffer-overflow
+    #0 0x55d3 in parse /src/proj/src/parse.c:10:5
+    #1 0x55e1 in main /src/proj/src/main.c:42:3
+    #2 0x7f00 in __libc_start_main (/lib/libc.so.6+0x29d90) (BuildId: 0123)
+freed by thread T0 here:
+    #0 0x4a00 in free (/out/fuzzer+0x4a00)
+    #1 0x55d0 in cleanup /src/proj/src/parse.c:20:1";
+        assert_eq!(
+            DistanceTarget::from_report(report),
+            vec![
+                DistanceTarget::Line {
+                    file: "/src/proj/src/parse.c".into(),
+                    line: 10
+                },
+                DistanceTarget::Line {
+                    file: "/src/proj/src/parse.c".into(),
+                    line: 20
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_distances() {
+        let targets = DistanceTarget::from_report("#0 0x55d3 in parse /src/proj/src/parse.c:10:5");
+        let map = DistanceMap::from_cfg_dumps([MAIN_DUMP, PARSE_DUMP], &targets).unwrap();
+
+        assert_eq!(map.function_distance("parse"), Some(0.0));
+        assert_eq!(map.function_distance("main"), Some(1.0));
+        assert_eq!(map.function_distance("log"), None);
+
+        // In the target function, only the blocks reaching the target line
+        assert_eq!(map.block_distance("parse", 1), Some(0.0));
+        assert_eq!(map.block_distance("parse", 0), Some(1.0));
+        assert_eq!(map.block_distance("parse", 2), None);
+
+        // The call site, and the blocks reaching it
+        assert_eq!(map.block_distance("main", 1), Some(10.0));
+        assert_eq!(map.block_distance("main", 0), Some(11.0));
+        assert_eq!(map.block_distance("main", 3), None);
+        assert_eq!(map.len(), 4);
+
+        let mut out = vec![];
+        map.write(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "main,0,1100\nmain,1,1000\nparse,0,100\nparse,1,0\n"
+        );
+
+        assert!(
+            DistanceMap::from_cfg_dumps([MAIN_DUMP], &[DistanceTarget::Function("nope".into())])
+                .is_err()
+        );
+    }
+}
@@ -34,6 +34,7 @@
 #include <set>
 
 #include "common-llvm.h"
+#include "llvm/IR/DebugInfoMetadata.h"
 #include <iostream>
 
 #include <nlohmann/json.hpp>
@@ -60,6 +61,7 @@ class DumpCfgPass : public PassInfoMixin<DumpCfgPass> {
   DenseMap<BasicBlock *, uint32_t>               bb_to_cur_loc;
   DenseMap<StringRef, BasicBlock *>              entry_bb;
   DenseMap<BasicBlock *, std::vector<StringRef>> calls_in_bb;
+  DenseMap<BasicBlock *, std::set<std::string>>  lines_in_bb;
 
  private:
   bool isLLVMIntrinsicFn(StringRef &n) {
@@ -105,6 +107,13 @@ PreservedAnalyses DumpCfgPass::run(Module &M, ModuleAnalysisManager &MAM) {
       bb_to_cur_loc[&BB] = bb_cnt;
       bb_cnt++;
       for (auto &IN : BB) {
+        if (DILocation *Loc = IN.getDebugLoc().get()) {
+          if (Loc->getLine()) {
+            lines_in_bb[&BB].insert(std::string(Loc->getFilename()) + ":" +
+                                    std::to_string(Loc->getLine()));
+          }
+        }
+
         CallBase *callBase = nullptr;
         if ((callBase = dyn_cast<CallBase>(&IN))) {
           auto F = callBase->getCalledFunction();
@@ -163,6 +172,22 @@ PreservedAnalyses DumpCfgPass::run(Module &M, ModuleAnalysisManager &MAM) {
     }
   }
 
+  for (auto record = lines_in_bb.begin(); record != lines_in_bb.end();
+       record++) {
+    auto        current_bb = record->getFirst();
+    auto        loc = bb_to_cur_loc[current_bb];
+    Function   *calling_func = current_bb->getParent();
+    std::string func_name = std::string("");
+
+    if (calling_func) {
+      func_name = std::string(calling_func->getName());
+    }
+
+    std::vector<std::string> lines(record->getSecond().begin(),
+                                   record->getSecond().end());
+    cfg["lines"][func_name][std::to_string(loc)] = lines;
+  }
+
   for (auto record = entry_bb.begin(); record != entry_bb.end(); record++) {
     cfg["entries"][std::string(record->getFirst())] =
         bb_to_cur_loc[record->getSecond()];
@@ -49,8 +49,12 @@ pub mod cfg;
 pub use cfg::{CfgEdge, ControlFlowGraph, EntryBasicBlockInfo, HasWeight};
 pub mod clang;
 pub use clang::{ClangWrapper, LLVMPasses};
+pub mod distance;
+pub use distance::{DistanceMap, DistanceTarget};
 pub mod libtool;
 pub use libtool::LibtoolWrapper;
+pub mod patch;
+pub use patch::ChangedLines;
 
 /// `LibAFL` CC Error Type
 #[derive(Debug)]
@@ -0,0 +1,184 @@
+/*
+   LibAFL - Patch LLVM pass
+   --------------------------------------------------
+
+   Copyright 2024 AFLplusplus Project. All rights reserved.
+
+   Licensed under the Apache License, Version 2.0 (the "License");
+   you may not use this file except in compliance with the License.
+   You may obtain a copy of the License at:
+
+     http://www.apache.org/licenses/LICENSE-2.0
+
+*/
+
+#include "common-llvm.h"
+#include "llvm/IR/DebugInfoMetadata.h"
+
+#include <fstream>
+#include <map>
+#include <set>
+#include <string>
+
+/*
+  Tags the sancov pcguard edges of the basic blocks built from the source
+  lines a patch changed, as written by `libafl_cc::patch::ChangedLines` to
+  the file in LIBAFL_PATCH_FILE, one `file:line` per line.
+
+  Each changed basic block calls `__libafl_patch_hit` right after its
+  `__sanitizer_cov_trace_pc_guard` call, which tags the edge the guard just
+  wrote to. If sancov did not run yet, the call is put at the start of the
+  block, and sancov puts its own call in front of it.
+  Build with -g, so the basic blocks can be mapped back to source lines.
+*/
+
+using namespace llvm;
+
+static cl::opt<bool> Debug("debug-patch", cl::desc("Debug prints"),
+                           cl::init(false), cl::NotHidden);
+
+namespace {
+
+class PatchPass : public PassInfoMixin<PatchPass> {
+ public:
+  PatchPass() {
+  }
+
+  PreservedAnalyses run(Module &M, ModuleAnalysisManager &MAM);
+
+ protected:
+  std::map<std::string, std::set<unsigned>> changed_lines;
+  /* The changed lines of each file name seen in the debug info, or null */
+  std::map<std::string, const std::set<unsigned> *> file_cache;
+
+ private:
+  void                      loadChangedLines(const char *path);
+  const std::set<unsigned> *linesOf(const std::string &file);
+  bool                      isChanged(BasicBlock &BB);
+  static bool               sameFile(StringRef a, StringRef b);
+};
+
+}  // namespace
+
+extern "C" ::llvm::PassPluginLibraryInfo LLVM_ATTRIBUTE_WEAK
+llvmGetPassPluginInfo() {
+  return {LLVM_PLUGIN_API_VERSION, "PatchPass", "v0.1",
+          /* lambda to insert our pass into the pass pipeline. */
+          [](PassBuilder &PB) {
+            PB.registerOptimizerLastEPCallback(
+                [](ModulePassManager &MPM, OptimizationLevel OL
+#if LLVM_VERSION_MAJOR >= 20
+                   ,
+                   ThinOrFullLTOPhase Phase
+#endif
+
+                ) { MPM.addPass(PatchPass()); });
+          }};
+}
+
+void PatchPass::loadChangedLines(const char *path) {
+  std::ifstream in(path);
+  if (!in.is_open()) { FATAL("Could not open the patch file %s\n", path); }
+
+  std::string line;
+  while (std::getline(in, line)) {
+    size_t sep = line.rfind(':');
+    if (sep == std::string::npos || sep == 0) { continue; }
+    changed_lines[line.substr(0, sep)].insert(std::stoul(line.substr(sep + 1)));
+  }
+}
+
+/* Whether one path is a suffix of the other, starting at a path component */
+bool PatchPass::sameFile(StringRef a, StringRef b) {
+  while (a.consume_front("./")) {}
+  while (b.consume_front("./")) {}
+  if (a.size() < b.size()) { std::swap(a, b); }
+  if (a == b) { return true; }
+#if LLVM_VERSION_MAJOR >= 18
+  return a.ends_with(b) && a[a.size() - b.size() - 1] == '/';
+#else
+  return a.endswith(b) && a[a.size() - b.size() - 1] == '/';
+#endif
+}
+
+const std::set<unsigned> *PatchPass::linesOf(const std::string &file) {
+  auto cached = file_cache.find(file);
+  if (cached != file_cache.end()) { return cached->second; }
+
+  const std::set<unsigned> *lines = nullptr;
+  for (auto &changed : changed_lines) {
+    if (sameFile(changed.first, file)) {
+      lines = &changed.second;
+      break;
+    }
+  }
+  file_cache[file] = lines;
+  return lines;
+}
+
+bool PatchPass::isChanged(BasicBlock &BB) {
+  for (auto &IN : BB) {
+    DILocation *Loc = IN.getDebugLoc().get();
+    if (!Loc || !Loc->getLine()) { continue; }
+
+    std::string file = std::string(Loc->getFilename());
+    if (!file.empty() && file[0] != '/' && !Loc->getDirectory().empty()) {
+      file = std::string(Loc->getDirectory()) + "/" + file;
+    }
+    const std::set<unsigned> *lines = linesOf(file);
+    if (lines && lines->count(Loc->getLine())) { return true; }
+  }
+  return false;
+}
+
+PreservedAnalyses PatchPass::run(Module &M, ModuleAnalysisManager &MAM) {
+  LLVMContext &C = M.getContext();
+  Type        *VoidTy = Type::getVoidTy(C);
+
+  auto PA = PreservedAnalyses::all();
+
+  const char *path = getenv("LIBAFL_PATCH_FILE");
+  if (!path) { FATAL("LIBAFL_PATCH_FILE not set!\n"); }
+  loadChangedLines(path);
+
+  FunctionCallee PatchHit = M.getOrInsertFunction("__libafl_patch_hit", VoidTy);
+
+  int inst_blocks = 0;
+
+  for (auto &F : M) {
+    if (isIgnoreFunction(&F)) { continue; }
+
+    // Collect first, so the inserted calls are not looked at
+    std::vector<BasicBlock *> todo;
+    for (auto &BB : F) {
+      if (isChanged(BB)) { todo.push_back(&BB); }
+    }
+
+    for (auto BB : todo) {
+      Instruction *GuardCall = nullptr;
+      for (auto &IN : *BB) {
+        if (auto *Call = dyn_cast<CallInst>(&IN)) {
+          Function *Callee = Call->getCalledFunction();
+          if (Callee &&
+              Callee->getName() == "__sanitizer_cov_trace_pc_guard") {
+            GuardCall = Call;
+            break;
+          }
+        }
+      }
+
+      IRBuilder<> IRB(GuardCall ? GuardCall->getNextNode()
+                                : &*BB->getFirstInsertionPt());
+      IRB.CreateCall(PatchHit)->setMetadata(M.getMDKindID("nosanitize"),
+                                            MDNode::get(C, None));
+      inst_blocks++;
+    }
+  }
+
+  if (Debug) {
+    fprintf(stderr, "Tagged %d basic blocks of changed lines.\n", inst_blocks);
+  }
+
+  if (inst_blocks) { PA = PreservedAnalyses::none(); }
+  return PA;
+}
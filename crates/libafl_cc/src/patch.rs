@@ -0,0 +1,220 @@
+//! The source lines changed by a patch, for patch-directed fuzzing.
+//!
+//! The [`crate::LLVMPasses::Patch`] pass reads the lines from the file in [`PATCH_FILE_VAR`], as written by
+//! [`ChangedLines::write_to_file`], and tags the coverage edges of the basic blocks built from them.
+//! Build the target with `-g`, so the pass can map basic blocks back to source lines.
+
+extern crate alloc;
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use std::{fs, io::Write, path::Path};
+
+use crate::{Error, distance::DistanceTarget};
+
+/// The environment variable the [`crate::LLVMPasses::Patch`] pass reads the changed lines from
+pub const PATCH_FILE_VAR: &str = "LIBAFL_PATCH_FILE";
+
+/// The lines a unified diff adds or modifies, per file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChangedLines {
+    files: BTreeMap<String, BTreeSet<u32>>,
+}
+
+impl ChangedLines {
+    /// Parses a unified diff, as produced by `git diff` or `diff -u`.
+    ///
+    /// Added lines count as changed. For removed lines, the line now in their place counts as changed.
+    #[must_use]
+    pub fn from_diff(diff: &str) -> Self {
+        let mut changed = Self::default();
+        let mut file: Option<String> = None;
+        // The next line in the new file, and the lines of the hunk left on each side
+        let mut line = 0;
+        let mut old_left = 0;
+        let mut new_left = 0;
+
+        for diff_line in diff.lines() {
+            if old_left > 0 || new_left > 0 {
+                match diff_line.as_bytes().first() {
+                    Some(b'+') => {
+                        changed.insert(file.as_deref(), line);
+                        line += 1;
+                        new_left = new_left.saturating_sub(1);
+                    }
+                    Some(b'-') => {
+                        changed.insert(file.as_deref(), line);
+                        old_left = old_left.saturating_sub(1);
+                    }
+                    Some(b'\\') => {}
+                    // Context lines, possibly with the trailing space stripped
+                    _ => {
+                        line += 1;
+                        old_left = old_left.saturating_sub(1);
+                        new_left = new_left.saturating_sub(1);
+                    }
+                }
+                continue;
+            }
+
+            if let Some(path) = diff_line.strip_prefix("+++ ") {
+                // Strip the timestamp of `diff -u`
+                let path = path.split('\t').next().unwrap_or(path).trim_end();
+                file = (path != "/dev/null")
+                    .then(|| path.strip_prefix("b/").unwrap_or(path).to_string());
+            } else if let Some(hunk) = diff_line.strip_prefix("@@ ")
+                && let Some((old, new)) = parse_hunk_header(hunk)
+            {
+                (line, new_left) = new;
+                old_left = old;
+            }
+        }
+        changed
+    }
+
+    /// Reads and parses a unified diff file
+    pub fn from_diff_file<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        Ok(Self::from_diff(
+            &fs::read_to_string(path).map_err(Error::Io)?,
+        ))
+    }
+
+    fn insert(&mut self, file: Option<&str>, line: u32) {
+        if let Some(file) = file
+            && line > 0
+        {
+            self.files.entry(file.to_string()).or_default().insert(line);
+        }
+    }
+
+    /// Whether `line` of `file` changed. `file` may be absolute, or relative to another directory than the diff.
+    #[must_use]
+    pub fn contains(&self, file: &str, line: u32) -> bool {
+        self.files.iter().any(|(changed_file, lines)| {
+            lines.contains(&line) && crate::distance::same_source_file(changed_file, file)
+        })
+    }
+
+    /// The changed files
+    pub fn files(&self) -> impl Iterator<Item = &str> {
+        self.files.keys().map(String::as_str)
+    }
+
+    /// The number of changed lines
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.files.values().map(BTreeSet::len).sum()
+    }
+
+    /// Whether no line changed
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The changed lines as targets for directed fuzzing, see [`crate::DistanceMap`]
+    #[must_use]
+    pub fn targets(&self) -> Vec<DistanceTarget> {
+        self.files
+            .iter()
+            .flat_map(|(file, lines)| {
+                lines.iter().map(|line| DistanceTarget::Line {
+                    file: file.clone(),
+                    line: *line,
+                })
+            })
+            .collect()
+    }
+
+    /// Writes the file read by the [`crate::LLVMPasses::Patch`] pass, one `file:line` per line
+    pub fn write<W>(&self, mut writer: W) -> Result<(), Error>
+    where
+        W: Write,
+    {
+        for (file, lines) in &self.files {
+            for line in lines {
+                writeln!(writer, "{file}:{line}").map_err(Error::Io)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the file read by the [`crate::LLVMPasses::Patch`] pass to `path`, to be passed in [`PATCH_FILE_VAR`]
+    pub fn write_to_file<P>(&self, path: P) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        let mut out = vec![];
+        self.write(&mut out)?;
+        fs::write(path, out).map_err(Error::Io)
+    }
+}
+
+/// Parses `-a,b +c,d @@`, returning the number of old lines, and the first and number of new lines
+fn parse_hunk_header(hunk: &str) -> Option<(u32, (u32, u32))> {
+    fn range(range: &str) -> Option<(u32, u32)> {
+        match range.split_once(',') {
+            Some((start, len)) => Some((start.parse().ok()?, len.parse().ok()?)),
+            None => Some((range.parse().ok()?, 1)),
+        }
+    }
+    let mut parts = hunk.split_whitespace();
+    let (_, old_len) = range(parts.next()?.strip_prefix('-')?)?;
+    let new = range(parts.next()?.strip_prefix('+')?)?;
+    Some((old_len, new))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::patch::ChangedLines;
+
+    const DIFF: &str = "diff --git a/src/parse.c b/src/parse.c
+index 0123456..789abcd 100644
+--- a/src/parse.c
++++ b/src/parse.c
+@@ -10,4 +10,5 @@ int parse(char *buf) {
+   int len = buf[0];
+-  if (len > 16) {
++  if (len >= 16) {
++    log(len);
+     return -1;
+   }
+@@ -30,3 +31,2 @@ int other(void) {
+   a();
+--- b();
+   c();
+diff --git a/old.c b/old.c
+deleted file mode 100644
+--- a/old.c
++++ /dev/null
+@@ -1,1 +0,0 @@
+-int x;
+";
+
+    #[test]
+    fn test_changed_lines() {
+        let changed = ChangedLines::from_diff(DIFF);
+        assert_eq!(changed.files().collect::<Vec<_>>(), vec!["src/parse.c"]);
+        // The modified line, the added line, and the line in place of the removed one
+        assert!(changed.contains("src/parse.c", 11));
+        assert!(changed.contains("/home/user/proj/src/parse.c", 12));
+        assert!(changed.contains("src/parse.c", 32));
+        assert!(!changed.contains("src/parse.c", 10));
+        assert!(!changed.contains("src/parse.c", 13));
+        assert!(!changed.contains("other/parse.c", 11));
+        assert_eq!(changed.len(), 3);
+
+        let mut out = vec![];
+        changed.write(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "src/parse.c:11\nsrc/parse.c:12\nsrc/parse.c:32\n"
+        );
+
+        // A hunk with more removed lines than its header claims
+        let changed = ChangedLines::from_diff("+++ b/a.c\n@@ -1,1 +1,2 @@\n-x\n-y\n+z\n");
+        assert!(changed.contains("a.c", 1));
+    }
+}
@@ -0,0 +1,24 @@
+//! Runtime for the distance pass of `libafl_cc`, used for directed fuzzing
+
+use alloc::borrow::Cow;
+
+use libafl::observers::DistanceObserver;
+
+/// The sum of the distances of the executed basic blocks, in hundredths, and their count,
+/// as written by the distance pass.
+#[unsafe(no_mangle)]
+#[allow(non_upper_case_globals)] // expect breaks here for some reason
+pub static mut __libafl_distance: [u64; 2] = [0; 2];
+pub use __libafl_distance as DISTANCE_MAP;
+
+/// Creates a [`DistanceObserver`] observing the [`DISTANCE_MAP`]
+///
+/// # Safety
+/// The [`DistanceObserver`] writes to the static [`DISTANCE_MAP`] before each execution,
+/// there must not be more than one of them at a time.
+pub unsafe fn distance_observer<'a, S>(name: S) -> DistanceObserver<'a>
+where
+    S: Into<Cow<'static, str>>,
+{
+    unsafe { DistanceObserver::from_mut_ptr(name, (&raw mut DISTANCE_MAP).cast()) }
+}
@@ -0,0 +1,120 @@
+//! The client of the harness protocol of the [`libafl::executors::HarnessExecutor`], for targets in Rust.
+//!
+//! Targets in C can include `harness_client.h` from this crate instead, which does not need to link it.
+
+use std::os::fd::{AsFd, BorrowedFd};
+
+use libafl::{
+    Error,
+    executors::harness::{
+        HARNESS_CTL_FD, HARNESS_HELLO, HARNESS_SHM_ENV_VAR, HARNESS_SHM_SIZE_ENV_VAR,
+        HARNESS_ST_FD, HARNESS_STATUS_OK,
+    },
+};
+use libafl_bolts::shmem::{ShMemId, ShMemProvider, UnixShMem, UnixShMemProvider};
+
+/// SAFETY:
+///
+/// The fd is opened by the [`libafl::executors::HarnessExecutor`] and never closed by the client.
+const HARNESS_CTL_R_FD: BorrowedFd<'static> = unsafe { BorrowedFd::borrow_raw(HARNESS_CTL_FD) };
+/// SAFETY:
+///
+/// The fd is opened by the [`libafl::executors::HarnessExecutor`] and never closed by the client.
+const HARNESS_ST_W_FD: BorrowedFd<'static> = unsafe { BorrowedFd::borrow_raw(HARNESS_ST_FD) };
+
+fn write_to_fuzzer(message: &[u8]) -> Result<(), Error> {
+    let bytes_written = nix::unistd::write(HARNESS_ST_W_FD, message)?;
+    if bytes_written != message.len() {
+        return Err(Error::illegal_state(format!(
+            "Could not write to st pipe. Expected {} bytes, wrote {bytes_written} bytes",
+            message.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Connection of a target to the [`libafl::executors::HarnessExecutor`]
+#[derive(Debug)]
+pub struct HarnessClient {
+    shmem: UnixShMem,
+    /// Whether the target runs an input, to report on the next one
+    running: bool,
+    /// The status to report for the current input
+    status: u32,
+}
+
+impl HarnessClient {
+    /// Maps the input shared memory and tells the fuzzer the target is ready.
+    ///
+    /// Fails if the target was not spawned by a [`libafl::executors::HarnessExecutor`].
+    pub fn connect() -> Result<Self, Error> {
+        let Ok(id_str) = std::env::var(HARNESS_SHM_ENV_VAR) else {
+            return Err(Error::illegal_argument(format!(
+                "Error: shared memory variable {HARNESS_SHM_ENV_VAR} is not set"
+            )));
+        };
+        let size = std::env::var(HARNESS_SHM_SIZE_ENV_VAR)
+            .ok()
+            .and_then(|size| size.parse().ok())
+            .ok_or_else(|| {
+                Error::illegal_argument(format!("Invalid {HARNESS_SHM_SIZE_ENV_VAR} value"))
+            })?;
+        let shmem = UnixShMemProvider::new()?
+            .shmem_from_id_and_size(ShMemId::from_string(&id_str), size)?;
+        write_to_fuzzer(&HARNESS_HELLO)?;
+        Ok(Self {
+            shmem,
+            running: false,
+            status: HARNESS_STATUS_OK,
+        })
+    }
+
+    /// Reports the previous input as done, and waits for the next one.
+    ///
+    /// Returns `None` once the fuzzer is done, the target should exit then.
+    pub fn next_input(&mut self) -> Result<Option<&[u8]>, Error> {
+        if self.running {
+            self.running = false;
+            write_to_fuzzer(&self.status.to_ne_bytes())?;
+        }
+        self.status = HARNESS_STATUS_OK;
+
+        let mut buf = [0_u8; 4];
+        match nix::unistd::read(HARNESS_CTL_R_FD.as_fd(), &mut buf) {
+            Ok(4) => {}
+            Ok(0) => return Ok(None),
+            Ok(bytes_read) => {
+                return Err(Error::illegal_state(format!(
+                    "Could not read from ctl pipe. Expected 4 bytes, got {bytes_read} bytes"
+                )));
+            }
+            Err(err) => return Err(err.into()),
+        }
+        let len = (u32::from_ne_bytes(buf) as usize).min(self.shmem.len());
+        self.running = true;
+        Ok(Some(&self.shmem[..len]))
+    }
+
+    /// Sets the status reported for the current input, e.g.,
+    /// [`libafl::executors::harness::HARNESS_STATUS_CRASH`] for a bug found without crashing
+    pub fn report(&mut self, status: u32) {
+        self.status = status;
+    }
+}
+
+/// Runs `harness` on each input of the [`libafl::executors::HarnessExecutor`], until the fuzzer is done.
+///
+/// The harness returns the status to report, e.g., [`HARNESS_STATUS_OK`].
+pub fn harness_loop<F>(mut harness: F) -> Result<(), Error>
+where
+    F: FnMut(&[u8]) -> u32,
+{
+    let mut client = HarnessClient::connect()?;
+    loop {
+        let Some(input) = client.next_input()? else {
+            return Ok(());
+        };
+        let status = harness(input);
+        client.report(status);
+    }
+}
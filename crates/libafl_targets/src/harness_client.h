@@ -0,0 +1,76 @@
+// Header-only client of the harness protocol of the HarnessExecutor of LibAFL,
+// for targets in C that do not link libafl_targets.
+//
+//   const uint8_t *data;
+//   size_t         len;
+//   while (libafl_harness_next(&data, &len)) {
+//     LLVMFuzzerTestOneInput(data, len);
+//   }
+
+#ifndef __LIBAFL_TARGETS_HARNESS_CLIENT__
+#define __LIBAFL_TARGETS_HARNESS_CLIENT__
+
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+#include <sys/shm.h>
+#include <unistd.h>
+
+#define LIBAFL_HARNESS_CTL_FD 196
+#define LIBAFL_HARNESS_ST_FD 197
+#define LIBAFL_HARNESS_SHM_ENV_VAR "__LIBAFL_HARNESS_SHM_ID"
+#define LIBAFL_HARNESS_SHM_SIZE_ENV_VAR "__LIBAFL_HARNESS_SHM_SIZE"
+#define LIBAFL_HARNESS_HELLO "LAFL"
+#define LIBAFL_HARNESS_STATUS_OK 0
+#define LIBAFL_HARNESS_STATUS_CRASH 1
+
+static uint8_t *libafl_harness_shm;
+static size_t   libafl_harness_shm_size;
+static int      libafl_harness_running;
+static uint32_t libafl_harness_status;
+
+// Sets the status reported for the current input, e.g.,
+// LIBAFL_HARNESS_STATUS_CRASH for a bug found without crashing.
+static inline void libafl_harness_report(uint32_t status) {
+  libafl_harness_status = status;
+}
+
+// Maps the input shared memory and tells the fuzzer the target is ready.
+// Returns 0 if the target was not spawned by the HarnessExecutor.
+static inline int libafl_harness_connect(void) {
+  const char *id = getenv(LIBAFL_HARNESS_SHM_ENV_VAR);
+  const char *size = getenv(LIBAFL_HARNESS_SHM_SIZE_ENV_VAR);
+  if (!id || !size) { return 0; }
+
+  void *shm = shmat(atoi(id), NULL, 0);
+  if (shm == (void *)-1) { return 0; }
+  libafl_harness_shm = (uint8_t *)shm;
+  libafl_harness_shm_size = (size_t)strtoull(size, NULL, 10);
+
+  return write(LIBAFL_HARNESS_ST_FD, LIBAFL_HARNESS_HELLO, 4) == 4;
+}
+
+// Reports the previous input as done, and waits for the next one.
+// Returns 0 once the fuzzer is done, or if it can not connect to it.
+static inline int libafl_harness_next(const uint8_t **data, size_t *len) {
+  uint32_t msg;
+
+  if (!libafl_harness_shm) {
+    if (!libafl_harness_connect()) { return 0; }
+  } else if (libafl_harness_running) {
+    if (write(LIBAFL_HARNESS_ST_FD, &libafl_harness_status, 4) != 4) {
+      return 0;
+    }
+  }
+  libafl_harness_running = 0;
+  libafl_harness_status = LIBAFL_HARNESS_STATUS_OK;
+
+  if (read(LIBAFL_HARNESS_CTL_FD, &msg, 4) != 4) { return 0; }
+  libafl_harness_running = 1;
+
+  *data = libafl_harness_shm;
+  *len = msg < libafl_harness_shm_size ? msg : libafl_harness_shm_size;
+  return 1;
+}
+
+#endif
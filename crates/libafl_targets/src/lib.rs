@@ -57,7 +57,9 @@ include!(concat!(env!("OUT_DIR"), "/constants.rs"));
     feature = "sancov_pcguard_hitcounts",
     feature = "sancov_ngram4",
     feature = "sancov_ngram8",
-    feature = "sancov_ctx"
+    feature = "sancov_ctx",
+    feature = "sancov_pcguard_patch",
+    feature = "sancov_sensitivity"
 ))]
 pub mod sancov_pcguard;
 #[cfg(any(
@@ -65,7 +67,9 @@ pub mod sancov_pcguard;
     feature = "sancov_pcguard_hitcounts",
     feature = "sancov_ngram4",
     feature = "sancov_ngram8",
-    feature = "sancov_ctx"
+    feature = "sancov_ctx",
+    feature = "sancov_pcguard_patch",
+    feature = "sancov_sensitivity"
 ))]
 pub use sancov_pcguard::*;
 
@@ -115,6 +119,26 @@ pub mod call;
 #[cfg(feature = "function-logging")]
 pub use call::*;
 
+#[cfg(feature = "distance")]
+pub mod distance;
+#[cfg(feature = "distance")]
+pub use distance::*;
+
+#[cfg(feature = "sancov_pcguard_patch")]
+pub mod patch;
+#[cfg(feature = "sancov_pcguard_patch")]
+pub use patch::*;
+
+#[cfg(feature = "sancov_sensitivity")]
+pub mod sensitivity;
+#[cfg(feature = "sancov_sensitivity")]
+pub use sensitivity::*;
+
+#[cfg(feature = "malloc_stats")]
+pub mod memory;
+#[cfg(feature = "malloc_stats")]
+pub use memory::*;
+
 /// runtime related to comparisons
 pub mod cmps;
 pub use cmps::*;
@@ -131,3 +155,8 @@ pub use windows_asan::*;
 pub mod forkserver;
 #[cfg(all(unix, feature = "std", feature = "forkserver"))]
 pub use forkserver::*;
+
+#[cfg(all(unix, feature = "harness_protocol"))]
+pub mod harness;
+#[cfg(all(unix, feature = "harness_protocol"))]
+pub use harness::*;
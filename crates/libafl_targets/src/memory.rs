@@ -0,0 +1,105 @@
+//! Runtime for the [`MemoryObserver`]: malloc hooks counting the heap usage and allocations of each execution.
+//!
+//! The hooks are called by the sanitizer runtimes, e.g., of `ASan`, so the target has to be built with one.
+//! With the `libfuzzer_oom` feature, its hooks record the allocations as well.
+
+use alloc::borrow::Cow;
+#[cfg(not(feature = "libfuzzer_oom"))]
+use core::ffi::c_void;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use libafl::observers::{MemoryObserver, memory::MEMORY_MAP_LEN};
+
+/// The peak heap usage, the number of allocations, the current heap usage, and whether an execution is running,
+/// as described in [`MemoryObserver`]
+#[unsafe(no_mangle)]
+#[allow(non_upper_case_globals)] // expect breaks here for some reason
+pub static mut __libafl_memory_stats: [u64; MEMORY_MAP_LEN] = [0; MEMORY_MAP_LEN];
+pub use __libafl_memory_stats as MEMORY_STATS_MAP;
+
+/// The entry `idx` of the [`MEMORY_STATS_MAP`]
+fn stat(idx: usize) -> &'static AtomicU64 {
+    // # Safety
+    // The entries are aligned `u64`s living forever, only accessed atomically while the target runs.
+    unsafe { AtomicU64::from_ptr((&raw mut MEMORY_STATS_MAP).cast::<u64>().add(idx)) }
+}
+
+/// Records an allocation of `size` bytes, if an execution is running
+pub fn record_malloc(size: usize) {
+    if stat(3).load(Ordering::Relaxed) == 0 {
+        return;
+    }
+    stat(1).fetch_add(1, Ordering::Relaxed);
+    let current = stat(2).fetch_add(size as u64, Ordering::Relaxed) + size as u64;
+    stat(0).fetch_max(current, Ordering::Relaxed);
+}
+
+/// Records freeing `size` bytes, if an execution is running
+pub fn record_free(size: usize) {
+    if stat(3).load(Ordering::Relaxed) == 0 {
+        return;
+    }
+    // Never fails, the closure always returns `Some`
+    let _ = stat(2).fetch_update(Ordering::Relaxed, Ordering::Relaxed, |current| {
+        Some(current.saturating_sub(size as u64))
+    });
+}
+
+/// The usable size of the allocation at `ptr`, or `0` if unknown
+///
+/// # Safety
+/// `ptr` has to be a live allocation of the system allocator.
+#[cfg(not(feature = "libfuzzer_oom"))]
+unsafe fn malloc_size(ptr: *const c_void) -> usize {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    unsafe {
+        libc::malloc_usable_size(ptr.cast_mut())
+    }
+    #[cfg(target_vendor = "apple")]
+    unsafe {
+        libc::malloc_size(ptr)
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "android", target_vendor = "apple")))]
+    {
+        let _ = ptr;
+        0
+    }
+}
+
+/// malloc hook which will be invoked if a sanitizer is present, counts the allocation
+///
+/// # Safety
+/// Is only safe to call with valid freshly allocated pointers backed by allocations of `size`.
+#[cfg(not(feature = "libfuzzer_oom"))]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn __sanitizer_malloc_hook(ptr: *const c_void, size: usize) {
+    let size = match unsafe { malloc_size(ptr) } {
+        0 => size,
+        real => real,
+    };
+    record_malloc(size);
+}
+
+/// free hook which will be invoked if a sanitizer is present, counts the freed memory
+///
+/// # Safety
+/// Is only safe to call with valid allocated pointers, about to be freed.
+#[cfg(not(feature = "libfuzzer_oom"))]
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn __sanitizer_free_hook(ptr: *const c_void) {
+    if !ptr.is_null() {
+        record_free(unsafe { malloc_size(ptr) });
+    }
+}
+
+/// Creates a [`MemoryObserver`] observing the [`MEMORY_STATS_MAP`]
+///
+/// # Safety
+/// The [`MemoryObserver`] writes to the static [`MEMORY_STATS_MAP`] before and after each execution,
+/// there must not be more than one of them at a time.
+pub unsafe fn memory_observer<'a, S>(name: S) -> MemoryObserver<'a>
+where
+    S: Into<Cow<'static, str>>,
+{
+    unsafe { MemoryObserver::from_mut_ptr(name, (&raw mut MEMORY_STATS_MAP).cast()) }
+}
@@ -0,0 +1,39 @@
+//! Runtime for the patch pass of `libafl_cc`, used for patch-directed fuzzing
+
+use libafl_bolts::ownedref::OwnedSlice;
+
+use crate::{EDGES_MAP_ALLOCATED_SIZE, sancov_pcguard::LAST_EDGE_POS};
+
+/// Nonzero at the positions of the edges map belonging to lines changed by the patch
+pub static mut PATCH_EDGES_MAP: [u8; EDGES_MAP_ALLOCATED_SIZE] = [0; EDGES_MAP_ALLOCATED_SIZE];
+
+/// Called by the patch pass right after the `__sanitizer_cov_trace_pc_guard` call of a changed basic block,
+/// tags the edge it wrote to in the [`PATCH_EDGES_MAP`].
+///
+/// # Safety
+/// Writes to the static [`PATCH_EDGES_MAP`]. Should usually not be called directly.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn __libafl_patch_hit() {
+    unsafe {
+        let patch_map_ptr = &raw mut PATCH_EDGES_MAP;
+        let patch_map = &mut *patch_map_ptr;
+        if let Some(tag) = patch_map.get_mut(LAST_EDGE_POS) {
+            *tag = 1;
+        }
+    }
+}
+
+/// The tags of the [`PATCH_EDGES_MAP`], for a [`libafl::feedbacks::PatchMapFeedback`]
+///
+/// # Safety
+/// The tags are written while the target runs, do not hold them across a change of the
+/// [`PATCH_EDGES_MAP`] from elsewhere.
+#[must_use]
+pub unsafe fn patch_edges_tags<'a>() -> OwnedSlice<'a, u8> {
+    unsafe {
+        OwnedSlice::from_raw_parts(
+            (&raw const PATCH_EDGES_MAP).cast(),
+            EDGES_MAP_ALLOCATED_SIZE,
+        )
+    }
+}
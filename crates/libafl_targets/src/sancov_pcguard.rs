@@ -23,6 +23,8 @@ use crate::EDGES_MAP_DEFAULT_SIZE;
     feature = "sancov_ctx",
     feature = "sancov_ngram4",
     feature = "sancov_ngram8",
+    feature = "sancov_pcguard_patch",
+    feature = "sancov_sensitivity",
 ))]
 use crate::coverage::EDGES_MAP;
 use crate::coverage::MAX_EDGES_FOUND;
@@ -205,6 +207,10 @@ unsafe extern "C" {
     pub static mut __afl_prev_ctx: u32;
 }
 
+/// The position in the edges map of the last executed edge, tagged by [`crate::patch::__libafl_patch_hit`]
+#[cfg(feature = "sancov_pcguard_patch")]
+pub static mut LAST_EDGE_POS: usize = 0;
+
 /// Callback for sancov `pc_guard` - usually called by `llvm` on each block or edge.
 ///
 /// # Safety
@@ -217,6 +223,11 @@ pub unsafe extern "C" fn __sanitizer_cov_trace_pc_guard(guard: *mut u32) {
         #[allow(unused_variables, unused_mut)] // cfg dependent
         let mut pos = *guard as usize;
 
+        #[cfg(feature = "sancov_sensitivity")]
+        {
+            crate::sensitivity::trace_sensitivity(pos);
+        }
+
         #[cfg(any(feature = "sancov_ngram4", feature = "sancov_ngram8"))]
         {
             pos = update_ngram(pos);
@@ -229,6 +240,11 @@ pub unsafe extern "C" fn __sanitizer_cov_trace_pc_guard(guard: *mut u32) {
             // println!("Wrinting to {} {}", pos, EDGES_MAP_DEFAULT_SIZE);
         }
 
+        #[cfg(feature = "sancov_pcguard_patch")]
+        {
+            LAST_EDGE_POS = pos;
+        }
+
         #[cfg(feature = "pointer_maps")]
         {
             #[cfg(feature = "sancov_pcguard_edges")]
@@ -0,0 +1,157 @@
+//! Coverage of different sensitivity, selectable at runtime: n-grams of edges and calling contexts, recorded into
+//! their own maps next to the plain edges of [`crate::EDGES_MAP`].
+//!
+//! Unlike the `sancov_ngram4`, `sancov_ngram8` and `sancov_ctx` features, which change what goes into the edges map,
+//! this keeps the plain edges, so a single build of the target can be fuzzed with any of them.
+//! Select the sensitivity with a [`SensitivityHook`] in the hooks of the executor.
+//! The calling context needs the ctx pass of `libafl_cc`, without it the ctx map mirrors the edges map.
+
+use alloc::borrow::Cow;
+use core::marker::PhantomData;
+
+use libafl::{executors::hooks::ExecutorHook, observers::StdMapObserver};
+use libafl_bolts::ownedref::OwnedMutSlice;
+
+use crate::{EDGES_MAP_ALLOCATED_SIZE, EDGES_MAP_DEFAULT_SIZE, sancov_pcguard::__afl_prev_ctx};
+
+/// The largest `N` of n-grams
+pub const MAX_NGRAM: usize = 16;
+
+/// The map for n-grams of edges
+pub static mut NGRAM_MAP: [u8; EDGES_MAP_ALLOCATED_SIZE] = [0; EDGES_MAP_ALLOCATED_SIZE];
+
+/// The map for edges in their calling context
+pub static mut CTX_MAP: [u8; EDGES_MAP_ALLOCATED_SIZE] = [0; EDGES_MAP_ALLOCATED_SIZE];
+
+/// The `N` of the recorded n-grams, `0` if disabled
+static mut NGRAM_N: usize = 0;
+
+/// Whether to record the [`CTX_MAP`]
+static mut CTX_ENABLED: bool = false;
+
+/// The last edges, as ring buffer
+static mut PREV_LOCS: [u32; MAX_NGRAM] = [0; MAX_NGRAM];
+
+/// The position of the last edge in [`PREV_LOCS`]
+static mut PREV_IDX: usize = 0;
+
+/// The position in the [`NGRAM_MAP`] of the n-gram ending in `pos`, after the `n - 1` edges before it.
+///
+/// The older an edge, the further it is shifted, so the order of the edges matters.
+#[must_use]
+pub fn ngram_pos(prev_locs: &[u32], last: usize, pos: u32) -> usize {
+    let n = prev_locs.len();
+    let mut hash = pos;
+    for age in 1..n {
+        let loc = prev_locs[(last + n + 1 - age) % n];
+        hash ^= loc.wrapping_shl(age as u32);
+    }
+    hash as usize % EDGES_MAP_DEFAULT_SIZE
+}
+
+/// Records the edge with the guard `pos` in the enabled maps, called from `__sanitizer_cov_trace_pc_guard`
+///
+/// # Safety
+/// Writes to the static maps, not thread safe.
+#[inline]
+pub unsafe fn trace_sensitivity(pos: usize) {
+    unsafe {
+        let n = NGRAM_N;
+        if n > 0 {
+            let prev_locs_ptr = &raw mut PREV_LOCS;
+            let prev_locs = &mut (&mut *prev_locs_ptr)[..n];
+            let idx = ngram_pos(prev_locs, PREV_IDX, pos as u32);
+            PREV_IDX = (PREV_IDX + 1) % n;
+            prev_locs[PREV_IDX] = pos as u32;
+
+            let ngram_map_ptr = &raw mut NGRAM_MAP;
+            let ngram_map = &mut *ngram_map_ptr;
+            ngram_map[idx] = ngram_map[idx].wrapping_add(1);
+        }
+        if CTX_ENABLED {
+            let idx = (pos ^ __afl_prev_ctx as usize) % EDGES_MAP_DEFAULT_SIZE;
+            let ctx_map_ptr = &raw mut CTX_MAP;
+            let ctx_map = &mut *ctx_map_ptr;
+            ctx_map[idx] = ctx_map[idx].wrapping_add(1);
+        }
+    }
+}
+
+/// Gets a new [`StdMapObserver`] over the [`NGRAM_MAP`]
+///
+/// # Safety
+/// The observer points to the static map, there must not be more than one of them at a time.
+#[must_use]
+pub unsafe fn ngram_map_observer<'a, S>(name: S) -> StdMapObserver<'a, u8, false>
+where
+    S: Into<Cow<'static, str>>,
+{
+    unsafe {
+        StdMapObserver::from_mut_slice(
+            name,
+            OwnedMutSlice::from_raw_parts_mut((&raw mut NGRAM_MAP).cast(), EDGES_MAP_DEFAULT_SIZE),
+        )
+    }
+}
+
+/// Gets a new [`StdMapObserver`] over the [`CTX_MAP`]
+///
+/// # Safety
+/// The observer points to the static map, there must not be more than one of them at a time.
+#[must_use]
+pub unsafe fn ctx_map_observer<'a, S>(name: S) -> StdMapObserver<'a, u8, false>
+where
+    S: Into<Cow<'static, str>>,
+{
+    unsafe {
+        StdMapObserver::from_mut_slice(
+            name,
+            OwnedMutSlice::from_raw_parts_mut((&raw mut CTX_MAP).cast(), EDGES_MAP_DEFAULT_SIZE),
+        )
+    }
+}
+
+/// The hook selecting the recorded coverage, and resetting the n-gram history before each run
+#[derive(Debug, Copy, Clone)]
+pub struct SensitivityHook<I, S> {
+    ngram: usize,
+    ctx: bool,
+    phantom: PhantomData<(I, S)>,
+}
+
+impl<I, S> SensitivityHook<I, S> {
+    /// Records n-grams of `ngram` edges, if not `0`, and the calling contexts, if `ctx` is set
+    ///
+    /// # Panics
+    /// Panics if `ngram` is larger than [`MAX_NGRAM`].
+    #[must_use]
+    pub fn new(ngram: usize, ctx: bool) -> Self {
+        assert!(
+            ngram <= MAX_NGRAM,
+            "n-grams of up to {MAX_NGRAM} edges are supported"
+        );
+        Self {
+            ngram,
+            ctx,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<I, S> ExecutorHook<I, S> for SensitivityHook<I, S> {
+    fn init(&mut self, _state: &mut S) {
+        unsafe {
+            NGRAM_N = self.ngram;
+            CTX_ENABLED = self.ctx;
+        }
+    }
+
+    fn pre_exec(&mut self, _state: &mut S, _input: &I) {
+        unsafe {
+            PREV_LOCS = [0; MAX_NGRAM];
+            PREV_IDX = 0;
+        }
+    }
+
+    fn post_exec(&mut self, _state: &mut S, _input: &I) {}
+}
@@ -71,6 +71,9 @@ pub fn parse_envs(opt: &mut Opt) -> Result<(), Error> {
     if let Ok(res) = std::env::var("AFL_AUTORESUME") {
         opt.auto_resume = parse_bool(&res)?;
     }
+    if let Ok(res) = std::env::var("AFL_DISABLE_TRIM") {
+        opt.disable_trim = parse_bool(&res)?;
+    }
     if let Ok(res) = std::env::var("AFL_DEFER_FORKSRV") {
         opt.defer_forkserver = parse_bool(&res)?;
     }
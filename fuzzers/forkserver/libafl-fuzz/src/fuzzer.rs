@@ -36,8 +36,9 @@ use libafl::{
         afl_stats::{AflStatsStage, CalibrationTime, FuzzTime, SyncTime},
         mutational::MultiMutationalStage,
         time_tracker::TimeTrackingStageWrapper,
-        CalibrationStage, ColorizationStage, IfStage, StagesTuple, StdMutationalStage,
-        StdPowerMutationalStage, SyncFromDiskStage, VerifyTimeoutsStage,
+        CalibrationStage, ColorizationStage, DeterministicStage, IfStage, StagesTuple,
+        StdMutationalStage, StdPowerMutationalStage, SyncFromDiskStage, TrimStage,
+        VerifyTimeoutsStage,
     },
     state::{
         HasCorpus, HasCurrentTestcase, HasExecutions, HasLastReportTime, HasStartTime, StdState,
@@ -278,6 +279,18 @@ define_run_client!(state, mgr, fuzzer_dir, core_id, opt, is_main_node, {
     let mutational_stage = TimeTrackingStageWrapper::<FuzzTime, _, _>::new(inner_mutational_stage);
     let strategy = opt.power_schedule.unwrap_or(BaseSchedule::EXPLORE);
 
+    // Create our TrimStage; it shrinks each queue entry once, unless AFL_DISABLE_TRIM is set.
+    let trim = IfStage::new(
+        |_, _, _, _| Ok(!opt.disable_trim),
+        tuple_list!(TrimStage::new(&edges_observer)),
+    );
+
+    // Create our DeterministicStage; like AFL++'s `-D`, it walks each queue entry only once.
+    let deterministic = IfStage::new(
+        |_, _, _, _| Ok(opt.deterministic),
+        tuple_list!(DeterministicStage::new(&edges_observer)),
+    );
+
     // Create our ColorizationStage
     let colorization = ColorizationStage::new(&edges_observer);
 
@@ -526,7 +539,9 @@ define_run_client!(state, mgr, fuzzer_dir, core_id, opt, is_main_node, {
         // The order of the stages matter!
         let mut stages = tuple_list!(
             calibration,
+            trim,
             cmplog,
+            deterministic,
             mutational_stage,
             timeout_verify_stage,
             afl_stats_stage,
@@ -546,6 +561,8 @@ define_run_client!(state, mgr, fuzzer_dir, core_id, opt, is_main_node, {
         // The order of the stages matter!
         let mut stages = tuple_list!(
             calibration,
+            trim,
+            deterministic,
             mutational_stage,
             timeout_verify_stage,
             afl_stats_stage,
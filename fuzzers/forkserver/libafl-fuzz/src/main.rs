@@ -245,6 +245,9 @@ struct Opt {
     /// sequential queue selection instead of weighted random
     #[arg(short = 'Z')]
     sequential_queue: bool,
+    /// enable deterministic fuzzing (once per queue entry)
+    #[arg(short = 'D')]
+    deterministic: bool,
     // TODO: enforce
     #[arg(short = 'm')]
     memory_limit: Option<usize>,
@@ -293,6 +296,8 @@ struct Opt {
     skip_bin_check: bool,
     #[clap(skip)]
     defer_forkserver: bool,
+    #[clap(skip)]
+    disable_trim: bool,
     /// in seconds
     #[clap(skip)]
     stats_interval: u64,
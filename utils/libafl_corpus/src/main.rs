@@ -0,0 +1,597 @@
+//! `libafl-corpus` inspects the directories written by an `OnDiskCorpus` or `InMemoryOnDiskCorpus`.
+//!
+//! It lists the testcases together with their `.metadata` files, filters and sorts them,
+//! converts the metadata between the `OnDiskMetadataFormat`s, and exports selected entries.
+
+use core::{cmp::Ordering, time::Duration};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use libafl::corpus::ondisk::OnDiskMetadataFormat;
+use libafl_bolts::{Error, compress::GzipCompressor, serdeany::SerdeAnyMap};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Parser, Debug)]
+#[clap(author, version, about, long_about = None)]
+#[command(
+    name = "libafl-corpus",
+    about,
+    long_about = "Inspects, filters, converts and exports the testcases of a LibAFL on-disk corpus"
+)]
+struct Opt {
+    #[arg(help = "The corpus directory")]
+    corpus: PathBuf,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Lists the testcases with their exec time, depth and the feedbacks they hit
+    List {
+        #[command(flatten)]
+        selection: Selection,
+        #[arg(long, help = "Print the list as JSON")]
+        json: bool,
+    },
+    /// Prints all metadata of a single testcase as JSON
+    Show {
+        #[arg(help = "The file name of the testcase")]
+        name: String,
+    },
+    /// Rewrites all metadata files in another format
+    Convert {
+        #[arg(long, value_enum, help = "The format to convert the metadata to")]
+        to: Format,
+    },
+    /// Copies the selected testcases to another directory
+    Export {
+        #[arg(help = "The directory to copy the testcases to")]
+        output: PathBuf,
+        #[command(flatten)]
+        selection: Selection,
+        #[arg(long, help = "Also copy the metadata files")]
+        with_metadata: bool,
+    },
+}
+
+/// Which testcases to work on, and in which order
+#[derive(Args, Debug)]
+struct Selection {
+    #[arg(long, value_enum, default_value = "name", help = "The key to sort by")]
+    sort: SortKey,
+    #[arg(long, help = "Reverse the order")]
+    reverse: bool,
+    #[arg(
+        long,
+        help = "Only keep testcases that were found by the feedback or objective with this name"
+    )]
+    feedback: Option<String>,
+    #[arg(long, help = "Only keep testcases at least this deep")]
+    min_depth: Option<u64>,
+    #[arg(long, help = "Only keep testcases at most this deep")]
+    max_depth: Option<u64>,
+    #[arg(long, help = "Only keep the first n testcases, after sorting")]
+    limit: Option<usize>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum SortKey {
+    Name,
+    Size,
+    ExecTime,
+    Executions,
+    Depth,
+}
+
+/// The command line name of an [`OnDiskMetadataFormat`]
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Postcard,
+    Json,
+    JsonPretty,
+    JsonGzip,
+}
+
+impl From<Format> for OnDiskMetadataFormat {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Postcard => Self::Postcard,
+            Format::Json => Self::Json,
+            Format::JsonPretty => Self::JsonPretty,
+            Format::JsonGzip => Self::JsonGzip,
+        }
+    }
+}
+
+/// An owned copy of the `OnDiskMetadata` written by the corpora.
+///
+/// The hit feedbacks are only written if `libafl` was built with `track_hit_feedbacks`.
+/// In JSON they are plain keys, while in postcard, which has no field tags, they are two vectors appended to the
+/// other fields, see [`StoredMetadata::parse`] and [`StoredMetadata::serialize`].
+#[derive(Debug, Serialize, Deserialize)]
+struct StoredMetadata {
+    metadata: SerdeAnyMap,
+    exec_time: Option<Duration>,
+    executions: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hit_feedbacks: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hit_objectives: Option<Vec<String>>,
+}
+
+impl StoredMetadata {
+    /// Parses metadata in any format, the metadata types have to be registered
+    fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        match detect_format(bytes) {
+            OnDiskMetadataFormat::Postcard => {
+                // Postcard is not self-describing, the hit feedbacks are there if there are bytes left
+                let ((metadata, exec_time, executions), rest): (
+                    (SerdeAnyMap, Option<Duration>, u64),
+                    &[u8],
+                ) = postcard::take_from_bytes(bytes)?;
+                let (hit_feedbacks, hit_objectives) = if rest.is_empty() {
+                    (None, None)
+                } else {
+                    let (feedbacks, objectives) = postcard::from_bytes(rest)?;
+                    (Some(feedbacks), Some(objectives))
+                };
+                Ok(Self {
+                    metadata,
+                    exec_time,
+                    executions,
+                    hit_feedbacks,
+                    hit_objectives,
+                })
+            }
+            OnDiskMetadataFormat::JsonGzip => {
+                serde_json::from_slice(&GzipCompressor::new().decompress(bytes)?)
+                    .map_err(json_error)
+            }
+            _ => serde_json::from_slice(bytes).map_err(json_error),
+        }
+    }
+
+    /// Serializes the metadata the same way the corpora do
+    fn serialize(&self, format: &OnDiskMetadataFormat) -> Result<Vec<u8>, Error> {
+        match format {
+            OnDiskMetadataFormat::Postcard => {
+                // The exact field layout of `OnDiskMetadata`, without the option tags serde would add
+                let mut bytes =
+                    postcard::to_allocvec(&(&self.metadata, &self.exec_time, &self.executions))?;
+                if self.hit_feedbacks.is_some() || self.hit_objectives.is_some() {
+                    let empty = Vec::new();
+                    bytes.extend(postcard::to_allocvec(&(
+                        self.hit_feedbacks.as_ref().unwrap_or(&empty),
+                        self.hit_objectives.as_ref().unwrap_or(&empty),
+                    ))?);
+                }
+                Ok(bytes)
+            }
+            OnDiskMetadataFormat::Json => serde_json::to_vec(self).map_err(json_error),
+            OnDiskMetadataFormat::JsonPretty => serde_json::to_vec_pretty(self).map_err(json_error),
+            OnDiskMetadataFormat::JsonGzip => Ok(GzipCompressor::new()
+                .compress(&serde_json::to_vec_pretty(self).map_err(json_error)?)),
+        }
+    }
+}
+
+#[expect(clippy::needless_pass_by_value)] // for `map_err`
+fn json_error(err: serde_json::Error) -> Error {
+    Error::serialize(format!("Failed to (de)serialize JSON metadata: {err:?}"))
+}
+
+/// Guesses the format of a metadata file from its first bytes.
+///
+/// JSON and pretty JSON cannot be told apart, and are both reported as [`OnDiskMetadataFormat::Json`].
+fn detect_format(bytes: &[u8]) -> OnDiskMetadataFormat {
+    if bytes.first() == Some(&b'{') {
+        OnDiskMetadataFormat::Json
+    } else if GzipCompressor::new()
+        .decompress(bytes)
+        .is_ok_and(|json| json.first() == Some(&b'{'))
+    {
+        OnDiskMetadataFormat::JsonGzip
+    } else {
+        OnDiskMetadataFormat::Postcard
+    }
+}
+
+/// Parses metadata in any format into untyped JSON.
+///
+/// JSON metadata can be read without knowing the types in it, postcard needs them to be registered.
+fn parse_json(bytes: &[u8]) -> Result<Value, Error> {
+    let json = match detect_format(bytes) {
+        OnDiskMetadataFormat::Postcard => {
+            serde_json::to_vec(&StoredMetadata::parse(bytes)?).map_err(json_error)?
+        }
+        OnDiskMetadataFormat::JsonGzip => GzipCompressor::new().decompress(bytes)?,
+        _ => bytes.to_vec(),
+    };
+    serde_json::from_slice(&json).map_err(json_error)
+}
+
+/// A testcase in the corpus directory
+#[derive(Debug)]
+struct Entry {
+    name: String,
+    path: PathBuf,
+    size: u64,
+    metadata_path: Option<PathBuf>,
+    metadata: Option<Value>,
+}
+
+/// A summary of an [`Entry`], as listed
+#[derive(Debug, Serialize)]
+struct Summary<'a> {
+    name: &'a str,
+    size: u64,
+    exec_time_us: Option<u128>,
+    executions: Option<u64>,
+    depth: Option<u64>,
+    hit_feedbacks: Vec<&'a str>,
+    hit_objectives: Vec<&'a str>,
+}
+
+impl Entry {
+    fn exec_time(&self) -> Option<Duration> {
+        let exec_time = self.metadata.as_ref()?.get("exec_time")?;
+        Some(Duration::new(
+            exec_time.get("secs")?.as_u64()?,
+            u32::try_from(exec_time.get("nanos")?.as_u64()?).ok()?,
+        ))
+    }
+
+    fn executions(&self) -> Option<u64> {
+        self.metadata.as_ref()?.get("executions")?.as_u64()
+    }
+
+    /// The depth is stored in the scheduler metadata, which is the only one with a `depth`
+    fn depth(&self) -> Option<u64> {
+        let map = self
+            .metadata
+            .as_ref()?
+            .get("metadata")?
+            .get("map")?
+            .as_object()?;
+        map.values()
+            .find_map(|entry| entry.get(1)?.get("depth")?.as_u64())
+    }
+
+    fn names(&self, key: &str) -> Vec<&str> {
+        self.metadata
+            .as_ref()
+            .and_then(|metadata| metadata.get(key)?.as_array())
+            .map(|names| names.iter().filter_map(Value::as_str).collect())
+            .unwrap_or_default()
+    }
+
+    fn summary(&self) -> Summary<'_> {
+        Summary {
+            name: &self.name,
+            size: self.size,
+            exec_time_us: self.exec_time().map(|exec_time| exec_time.as_micros()),
+            executions: self.executions(),
+            depth: self.depth(),
+            hit_feedbacks: self.names("hit_feedbacks"),
+            hit_objectives: self.names("hit_objectives"),
+        }
+    }
+}
+
+/// Finds the metadata file of the testcase `name`, also if it was written with locking enabled
+fn find_metadata(dir: &Path, name: &str) -> Option<PathBuf> {
+    let path = dir.join(format!(".{name}.metadata"));
+    if path.is_file() {
+        return Some(path);
+    }
+    let prefix = format!(".{name}_");
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(Result::ok)
+        .filter_map(|file| {
+            let file_name = file.file_name().into_string().ok()?;
+            let ctr = file_name
+                .strip_prefix(&prefix)?
+                .strip_suffix(".metadata")?
+                .parse::<u32>()
+                .ok()?;
+            Some((ctr, file.path()))
+        })
+        .min_by_key(|(ctr, _)| *ctr)
+        .map(|(_, path)| path)
+}
+
+/// Reads all testcases in `dir`, skipping the hidden metadata and lock files
+fn read_corpus(dir: &Path) -> Result<Vec<Entry>, Error> {
+    let mut entries = Vec::new();
+    for file in fs::read_dir(dir)? {
+        let file = file?;
+        let Ok(name) = file.file_name().into_string() else {
+            continue;
+        };
+        if name.starts_with('.') || !file.file_type()?.is_file() {
+            continue;
+        }
+
+        let metadata_path = find_metadata(dir, &name);
+        let metadata = match &metadata_path {
+            Some(path) => match parse_json(&fs::read(path)?) {
+                Ok(metadata) => Some(metadata),
+                Err(err) => {
+                    eprintln!("Warning: failed to read {}: {err}", path.display());
+                    None
+                }
+            },
+            None => None,
+        };
+        entries.push(Entry {
+            name,
+            path: file.path(),
+            size: file.metadata()?.len(),
+            metadata_path,
+            metadata,
+        });
+    }
+    Ok(entries)
+}
+
+/// Filters and sorts the entries according to the selection
+fn select(mut entries: Vec<Entry>, selection: &Selection) -> Vec<Entry> {
+    entries.retain(|entry| {
+        if let Some(feedback) = &selection.feedback {
+            let name = feedback.as_str();
+            if !entry.names("hit_feedbacks").contains(&name)
+                && !entry.names("hit_objectives").contains(&name)
+            {
+                return false;
+            }
+        }
+        if selection.min_depth.is_some() || selection.max_depth.is_some() {
+            let Some(depth) = entry.depth() else {
+                return false;
+            };
+            if selection.min_depth.is_some_and(|min| depth < min)
+                || selection.max_depth.is_some_and(|max| depth > max)
+            {
+                return false;
+            }
+        }
+        true
+    });
+
+    entries.sort_by(|a, b| {
+        let ordering = match selection.sort {
+            SortKey::Name => Ordering::Equal,
+            SortKey::Size => a.size.cmp(&b.size),
+            SortKey::ExecTime => a.exec_time().cmp(&b.exec_time()),
+            SortKey::Executions => a.executions().cmp(&b.executions()),
+            SortKey::Depth => a.depth().cmp(&b.depth()),
+        };
+        ordering.then_with(|| a.name.cmp(&b.name))
+    });
+    if selection.reverse {
+        entries.reverse();
+    }
+    if let Some(limit) = selection.limit {
+        entries.truncate(limit);
+    }
+    entries
+}
+
+fn list(entries: &[Entry], json: bool) -> Result<(), Error> {
+    if json {
+        let summaries = entries.iter().map(Entry::summary).collect::<Vec<_>>();
+        let json = serde_json::to_string_pretty(&summaries).map_err(json_error)?;
+        println!("{json}");
+        return Ok(());
+    }
+
+    let or_dash = |value: Option<String>| value.unwrap_or_else(|| "-".into());
+    println!(
+        "{:<40} {:>10} {:>14} {:>12} {:>6}  feedbacks",
+        "name", "size", "exec time (us)", "executions", "depth"
+    );
+    for entry in entries {
+        let summary = entry.summary();
+        let mut hits = summary.hit_feedbacks;
+        hits.extend(summary.hit_objectives);
+        println!(
+            "{:<40} {:>10} {:>14} {:>12} {:>6}  {}",
+            summary.name,
+            summary.size,
+            or_dash(summary.exec_time_us.map(|us| us.to_string())),
+            or_dash(summary.executions.map(|executions| executions.to_string())),
+            or_dash(summary.depth.map(|depth| depth.to_string())),
+            hits.join(",")
+        );
+    }
+    Ok(())
+}
+
+fn show(entries: &[Entry], name: &str) -> Result<(), Error> {
+    let entry = entries
+        .iter()
+        .find(|entry| entry.name == name)
+        .ok_or_else(|| Error::key_not_found(format!("No testcase named {name}")))?;
+    let metadata_path = entry
+        .metadata_path
+        .as_ref()
+        .ok_or_else(|| Error::key_not_found(format!("Testcase {name} has no metadata file")))?;
+    let metadata = parse_json(&fs::read(metadata_path)?)?;
+    let json = serde_json::to_string_pretty(&metadata).map_err(json_error)?;
+    println!("{json}");
+    Ok(())
+}
+
+fn convert(entries: &[Entry], to: Format) -> Result<(), Error> {
+    let mut converted = 0;
+    for metadata_path in entries
+        .iter()
+        .filter_map(|entry| entry.metadata_path.as_ref())
+    {
+        let metadata = match StoredMetadata::parse(&fs::read(metadata_path)?) {
+            Ok(metadata) => metadata,
+            Err(err) => {
+                // Most likely a custom metadata type of the fuzzer this tool does not know
+                eprintln!("Warning: skipping {}: {err}", metadata_path.display());
+                continue;
+            }
+        };
+        let serialized = metadata.serialize(&to.into())?;
+
+        // Write to a temporary file first, like the corpora, so a concurrent reader never sees half a file
+        let mut tmpfile_path = metadata_path.clone();
+        let file_name = metadata_path.file_name().unwrap().to_string_lossy();
+        tmpfile_path.set_file_name(format!(".{file_name}.tmp"));
+        fs::write(&tmpfile_path, serialized)?;
+        fs::rename(&tmpfile_path, metadata_path)?;
+        converted += 1;
+    }
+    println!("Converted {converted} metadata files to {to:?}");
+    Ok(())
+}
+
+fn export(entries: &[Entry], output: &Path, with_metadata: bool) -> Result<(), Error> {
+    fs::create_dir_all(output)?;
+    for entry in entries {
+        fs::copy(&entry.path, output.join(&entry.name))?;
+        if with_metadata && let Some(metadata_path) = &entry.metadata_path {
+            fs::copy(
+                metadata_path,
+                output.join(format!(".{}.metadata", entry.name)),
+            )?;
+        }
+    }
+    println!(
+        "Exported {} testcases to {}",
+        entries.len(),
+        output.display()
+    );
+    Ok(())
+}
+
+fn main() -> Result<(), Error> {
+    let opt = Opt::parse();
+    let entries = read_corpus(&opt.corpus)?;
+
+    match opt.command {
+        Command::List { selection, json } => list(&select(entries, &selection), json),
+        Command::Show { name } => show(&entries, &name),
+        Command::Convert { to } => convert(&entries, to),
+        Command::Export {
+            output,
+            selection,
+            with_metadata,
+        } => export(&select(entries, &selection), &output, with_metadata),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use libafl::{
+        HasMetadata,
+        corpus::{
+            Corpus, InMemoryOnDiskCorpus, SchedulerTestcaseMetadata, Testcase,
+            ondisk::OnDiskMetadataFormat,
+        },
+        inputs::BytesInput,
+    };
+    use libafl_bolts::{compress::GzipCompressor, serdeany::SerdeAnyMap};
+
+    use super::{Format, StoredMetadata, convert, parse_json, read_corpus};
+
+    const FORMATS: [Format; 4] = [
+        Format::Postcard,
+        Format::Json,
+        Format::JsonPretty,
+        Format::JsonGzip,
+    ];
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("libafl_corpus_test_{name}_{}", std::process::id()));
+        drop(fs::remove_dir_all(&dir));
+        dir
+    }
+
+    /// Writes a corpus with one testcase at depth 3, with metadata in the given format
+    fn write_corpus(dir: &PathBuf, format: Format) {
+        let mut corpus = InMemoryOnDiskCorpus::<BytesInput>::with_meta_format(
+            dir,
+            Some(OnDiskMetadataFormat::from(format)),
+        )
+        .unwrap();
+        let mut testcase = Testcase::new(BytesInput::new(b"abc".to_vec()));
+        testcase.add_metadata(SchedulerTestcaseMetadata::new(3));
+        testcase.set_exec_time(core::time::Duration::from_micros(42));
+        corpus.add(testcase).unwrap();
+    }
+
+    #[test]
+    fn test_metadata_roundtrip() {
+        for from in FORMATS {
+            for to in FORMATS {
+                let dir = test_dir(&format!("{from:?}_{to:?}"));
+                write_corpus(&dir, from);
+
+                let entries = read_corpus(&dir).unwrap();
+                assert_eq!(entries.len(), 1);
+                let path = entries[0].metadata_path.clone().unwrap();
+                let written = fs::read(&path).unwrap();
+
+                // What the tool writes is what the corpus writes
+                let serialized = StoredMetadata::parse(&written)
+                    .unwrap()
+                    .serialize(&from.into())
+                    .unwrap();
+                if from == Format::JsonGzip {
+                    let compressor = GzipCompressor::new();
+                    assert_eq!(
+                        compressor.decompress(&serialized).unwrap(),
+                        compressor.decompress(&written).unwrap()
+                    );
+                } else {
+                    assert_eq!(serialized, written);
+                }
+
+                convert(&entries, to).unwrap();
+                let converted = read_corpus(&dir).unwrap();
+                assert_eq!(converted[0].depth(), Some(3));
+                assert_eq!(
+                    converted[0].exec_time(),
+                    Some(core::time::Duration::from_micros(42))
+                );
+                assert_eq!(
+                    parse_json(&fs::read(&path).unwrap()).unwrap(),
+                    parse_json(&written).unwrap()
+                );
+
+                fs::remove_dir_all(&dir).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_metadata_hit_feedbacks_roundtrip() {
+        for format in FORMATS {
+            let stored = StoredMetadata {
+                metadata: SerdeAnyMap::new(),
+                exec_time: None,
+                executions: 7,
+                hit_feedbacks: Some(vec!["edges".into()]),
+                hit_objectives: Some(vec![]),
+            };
+            let parsed = StoredMetadata::parse(&stored.serialize(&format.into()).unwrap()).unwrap();
+            assert_eq!(parsed.executions, 7);
+            assert_eq!(parsed.hit_feedbacks, stored.hit_feedbacks);
+            assert_eq!(parsed.hit_objectives, stored.hit_objectives);
+        }
+    }
+}